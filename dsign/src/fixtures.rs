@@ -0,0 +1,105 @@
+//! Programmatic PDF and PNG fixtures for exercising the stamping helpers.
+
+use image::{ImageBuffer, Rgba};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::error::Error;
+use std::path::Path;
+
+/// Shape of a single generated page.
+#[derive(Debug, Clone)]
+pub struct FixturePage {
+    pub media_box: [i64; 4],
+    /// Give the page a `/Resources` dictionary with a Helvetica `/F1` font.
+    pub with_resources: bool,
+    /// Existing content stream, if any.
+    pub content: Option<Vec<u8>>,
+    /// Store `/Contents` as an array of references instead of a single one.
+    pub content_as_array: bool,
+}
+
+impl Default for FixturePage {
+    fn default() -> Self {
+        FixturePage {
+            media_box: [0, 0, 612, 792],
+            with_resources: false,
+            content: None,
+            content_as_array: false,
+        }
+    }
+}
+
+/// Builds a document whose pages follow `pages`, with a proper
+/// Catalog -> Pages -> Page tree.
+pub fn build_document(pages: &[FixturePage]) -> Document {
+    let mut doc = Document::with_version("1.5");
+    let pages_id: ObjectId = doc.new_object_id();
+    let font_id = doc.add_object(
+        dictionary! {"Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica"},
+    );
+
+    let mut kids = Vec::with_capacity(pages.len());
+    for page in pages {
+        let mut page_dict = dictionary! {
+            "Type" => "Page",
+            "Parent" => Object::Reference(pages_id),
+            "MediaBox" => page.media_box.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+        };
+        if page.with_resources {
+            page_dict.set(
+                "Resources",
+                dictionary! { "Font" => dictionary! { "F1" => Object::Reference(font_id) } },
+            );
+        }
+        if let Some(content) = &page.content {
+            let content_id = doc.add_object(Stream::new(Dictionary::new(), content.clone()));
+            if page.content_as_array {
+                page_dict.set("Contents", vec![Object::Reference(content_id)]);
+            } else {
+                page_dict.set("Contents", Object::Reference(content_id));
+            }
+        }
+        kids.push(Object::Reference(doc.add_object(page_dict)));
+    }
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => Object::Reference(pages_id),
+    });
+    doc.trailer.set("Root", Object::Reference(catalog_id));
+    doc
+}
+
+/// The two-page layout `dsign` used to bootstrap: a bare first page and a
+/// second page with a font resource and a single existing content stream.
+pub fn two_page_document() -> Document {
+    build_document(&[
+        FixturePage::default(),
+        FixturePage {
+            with_resources: true,
+            content: Some(
+                b"BT /F1 12 Tf 100 700 Td (Page 2 Existing Dummy Content) Tj ET".to_vec(),
+            ),
+            ..FixturePage::default()
+        },
+    ])
+}
+
+/// Writes a solid-colour PNG of the given size.
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    color: Rgba<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let image: ImageBuffer<Rgba<u8>, _> = ImageBuffer::from_pixel(width, height, color);
+    image.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use dsign::fixtures::{self, FixturePage};
+use dsign::{add_png_image_to_pdf, add_text_image_to_pdf};
+use flate2::read::ZlibDecoder;
+use image::Rgba;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const IMAGE_WIDTH: u32 = 40;
+const IMAGE_HEIGHT: u32 = 20;
+
+fn png_fixture(dir: &TempDir) -> PathBuf {
+    let path = dir.path().join("stamp.png");
+    fixtures::write_png(&path, IMAGE_WIDTH, IMAGE_HEIGHT, Rgba([200, 10, 10, 128])).unwrap();
+    path
+}
+
+/// Saves and reloads `doc` so assertions run against what lopdf parses back.
+fn round_trip(doc: &mut Document, dir: &TempDir) -> Document {
+    let path = dir.path().join("out.pdf");
+    doc.save(&path).unwrap();
+    Document::load(&path).unwrap()
+}
+
+fn page_id(doc: &Document, number: u32) -> ObjectId {
+    *doc.get_pages().get(&number).expect("page exists")
+}
+
+fn page_dict(doc: &Document, number: u32) -> &Dictionary {
+    doc.get_dictionary(page_id(doc, number)).unwrap()
+}
+
+// lopdf has switched `Object::Real` between f32 and f64 across releases.
+#[allow(clippy::useless_conversion)]
+fn number(object: &Object) -> f64 {
+    match object {
+        Object::Integer(value) => *value as f64,
+        Object::Real(value) => f64::from(*value),
+        other => panic!("expected a number, got {:?}", other),
+    }
+}
+
+fn content_ids(doc: &Document, number: u32) -> Vec<ObjectId> {
+    page_dict(doc, number)
+        .get(b"Contents")
+        .unwrap()
+        .as_array()
+        .expect("Contents is an array after stamping")
+        .iter()
+        .map(|o| o.as_reference().unwrap())
+        .collect()
+}
+
+fn decoded_ops(doc: &Document, id: ObjectId) -> Vec<(String, Vec<Object>)> {
+    let stream = doc.get_object(id).unwrap().as_stream().unwrap();
+    let data = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Content::decode(&data)
+        .unwrap()
+        .operations
+        .into_iter()
+        .map(|op| (op.operator, op.operands))
+        .collect()
+}
+
+fn xobject_ref(doc: &Document, page: u32, name: &str) -> ObjectId {
+    page_dict(doc, page)
+        .get(b"Resources")
+        .unwrap()
+        .as_dict()
+        .unwrap()
+        .get(b"XObject")
+        .unwrap()
+        .as_dict()
+        .unwrap()
+        .get(name.as_bytes())
+        .unwrap_or_else(|_| panic!("XObject {} registered", name))
+        .as_reference()
+        .unwrap()
+}
+
+fn assert_draw_stream(doc: &Document, id: ObjectId, name: &str, expected: [f64; 6]) {
+    let ops = decoded_ops(doc, id);
+    let operators: Vec<&str> = ops.iter().map(|(op, _)| op.as_str()).collect();
+    assert_eq!(operators, ["q", "cm", "Do", "Q"]);
+
+    let matrix: Vec<f64> = ops[1].1.iter().map(number).collect();
+    assert_eq!(matrix, expected);
+    assert_eq!(ops[2].1.len(), 1);
+    assert_eq!(ops[2].1[0].as_name_str().unwrap(), name);
+}
+
+#[test]
+fn stamps_bare_page_with_image_xobject_and_placement_matrix() {
+    let dir = TempDir::new().unwrap();
+    let png = png_fixture(&dir);
+    let mut doc = fixtures::two_page_document();
+    let target = page_id(&doc, 1);
+
+    add_png_image_to_pdf(
+        &mut doc,
+        target,
+        png.to_str().unwrap(),
+        "Stamp",
+        110.0,
+        150.0,
+        1.5,
+        true,
+    )
+    .unwrap();
+    let doc = round_trip(&mut doc, &dir);
+
+    let image_id = xobject_ref(&doc, 1, "Stamp");
+    let image = doc.get_object(image_id).unwrap().as_stream().unwrap();
+    assert_eq!(
+        image.dict.get(b"Subtype").unwrap().as_name_str().unwrap(),
+        "Image"
+    );
+    assert_eq!(
+        image.dict.get(b"Width").unwrap().as_i64().unwrap(),
+        IMAGE_WIDTH as i64
+    );
+    assert_eq!(
+        image.dict.get(b"Height").unwrap().as_i64().unwrap(),
+        IMAGE_HEIGHT as i64
+    );
+    assert_eq!(
+        image
+            .dict
+            .get(b"ColorSpace")
+            .unwrap()
+            .as_name_str()
+            .unwrap(),
+        "DeviceRGB"
+    );
+    assert_eq!(
+        image.dict.get(b"Filter").unwrap().as_name_str().unwrap(),
+        "FlateDecode"
+    );
+
+    let smask_id = image.dict.get(b"SMask").unwrap().as_reference().unwrap();
+    let smask = doc.get_object(smask_id).unwrap().as_stream().unwrap();
+    assert_eq!(
+        smask
+            .dict
+            .get(b"ColorSpace")
+            .unwrap()
+            .as_name_str()
+            .unwrap(),
+        "DeviceGray"
+    );
+    // lopdf refuses to decompress image streams, so inflate the mask by hand.
+    let mut alpha = Vec::new();
+    ZlibDecoder::new(smask.content.as_slice())
+        .read_to_end(&mut alpha)
+        .unwrap();
+    assert_eq!(alpha.len(), (IMAGE_WIDTH * IMAGE_HEIGHT) as usize);
+    assert!(alpha.iter().all(|&a| a == 128));
+
+    let contents = content_ids(&doc, 1);
+    assert_eq!(contents.len(), 1);
+    assert_draw_stream(
+        &doc,
+        contents[0],
+        "Stamp",
+        [60.0, 0.0, 0.0, 30.0, 110.0, 150.0],
+    );
+}
+
+#[test]
+fn keeps_existing_content_and_resources() {
+    let dir = TempDir::new().unwrap();
+    let png = png_fixture(&dir);
+    let mut doc = fixtures::two_page_document();
+    let target = page_id(&doc, 2);
+    let original_content = doc.get_page_content(target).unwrap();
+
+    add_png_image_to_pdf(
+        &mut doc,
+        target,
+        png.to_str().unwrap(),
+        "Sign",
+        10.0,
+        20.0,
+        1.0,
+        true,
+    )
+    .unwrap();
+    let doc = round_trip(&mut doc, &dir);
+
+    let resources = page_dict(&doc, 2)
+        .get(b"Resources")
+        .unwrap()
+        .as_dict()
+        .unwrap();
+    assert!(
+        resources
+            .get(b"Font")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .has(b"F1")
+    );
+    xobject_ref(&doc, 2, "Sign");
+
+    let contents = content_ids(&doc, 2);
+    assert_eq!(contents.len(), 2, "existing stream kept, stamp appended");
+    let first = doc.get_object(contents[0]).unwrap().as_stream().unwrap();
+    let first_content = first
+        .decompressed_content()
+        .unwrap_or_else(|_| first.content.clone());
+    assert_eq!(first_content, original_content);
+    assert_draw_stream(
+        &doc,
+        contents[1],
+        "Sign",
+        [40.0, 0.0, 0.0, 20.0, 10.0, 20.0],
+    );
+
+    // The untouched page stays untouched.
+    assert!(!page_dict(&doc, 1).has(b"Contents"));
+}
+
+#[test]
+fn omits_smask_without_transparency() {
+    let dir = TempDir::new().unwrap();
+    let png = png_fixture(&dir);
+    let mut doc = fixtures::two_page_document();
+    let target = page_id(&doc, 1);
+
+    add_png_image_to_pdf(
+        &mut doc,
+        target,
+        png.to_str().unwrap(),
+        "Opaque",
+        0.0,
+        0.0,
+        1.0,
+        false,
+    )
+    .unwrap();
+    let doc = round_trip(&mut doc, &dir);
+
+    let image = doc
+        .get_object(xobject_ref(&doc, 1, "Opaque"))
+        .unwrap()
+        .as_stream()
+        .unwrap();
+    assert!(!image.dict.has(b"SMask"));
+}
+
+#[test]
+fn appends_to_contents_array_and_supports_multiple_stamps() {
+    let dir = TempDir::new().unwrap();
+    let png = png_fixture(&dir);
+    let mut doc = fixtures::build_document(&[FixturePage {
+        with_resources: true,
+        content: Some(b"0 0 m 100 100 l S".to_vec()),
+        content_as_array: true,
+        ..FixturePage::default()
+    }]);
+    let target = page_id(&doc, 1);
+    let png = png.to_str().unwrap();
+
+    add_png_image_to_pdf(&mut doc, target, png, "First", 50.0, 60.0, 0.5, true).unwrap();
+    add_png_image_to_pdf(&mut doc, target, png, "Second", 300.0, 400.0, 2.0, true).unwrap();
+    let doc = round_trip(&mut doc, &dir);
+
+    assert_ne!(
+        xobject_ref(&doc, 1, "First"),
+        xobject_ref(&doc, 1, "Second")
+    );
+    let contents = content_ids(&doc, 1);
+    assert_eq!(contents.len(), 3);
+    assert_draw_stream(
+        &doc,
+        contents[1],
+        "First",
+        [20.0, 0.0, 0.0, 10.0, 50.0, 60.0],
+    );
+    assert_draw_stream(
+        &doc,
+        contents[2],
+        "Second",
+        [80.0, 0.0, 0.0, 40.0, 300.0, 400.0],
+    );
+}
+
+#[test]
+fn text_signature_is_rasterised_and_stamped() {
+    let dir = TempDir::new().unwrap();
+    let font = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts/arial.ttf");
+    let mut doc = fixtures::two_page_document();
+    let target = page_id(&doc, 2);
+    let name = "TestTextSignature";
+
+    add_text_image_to_pdf(
+        &mut doc,
+        target,
+        "Pramod",
+        font.to_str().unwrap(),
+        name,
+        110.0,
+        150.0,
+        1.0,
+        true,
+    )
+    .unwrap();
+    let doc = round_trip(&mut doc, &dir);
+
+    let image = doc
+        .get_object(xobject_ref(&doc, 2, name))
+        .unwrap()
+        .as_stream()
+        .unwrap();
+    let width = image.dict.get(b"Width").unwrap().as_i64().unwrap();
+    let height = image.dict.get(b"Height").unwrap().as_i64().unwrap();
+    assert!(width > 20 && height > 20, "padding around rendered text");
+
+    let contents = content_ids(&doc, 2);
+    assert_draw_stream(
+        &doc,
+        *contents.last().unwrap(),
+        name,
+        [width as f64, 0.0, 0.0, height as f64, 110.0, 150.0],
+    );
+    let temp_png = std::env::temp_dir().join(format!("{}-{}.png", name, std::process::id()));
+    assert!(!temp_png.exists(), "temporary PNG cleaned up");
+    assert!(
+        !Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(format!("{}.png", name))
+            .exists(),
+        "nothing written into the crate directory"
+    );
+}
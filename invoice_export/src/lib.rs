@@ -0,0 +1,893 @@
+//! Exports the line items read from an invoice to Airtable, CSV, JSON
+//! Lines, SQLite and signed webhooks, for the invoice readers `pd_air` and
+//! `pdf_to_airtable`.
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use log::{error, info};
+use reqwest::header;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Airtable creates at most this many records per request
+const AIRTABLE_BATCH: usize = 10;
+
+// Columns that identify an item's Airtable record, so that a retried
+// export updates the records an earlier attempt created
+const AIRTABLE_MERGE_ON: [&str; 2] = ["SHA-256", "No"];
+
+// Header carrying the webhook payload's HMAC-SHA256, as `sha256=<hex>`
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+const CSV_HEADER: [&str; 9] = [
+    "Source",
+    "SHA-256",
+    "No",
+    "Description",
+    "Quantity",
+    "Price",
+    "Total",
+    "Client Name",
+    "Invoice Date",
+];
+
+/// Why an export failed.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Environment variable error: {0}")]
+    Env(String),
+    #[error("HTTP request error: {0}")]
+    Request(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A sink's own failure, prefixed with its name.
+    #[error("{0}")]
+    Sink(String),
+}
+
+/// One line item of an invoice, with the invoice's client and date. The
+/// field names are the Airtable columns.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvoiceItem {
+    #[serde(rename = "No")]
+    pub no: u32,
+    #[serde(rename = "Description")]
+    pub description: String,
+    #[serde(rename = "Quantity")]
+    pub quantity: u32,
+    #[serde(rename = "Price")]
+    pub price: f64,
+    #[serde(rename = "Total")]
+    pub total: f64,
+    #[serde(rename = "Client Name")]
+    pub client_name: String,
+    #[serde(rename = "Invoice Date")]
+    pub invoice_date: String,
+}
+
+/// One invoice to export: the file it was read from and its line items,
+/// which all carry the invoice's client and date.
+pub struct Export<'a> {
+    pub source: &'a str,
+    pub sha256: &'a str,
+    pub items: &'a [InvoiceItem],
+}
+
+impl Export<'_> {
+    fn client_name(&self) -> &str {
+        self.items
+            .first()
+            .map_or("", |item| item.client_name.as_str())
+    }
+
+    fn invoice_date(&self) -> &str {
+        self.items
+            .first()
+            .map_or("", |item| item.invoice_date.as_str())
+    }
+}
+
+// An invoice as written to JSON Lines and sent to webhooks
+#[derive(Serialize)]
+struct Record<'a> {
+    source: &'a str,
+    sha256: &'a str,
+    client_name: &'a str,
+    invoice_date: &'a str,
+    exported_at: String,
+    items: &'a [InvoiceItem],
+}
+
+impl<'a> From<&'a Export<'a>> for Record<'a> {
+    fn from(export: &'a Export<'a>) -> Self {
+        Record {
+            source: export.source,
+            sha256: export.sha256,
+            client_name: export.client_name(),
+            invoice_date: export.invoice_date(),
+            exported_at: chrono::Local::now().to_rfc3339(),
+            items: export.items,
+        }
+    }
+}
+
+/// A destination for extracted invoices.
+#[async_trait]
+pub trait Sink: Send {
+    fn name(&self) -> String;
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error>;
+}
+
+fn sink_error(sink: &str, e: impl std::fmt::Display) -> Error {
+    Error::Sink(format!("{}: {}", sink, e))
+}
+
+#[derive(Debug, Serialize)]
+struct AirtableFields<'a> {
+    #[serde(rename = "Source")]
+    source: &'a str,
+    #[serde(rename = "SHA-256")]
+    sha256: &'a str,
+    #[serde(flatten)]
+    item: &'a InvoiceItem,
+}
+
+#[derive(Debug, Serialize)]
+struct AirtableRecord<'a> {
+    fields: AirtableFields<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AirtableUpsert {
+    fields_to_merge_on: [&'static str; 2],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AirtablePayload<'a> {
+    perform_upsert: AirtableUpsert,
+    records: Vec<AirtableRecord<'a>>,
+}
+
+/// Upserts one Airtable record per line item, keyed on the invoice's
+/// SHA-256 and the item's number, so exporting an invoice again, as a
+/// retry does after a batch failed, doesn't duplicate the items already
+/// sent. The table needs `Source` and `SHA-256` text columns besides the
+/// item's.
+pub struct AirtableSink {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+impl AirtableSink {
+    pub fn from_env() -> Result<Self, Error> {
+        let base_id = env::var("AIRTABLE_BASE_ID").map_err(|e| Error::Env(e.to_string()))?;
+        let table_name = env::var("AIRTABLE_TABLE_NAME").unwrap_or_else(|_| "Invoices".to_string());
+        let api_key = env::var("AIRTABLE_API_KEY").map_err(|e| Error::Env(e.to_string()))?;
+        Ok(AirtableSink {
+            client: reqwest::Client::new(),
+            url: format!("https://api.airtable.com/v0/{}/{}", base_id, table_name),
+            api_key,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for AirtableSink {
+    fn name(&self) -> String {
+        "Airtable".to_string()
+    }
+
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error> {
+        for chunk in export.items.chunks(AIRTABLE_BATCH) {
+            info!("Preparing to upload {} items to Airtable", chunk.len());
+            let records: Vec<AirtableRecord> = chunk
+                .iter()
+                .map(|item| AirtableRecord {
+                    fields: AirtableFields {
+                        source: export.source,
+                        sha256: export.sha256,
+                        item,
+                    },
+                })
+                .collect();
+
+            let payload = AirtablePayload {
+                perform_upsert: AirtableUpsert {
+                    fields_to_merge_on: AIRTABLE_MERGE_ON,
+                },
+                records,
+            };
+
+            // Airtable upserts on PATCH only
+            let response = self
+                .client
+                .patch(&self.url)
+                .json(&payload)
+                .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
+                .send()
+                .await
+                .map_err(|e| Error::Request(e.to_string()))?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let response_body = response
+                    .text()
+                    .await
+                    .map_err(|e| Error::Request(e.to_string()))?;
+                error!("Airtable API error (status {}): {}", status, response_body);
+                return Err(Error::Request(format!(
+                    "API returned status: {}, body: {}",
+                    status, response_body
+                )));
+            }
+            info!("Successfully uploaded batch of {} items", chunk.len());
+        }
+        Ok(())
+    }
+}
+
+/// Appends one row per line item to a CSV file, writing the header when
+/// the file is new.
+pub struct CsvSink {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl Sink for CsvSink {
+    fn name(&self) -> String {
+        format!("CSV {}", self.path.display())
+    }
+
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let new = file.metadata()?.len() == 0;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        let write = |writer: &mut csv::Writer<_>| -> Result<(), csv::Error> {
+            if new {
+                writer.write_record(CSV_HEADER)?;
+            }
+            for item in export.items {
+                writer.write_record([
+                    export.source,
+                    export.sha256,
+                    &item.no.to_string(),
+                    &item.description,
+                    &item.quantity.to_string(),
+                    &format!("{:.2}", item.price),
+                    &format!("{:.2}", item.total),
+                    &item.client_name,
+                    &item.invoice_date,
+                ])?;
+            }
+            writer.flush()?;
+            Ok(())
+        };
+        write(&mut writer).map_err(|e| sink_error(&self.name(), e))
+    }
+}
+
+/// Appends one JSON object per invoice to a JSON Lines file.
+pub struct JsonLinesSink {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl Sink for JsonLinesSink {
+    fn name(&self) -> String {
+        format!("JSON Lines {}", self.path.display())
+    }
+
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error> {
+        let line = serde_json::to_string(&Record::from(export))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Stores invoices in SQLite, with one `invoices` row per invoice and its
+/// line items in `line_items`, referencing it.
+pub struct SqliteSink {
+    path: PathBuf,
+    connection: Connection,
+}
+
+const SQLITE_SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS invoices (
+        id INTEGER PRIMARY KEY,
+        source TEXT NOT NULL,
+        sha256 TEXT NOT NULL,
+        client_name TEXT NOT NULL,
+        invoice_date TEXT NOT NULL,
+        exported_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS line_items (
+        id INTEGER PRIMARY KEY,
+        invoice_id INTEGER NOT NULL REFERENCES invoices (id) ON DELETE CASCADE,
+        no INTEGER NOT NULL,
+        description TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        price REAL NOT NULL,
+        total REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS line_items_invoice_id ON line_items (invoice_id);
+    CREATE INDEX IF NOT EXISTS invoices_sha256 ON invoices (sha256);
+";
+
+impl SqliteSink {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let name = format!("SQLite {}", path.display());
+        let connection = Connection::open(path).map_err(|e| sink_error(&name, e))?;
+        connection
+            .execute_batch(SQLITE_SCHEMA)
+            .map_err(|e| sink_error(&name, e))?;
+        Ok(SqliteSink {
+            path: path.to_path_buf(),
+            connection,
+        })
+    }
+
+    fn insert(&mut self, export: &Export<'_>) -> Result<(), rusqlite::Error> {
+        // The invoice and its items are stored together or not at all
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO invoices (source, sha256, client_name, invoice_date, exported_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                export.source,
+                export.sha256,
+                export.client_name(),
+                export.invoice_date(),
+                chrono::Local::now().to_rfc3339(),
+            ],
+        )?;
+        let invoice_id = transaction.last_insert_rowid();
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO line_items (invoice_id, no, description, quantity, price, total)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for item in export.items {
+                statement.execute(params![
+                    invoice_id,
+                    item.no,
+                    item.description,
+                    item.quantity,
+                    item.price,
+                    item.total,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+#[async_trait]
+impl Sink for SqliteSink {
+    fn name(&self) -> String {
+        format!("SQLite {}", self.path.display())
+    }
+
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error> {
+        self.insert(export).map_err(|e| sink_error(&self.name(), e))
+    }
+}
+
+/// POSTs each invoice as JSON to a URL, signed with an HMAC-SHA256 of the
+/// body in the `X-Signature-256` header so the receiver can check it came
+/// from us.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: reqwest::Url,
+    secret: String,
+}
+
+impl WebhookSink {
+    pub fn from_env() -> Result<Self, Error> {
+        let url = env::var("WEBHOOK_URL").map_err(|e| Error::Env(e.to_string()))?;
+        let url = reqwest::Url::parse(&url)
+            .map_err(|e| Error::Env(format!("WEBHOOK_URL is not a URL: {}", e)))?;
+        let secret = env::var("WEBHOOK_SECRET").map_err(|e| Error::Env(e.to_string()))?;
+        Ok(WebhookSink {
+            client: reqwest::Client::new(),
+            url,
+            secret,
+        })
+    }
+}
+
+// The hex HMAC-SHA256 of `body` under `secret`
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    // The host only: the path or query may hold a token, and the name is
+    // logged and kept in the ledger
+    fn name(&self) -> String {
+        format!("webhook {}", self.url.host_str().unwrap_or_default())
+    }
+
+    async fn export(&mut self, export: &Export<'_>) -> Result<(), Error> {
+        let body = serde_json::to_vec(&Record::from(export))?;
+        let signature = format!("sha256={}", sign(&self.secret, &body));
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| Error::Request(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let response_body = response
+                .text()
+                .await
+                .map_err(|e| Error::Request(e.to_string()))?;
+            return Err(Error::Request(format!(
+                "Webhook returned status: {}, body: {}",
+                status, response_body
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the sinks named in `EXPORT_SINKS`, a comma-separated list of
+/// `airtable`, `csv`, `jsonl`, `sqlite` and `webhook`; Airtable alone by
+/// default. File sinks write to `CSV_PATH`, `JSONL_PATH` and `SQLITE_PATH`.
+pub fn from_env() -> Result<Vec<Box<dyn Sink>>, Error> {
+    let names = env::var("EXPORT_SINKS").unwrap_or_else(|_| "airtable".to_string());
+    let path = |key: &str, default: &str| {
+        PathBuf::from(env::var(key).unwrap_or_else(|_| default.to_string()))
+    };
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let sink: Box<dyn Sink> = match name.to_lowercase().as_str() {
+            "airtable" => Box::new(AirtableSink::from_env()?),
+            "csv" => Box::new(CsvSink {
+                path: path("CSV_PATH", "invoices.csv"),
+            }),
+            "jsonl" => Box::new(JsonLinesSink {
+                path: path("JSONL_PATH", "invoices.jsonl"),
+            }),
+            "sqlite" => Box::new(SqliteSink::open(&path("SQLITE_PATH", "invoices.db"))?),
+            "webhook" => Box::new(WebhookSink::from_env()?),
+            _ => {
+                return Err(Error::Env(format!(
+                    "Unknown export sink '{}' in EXPORT_SINKS; expected airtable, csv, jsonl, sqlite or webhook",
+                    name
+                )))
+            }
+        };
+        sinks.push(sink);
+    }
+    if sinks.is_empty() {
+        return Err(Error::Env("EXPORT_SINKS names no sinks".to_string()));
+    }
+    info!(
+        "Exporting to: {}",
+        sinks
+            .iter()
+            .map(|sink| sink.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(sinks)
+}
+
+/// Exports the invoice to each sink not named in `done`, adding the name of
+/// each that succeeds. Every sink is tried even after one fails, so that a
+/// retry has only the failed ones left; their errors are returned together.
+pub async fn export_all(
+    sinks: &mut [Box<dyn Sink>],
+    export: &Export<'_>,
+    done: &mut Vec<String>,
+) -> Result<(), Error> {
+    let mut failures = Vec::new();
+    for sink in sinks.iter_mut() {
+        let name = sink.name();
+        if done.contains(&name) {
+            info!(
+                "{} was already exported to {}; skipping",
+                export.source, name
+            );
+            continue;
+        }
+        match sink.export(export).await {
+            Ok(()) => {
+                info!(
+                    "Exported {} items from {} to {}",
+                    export.items.len(),
+                    export.source,
+                    name
+                );
+                done.push(name);
+            }
+            Err(e) => {
+                error!("Failed to export {} to {}: {}", export.source, name, e);
+                failures.push(format!("{} failed: {}", name, e));
+            }
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Sink(failures.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, VecDeque};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // A fresh directory under the system temp dir, unique to this test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("invoice_export-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn items() -> Vec<InvoiceItem> {
+        [
+            (1, "Widget, large", 2, 10.0),
+            (2, "Gadget \"pro\"", 1, 25.5),
+        ]
+        .into_iter()
+        .map(|(no, description, quantity, price)| InvoiceItem {
+            no,
+            description: description.to_string(),
+            quantity,
+            price,
+            total: quantity as f64 * price,
+            client_name: "Acme Ltd".to_string(),
+            invoice_date: "3 March 2025".to_string(),
+        })
+        .collect()
+    }
+
+    fn export(items: &[InvoiceItem]) -> Export<'_> {
+        Export {
+            source: "invoice.pdf",
+            sha256: "abc123",
+            items,
+        }
+    }
+
+    #[tokio::test]
+    async fn csv_header_is_written_once() {
+        let path = scratch("csv").join("invoices.csv");
+        let mut sink = CsvSink { path: path.clone() };
+        let items = items();
+        sink.export(&export(&items)).await.unwrap();
+        sink.export(&export(&items)).await.unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| &row[0] != "Source"));
+        assert_eq!(&rows[0][3], "Widget, large");
+        assert_eq!(&rows[1][3], "Gadget \"pro\"");
+        assert_eq!(&rows[1][5], "25.50");
+    }
+
+    #[tokio::test]
+    async fn json_lines_record_round_trips() {
+        let path = scratch("jsonl").join("invoices.jsonl");
+        let mut sink = JsonLinesSink { path: path.clone() };
+        let items = items();
+        sink.export(&export(&items)).await.unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let record: serde_json::Value = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(record["source"], "invoice.pdf");
+        assert_eq!(record["sha256"], "abc123");
+        assert_eq!(record["client_name"], "Acme Ltd");
+        assert_eq!(record["invoice_date"], "3 March 2025");
+        let read: Vec<InvoiceItem> = serde_json::from_value(record["items"].clone()).unwrap();
+        assert_eq!(read.len(), items.len());
+        for (read, written) in read.iter().zip(&items) {
+            assert_eq!(read.no, written.no);
+            assert_eq!(read.description, written.description);
+            assert_eq!(read.quantity, written.quantity);
+            assert_eq!(read.total, written.total);
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_links_items_to_their_invoice() {
+        let path = scratch("sqlite").join("invoices.db");
+        let mut sink = SqliteSink::open(&path).unwrap();
+        let items = items();
+        sink.export(&export(&items)).await.unwrap();
+        sink.export(&export(&items[..1])).await.unwrap();
+
+        let connection = &sink.connection;
+        let invoices: i64 = connection
+            .query_row("SELECT COUNT(*) FROM invoices", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(invoices, 2);
+        let mut statement = connection
+            .prepare(
+                "SELECT invoices.id, invoices.client_name, line_items.description
+                 FROM line_items JOIN invoices ON invoices.id = line_items.invoice_id
+                 ORDER BY line_items.id",
+            )
+            .unwrap();
+        let rows: Vec<(i64, String, String)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].0, rows[1].0);
+        assert_ne!(rows[1].0, rows[2].0);
+        assert!(rows.iter().all(|row| row.1 == "Acme Ltd"));
+        assert_eq!(rows[1].2, "Gadget \"pro\"");
+
+        // Line items must belong to an invoice
+        let orphan = connection.execute(
+            "INSERT INTO line_items (invoice_id, no, description, quantity, price, total)
+             VALUES (999, 1, 'x', 1, 1.0, 1.0)",
+            [],
+        );
+        assert!(orphan.is_err());
+    }
+
+    #[tokio::test]
+    async fn sqlite_rolls_back_a_failed_invoice() {
+        let path = scratch("sqlite-rollback").join("invoices.db");
+        let mut sink = SqliteSink::open(&path).unwrap();
+        // Fail on the second item, after the invoice row was inserted
+        sink.connection
+            .execute_batch(
+                "CREATE TRIGGER fail_second BEFORE INSERT ON line_items WHEN NEW.no = 2
+                 BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+            )
+            .unwrap();
+        let items = items();
+        assert!(sink.export(&export(&items)).await.is_err());
+
+        let count = |table: &str| -> i64 {
+            sink.connection
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        assert_eq!(count("invoices"), 0);
+        assert_eq!(count("line_items"), 0);
+    }
+
+    // A request as the fake server saw it: method, lowercased headers and
+    // the body
+    struct Request {
+        method: String,
+        headers: BTreeMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    #[derive(Default)]
+    struct Server {
+        // Status of each response in turn; 200 once they run out
+        statuses: VecDeque<u16>,
+        requests: Vec<(Request, u16)>,
+    }
+
+    // Answers HTTP/1.1 requests on one connection until the client closes it
+    async fn serve(mut stream: TcpStream, server: Arc<Mutex<Server>>) {
+        let mut buffer = Vec::new();
+        loop {
+            let header_end = loop {
+                if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+                let mut chunk = [0; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                }
+            };
+            let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+            let mut lines = head.lines();
+            let method = lines.next().unwrap().split(' ').next().unwrap().to_string();
+            let headers: BTreeMap<String, String> = lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+                .collect();
+            let length: usize = headers
+                .get("content-length")
+                .map_or(0, |length| length.parse().unwrap());
+            while buffer.len() < header_end + length {
+                let mut chunk = [0; 4096];
+                let read = stream.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..read]);
+            }
+            let body = buffer[header_end..header_end + length].to_vec();
+            buffer.drain(..header_end + length);
+
+            let status = {
+                let mut server = server.lock().unwrap();
+                let status = server.statuses.pop_front().unwrap_or(200);
+                let request = Request {
+                    method,
+                    headers,
+                    body,
+                };
+                server.requests.push((request, status));
+                status
+            };
+            let reply = if status == 200 {
+                "{}"
+            } else {
+                "{\"error\":\"SERVER_ERROR\"}"
+            };
+            let response = format!(
+                "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                reply.len(),
+                reply
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    // A local HTTP server answering with `statuses` in turn, and its URL
+    async fn fake_server(statuses: &[u16]) -> (String, Arc<Mutex<Server>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/v0/app123/Invoices",
+            listener.local_addr().unwrap()
+        );
+        let server = Arc::new(Mutex::new(Server {
+            statuses: statuses.iter().copied().collect(),
+            requests: Vec::new(),
+        }));
+        let shared = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone()));
+            }
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn airtable_retry_upserts_instead_of_duplicating() {
+        let (url, server) = fake_server(&[200, 500]).await;
+        let mut sink = AirtableSink {
+            client: reqwest::Client::new(),
+            url,
+            api_key: "key123".to_string(),
+        };
+        let items: Vec<InvoiceItem> = (1..=12)
+            .map(|no| InvoiceItem {
+                no,
+                description: format!("Item {}", no),
+                quantity: 1,
+                price: 5.0,
+                total: 5.0,
+                client_name: "Acme Ltd".to_string(),
+                invoice_date: "3 March 2025".to_string(),
+            })
+            .collect();
+
+        // The second batch fails, after the first was stored
+        assert!(sink.export(&export(&items)).await.is_err());
+        sink.export(&export(&items)).await.unwrap();
+
+        let server = server.lock().unwrap();
+        let statuses: Vec<u16> = server.requests.iter().map(|(_, status)| *status).collect();
+        assert_eq!(statuses, [200, 500, 200, 200]);
+
+        // Replay the accepted requests the way Airtable applies them: an
+        // upsert replaces the record with the same merge fields, anything
+        // else creates one
+        let mut table: Vec<(String, u64)> = Vec::new();
+        for (request, status) in &server.requests {
+            assert_eq!(request.method, "PATCH");
+            assert_eq!(request.headers["authorization"], "Bearer key123");
+            let payload: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let upsert =
+                payload["performUpsert"]["fieldsToMergeOn"] == serde_json::json!(["SHA-256", "No"]);
+            let records = payload["records"].as_array().unwrap();
+            assert!(records.len() <= AIRTABLE_BATCH);
+            if *status != 200 {
+                continue;
+            }
+            for record in records {
+                let fields = &record["fields"];
+                assert_eq!(fields["Source"], "invoice.pdf");
+                assert_eq!(fields["Client Name"], "Acme Ltd");
+                let key = (
+                    fields["SHA-256"].as_str().unwrap().to_string(),
+                    fields["No"].as_u64().unwrap(),
+                );
+                if !upsert || !table.contains(&key) {
+                    table.push(key);
+                }
+            }
+        }
+        assert_eq!(table.len(), items.len());
+    }
+
+    #[tokio::test]
+    async fn webhook_is_named_by_host_and_signs_its_body() {
+        let (url, server) = fake_server(&[]).await;
+        let mut sink = WebhookSink {
+            client: reqwest::Client::new(),
+            url: reqwest::Url::parse(&format!("{}?token=s3cret", url)).unwrap(),
+            secret: "shared".to_string(),
+        };
+        assert_eq!(sink.name(), "webhook 127.0.0.1");
+        let named = WebhookSink {
+            client: reqwest::Client::new(),
+            url: reqwest::Url::parse("https://user:pw@hooks.example.com/T0/s3cret").unwrap(),
+            secret: String::new(),
+        };
+        assert_eq!(named.name(), "webhook hooks.example.com");
+
+        let items = items();
+        sink.export(&export(&items)).await.unwrap();
+        let server = server.lock().unwrap();
+        let (request, _) = &server.requests[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.headers["x-signature-256"],
+            format!("sha256={}", sign("shared", &request.body))
+        );
+        let record: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(record["sha256"], "abc123");
+    }
+
+    #[test]
+    fn sign_matches_rfc_4231() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
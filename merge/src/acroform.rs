@@ -0,0 +1,382 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeSet;
+
+/// Accumulates the interactive forms of every merged file into one
+/// `/AcroForm` dictionary.
+#[derive(Debug, Default)]
+pub struct FormMerger {
+    fields: Vec<Object>,
+    /// Top-level partial names already in use, i.e. the first component of
+    /// every fully-qualified field name.
+    top_names: BTreeSet<Vec<u8>>,
+    resources: Dictionary,
+    default_appearance: Option<Object>,
+    quadding: Option<Object>,
+    need_appearances: bool,
+    sig_flags: i64,
+    calculation_order: Vec<Object>,
+}
+
+fn resolved_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object).ok()?.1.as_dict().ok()
+}
+
+/// Drops the parts of a field tree whose widgets sit on pages that were not
+/// selected, recording what is left in `kept`. Returns whether anything of
+/// `field_id` is left.
+fn retain_kept_widgets(
+    doc: &mut Document,
+    field_id: ObjectId,
+    kept_annots: &BTreeSet<ObjectId>,
+    kept: &mut BTreeSet<ObjectId>,
+    seen: &mut BTreeSet<ObjectId>,
+) -> bool {
+    if !seen.insert(field_id) {
+        return kept.contains(&field_id);
+    }
+    let kids = match doc.get_dictionary(field_id) {
+        Ok(field) => match field.get(b"Kids").and_then(Object::as_array) {
+            Ok(kids) if !kids.is_empty() => kids.clone(),
+            // A terminal field merged with its only widget
+            _ => {
+                if kept_annots.contains(&field_id) {
+                    kept.insert(field_id);
+                }
+                return kept.contains(&field_id);
+            }
+        },
+        Err(_) => return false,
+    };
+
+    let kids: Vec<Object> = kids
+        .into_iter()
+        .filter(|kid| match kid.as_reference() {
+            Ok(kid_id) => retain_kept_widgets(doc, kid_id, kept_annots, kept, seen),
+            Err(_) => false,
+        })
+        .collect();
+    if kids.is_empty() {
+        return false;
+    }
+    if let Ok(field) = doc.get_dictionary_mut(field_id) {
+        field.set("Kids", kids);
+    }
+    kept.insert(field_id);
+    true
+}
+
+/// The first component of every fully-qualified name under `field_id`: its
+/// own `/T`, or for a root without one, the names of its nearest named kids.
+fn top_level_names(
+    doc: &Document,
+    field_id: ObjectId,
+    seen: &mut BTreeSet<ObjectId>,
+) -> Vec<Vec<u8>> {
+    if !seen.insert(field_id) {
+        return Vec::new();
+    }
+    let Ok(field) = doc.get_dictionary(field_id) else {
+        return Vec::new();
+    };
+    if let Ok(name) = field.get(b"T").and_then(Object::as_str) {
+        return vec![name.to_vec()];
+    }
+    field
+        .get(b"Kids")
+        .and_then(Object::as_array)
+        .map(|kids| {
+            kids.iter()
+                .filter_map(|kid| kid.as_reference().ok())
+                .flat_map(|kid| top_level_names(doc, kid, seen))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl FormMerger {
+    /// Adds the fields of `doc` that have a widget on one of `pages`.
+    ///
+    /// A top-level field whose name is already taken by an earlier document
+    /// is renamed to `<prefix>_<name>`, which renames every field below it.
+    /// A root without a `/T` takes its names from its kids; if any of those
+    /// clash it is given the `/T` `<prefix>`. Returns the number of renamed
+    /// top-level fields.
+    pub fn add_document(&mut self, doc: &mut Document, pages: &[ObjectId], prefix: &str) -> usize {
+        let Some(acroform) = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"AcroForm").ok())
+            .and_then(|form| resolved_dict(doc, form))
+            .cloned()
+        else {
+            return 0;
+        };
+
+        let kept_annots: BTreeSet<ObjectId> = pages
+            .iter()
+            .filter_map(|page_id| doc.get_dictionary(*page_id).ok())
+            .filter_map(|page| page.get(b"Annots").ok())
+            .filter_map(|annots| doc.dereference(annots).ok())
+            .filter_map(|(_, annots)| annots.as_array().ok())
+            .flatten()
+            .filter_map(|annot| annot.as_reference().ok())
+            .collect();
+
+        let fields = acroform
+            .get(b"Fields")
+            .and_then(|f| doc.dereference(f))
+            .and_then(|(_, f)| f.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        // Form-level defaults that differ from the ones already merged are
+        // pushed down onto this document's top-level fields.
+        let default_appearance = acroform.get(b"DA").ok().cloned();
+        let quadding = acroform.get(b"Q").ok().cloned();
+        if self.default_appearance.is_none() {
+            self.default_appearance = default_appearance.clone();
+        }
+        if self.quadding.is_none() {
+            self.quadding = quadding.clone();
+        }
+
+        let mut kept = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        let mut renamed = 0;
+        for field in fields {
+            let Ok(field_id) = field.as_reference() else {
+                continue;
+            };
+            if !retain_kept_widgets(doc, field_id, &kept_annots, &mut kept, &mut seen) {
+                continue;
+            }
+            let Ok(dict) = doc.get_dictionary_mut(field_id) else {
+                continue;
+            };
+
+            for (key, value, merged) in [
+                ("DA", &default_appearance, &self.default_appearance),
+                ("Q", &quadding, &self.quadding),
+            ] {
+                if let Some(value) = value
+                    && !dict.has(key.as_bytes())
+                    && merged.as_ref() != Some(value)
+                {
+                    dict.set(key, value.clone());
+                }
+            }
+
+            let own_name = dict
+                .get(b"T")
+                .and_then(Object::as_str)
+                .ok()
+                .map(<[u8]>::to_vec);
+
+            let mut names = top_level_names(doc, field_id, &mut BTreeSet::new());
+            if names.iter().any(|name| self.top_names.contains(name)) {
+                let candidate = self.unused_name(prefix, own_name.as_deref());
+                if let Ok(dict) = doc.get_dictionary_mut(field_id) {
+                    dict.set("T", Object::string_literal(candidate.clone()));
+                }
+                names = vec![candidate];
+                renamed += 1;
+            }
+            self.top_names.extend(names);
+            self.fields.push(field);
+        }
+
+        if let Some(resources) = acroform
+            .get(b"DR")
+            .ok()
+            .and_then(|dr| resolved_dict(doc, dr))
+        {
+            self.add_resources(doc, resources);
+        }
+        if let Ok(Object::Boolean(true)) = acroform.get(b"NeedAppearances") {
+            self.need_appearances = true;
+        }
+        if let Ok(flags) = acroform.get(b"SigFlags").and_then(Object::as_i64) {
+            self.sig_flags |= flags;
+        }
+        if let Ok((_, Object::Array(order))) =
+            acroform.get(b"CO").and_then(|co| doc.dereference(co))
+        {
+            self.calculation_order.extend(
+                order
+                    .iter()
+                    .filter(|field| matches!(field.as_reference(), Ok(id) if kept.contains(&id)))
+                    .cloned(),
+            );
+        }
+        renamed
+    }
+
+    /// A top-level name no earlier document uses: `<prefix>_<name>`, or just
+    /// `<prefix>` for a root without a `/T`, numbered if that is taken too.
+    fn unused_name(&self, prefix: &str, name: Option<&[u8]>) -> Vec<u8> {
+        let build = |prefix: &str| match name {
+            Some(name) => [prefix.as_bytes(), b"_", name].concat(),
+            None => prefix.as_bytes().to_vec(),
+        };
+        let mut candidate = build(prefix);
+        let mut suffix = 2;
+        while self.top_names.contains(&candidate) {
+            candidate = build(&format!("{}{}", prefix, suffix));
+            suffix += 1;
+        }
+        candidate
+    }
+
+    /// Unions `/DR` by resource category. When two documents define the same
+    /// resource name, the first definition wins: existing fields keep their
+    /// own appearance streams, so this only matters for newly generated ones.
+    fn add_resources(&mut self, doc: &Document, resources: &Dictionary) {
+        for (category, entries) in resources.iter() {
+            let Some(entries) = resolved_dict(doc, entries) else {
+                // e.g. /ProcSet arrays: keep the first one seen
+                if !self.resources.has(category) {
+                    self.resources.set(category.clone(), entries.clone());
+                }
+                continue;
+            };
+            if !matches!(self.resources.get(category), Ok(Object::Dictionary(_))) {
+                self.resources
+                    .set(category.clone(), Object::Dictionary(Dictionary::new()));
+            }
+            if let Ok(Object::Dictionary(merged)) = self.resources.get_mut(category) {
+                for (name, value) in entries.iter() {
+                    if !merged.has(name) {
+                        merged.set(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Everything merged so far that may refer to other objects, for
+    /// callers that copy each document's objects out as they go.
+    pub fn entries(&self) -> impl Iterator<Item = &Object> {
+        self.fields
+            .iter()
+            .chain(self.resources.iter().map(|(_, value)| value))
+            .chain(&self.calculation_order)
+    }
+
+    /// The combined `/AcroForm` dictionary, if any document had fields left.
+    pub fn into_acroform(self) -> Option<Dictionary> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        let mut acroform = Dictionary::new();
+        acroform.set("Fields", self.fields);
+        if !self.resources.is_empty() {
+            acroform.set("DR", self.resources);
+        }
+        if let Some(da) = self.default_appearance {
+            acroform.set("DA", da);
+        }
+        if let Some(q) = self.quadding {
+            acroform.set("Q", q);
+        }
+        if self.need_appearances {
+            acroform.set("NeedAppearances", true);
+        }
+        if self.sig_flags != 0 {
+            acroform.set("SigFlags", self.sig_flags);
+        }
+        if !self.calculation_order.is_empty() {
+            acroform.set("CO", self.calculation_order);
+        }
+        Some(acroform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+
+    /// A two-page document with a text field `name` on page 1, a field
+    /// `total` on page 2 and, optionally, a root without `/T` whose only kid
+    /// is another `name` on page 1.
+    fn form(with_nameless_root: bool) -> (Document, [ObjectId; 2]) {
+        let mut doc = Document::with_version("1.5");
+        let pages = [doc.new_object_id(), doc.new_object_id()];
+        let widget = |doc: &mut Document, name: &str, page: ObjectId| {
+            doc.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Widget",
+                "FT" => "Tx",
+                "T" => Object::string_literal(name),
+                "P" => page,
+            })
+        };
+        let name = widget(&mut doc, "name", pages[0]);
+        let total = widget(&mut doc, "total", pages[1]);
+        let mut fields = vec![name.into(), total.into()];
+        let mut page_one_annots = vec![name.into()];
+        if with_nameless_root {
+            let kid = widget(&mut doc, "name", pages[0]);
+            let root = doc.add_object(dictionary! { "Kids" => vec![kid.into()] });
+            doc.get_dictionary_mut(kid).unwrap().set("Parent", root);
+            fields.push(root.into());
+            page_one_annots.push(kid.into());
+        }
+        for (page, annots) in pages.iter().zip([page_one_annots, vec![total.into()]]) {
+            doc.objects.insert(
+                *page,
+                Object::Dictionary(dictionary! { "Type" => "Page", "Annots" => annots }),
+            );
+        }
+        let acroform = doc.add_object(dictionary! { "Fields" => fields });
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "AcroForm" => acroform });
+        doc.trailer.set("Root", catalog);
+        (doc, pages)
+    }
+
+    fn field_names(merger: FormMerger, docs: &[&Document]) -> Vec<String> {
+        let acroform = merger.into_acroform().unwrap();
+        acroform
+            .get(b"Fields")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| {
+                let id = field.as_reference().unwrap();
+                let dict = docs
+                    .iter()
+                    .find_map(|doc| doc.get_dictionary(id).ok())
+                    .unwrap();
+                dict.get(b"T")
+                    .and_then(Object::as_str)
+                    .map_or("-".to_string(), |t| String::from_utf8_lossy(t).to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drops_fields_on_unselected_pages() {
+        let (mut doc, pages) = form(false);
+        let mut merger = FormMerger::default();
+        assert_eq!(merger.add_document(&mut doc, &pages[..1], "a"), 0);
+        assert_eq!(field_names(merger, &[&doc]), ["name"]);
+    }
+
+    #[test]
+    fn renames_clashing_top_level_fields() {
+        let (mut first, first_pages) = form(false);
+        let (mut second, second_pages) = form(true);
+        second.renumber_objects_with(first.max_id + 1);
+        let second_pages = second_pages.map(|(id, generation)| (id + first.max_id, generation));
+
+        let mut merger = FormMerger::default();
+        assert_eq!(merger.add_document(&mut first, &first_pages, "a"), 0);
+        // `name` and `total` clash, and so does the nameless root's kid
+        assert_eq!(merger.add_document(&mut second, &second_pages, "b"), 3);
+        assert_eq!(
+            field_names(merger, &[&first, &second]),
+            ["name", "total", "b_name", "b_total", "b"]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Size reductions for merged output: collapsing identical objects,
+//! recompressing Flate streams and writing PDF 1.5 object/xref streams.
+
+use crate::serialize::{self, CountingFile};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DedupReport {
+    pub objects_removed: usize,
+    pub bytes_saved: usize,
+}
+
+/// Objects that must stay distinct even when their bytes match: the page
+/// tree, annotations and form fields all belong to exactly one parent.
+fn can_share(object: &Object) -> bool {
+    let dict = match object {
+        Object::Dictionary(dict) => dict,
+        Object::Stream(stream) => &stream.dict,
+        _ => return true,
+    };
+    let type_name = dict.get(b"Type").and_then(Object::as_name).unwrap_or(b"");
+    if [
+        b"Catalog".as_slice(),
+        b"Pages",
+        b"Page",
+        b"Annot",
+        b"Outlines",
+        b"ObjStm",
+        b"XRef",
+    ]
+    .contains(&type_name)
+    {
+        return false;
+    }
+    ![b"Parent".as_slice(), b"Rect", b"Kids", b"T", b"FT"]
+        .iter()
+        .any(|key| dict.has(key))
+}
+
+fn rewrite_references(object: &mut Object, replace: &BTreeMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(new_id) = replace.get(id) {
+                *id = *new_id;
+            }
+        }
+        Object::Array(items) => {
+            for item in items {
+                rewrite_references(item, replace);
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter_mut() {
+                rewrite_references(value, replace);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter_mut() {
+                rewrite_references(value, replace);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collapses byte-identical objects (font programs, images, shared
+/// resource dictionaries...) into one and points every reference at it.
+///
+/// Runs until nothing changes: once two fonts share a descriptor, the font
+/// dictionaries themselves become identical on the next pass.
+pub fn deduplicate(doc: &mut Document) -> DedupReport {
+    let mut report = DedupReport::default();
+    loop {
+        let mut first_seen: HashMap<[u8; 32], ObjectId> = HashMap::new();
+        let mut replace = BTreeMap::new();
+        let mut bytes = Vec::new();
+        for (id, object) in &doc.objects {
+            if !can_share(object) {
+                continue;
+            }
+            bytes.clear();
+            serialize::write_object(&mut bytes, object);
+            let digest: [u8; 32] = Sha256::digest(&bytes).into();
+            match first_seen.get(&digest) {
+                Some(original) => {
+                    replace.insert(*id, *original);
+                    report.bytes_saved += bytes.len();
+                }
+                None => {
+                    first_seen.insert(digest, *id);
+                }
+            }
+        }
+        if replace.is_empty() {
+            return report;
+        }
+
+        report.objects_removed += replace.len();
+        for id in replace.keys() {
+            doc.objects.remove(id);
+        }
+        for object in doc.objects.values_mut() {
+            rewrite_references(object, &replace);
+        }
+        for (_, value) in doc.trailer.iter_mut() {
+            rewrite_references(value, &replace);
+        }
+    }
+}
+
+/// Re-deflates plain Flate streams at the best compression level and
+/// deflates unfiltered ones. Returns the number of bytes saved.
+pub fn recompress(doc: &mut Document) -> usize {
+    let mut saved = 0;
+    for object in doc.objects.values_mut() {
+        let Object::Stream(stream) = object else {
+            continue;
+        };
+        if !stream.allows_compression || stream.dict.has(b"DecodeParms") {
+            continue;
+        }
+        let filter = match stream.dict.get(b"Filter") {
+            Err(_) => None,
+            Ok(Object::Name(name)) => Some(name.as_slice()),
+            Ok(Object::Array(filters)) if filters.len() == 1 => filters[0].as_name().ok(),
+            Ok(_) => continue,
+        };
+        let raw = match filter {
+            None => stream.content.clone(),
+            Some(b"FlateDecode") => {
+                let mut raw = Vec::new();
+                if ZlibDecoder::new(stream.content.as_slice())
+                    .read_to_end(&mut raw)
+                    .is_err()
+                {
+                    continue;
+                }
+                raw
+            }
+            Some(_) => continue,
+        };
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        if encoder.write_all(&raw).is_err() {
+            continue;
+        }
+        let Ok(compressed) = encoder.finish() else {
+            continue;
+        };
+        if compressed.len() < stream.content.len() {
+            saved += stream.content.len() - compressed.len();
+            stream.dict.set("Filter", "FlateDecode");
+            stream.set_content(compressed);
+        }
+    }
+    saved
+}
+
+fn deflate(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Where an object ends up in the output file.
+enum XrefEntry {
+    Offset(u64, u16),
+    /// Object number of the object stream and index inside it.
+    Packed(u32, u16),
+}
+
+/// Objects per object stream; keeps each stream small enough that readers
+/// don't have to inflate a huge blob to reach one object.
+const OBJECTS_PER_STREAM: usize = 100;
+
+/// Saves `doc` as PDF 1.5 with non-stream objects packed into compressed
+/// object streams and a compressed cross-reference stream. Returns the
+/// number of bytes written.
+pub fn save_with_object_streams(doc: &Document, path: &Path) -> io::Result<u64> {
+    let mut out = CountingFile::create(path)?;
+    out.emit(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n")?;
+
+    // Old object and xref streams are rebuilt, never copied
+    let encrypt = doc
+        .trailer
+        .get(b"Encrypt")
+        .and_then(Object::as_reference)
+        .ok();
+    let (packable, direct): (Vec<_>, Vec<_>) = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| !matches!(object.type_name(), Ok("ObjStm" | "XRef")))
+        .partition(|(id, object)| {
+            id.1 == 0 && !matches!(object, Object::Stream(_)) && Some(**id) != encrypt
+        });
+
+    let mut xref: BTreeMap<u32, XrefEntry> = BTreeMap::new();
+    let mut next_id = doc
+        .max_id
+        .max(doc.objects.keys().map(|id| id.0).max().unwrap_or(0))
+        + 1;
+
+    for ((id, generation), object) in direct {
+        let start = out.emit_object(*id, *generation, object)?;
+        xref.insert(*id, XrefEntry::Offset(start, *generation));
+    }
+
+    for chunk in packable.chunks(OBJECTS_PER_STREAM) {
+        let stream_id = next_id;
+        next_id += 1;
+
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        for (index, ((id, _), object)) in chunk.iter().enumerate() {
+            let _ = write!(header, "{} {} ", id, body.len());
+            serialize::write_object(&mut body, object);
+            body.push(b'\n');
+            xref.insert(*id, XrefEntry::Packed(stream_id, index as u16));
+        }
+        let first = header.len();
+        header.extend_from_slice(&body);
+
+        let stream = Stream::new(
+            dictionary! {
+                "Type" => "ObjStm",
+                "N" => chunk.len() as i64,
+                "First" => first as i64,
+                "Filter" => "FlateDecode",
+            },
+            deflate(&header)?,
+        );
+        let start = out.emit_object(stream_id, 0, &Object::Stream(stream))?;
+        xref.insert(stream_id, XrefEntry::Offset(start, 0));
+    }
+
+    // The cross-reference stream describes itself too
+    let xref_id = next_id;
+    let xref_start = out.offset();
+    xref.insert(xref_id, XrefEntry::Offset(xref_start, 0));
+    let size = xref_id + 1;
+
+    // Offsets past 4 GiB need a wider second field
+    let width = if xref_start > u64::from(u32::MAX) {
+        8
+    } else {
+        4
+    };
+    let field = |value: u64| value.to_be_bytes()[8 - width..].to_vec();
+
+    let mut rows = Vec::with_capacity(size as usize * (width + 3));
+    for id in 0..size {
+        match xref.get(&id) {
+            Some(XrefEntry::Offset(at, generation)) => {
+                rows.push(1);
+                rows.extend_from_slice(&field(*at));
+                rows.extend_from_slice(&generation.to_be_bytes());
+            }
+            Some(XrefEntry::Packed(container, index)) => {
+                rows.push(2);
+                rows.extend_from_slice(&field(u64::from(*container)));
+                rows.extend_from_slice(&index.to_be_bytes());
+            }
+            None => {
+                rows.push(0);
+                rows.extend_from_slice(&field(0));
+                rows.extend_from_slice(&(if id == 0 { u16::MAX } else { 0 }).to_be_bytes());
+            }
+        }
+    }
+
+    let mut dict = Dictionary::new();
+    for key in [b"Root".as_slice(), b"Info", b"ID", b"Encrypt"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            dict.set(key, value.clone());
+        }
+    }
+    dict.set("Type", "XRef");
+    dict.set("Size", size as i64);
+    dict.set("W", vec![1.into(), (width as i64).into(), 2.into()]);
+    dict.set("Filter", "FlateDecode");
+    out.emit_object(
+        xref_id,
+        0,
+        &Object::Stream(Stream::new(dict, deflate(&rows)?)),
+    )?;
+    out.emit(format!("startxref\n{}\n%%EOF\n", xref_start).as_bytes())?;
+    out.finish()
+}
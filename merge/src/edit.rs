@@ -0,0 +1,184 @@
+//! Single-file page operations. Each one picks pages of its input and runs
+//! them through the same renumbering and pruning as a merge, so outlines,
+//! links and form fields of the pages that are kept survive.
+
+use crate::merge::{self, MergeOptions, Rotation};
+use crate::outline::{self, DestResolver};
+use crate::selection::{self, InputSpec};
+use lopdf::Document;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn load(path: &Path) -> Result<(InputSpec, Document, u32), Box<dyn Error>> {
+    let input = InputSpec {
+        path: path.to_path_buf(),
+        pages: None,
+        title: None,
+    };
+    let doc = merge::load_document(&input)?;
+    let page_count = doc.get_pages().len() as u32;
+    if page_count == 0 {
+        return Err(format!("'{}' has no pages", path.display()).into());
+    }
+    Ok((input, doc, page_count))
+}
+
+/// Rebuilds `doc` with only `pages`, in that order.
+fn rebuild(
+    input: &InputSpec,
+    doc: Document,
+    pages: &[u32],
+    rotation: Option<Rotation>,
+) -> Result<Document, Box<dyn Error>> {
+    let input = InputSpec {
+        pages: Some(selection::format_pages(pages)),
+        ..input.clone()
+    };
+    let options = MergeOptions {
+        rotation,
+        ..MergeOptions::default()
+    };
+    Ok(merge::merge_loaded(vec![(&input, doc)], &options)?.document)
+}
+
+/// Copies the selected pages into a new document.
+pub fn extract(path: &Path, pages: &str) -> Result<Document, Box<dyn Error>> {
+    let (input, doc, page_count) = load(path)?;
+    let selected = selection::resolve_pages(Some(pages), page_count)?;
+    rebuild(&input, doc, &selected, None)
+}
+
+/// Puts the pages in an explicit order, which must name every page once.
+pub fn reorder(path: &Path, order: &str) -> Result<Document, Box<dyn Error>> {
+    let (input, doc, page_count) = load(path)?;
+    let order = selection::resolve_pages(Some(order), page_count)?;
+    let unique: BTreeSet<u32> = order.iter().copied().collect();
+    if order.len() != page_count as usize || unique.len() != order.len() {
+        return Err(format!(
+            "Page order must list each of the {} pages exactly once (use extract to pick a subset)",
+            page_count
+        )
+        .into());
+    }
+    rebuild(&input, doc, &order, None)
+}
+
+/// Removes the selected pages.
+pub fn delete(path: &Path, pages: &str) -> Result<Document, Box<dyn Error>> {
+    let (input, doc, page_count) = load(path)?;
+    let removed: BTreeSet<u32> = selection::resolve_pages(Some(pages), page_count)?
+        .into_iter()
+        .collect();
+    let kept: Vec<u32> = (1..=page_count).filter(|p| !removed.contains(p)).collect();
+    if kept.is_empty() {
+        return Err("Refusing to delete every page".into());
+    }
+    rebuild(&input, doc, &kept, None)
+}
+
+/// Sets or adjusts `/Rotate` on the selected pages.
+pub fn rotate(path: &Path, rotation: Rotation) -> Result<Document, Box<dyn Error>> {
+    let (input, doc, page_count) = load(path)?;
+    let all: Vec<u32> = (1..=page_count).collect();
+    rebuild(&input, doc, &all, Some(rotation))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SplitMode {
+    /// A new file every N pages.
+    Every(u32),
+    /// A new file at each top-level bookmark.
+    Bookmarks,
+}
+
+/// First page and title of every top-level bookmark, in page order.
+fn bookmark_starts(doc: &Document) -> Vec<(u32, String)> {
+    let pages = doc.get_pages();
+    let page_numbers: Vec<_> = pages.iter().map(|(n, id)| (*id, *n)).collect();
+    let resolver = DestResolver::new(doc, pages.values().copied().collect());
+    let mut starts: Vec<(u32, String)> = outline::read_outline(doc, &resolver)
+        .into_iter()
+        .filter_map(|item| {
+            let page_id = item.dest.as_ref()?.first()?.as_reference().ok()?;
+            let page = page_numbers.iter().find(|(id, _)| *id == page_id)?.1;
+            let title = lopdf::decode_text_string(&item.title).unwrap_or_default();
+            Some((page, title))
+        })
+        .collect();
+    starts.sort_by_key(|(page, _)| *page);
+    starts.dedup_by_key(|(page, _)| *page);
+    starts
+}
+
+/// Keeps titles usable as file names.
+fn slug(title: &str) -> String {
+    let slug: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug.trim_matches('-');
+    slug.chars().take(40).collect()
+}
+
+/// Splits a document into several files under `output_dir`, named
+/// `<stem>-001.pdf`, `<stem>-002.pdf`, ... (with the bookmark title appended
+/// when splitting at bookmarks). Returns the written paths.
+pub fn split(
+    path: &Path,
+    mode: SplitMode,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let (input, doc, page_count) = load(path)?;
+
+    // Each part as (first page, last page, label)
+    let mut parts: Vec<(u32, u32, Option<String>)> = Vec::new();
+    match mode {
+        SplitMode::Every(0) => return Err("--every must be at least 1".into()),
+        SplitMode::Every(size) => {
+            for start in (1..=page_count).step_by(size as usize) {
+                parts.push((start, start.saturating_add(size - 1).min(page_count), None));
+            }
+        }
+        SplitMode::Bookmarks => {
+            let mut starts = bookmark_starts(&doc);
+            if starts.is_empty() {
+                return Err(format!("'{}' has no bookmarks to split at", path.display()).into());
+            }
+            // Pages before the first bookmark become a part of their own
+            if starts[0].0 > 1 {
+                starts.insert(0, (1, String::new()));
+            }
+            for (index, (start, title)) in starts.iter().enumerate() {
+                let end = starts
+                    .get(index + 1)
+                    .map_or(page_count, |(next, _)| next - 1);
+                parts.push((*start, end, Some(title.clone())));
+            }
+        }
+    }
+
+    fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create '{}': {}", output_dir.display(), e))?;
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "part".to_string(), |s| s.to_string_lossy().to_string());
+
+    let mut written = Vec::with_capacity(parts.len());
+    for (index, (start, end, label)) in parts.into_iter().enumerate() {
+        let mut name = format!("{}-{:03}", stem, index + 1);
+        if let Some(label) = label.as_deref().map(slug).filter(|s| !s.is_empty()) {
+            name = format!("{}-{}", name, label);
+        }
+        let output = output_dir.join(format!("{}.pdf", name));
+
+        let pages: Vec<u32> = (start..=end).collect();
+        let mut part = rebuild(&input, doc.clone(), &pages, None)?;
+        part.save(&output)
+            .map_err(|e| format!("Failed to write '{}': {}", output.display(), e))?;
+        println!("{}: pages {}-{}", output.display(), start, end);
+        written.push(output);
+    }
+    Ok(written)
+}
@@ -0,0 +1,368 @@
+//! N-up and booklet imposition: every page becomes a form XObject that is
+//! scaled onto a new, larger sheet. Runs on a finished document, so it sees
+//! the merged pages in their final order.
+
+use crate::merge;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Room left around the grid for crop marks, in points.
+const MARK_MARGIN: f32 = 18.0;
+/// Gap between a crop mark and the trim line it marks.
+const MARK_OFFSET: f32 = 3.0;
+
+const IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Destination types; an array starting with a page and one of these is a
+/// destination that has to follow its page onto a sheet.
+const DEST_KINDS: [&[u8]; 8] = [
+    b"XYZ", b"Fit", b"FitH", b"FitV", b"FitR", b"FitB", b"FitBH", b"FitBV",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SheetSize {
+    A4,
+    Letter,
+}
+
+impl SheetSize {
+    pub fn parse(arg: &str) -> Result<SheetSize, String> {
+        match arg.to_ascii_lowercase().as_str() {
+            "a4" => Ok(SheetSize::A4),
+            "letter" => Ok(SheetSize::Letter),
+            _ => Err(format!("unknown sheet size '{}' (use a4 or letter)", arg)),
+        }
+    }
+
+    /// Portrait width and height in points.
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            SheetSize::A4 => (595.28, 841.89),
+            SheetSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Arrangement {
+    /// N pages per sheet side, left to right and top to bottom.
+    NUp(u32),
+    /// Two pages per side in saddle-stitch order: printed duplex (flipped
+    /// on the short edge), folded and stapled, the sheets read in order.
+    Booklet,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub arrangement: Arrangement,
+    pub sheet: SheetSize,
+    /// Space between neighbouring pages, in points.
+    pub gutter: f32,
+    pub crop_marks: bool,
+}
+
+impl Layout {
+    /// Columns, rows and whether the sheet is turned to landscape.
+    fn grid(&self) -> Result<(u32, u32, bool), Box<dyn Error>> {
+        Ok(match self.arrangement {
+            Arrangement::Booklet | Arrangement::NUp(2) => (2, 1, true),
+            Arrangement::NUp(4) => (2, 2, false),
+            Arrangement::NUp(6) => (3, 2, true),
+            Arrangement::NUp(8) => (4, 2, true),
+            Arrangement::NUp(9) => (3, 3, false),
+            Arrangement::NUp(16) => (4, 4, false),
+            Arrangement::NUp(n) => {
+                return Err(
+                    format!("Can't put {} pages on a sheet (use 2, 4, 6, 8, 9 or 16)", n).into(),
+                );
+            }
+        })
+    }
+}
+
+/// Which source page (by index) goes into each cell of each sheet side.
+fn sheet_slots(layout: &Layout, page_count: usize, cells: usize) -> Vec<Vec<Option<usize>>> {
+    match layout.arrangement {
+        Arrangement::NUp(_) => (0..page_count)
+            .collect::<Vec<_>>()
+            .chunks(cells)
+            .map(|chunk| {
+                let mut side: Vec<Option<usize>> = chunk.iter().copied().map(Some).collect();
+                side.resize(cells, None);
+                side
+            })
+            .collect(),
+        Arrangement::Booklet => {
+            // Padded with blank pages to whole sheets of four
+            let padded = page_count.div_ceil(4) * 4;
+            let page = |index: usize| (index < page_count).then_some(index);
+            let mut sides = Vec::with_capacity(padded / 2);
+            for sheet in 0..padded / 4 {
+                sides.push(vec![page(padded - 1 - 2 * sheet), page(2 * sheet)]);
+                sides.push(vec![page(2 * sheet + 1), page(padded - 2 - 2 * sheet)]);
+            }
+            sides
+        }
+    }
+}
+
+/// A source page turned into a form XObject.
+struct Placed {
+    xobject: ObjectId,
+    /// Width and height as displayed, i.e. after `/Rotate`.
+    width: f32,
+    height: f32,
+    /// Maps the XObject's bounding box, moved to the origin, onto its
+    /// displayed orientation.
+    rotation: [f32; 6],
+}
+
+/// Copies a page's content and resources into a form XObject.
+fn page_to_xobject(doc: &mut Document, page_id: ObjectId) -> Result<Placed, Box<dyn Error>> {
+    let page = merge::materialize_inherited(doc, page_id)?;
+    let [x1, y1, x2, y2] = merge::page_box(&page)?;
+    let (box_width, box_height) = (x2 - x1, y2 - y1);
+
+    // Content streams are joined with a newline so tokens at their edges
+    // don't run together
+    let mut content = Vec::new();
+    for stream_id in doc.get_page_contents(page_id) {
+        if let Ok(stream) = doc.get_object(stream_id).and_then(Object::as_stream) {
+            content.extend(
+                stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone()),
+            );
+            content.push(b'\n');
+        }
+    }
+
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => vec![x1.into(), y1.into(), x2.into(), y2.into()],
+        "Matrix" => vec![1.into(), 0.into(), 0.into(), 1.into(), (-x1).into(), (-y1).into()],
+    };
+    if let Ok(resources) = page.get(b"Resources") {
+        dict.set("Resources", resources.clone());
+    }
+    if let Ok(group) = page.get(b"Group") {
+        dict.set("Group", group.clone());
+    }
+    let xobject = doc.add_object(Stream::new(dict, content));
+
+    let rotate = page
+        .get(b"Rotate")
+        .and_then(Object::as_i64)
+        .unwrap_or(0)
+        .rem_euclid(360);
+    let (width, height, rotation) = match rotate {
+        90 => (box_height, box_width, [0.0, -1.0, 1.0, 0.0, 0.0, box_width]),
+        180 => (
+            box_width,
+            box_height,
+            [-1.0, 0.0, 0.0, -1.0, box_width, box_height],
+        ),
+        270 => (
+            box_height,
+            box_width,
+            [0.0, 1.0, -1.0, 0.0, box_height, 0.0],
+        ),
+        _ => (box_width, box_height, IDENTITY),
+    };
+    Ok(Placed {
+        xobject,
+        width,
+        height,
+        rotation,
+    })
+}
+
+fn cm(matrix: [f32; 6]) -> Operation {
+    Operation::new("cm", matrix.iter().map(|v| (*v).into()).collect())
+}
+
+fn line(from: (f32, f32), to: (f32, f32)) -> [Operation; 3] {
+    [
+        Operation::new("m", vec![from.0.into(), from.1.into()]),
+        Operation::new("l", vec![to.0.into(), to.1.into()]),
+        Operation::new("S", vec![]),
+    ]
+}
+
+/// Crop marks in the sheet margin, in line with every cell edge.
+fn crop_marks(sheet: (f32, f32), columns: &[(f32, f32)], rows: &[(f32, f32)]) -> Vec<Operation> {
+    let (width, height) = sheet;
+    let (left, right) = (columns[0].0, columns[columns.len() - 1].1);
+    let (bottom, top) = (rows[rows.len() - 1].0, rows[0].1);
+    let mut operations = vec![
+        Operation::new("q", vec![]),
+        Operation::new("w", vec![0.25.into()]),
+        Operation::new("G", vec![0.into()]),
+    ];
+    for x in columns.iter().flat_map(|(start, end)| [*start, *end]) {
+        operations.extend(line((x, top + MARK_OFFSET), (x, height)));
+        operations.extend(line((x, 0.0), (x, bottom - MARK_OFFSET)));
+    }
+    for y in rows.iter().flat_map(|(start, end)| [*start, *end]) {
+        operations.extend(line((0.0, y), (left - MARK_OFFSET, y)));
+        operations.extend(line((right + MARK_OFFSET, y), (width, y)));
+    }
+    operations.push(Operation::new("Q", vec![]));
+    operations
+}
+
+/// Splits `length` into `count` cells separated by `gutter`, after leaving
+/// `margin` at both ends. Returns (start, end) of each cell.
+fn cells(length: f32, count: u32, margin: f32, gutter: f32) -> Vec<(f32, f32)> {
+    let size = (length - 2.0 * margin - gutter * (count - 1) as f32) / count as f32;
+    (0..count)
+        .map(|index| {
+            let start = margin + index as f32 * (size + gutter);
+            (start, start + size)
+        })
+        .collect()
+}
+
+/// Points destinations on old pages at the sheet the page ended up on.
+fn retarget_dests(object: &mut Object, sheet_of: &BTreeMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Array(items) => {
+            let target = match (items.first(), items.get(1)) {
+                (Some(Object::Reference(page)), Some(Object::Name(kind)))
+                    if DEST_KINDS.contains(&kind.as_slice()) =>
+                {
+                    sheet_of.get(page).copied()
+                }
+                _ => None,
+            };
+            match target {
+                Some(sheet) => *items = vec![Object::Reference(sheet), "Fit".into()],
+                None => {
+                    for item in items {
+                        retarget_dests(item, sheet_of);
+                    }
+                }
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter_mut() {
+                retarget_dests(value, sheet_of);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replaces the pages of `doc` with imposed sheets and returns how many
+/// sheet sides were made.
+///
+/// Annotations and form fields stay with the original pages and are
+/// dropped; outline and named destinations move to the sheet holding their
+/// page.
+pub fn impose(doc: &mut Document, layout: &Layout) -> Result<usize, Box<dyn Error>> {
+    let (columns, rows, landscape) = layout.grid()?;
+    if layout.gutter < 0.0 {
+        return Err("Gutter can't be negative".into());
+    }
+    let (mut width, mut height) = layout.sheet.dimensions();
+    if landscape {
+        (width, height) = (height, width);
+    }
+    let margin = if layout.crop_marks { MARK_MARGIN } else { 0.0 };
+    let column_cells = cells(width, columns, margin, layout.gutter);
+    // Rows are filled from the top of the sheet
+    let mut row_cells = cells(height, rows, margin, layout.gutter);
+    row_cells.reverse();
+    if column_cells[0].1 <= column_cells[0].0 || row_cells[0].1 <= row_cells[0].0 {
+        return Err("Gutter leaves no room for the pages".into());
+    }
+
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if page_ids.is_empty() {
+        return Err("Nothing to impose: the document has no pages".into());
+    }
+    let placed = page_ids
+        .iter()
+        .map(|page_id| page_to_xobject(doc, *page_id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let pages_id = doc
+        .get_dictionary(catalog_id)?
+        .get(b"Pages")?
+        .as_reference()?;
+
+    let mut kids = Vec::new();
+    let mut sheet_of = BTreeMap::new();
+    for side in sheet_slots(layout, placed.len(), (columns * rows) as usize) {
+        let sheet_id = doc.new_object_id();
+        let mut xobjects = Dictionary::new();
+        let mut operations = Vec::new();
+        for (cell, slot) in side.into_iter().enumerate() {
+            let Some(index) = slot else {
+                continue;
+            };
+            let page = &placed[index];
+            let (x1, x2) = column_cells[cell % columns as usize];
+            let (y1, y2) = row_cells[cell / columns as usize];
+            let scale = ((x2 - x1) / page.width).min((y2 - y1) / page.height);
+            let x = x1 + ((x2 - x1) - page.width * scale) / 2.0;
+            let y = y1 + ((y2 - y1) - page.height * scale) / 2.0;
+
+            let name = format!("P{}", index + 1);
+            xobjects.set(name.as_bytes(), page.xobject);
+            operations.push(Operation::new("q", vec![]));
+            operations.push(cm([scale, 0.0, 0.0, scale, x, y]));
+            if page.rotation != IDENTITY {
+                operations.push(cm(page.rotation));
+            }
+            operations.push(Operation::new("Do", vec![Object::Name(name.into_bytes())]));
+            operations.push(Operation::new("Q", vec![]));
+            sheet_of.insert(page_ids[index], sheet_id);
+        }
+        if layout.crop_marks {
+            operations.extend(crop_marks((width, height), &column_cells, &row_cells));
+        }
+
+        let content = Content { operations }.encode()?;
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+        doc.objects.insert(
+            sheet_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Resources" => dictionary! { "XObject" => xobjects },
+                "Contents" => content_id,
+            }),
+        );
+        kids.push(Object::Reference(sheet_id));
+    }
+
+    for page_id in &page_ids {
+        doc.objects.remove(page_id);
+    }
+    let catalog = doc.get_dictionary_mut(catalog_id)?;
+    if catalog.remove(b"AcroForm").is_some() {
+        eprintln!("⚠️ Form fields can't be carried onto imposed sheets and were dropped");
+    }
+    for object in doc.objects.values_mut() {
+        retarget_dests(object, &sheet_of);
+    }
+
+    let sides = kids.len();
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => sides as u32,
+            "Kids" => kids,
+        }),
+    );
+    doc.prune_objects();
+    doc.compress();
+    Ok(sides)
+}
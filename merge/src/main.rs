@@ -1,182 +1,422 @@
-use lopdf::content::{Content, Operation};
-use lopdf::dictionary;
-use lopdf::{Bookmark, Document, Object, ObjectId, Stream};
-use std::collections::BTreeMap;
-use std::path::Path;
-
-fn main() -> std::io::Result<()> {
-    // List of PDF files to merge (replace these with your actual file paths)
-    let pdf_files = vec![
-        "inputfile1.pdf",
-        "inputfile2.pdf",
-        "inputfile3.pdf",
-        "inputfile4.pdf",
-        "inputfile5.pdf",
-    ];
-
-    // Load all documents
-    let documents: Vec<Document> = pdf_files
-        .iter()
-        .map(|path| {
-            Document::load(Path::new(path))
-                .unwrap_or_else(|_| panic!("Failed to load PDF: {}", path))
-        })
-        .collect();
-
-    // Define a starting `max_id` (will be used as start index for object_ids)
-    let mut max_id = 1;
-    let mut pagenum = 1;
-    // Collect all Documents Objects grouped by a map
-    let mut documents_pages = BTreeMap::new();
-    let mut documents_objects = BTreeMap::new();
-    let mut document = Document::with_version("1.5");
-
-    for mut doc in documents {
-        let mut first = false;
-        doc.renumber_objects_with(max_id);
-
-        max_id = doc.max_id + 1;
-
-        documents_pages.extend(
-            doc.get_pages()
-                .into_iter()
-                .map(|(_, object_id)| {
-                    if !first {
-                        let bookmark = Bookmark::new(
-                            String::from(format!("Page {}", pagenum)),
-                            [0.0, 0.0, 1.0],
-                            0,
-                            object_id,
-                        );
-                        document.add_bookmark(bookmark, None);
-                        first = true;
-                        pagenum += 1;
-                    }
-
-                    (object_id, doc.get_object(object_id).unwrap().to_owned())
-                })
-                .collect::<BTreeMap<ObjectId, Object>>(),
-        );
-        documents_objects.extend(doc.objects);
-    }
+mod acroform;
+mod compact;
+mod edit;
+mod impose;
+mod merge;
+mod outline;
+mod selection;
+mod serialize;
+mod stamp;
+mod stream;
+
+use clap::{Parser, Subcommand};
+use impose::{Arrangement, Layout, SheetSize};
+use lopdf::Document;
+use merge::{MergeOptions, Rotation};
+use selection::InputSpec;
+use stamp::{Color, Position, Stamp};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Merges PDFs, optionally picking pages from each input, and splits, \
+             extracts, reorders, deletes or rotates pages of a single PDF.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    merge: MergeArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    #[clap(
+        required = true,
+        value_name = "FILE[:PAGES]",
+        value_parser = InputSpec::parse,
+        help = "Inputs in order, e.g. a.pdf:1-3,7 b.pdf:odd c.pdf:last. \
+                PAGES is all, first, last, odd, even or a list of pages and ranges; \
+                a range like last-1 runs backwards."
+    )]
+    inputs: Vec<InputSpec>,
+    #[clap(
+        short,
+        long = "title",
+        value_name = "TITLE",
+        help = "Bookmark title for each input, in order (defaults to the file name)."
+    )]
+    titles: Vec<String>,
+    #[clap(short, long, default_value = "merged.pdf")]
+    output: String,
+    #[clap(long, help = "Reverse the order of the merged pages.")]
+    reverse: bool,
+    #[clap(
+        long,
+        help = "Take one page from each input in turn (e.g. front and back scans)."
+    )]
+    interleave: bool,
+    #[clap(long, help = "Keep identical fonts, images, etc. as separate objects.")]
+    no_dedup: bool,
+    #[clap(long, help = "Re-deflate Flate streams at the best compression level.")]
+    recompress: bool,
+    #[clap(
+        long,
+        help = "Pack objects into object streams with an xref stream (PDF 1.5)."
+    )]
+    object_streams: bool,
+    #[clap(
+        long,
+        conflicts_with = "object_streams",
+        help = "Write each input to the output as soon as it is read, keeping memory \
+                bounded for very large merges. Identical objects are not collapsed."
+    )]
+    stream: bool,
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["booklet", "stream"],
+        help = "Print N pages on each sheet: 2, 4, 6, 8, 9 or 16."
+    )]
+    nup: Option<u32>,
+    #[clap(
+        long,
+        conflicts_with = "stream",
+        help = "Impose as a saddle-stitched booklet: two pages per side in fold order, \
+                for duplex printing flipped on the short edge."
+    )]
+    booklet: bool,
+    #[clap(
+        long,
+        default_value = "a4",
+        value_parser = SheetSize::parse,
+        help = "Sheet size for --nup and --booklet: a4 or letter."
+    )]
+    sheet: SheetSize,
+    #[clap(
+        long,
+        value_name = "PT",
+        default_value_t = 0.0,
+        help = "Space between imposed pages, in points."
+    )]
+    gutter: f32,
+    #[clap(long, help = "Draw crop marks around imposed pages.")]
+    crop_marks: bool,
+    #[clap(flatten)]
+    stamp: StampArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[clap(next_help_heading = "Stamping")]
+struct StampArgs {
+    #[clap(
+        long,
+        value_name = "TEXT",
+        conflicts_with = "stream",
+        help = "Text stamped on every page; {bates}, {page}, {pages}, {date} and {file} \
+                are filled in. Defaults to {bates} when --bates is given."
+    )]
+    stamp: Option<String>,
+    #[clap(
+        long,
+        value_name = "PREFIX",
+        conflicts_with = "stream",
+        help = "Number pages with PREFIX and a zero-padded counter, e.g. ACME000001."
+    )]
+    bates: Option<String>,
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    bates_start: u64,
+    #[clap(long, value_name = "N", default_value_t = 6)]
+    bates_digits: usize,
+    #[clap(
+        long,
+        default_value = "bottom-right",
+        value_parser = Position::parse,
+        help = "top-left, top-center, top-right, bottom-left, bottom-center or bottom-right."
+    )]
+    position: Position,
+    #[clap(long, value_name = "PT", default_value_t = 24.0)]
+    margin: f32,
+    #[clap(long, value_name = "PT", default_value_t = 10.0)]
+    font_size: f32,
+    #[clap(long, default_value = "000000", value_parser = Color::parse, help = "Text colour as RRGGBB.")]
+    color: Color,
+    #[clap(long, help = "Value for {date} (defaults to today, as YYYY-MM-DD).")]
+    date: Option<String>,
+    #[clap(
+        long,
+        value_name = "TEXT",
+        conflicts_with = "stream",
+        help = "Semi-transparent text set diagonally across every page, e.g. CONFIDENTIAL."
+    )]
+    watermark: Option<String>,
+    #[clap(long, value_name = "ALPHA", default_value_t = 0.25)]
+    watermark_opacity: f32,
+}
 
-    // "Catalog" and "Pages" are mandatory
-    let mut catalog_object: Option<(ObjectId, Object)> = None;
-    let mut pages_object: Option<(ObjectId, Object)> = None;
-
-    // Process all objects except "Page" type
-    for (object_id, object) in documents_objects.iter() {
-        match object.type_name().unwrap_or("") {
-            "Catalog" => {
-                catalog_object = Some((
-                    if let Some((id, _)) = catalog_object {
-                        id
-                    } else {
-                        *object_id
-                    },
-                    object.clone(),
-                ));
-            }
-            "Pages" => {
-                if let Ok(dictionary) = object.as_dict() {
-                    let mut dictionary = dictionary.clone();
-                    if let Some((_, ref object)) = pages_object {
-                        if let Ok(old_dictionary) = object.as_dict() {
-                            dictionary.extend(old_dictionary);
-                        }
-                    }
-
-                    pages_object = Some((
-                        if let Some((id, _)) = pages_object {
-                            id
-                        } else {
-                            *object_id
-                        },
-                        Object::Dictionary(dictionary),
-                    ));
-                }
-            }
-            "Page" => {}     // Ignored, processed later
-            "Outlines" => {} // Ignored
-            "Outline" => {}  // Ignored
-            _ => {
-                document.objects.insert(*object_id, object.clone());
-            }
+impl StampArgs {
+    fn stamp(&self) -> Option<Stamp> {
+        let text = self
+            .stamp
+            .clone()
+            .or_else(|| self.bates.as_ref().map(|_| "{bates}".to_string()));
+        if text.is_none() && self.watermark.is_none() {
+            return None;
         }
+        Some(Stamp {
+            text,
+            bates_prefix: self.bates.clone().unwrap_or_default(),
+            bates_start: self.bates_start,
+            bates_digits: self.bates_digits,
+            position: self.position,
+            margin: self.margin,
+            font_size: self.font_size,
+            color: self.color,
+            date: self.date.clone().unwrap_or_else(stamp::today),
+            watermark: self.watermark.clone(),
+            watermark_opacity: self.watermark_opacity,
+        })
     }
+}
 
-    if pages_object.is_none() {
-        println!("Pages root not found.");
-        return Ok(());
+impl MergeArgs {
+    fn layout(&self) -> Option<Layout> {
+        let arrangement = match (self.nup, self.booklet) {
+            (Some(n), _) => Arrangement::NUp(n),
+            (None, true) => Arrangement::Booklet,
+            (None, false) => return None,
+        };
+        Some(Layout {
+            arrangement,
+            sheet: self.sheet,
+            gutter: self.gutter,
+            crop_marks: self.crop_marks,
+        })
     }
+}
 
-    // Process all pages
-    for (object_id, object) in documents_pages.iter() {
-        if let Ok(dictionary) = object.as_dict() {
-            let mut dictionary = dictionary.clone();
-            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Merge PDFs (the default when no subcommand is given).
+    Merge(MergeArgs),
+    /// Split a PDF every N pages or at its top-level bookmarks.
+    Split {
+        input: PathBuf,
+        #[clap(
+            long,
+            value_name = "N",
+            required_unless_present = "at_bookmarks",
+            conflicts_with = "at_bookmarks"
+        )]
+        every: Option<u32>,
+        #[clap(long)]
+        at_bookmarks: bool,
+        #[clap(short, long, default_value = ".")]
+        output_dir: PathBuf,
+    },
+    /// Copy a page range to a new file.
+    Extract {
+        input: PathBuf,
+        #[clap(help = "Pages to keep, e.g. 2-5,9 or last-1.")]
+        pages: String,
+        #[clap(short, long, default_value = "extracted.pdf")]
+        output: String,
+    },
+    /// Rewrite a PDF with its pages in an explicit order.
+    Reorder {
+        input: PathBuf,
+        #[clap(help = "Every page exactly once, e.g. 3,1,2,4-last.")]
+        order: String,
+        #[clap(short, long, default_value = "reordered.pdf")]
+        output: String,
+    },
+    /// Remove pages from a PDF.
+    Delete {
+        input: PathBuf,
+        #[clap(help = "Pages to remove, e.g. 1,even.")]
+        pages: String,
+        #[clap(short, long, default_value = "deleted.pdf")]
+        output: String,
+    },
+    /// Rotate pages by a multiple of 90 degrees.
+    Rotate {
+        input: PathBuf,
+        #[clap(long, default_value_t = 90, allow_negative_numbers = true)]
+        degrees: i64,
+        #[clap(short, long, help = "Pages to rotate (default: all).")]
+        pages: Option<String>,
+        #[clap(long, help = "Set /Rotate to DEGREES instead of adding to it.")]
+        absolute: bool,
+        #[clap(short, long, default_value = "rotated.pdf")]
+        output: String,
+    },
+}
 
-            document
-                .objects
-                .insert(*object_id, Object::Dictionary(dictionary));
-        }
+fn save(document: &mut Document, output: &str) -> Result<(), Box<dyn Error>> {
+    document
+        .save(output)
+        .map_err(|e| format!("Failed to write '{}': {}", output, e))?;
+    println!("Wrote {} ({} pages)", output, document.get_pages().len());
+    Ok(())
+}
+
+fn merge_pdfs(mut args: MergeArgs) -> Result<(), Box<dyn Error>> {
+    if args.titles.len() > args.inputs.len() {
+        return Err(format!(
+            "Got {} titles for {} inputs",
+            args.titles.len(),
+            args.inputs.len()
+        )
+        .into());
+    }
+    for (input, title) in args.inputs.iter_mut().zip(args.titles.drain(..)) {
+        input.title = Some(title);
     }
 
-    if catalog_object.is_none() {
-        println!("Catalog root not found.");
+    let options = MergeOptions {
+        interleave: args.interleave,
+        reverse: args.reverse,
+        file_bookmarks: true,
+        rotation: None,
+    };
+    if args.stream {
+        let report = stream::merge_to_file(
+            &args.inputs,
+            &options,
+            args.recompress,
+            Path::new(&args.output),
+        )?;
+        println!(
+            "PDFs merged successfully into {} ({} pages from {} files, {} bytes)",
+            args.output,
+            report.pages,
+            args.inputs.len(),
+            report.bytes
+        );
         return Ok(());
     }
 
-    let catalog_object = catalog_object.unwrap();
-    let pages_object = pages_object.unwrap();
-
-    // Update pages dictionary
-    if let Ok(dictionary) = pages_object.1.as_dict() {
-        let mut dictionary = dictionary.clone();
-        dictionary.set("Count", documents_pages.len() as u32);
-        dictionary.set(
-            "Kids",
-            documents_pages
-                .into_iter()
-                .map(|(object_id, _)| Object::Reference(object_id))
-                .collect::<Vec<_>>(),
-        );
+    let merged = merge::merge_documents(&args.inputs, &options)?;
+    let mut document = merged.document;
+    let mut page_count = document.get_pages().len();
 
-        document
-            .objects
-            .insert(pages_object.0, Object::Dictionary(dictionary));
+    // Stamps go on the merged pages, before imposition shrinks them
+    if let Some(stamp) = args.stamp.stamp() {
+        let files: Vec<String> = merged
+            .page_sources
+            .iter()
+            .map(|&index| {
+                let path = &args.inputs[index].path;
+                path.file_name().map_or_else(
+                    || path.display().to_string(),
+                    |name| name.to_string_lossy().to_string(),
+                )
+            })
+            .collect();
+        stamp::stamp_pages(&mut document, &stamp, &files)?;
+        match stamp.text.as_deref() {
+            Some(text) if text.contains("{bates}") => println!(
+                "Stamped {} pages, Bates numbers {} to {}",
+                page_count,
+                stamp.bates(0),
+                stamp.bates(page_count.saturating_sub(1))
+            ),
+            _ => println!("Stamped {} pages", page_count),
+        }
     }
 
-    // Update catalog dictionary
-    if let Ok(dictionary) = catalog_object.1.as_dict() {
-        let mut dictionary = dictionary.clone();
-        dictionary.set("Pages", pages_object.0);
-        dictionary.remove(b"Outlines");
-
-        document
-            .objects
-            .insert(catalog_object.0, Object::Dictionary(dictionary));
+    if let Some(layout) = args.layout() {
+        let sides = impose::impose(&mut document, &layout)?;
+        println!("Imposed {} pages onto {} sheet sides", page_count, sides);
+        page_count = sides;
     }
 
-    document.trailer.set("Root", catalog_object.0);
-    document.max_id = document.objects.len() as u32;
-    document.renumber_objects();
-    document.adjust_zero_pages();
-
-    // Add bookmarks to outline
-    if let Some(n) = document.build_outline() {
-        if let Ok(x) = document.get_object_mut(catalog_object.0) {
-            if let Object::Dictionary(dict) = x {
-                dict.set("Outlines", Object::Reference(n));
-            }
+    if !args.no_dedup {
+        let report = compact::deduplicate(&mut document);
+        if report.objects_removed > 0 {
+            document.renumber_objects();
+            println!(
+                "Collapsed {} duplicate objects, saving {} bytes",
+                report.objects_removed, report.bytes_saved
+            );
         }
     }
-
-    document.compress();
+    if args.recompress {
+        let saved = compact::recompress(&mut document);
+        println!("Recompressed streams, saving {} bytes", saved);
+    }
 
     // Save the merged PDF
-    document.save("merged.pdf").unwrap();
-    println!("PDFs merged successfully into merged.pdf");
+    let written = if args.object_streams {
+        compact::save_with_object_streams(&document, Path::new(&args.output))
+    } else {
+        document
+            .save(&args.output)
+            .and_then(|file| file.metadata())
+            .map(|m| m.len())
+    };
+    let written = written.map_err(|e| format!("Failed to write '{}': {}", args.output, e))?;
+    println!(
+        "PDFs merged successfully into {} ({} pages from {} files, {} bytes)",
+        args.output,
+        page_count,
+        args.inputs.len(),
+        written
+    );
 
     Ok(())
 }
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    match args.command {
+        None => merge_pdfs(args.merge),
+        Some(Command::Merge(merge)) => merge_pdfs(merge),
+        Some(Command::Split {
+            input,
+            every,
+            at_bookmarks: _,
+            output_dir,
+        }) => {
+            let mode = match every {
+                Some(size) => edit::SplitMode::Every(size),
+                None => edit::SplitMode::Bookmarks,
+            };
+            let written = edit::split(&input, mode, &output_dir)?;
+            println!("Split {} into {} files", input.display(), written.len());
+            Ok(())
+        }
+        Some(Command::Extract {
+            input,
+            pages,
+            output,
+        }) => save(&mut edit::extract(&input, &pages)?, &output),
+        Some(Command::Reorder {
+            input,
+            order,
+            output,
+        }) => save(&mut edit::reorder(&input, &order)?, &output),
+        Some(Command::Delete {
+            input,
+            pages,
+            output,
+        }) => save(&mut edit::delete(&input, &pages)?, &output),
+        Some(Command::Rotate {
+            input,
+            degrees,
+            pages,
+            absolute,
+            output,
+        }) => {
+            let rotation = Rotation {
+                pages,
+                degrees,
+                absolute,
+            };
+            save(&mut edit::rotate(&input, rotation)?, &output)
+        }
+    }
+}
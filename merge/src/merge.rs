@@ -0,0 +1,454 @@
+use crate::acroform::FormMerger;
+use crate::outline::{self, DestResolver, OutlineNode};
+use crate::selection::{self, InputSpec};
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat, dictionary};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// How the selected pages of all inputs are put together.
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Take one page from each input in turn instead of one input after another.
+    pub interleave: bool,
+    /// Reverse the final page order.
+    pub reverse: bool,
+    /// Nest each input's outline under a bookmark for the file. Without it
+    /// the outlines are simply concatenated, which is what single-file
+    /// operations want.
+    pub file_bookmarks: bool,
+    pub rotation: Option<Rotation>,
+}
+
+/// A `/Rotate` change applied to pages of the result.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    /// Output pages to rotate; all of them when `None`.
+    pub pages: Option<String>,
+    pub degrees: i64,
+    /// Set `/Rotate` to `degrees` instead of adding to the current value.
+    pub absolute: bool,
+}
+
+impl Rotation {
+    fn apply(&self, page: &mut Dictionary) -> Result<(), Box<dyn Error>> {
+        if self.degrees % 90 != 0 {
+            return Err(format!("Rotation must be a multiple of 90, got {}", self.degrees).into());
+        }
+        let current = if self.absolute {
+            0
+        } else {
+            page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0)
+        };
+        page.set("Rotate", (current + self.degrees).rem_euclid(360));
+        Ok(())
+    }
+}
+
+/// Page attributes a page may inherit from its ancestors in the page tree.
+const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+pub fn load_document(input: &InputSpec) -> Result<Document, Box<dyn Error>> {
+    Document::load(&input.path)
+        .map_err(|e| format!("Failed to load PDF '{}': {}", input.path.display(), e).into())
+}
+
+/// Copies inherited attributes onto the page itself, since the intermediate
+/// `Pages` nodes they come from are not carried into the merged tree.
+pub fn materialize_inherited(
+    doc: &Document,
+    page_id: ObjectId,
+) -> Result<Dictionary, Box<dyn Error>> {
+    let mut page = doc.get_dictionary(page_id)?.clone();
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    let mut seen = BTreeSet::new();
+    while let Some(parent_id) = parent {
+        if !seen.insert(parent_id) {
+            break;
+        }
+        let Ok(node) = doc.get_dictionary(parent_id) else {
+            break;
+        };
+        for key in INHERITABLE {
+            if !page.has(key)
+                && let Ok(value) = node.get(key)
+            {
+                page.set(key, value.clone());
+            }
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    Ok(page)
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(value) => Some(*value as f32),
+        Object::Real(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// The visible area of a page, `/CropBox` falling back to `/MediaBox`,
+/// as `[left, bottom, right, top]`.
+pub fn page_box(page: &Dictionary) -> Result<[f32; 4], Box<dyn Error>> {
+    let rect = page
+        .get(b"CropBox")
+        .or_else(|_| page.get(b"MediaBox"))
+        .and_then(Object::as_array)
+        .map_err(|_| "Page has no MediaBox")?;
+    let values: Vec<f32> = rect.iter().filter_map(number).collect();
+    let [x1, y1, x2, y2] = values[..] else {
+        return Err("Page box is not a rectangle".into());
+    };
+    Ok([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)])
+}
+
+/// Selects pages of a freshly loaded `doc`, returning their ids in the
+/// order asked for. Fails rather than return an empty group, since every
+/// input needs a first page to bookmark.
+pub fn selected_page_ids(
+    doc: &Document,
+    input: &InputSpec,
+) -> Result<Vec<ObjectId>, Box<dyn Error>> {
+    let pages = doc.get_pages();
+    if pages.is_empty() {
+        return Err(format!("'{}' has no pages", input.path.display()).into());
+    }
+    let selected = selection::resolve_pages(input.pages.as_deref(), pages.len() as u32)
+        .map_err(|e| format!("{}: {}", input.path.display(), e))?;
+    if selected.is_empty() {
+        return Err(format!("'{}': page selection is empty", input.path.display()).into());
+    }
+    Ok(selected.iter().map(|page| pages[page]).collect())
+}
+
+/// The bookmark an input's pages are filed under.
+pub fn bookmark_title(input: &InputSpec) -> String {
+    input.title.clone().unwrap_or_else(|| {
+        input.path.file_name().map_or_else(
+            || input.path.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    })
+}
+
+/// Adds the named destinations of one input that land on kept pages; a name
+/// an earlier input already defined keeps its first meaning.
+pub fn add_named_destinations(
+    named_dests: &mut BTreeMap<Vec<u8>, Vec<Object>>,
+    resolver: &DestResolver,
+    input: &InputSpec,
+) {
+    for (name, dest) in resolver.named_destinations() {
+        if named_dests.contains_key(name) {
+            eprintln!(
+                "⚠️ {}: named destination '{}' already defined by an earlier file, skipped",
+                input.path.display(),
+                String::from_utf8_lossy(name)
+            );
+        } else {
+            named_dests.insert(name.clone(), dest.clone());
+        }
+    }
+}
+
+/// Adds the form fields of one input, reporting any that had to be renamed.
+pub fn add_form_fields(
+    forms: &mut FormMerger,
+    doc: &mut Document,
+    pages: &[ObjectId],
+    input: &InputSpec,
+) {
+    // Field names are dotted paths, so the prefix must not contain dots
+    let prefix = input
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().replace('.', "_"))
+        .unwrap_or_else(|| "form".to_string());
+    let renamed = forms.add_document(doc, pages, &prefix);
+    if renamed > 0 {
+        println!(
+            "{}: renamed {} form field(s) with prefix '{}_' to avoid name clashes",
+            input.path.display(),
+            renamed,
+            prefix
+        );
+    }
+}
+
+/// A flat `/Dests` name tree, or `None` when there is nothing to put in it.
+pub fn dests_name_tree(named_dests: BTreeMap<Vec<u8>, Vec<Object>>) -> Option<Dictionary> {
+    if named_dests.is_empty() {
+        return None;
+    }
+    let mut flat = Vec::with_capacity(named_dests.len() * 2);
+    for (name, dest) in named_dests {
+        flat.push(Object::String(name, StringFormat::Literal));
+        flat.push(Object::Array(dest));
+    }
+    Some(dictionary! { "Names" => flat })
+}
+
+/// A merged document and where its pages came from.
+pub struct Merged {
+    pub document: Document,
+    /// For each page of `document`, the index of the input it was taken from.
+    pub page_sources: Vec<usize>,
+}
+
+/// Merges the selected pages of every input into a new document.
+pub fn merge_documents(
+    inputs: &[InputSpec],
+    options: &MergeOptions,
+) -> Result<Merged, Box<dyn Error>> {
+    let sources = inputs
+        .iter()
+        .map(|input| Ok((input, load_document(input)?)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    merge_loaded(sources, options)
+}
+
+/// Same as [`merge_documents`] for documents that are already loaded; each
+/// is paired with the spec saying which of its pages to take.
+pub fn merge_loaded(
+    sources: Vec<(&InputSpec, Document)>,
+    options: &MergeOptions,
+) -> Result<Merged, Box<dyn Error>> {
+    // Define a starting `max_id` (will be used as start index for object_ids)
+    let mut max_id = 1;
+    // Selected page ids of each input, in the order they were asked for
+    let mut groups: Vec<Vec<ObjectId>> = Vec::new();
+    let mut source_of: BTreeMap<ObjectId, usize> = BTreeMap::new();
+    let mut documents_pages: BTreeMap<ObjectId, Dictionary> = BTreeMap::new();
+    let mut documents_objects = BTreeMap::new();
+    let mut outline_items = Vec::new();
+    let mut named_dests: BTreeMap<Vec<u8>, Vec<Object>> = BTreeMap::new();
+    let mut forms = FormMerger::default();
+    let mut document = Document::with_version("1.5");
+
+    for (index, (input, mut doc)) in sources.into_iter().enumerate() {
+        doc.renumber_objects_with(max_id);
+        max_id = doc.max_id + 1;
+
+        let group = selected_page_ids(&doc, input)?;
+
+        // Destinations keep pointing at the same page ids; the final
+        // renumbering rewrites every reference to them in one go.
+        let resolver = DestResolver::new(&doc, group.iter().copied().collect());
+        for &object_id in &group {
+            if let Entry::Vacant(entry) = documents_pages.entry(object_id) {
+                outline::rewrite_links(&mut doc, object_id, &resolver);
+                entry.insert(materialize_inherited(&doc, object_id)?);
+            }
+        }
+        add_named_destinations(&mut named_dests, &resolver, input);
+        add_form_fields(&mut forms, &mut doc, &group, input);
+
+        let items = outline::read_outline(&doc, &resolver);
+        if options.file_bookmarks {
+            outline_items.push(OutlineNode::for_file(
+                &bookmark_title(input),
+                group[0],
+                items,
+            ));
+        } else {
+            outline_items.extend(items);
+        }
+
+        source_of.extend(group.iter().map(|id| (*id, index)));
+        groups.push(group);
+        documents_objects.extend(doc.objects);
+    }
+
+    let mut order: Vec<ObjectId> = if options.interleave {
+        selection::interleave(groups)
+    } else {
+        groups.into_iter().flatten().collect()
+    };
+    if options.reverse {
+        order.reverse();
+    }
+    let page_sources = order.iter().map(|id| source_of[id]).collect();
+
+    // "Catalog" and "Pages" are mandatory
+    let mut catalog_object: Option<(ObjectId, Object)> = None;
+    let mut pages_id: Option<ObjectId> = None;
+
+    // Process all objects except "Page" type
+    for (object_id, object) in documents_objects.iter() {
+        match object.type_name().unwrap_or("") {
+            "Catalog" => {
+                catalog_object = Some((
+                    if let Some((id, _)) = catalog_object {
+                        id
+                    } else {
+                        *object_id
+                    },
+                    object.clone(),
+                ));
+            }
+            "Pages" => {
+                pages_id.get_or_insert(*object_id);
+            }
+            "Page" => {}     // Ignored, processed later
+            "Outlines" => {} // Ignored
+            "Outline" => {}  // Ignored
+            _ => {
+                document.objects.insert(*object_id, object.clone());
+            }
+        }
+    }
+
+    let pages_id = pages_id.ok_or("Pages root not found")?;
+    let (catalog_id, catalog) = catalog_object.ok_or("Catalog root not found")?;
+
+    let rotated: BTreeSet<u32> = match &options.rotation {
+        Some(rotation) => selection::resolve_pages(rotation.pages.as_deref(), order.len() as u32)?
+            .into_iter()
+            .collect(),
+        None => BTreeSet::new(),
+    };
+
+    // Process all pages; a page selected more than once gets a copy
+    let mut kids = Vec::with_capacity(order.len());
+    let mut placed = BTreeSet::new();
+    for (position, object_id) in (1..).zip(order) {
+        let mut dictionary = documents_pages[&object_id].clone();
+        dictionary.set("Parent", pages_id);
+        if let Some(rotation) = &options.rotation
+            && rotated.contains(&position)
+        {
+            rotation.apply(&mut dictionary)?;
+        }
+        let target_id = if placed.insert(object_id) {
+            object_id
+        } else {
+            max_id += 1;
+            (max_id, 0)
+        };
+        document
+            .objects
+            .insert(target_id, Object::Dictionary(dictionary));
+        kids.push(Object::Reference(target_id));
+    }
+
+    // Build the pages dictionary from scratch: inherited attributes were
+    // already copied onto each page
+    let mut pages_dictionary = Dictionary::new();
+    pages_dictionary.set("Type", "Pages");
+    pages_dictionary.set("Count", kids.len() as u32);
+    pages_dictionary.set("Kids", kids);
+    document
+        .objects
+        .insert(pages_id, Object::Dictionary(pages_dictionary));
+
+    document.max_id = max_id;
+    let outlines_id = outline::write_outline(&mut document, outline_items);
+
+    // Update catalog dictionary
+    let mut catalog = catalog.as_dict()?.clone();
+    catalog.set("Pages", pages_id);
+    catalog.remove(b"Outlines");
+    if let Some(outlines_id) = outlines_id {
+        catalog.set("Outlines", outlines_id);
+    }
+    catalog.remove(b"AcroForm");
+    if let Some(acroform) = forms.into_acroform() {
+        catalog.set("AcroForm", acroform);
+    }
+
+    // Named destinations of every file go into a single name tree; the
+    // old-style /Dests dictionary is folded into it.
+    catalog.remove(b"Dests");
+    let mut names = catalog
+        .get(b"Names")
+        .and_then(|n| document.dereference(n))
+        .and_then(|(_, n)| n.as_dict())
+        .cloned()
+        .unwrap_or_default();
+    names.remove(b"Dests");
+    if let Some(dests) = dests_name_tree(named_dests) {
+        names.set("Dests", dests);
+    }
+    if names.is_empty() {
+        catalog.remove(b"Names");
+    } else {
+        catalog.set("Names", names);
+    }
+
+    document
+        .objects
+        .insert(catalog_id, Object::Dictionary(catalog));
+
+    document.trailer.set("Root", catalog_id);
+    // Drop whatever only the unselected pages referenced
+    document.prune_objects();
+    document.renumber_objects();
+
+    document.compress();
+    Ok(Merged {
+        document,
+        page_sources,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(page_count: u32) -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> = (0..page_count)
+            .map(|_| {
+                doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id })
+                    .into()
+            })
+            .collect();
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => page_count,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    fn input(pages: Option<&str>) -> InputSpec {
+        InputSpec {
+            path: "in.pdf".into(),
+            pages: pages.map(str::to_string),
+            title: None,
+        }
+    }
+
+    #[test]
+    fn selects_pages_in_the_order_asked_for() {
+        let doc = document(4);
+        let pages = doc.get_pages();
+        let ids = |numbers: &[u32]| numbers.iter().map(|n| pages[n]).collect::<Vec<_>>();
+
+        let all = selected_page_ids(&doc, &input(None)).unwrap();
+        assert_eq!(all, ids(&[1, 2, 3, 4]));
+        let picked = selected_page_ids(&doc, &input(Some("last-3,1,1"))).unwrap();
+        assert_eq!(picked, ids(&[4, 3, 1, 1]));
+        let even = selected_page_ids(&doc, &input(Some("even"))).unwrap();
+        assert_eq!(even, ids(&[2, 4]));
+    }
+
+    #[test]
+    fn rejects_documents_and_selections_without_pages() {
+        let error = selected_page_ids(&document(0), &input(None)).unwrap_err();
+        assert_eq!(error.to_string(), "'in.pdf' has no pages");
+
+        for pages in ["even", "3"] {
+            let error = selected_page_ids(&document(1), &input(Some(pages))).unwrap_err();
+            assert!(error.to_string().starts_with("in.pdf: "), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Looks up a source document's destinations and keeps only those that land
+/// on one of its selected pages.
+pub struct DestResolver {
+    named: BTreeMap<Vec<u8>, Vec<Object>>,
+    kept: BTreeSet<ObjectId>,
+}
+
+/// An explicit destination array, whether given directly or wrapped in a
+/// `<< /D [...] >>` dictionary.
+fn dest_array(doc: &Document, object: &Object) -> Option<Vec<Object>> {
+    match doc.dereference(object).ok()?.1 {
+        Object::Array(array) => Some(array.clone()),
+        Object::Dictionary(dict) => dest_array(doc, dict.get(b"D").ok()?),
+        _ => None,
+    }
+}
+
+/// Walks a name tree, collecting its key/value pairs.
+fn walk_name_tree(
+    doc: &Document,
+    node: &Object,
+    seen: &mut BTreeSet<ObjectId>,
+    out: &mut BTreeMap<Vec<u8>, Vec<Object>>,
+) {
+    if let Object::Reference(id) = node
+        && !seen.insert(*id)
+    {
+        return;
+    }
+    let Ok((_, Object::Dictionary(node))) = doc.dereference(node) else {
+        return;
+    };
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [key, value] = pair
+                && let Ok(key) = key.as_str()
+                && let Some(dest) = dest_array(doc, value)
+            {
+                out.entry(key.to_vec()).or_insert(dest);
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            walk_name_tree(doc, kid, seen, out);
+        }
+    }
+}
+
+impl DestResolver {
+    /// Collects named destinations from both the PDF 1.1 catalog `/Dests`
+    /// dictionary and the `/Names /Dests` name tree.
+    pub fn new(doc: &Document, kept: BTreeSet<ObjectId>) -> DestResolver {
+        let mut named = BTreeMap::new();
+        if let Ok(catalog) = doc.catalog() {
+            if let Ok((_, Object::Dictionary(dests))) =
+                catalog.get(b"Dests").and_then(|d| doc.dereference(d))
+            {
+                for (name, value) in dests.iter() {
+                    if let Some(dest) = dest_array(doc, value) {
+                        named.insert(name.clone(), dest);
+                    }
+                }
+            }
+            if let Ok((_, Object::Dictionary(names))) =
+                catalog.get(b"Names").and_then(|n| doc.dereference(n))
+                && let Ok(tree) = names.get(b"Dests")
+            {
+                walk_name_tree(doc, tree, &mut BTreeSet::new(), &mut named);
+            }
+        }
+        DestResolver { named, kept }
+    }
+
+    fn targets_kept_page(&self, dest: &[Object]) -> bool {
+        matches!(dest.first(), Some(Object::Reference(page)) if self.kept.contains(page))
+    }
+
+    /// Resolves a `/Dest` value (explicit array, name or string) to an
+    /// explicit destination on a kept page.
+    pub fn resolve(&self, doc: &Document, dest: &Object) -> Option<Vec<Object>> {
+        let dest = match doc.dereference(dest).ok()?.1 {
+            Object::Name(name) => self.named.get(name)?.clone(),
+            Object::String(name, _) => self.named.get(name)?.clone(),
+            other => dest_array(doc, other)?,
+        };
+        self.targets_kept_page(&dest).then_some(dest)
+    }
+
+    /// Named destinations that survive the page selection.
+    pub fn named_destinations(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<Object>)> {
+        self.named
+            .iter()
+            .filter(|(_, dest)| self.targets_kept_page(dest))
+    }
+}
+
+/// Where an outline item or link goes once destinations are resolved.
+enum Target {
+    /// Had no destination or action in the source.
+    None,
+    /// Pointed at a page that was not selected.
+    Dropped,
+    Dest(Vec<Object>),
+    /// A non-GoTo action (URI, Launch, ...), carried over unchanged.
+    Action(Object),
+}
+
+/// Resolves a `/Dest` or `/A` entry of an outline item or link annotation.
+fn resolve_target(doc: &Document, resolver: &DestResolver, dict: &Dictionary) -> Target {
+    if let Ok(dest) = dict.get(b"Dest") {
+        return match resolver.resolve(doc, dest) {
+            Some(dest) => Target::Dest(dest),
+            None => Target::Dropped,
+        };
+    }
+    let Ok(action) = dict.get(b"A") else {
+        return Target::None;
+    };
+    let is_goto = doc
+        .dereference(action)
+        .ok()
+        .and_then(|(_, a)| a.as_dict().ok())
+        .and_then(|a| a.get(b"S").ok())
+        .and_then(|s| s.as_name().ok())
+        == Some(b"GoTo".as_slice());
+    if !is_goto {
+        return Target::Action(action.clone());
+    }
+    match doc
+        .dereference(action)
+        .ok()
+        .and_then(|(_, a)| a.as_dict().ok())
+        .and_then(|a| a.get(b"D").ok())
+        .and_then(|d| resolver.resolve(doc, d))
+    {
+        Some(dest) => Target::Dest(dest),
+        None => Target::Dropped,
+    }
+}
+
+/// Points link annotations on a selected page at explicit destinations and
+/// strips the destination from links into pages that were left out.
+pub fn rewrite_links(doc: &mut Document, page_id: ObjectId, resolver: &DestResolver) {
+    let Ok(page) = doc.get_dictionary(page_id) else {
+        return;
+    };
+    let annots = match page.get(b"Annots").map(|a| doc.dereference(a)) {
+        Ok(Ok((_, Object::Array(annots)))) => annots.clone(),
+        _ => return,
+    };
+
+    let mut updates = Vec::new();
+    for annot in annots {
+        let Ok(annot_id) = annot.as_reference() else {
+            continue;
+        };
+        let Ok(dict) = doc.get_dictionary(annot_id) else {
+            continue;
+        };
+        if dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link".as_slice()) {
+            continue;
+        }
+        match resolve_target(doc, resolver, dict) {
+            Target::Dest(dest) => updates.push((annot_id, Some(dest))),
+            Target::Dropped => updates.push((annot_id, None)),
+            Target::None | Target::Action(_) => {}
+        }
+    }
+
+    for (annot_id, dest) in updates {
+        if let Ok(dict) = doc.get_dictionary_mut(annot_id) {
+            dict.remove(b"A");
+            match dest {
+                Some(dest) => dict.set("Dest", dest),
+                None => {
+                    dict.remove(b"Dest");
+                }
+            }
+        }
+    }
+}
+
+/// One outline item, detached from its source document.
+pub struct OutlineNode {
+    pub title: Object,
+    pub dest: Option<Vec<Object>>,
+    pub action: Option<Object>,
+    /// `/C` colour and `/F` style flags.
+    pub style: Dictionary,
+    pub open: bool,
+    pub children: Vec<OutlineNode>,
+}
+
+impl OutlineNode {
+    /// A top-level item for one merged file, opened on `page`.
+    pub fn for_file(title: &str, page: ObjectId, children: Vec<OutlineNode>) -> OutlineNode {
+        OutlineNode {
+            title: lopdf::text_string(title),
+            dest: Some(vec![Object::Reference(page), "Fit".into()]),
+            action: None,
+            style: Dictionary::new(),
+            open: true,
+            children,
+        }
+    }
+
+    /// Number of items shown below this one when it is expanded.
+    fn visible_descendants(&self) -> i64 {
+        self.children
+            .iter()
+            .map(|child| {
+                1 + if child.open {
+                    child.visible_descendants()
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+}
+
+fn read_items(
+    doc: &Document,
+    resolver: &DestResolver,
+    first: Option<ObjectId>,
+    seen: &mut BTreeSet<ObjectId>,
+) -> Vec<OutlineNode> {
+    let mut items = Vec::new();
+    let mut current = first;
+    while let Some(id) = current {
+        if !seen.insert(id) {
+            break;
+        }
+        let Ok(item) = doc.get_dictionary(id) else {
+            break;
+        };
+        current = item.get(b"Next").and_then(Object::as_reference).ok();
+
+        let children = read_items(
+            doc,
+            resolver,
+            item.get(b"First").and_then(Object::as_reference).ok(),
+            seen,
+        );
+        let (dest, action) = match resolve_target(doc, resolver, item) {
+            Target::None => (None, None),
+            Target::Dest(dest) => (Some(dest), None),
+            Target::Action(action) => (None, Some(action)),
+            // The item's page was left out: keep it as a heading for its
+            // surviving children, opening the first of them.
+            Target::Dropped => match children.first() {
+                Some(child) => (child.dest.clone(), None),
+                None => continue,
+            },
+        };
+
+        let mut style = Dictionary::new();
+        for key in [b"C".as_slice(), b"F"] {
+            if let Ok(value) = item.get(key) {
+                style.set(key, value.clone());
+            }
+        }
+        items.push(OutlineNode {
+            title: item
+                .get(b"Title")
+                .cloned()
+                .unwrap_or_else(|_| Object::string_literal("")),
+            dest,
+            action,
+            style,
+            open: item.get(b"Count").and_then(Object::as_i64).unwrap_or(0) > 0,
+            children,
+        });
+    }
+    items
+}
+
+/// Reads a document's outline tree, dropping items that only lead to pages
+/// outside the selection.
+pub fn read_outline(doc: &Document, resolver: &DestResolver) -> Vec<OutlineNode> {
+    let first = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|outlines| doc.dereference(outlines).ok())
+        .and_then(|(_, outlines)| outlines.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").and_then(Object::as_reference).ok());
+    read_items(doc, resolver, first, &mut BTreeSet::new())
+}
+
+/// Writes sibling items under `parent` and points `parent` at the first
+/// and last of them.
+fn write_items(
+    document: &mut Document,
+    parent: ObjectId,
+    parent_dict: &mut Dictionary,
+    items: Vec<OutlineNode>,
+) {
+    let ids: Vec<ObjectId> = items.iter().map(|_| document.new_object_id()).collect();
+    if let (Some(first), Some(last)) = (ids.first(), ids.last()) {
+        parent_dict.set("First", *first);
+        parent_dict.set("Last", *last);
+    }
+
+    for (index, item) in items.into_iter().enumerate() {
+        let visible = item.visible_descendants();
+        let mut dict = item.style;
+        dict.set("Title", item.title);
+        dict.set("Parent", parent);
+        if index > 0 {
+            dict.set("Prev", ids[index - 1]);
+        }
+        if let Some(next) = ids.get(index + 1) {
+            dict.set("Next", *next);
+        }
+        if let Some(dest) = item.dest {
+            dict.set("Dest", dest);
+        } else if let Some(action) = item.action {
+            dict.set("A", action);
+        }
+        if !item.children.is_empty() {
+            write_items(document, ids[index], &mut dict, item.children);
+            dict.set("Count", if item.open { visible } else { -visible });
+        }
+        document
+            .objects
+            .insert(ids[index], Object::Dictionary(dict));
+    }
+}
+
+/// Writes an outline tree into `document` and returns the `/Outlines` id.
+pub fn write_outline(document: &mut Document, items: Vec<OutlineNode>) -> Option<ObjectId> {
+    if items.is_empty() {
+        return None;
+    }
+    let root = OutlineNode {
+        title: Object::Null,
+        dest: None,
+        action: None,
+        style: Dictionary::new(),
+        open: true,
+        children: items,
+    };
+    let count = root.visible_descendants();
+    let outlines_id = document.new_object_id();
+    let mut outlines = Dictionary::new();
+    outlines.set("Type", "Outlines");
+    write_items(document, outlines_id, &mut outlines, root.children);
+    outlines.set("Count", count);
+    document
+        .objects
+        .insert(outlines_id, Object::Dictionary(outlines));
+    Some(outlines_id)
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// One input on the command line: a file and, optionally, which of its pages
+/// to take, e.g. `a.pdf:1-3,7`, `b.pdf:odd`, `c.pdf:last-1`.
+#[derive(Debug, Clone)]
+pub struct InputSpec {
+    pub path: PathBuf,
+    pub pages: Option<String>,
+    /// Title of the file's top-level bookmark; defaults to the file name.
+    pub title: Option<String>,
+}
+
+impl InputSpec {
+    /// Splits `FILE[:PAGES]`. A name that exists on disk as given is never
+    /// split, so paths that themselves contain `:` still work.
+    pub fn parse(arg: &str) -> Result<InputSpec, String> {
+        if Path::new(arg).exists() {
+            return Ok(InputSpec {
+                path: PathBuf::from(arg),
+                pages: None,
+                title: None,
+            });
+        }
+        match arg.rsplit_once(':') {
+            Some((path, pages)) if !path.is_empty() => {
+                if pages.trim().is_empty() {
+                    return Err(format!("Empty page selection in '{}'", arg));
+                }
+                Ok(InputSpec {
+                    path: PathBuf::from(path),
+                    pages: Some(pages.to_string()),
+                    title: None,
+                })
+            }
+            _ => Ok(InputSpec {
+                path: PathBuf::from(arg),
+                pages: None,
+                title: None,
+            }),
+        }
+    }
+}
+
+/// Resolves a page selection against a document with `page_count` pages.
+///
+/// Accepts `all`, `first`, `last`, `odd`, `even`, or a comma-separated list
+/// of pages and ranges. Range ends may be `last`, and a descending range such
+/// as `last-1` yields the pages in reverse. Pages keep the order given, and
+/// may repeat.
+pub fn resolve_pages(spec: Option<&str>, page_count: u32) -> Result<Vec<u32>, Box<dyn Error>> {
+    let Some(spec) = spec else {
+        return Ok((1..=page_count).collect());
+    };
+    let in_range = |page: u32| -> Result<u32, String> {
+        if page >= 1 && page <= page_count {
+            Ok(page)
+        } else {
+            Err(format!(
+                "Page {} out of range (document has {} pages)",
+                page, page_count
+            ))
+        }
+    };
+    let parse = |s: &str| -> Result<u32, String> {
+        match s.trim().to_lowercase().as_str() {
+            "first" => in_range(1),
+            "last" => in_range(page_count),
+            number => number
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid page selection '{}'", spec))
+                .and_then(in_range),
+        }
+    };
+
+    let mut pages = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.to_lowercase().as_str() {
+            "all" => pages.extend(1..=page_count),
+            "odd" => pages.extend((1..=page_count).step_by(2)),
+            "even" => pages.extend((2..=page_count).step_by(2)),
+            _ => match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start <= end {
+                        pages.extend(start..=end);
+                    } else {
+                        pages.extend((end..=start).rev());
+                    }
+                }
+                None => pages.push(parse(part)?),
+            },
+        }
+    }
+    if pages.is_empty() {
+        return Err(format!("Page selection '{}' selects no pages", spec).into());
+    }
+    Ok(pages)
+}
+
+/// Writes pages back as a selection string, collapsing ascending runs into
+/// ranges: `[1, 2, 3, 7]` becomes `1-3,7`.
+pub fn format_pages(pages: &[u32]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < pages.len() {
+        let start = pages[index];
+        let mut end = start;
+        while index + 1 < pages.len() && pages[index + 1] == end + 1 {
+            end += 1;
+            index += 1;
+        }
+        parts.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{}-{}", start, end)
+        });
+        index += 1;
+    }
+    parts.join(",")
+}
+
+/// Takes one page from each input in turn until all are exhausted, so
+/// `fronts.pdf backs.pdf:last-1` rebuilds a duplex scan.
+pub fn interleave<T>(groups: Vec<Vec<T>>) -> Vec<T> {
+    let total = groups.iter().map(Vec::len).sum();
+    let mut iters: Vec<_> = groups.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Vec::with_capacity(total);
+    while merged.len() < total {
+        for iter in iters.iter_mut() {
+            if let Some(item) = iter.next() {
+                merged.push(item);
+            }
+        }
+    }
+    merged
+}
@@ -0,0 +1,130 @@
+//! PDF syntax for single objects. lopdf keeps its writer private, and we
+//! need object bytes for hashing, object streams and incremental output.
+
+use lopdf::{Dictionary, Object, StringFormat};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &byte in name {
+        if (0x21..=0x7e).contains(&byte) && !b"#()<>[]{}/%".contains(&byte) {
+            out.push(byte);
+        } else {
+            let _ = write!(out, "#{:02X}", byte);
+        }
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, bytes: &[u8], format: &StringFormat) {
+    match format {
+        StringFormat::Literal => {
+            out.push(b'(');
+            for &byte in bytes {
+                match byte {
+                    b'\\' | b'(' | b')' => out.extend_from_slice(&[b'\\', byte]),
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    _ => out.push(byte),
+                }
+            }
+            out.push(b')');
+        }
+        StringFormat::Hexadecimal => {
+            out.push(b'<');
+            for byte in bytes {
+                let _ = write!(out, "{:02X}", byte);
+            }
+            out.push(b'>');
+        }
+    }
+}
+
+fn write_dictionary(out: &mut Vec<u8>, dict: &Dictionary) {
+    out.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(out, key);
+        out.push(b' ');
+        write_object(out, value);
+    }
+    out.extend_from_slice(b">>");
+}
+
+/// Appends `object` in PDF syntax. Stream lengths are taken from the
+/// content, not from the dictionary.
+pub fn write_object(out: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => out.extend_from_slice(if *value { b"true" } else { b"false" }),
+        Object::Integer(value) => {
+            let _ = write!(out, "{}", value);
+        }
+        Object::Real(value) => {
+            let _ = write!(out, "{}", value);
+        }
+        Object::Name(name) => write_name(out, name),
+        Object::String(bytes, format) => write_string(out, bytes, format),
+        Object::Array(items) => {
+            out.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(b' ');
+                }
+                write_object(out, item);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(out, dict),
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_dictionary(out, &dict);
+            out.extend_from_slice(b"\nstream\n");
+            out.extend_from_slice(&stream.content);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((id, generation)) => {
+            let _ = write!(out, "{} {} R", id, generation);
+        }
+    }
+}
+
+/// A file writer that knows its current offset, for the xref.
+pub struct CountingFile {
+    file: BufWriter<File>,
+    offset: u64,
+}
+
+impl CountingFile {
+    pub fn create(path: &Path) -> io::Result<CountingFile> {
+        Ok(CountingFile {
+            file: BufWriter::new(File::create(path)?),
+            offset: 0,
+        })
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Writes `bytes` and returns the offset they start at.
+    pub fn emit(&mut self, bytes: &[u8]) -> io::Result<u64> {
+        let start = self.offset;
+        self.file.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(start)
+    }
+
+    pub fn emit_object(&mut self, id: u32, generation: u16, object: &Object) -> io::Result<u64> {
+        let mut buffer = format!("{} {} obj\n", id, generation).into_bytes();
+        write_object(&mut buffer, object);
+        buffer.extend_from_slice(b"\nendobj\n");
+        self.emit(&buffer)
+    }
+
+    /// Flushes the file and returns the number of bytes written.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.file.flush()?;
+        Ok(self.offset)
+    }
+}
@@ -0,0 +1,331 @@
+//! Text overlaid on every page of the result: Bates numbers, headers or
+//! footers built from a template, and a diagonal watermark.
+
+use crate::merge;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, StringFormat, dictionary};
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Resource names for the stamp font and transparency; unusual enough not
+/// to clash with what the page already uses.
+const FONT_NAME: &str = "FStamp";
+const GSTATE_NAME: &str = "GSStamp";
+
+/// Glyph widths of Helvetica for ASCII 32..=126, in 1/1000 em, from the
+/// `WX` values in Adobe's Core 14 `Helvetica.afm`.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+fn text_width(text: &str, font_size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f32 * font_size / 1000.0
+}
+
+/// Latin-1 bytes for a simple-font `Tj` string, `?` for anything outside it.
+fn win_ansi_bytes(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Position {
+    pub fn parse(arg: &str) -> Result<Position, String> {
+        Ok(match arg.to_ascii_lowercase().as_str() {
+            "top-left" => Position::TopLeft,
+            "top-center" | "top" => Position::TopCenter,
+            "top-right" => Position::TopRight,
+            "bottom-left" => Position::BottomLeft,
+            "bottom-center" | "bottom" => Position::BottomCenter,
+            "bottom-right" => Position::BottomRight,
+            _ => {
+                return Err(format!(
+                    "unknown position '{}' (use top-left, top-center, top-right, \
+                     bottom-left, bottom-center or bottom-right)",
+                    arg
+                ));
+            }
+        })
+    }
+}
+
+/// An RGB fill colour, each component 0.0-1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub [f32; 3]);
+
+impl Color {
+    /// Parses `RRGGBB`, with or without a leading `#`.
+    pub fn parse(arg: &str) -> Result<Color, String> {
+        let hex = arg.trim_start_matches('#');
+        let value = (hex.len() == 6)
+            .then(|| u32::from_str_radix(hex, 16).ok())
+            .flatten()
+            .ok_or_else(|| format!("'{}' is not a colour like 1F4E79", arg))?;
+        let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+        Ok(Color([channel(16), channel(8), channel(0)]))
+    }
+}
+
+/// What to stamp on each page and how.
+#[derive(Debug, Clone)]
+pub struct Stamp {
+    /// Text for every page. `{bates}`, `{page}`, `{pages}`, `{date}` and
+    /// `{file}` are filled in per page.
+    pub text: Option<String>,
+    pub bates_prefix: String,
+    pub bates_start: u64,
+    /// Width the Bates counter is zero-padded to.
+    pub bates_digits: usize,
+    pub position: Position,
+    /// Distance from the page edges, in points.
+    pub margin: f32,
+    pub font_size: f32,
+    pub color: Color,
+    pub date: String,
+    /// Text set diagonally across the middle of every page.
+    pub watermark: Option<String>,
+    pub watermark_opacity: f32,
+}
+
+impl Stamp {
+    /// Bates number of the page at `index` (0-based) in the output.
+    pub fn bates(&self, index: usize) -> String {
+        format!(
+            "{}{:0width$}",
+            self.bates_prefix,
+            self.bates_start + index as u64,
+            width = self.bates_digits
+        )
+    }
+
+    fn expand(&self, index: usize, page_count: usize, file: &str) -> Option<String> {
+        let text = self.text.as_ref()?;
+        Some(
+            text.replace("{bates}", &self.bates(index))
+                .replace("{page}", &(index + 1).to_string())
+                .replace("{pages}", &page_count.to_string())
+                .replace("{date}", &self.date)
+                .replace("{file}", file),
+        )
+    }
+}
+
+/// Today's date (UTC) as YYYY-MM-DD.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64;
+    // Days to civil date, after Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Matrix from the page as the reader sees it (origin bottom left, after
+/// `/Rotate`) to its user space, and the seen width and height.
+fn display_space(page: &Dictionary) -> Result<([f32; 6], f32, f32), Box<dyn Error>> {
+    let [x1, y1, x2, y2] = merge::page_box(page)?;
+    let (width, height) = (x2 - x1, y2 - y1);
+    let rotate = page
+        .get(b"Rotate")
+        .and_then(Object::as_i64)
+        .unwrap_or(0)
+        .rem_euclid(360);
+    Ok(match rotate {
+        90 => ([0.0, 1.0, -1.0, 0.0, x2, y1], height, width),
+        180 => ([-1.0, 0.0, 0.0, -1.0, x2, y2], width, height),
+        270 => ([0.0, -1.0, 1.0, 0.0, x1, y2], height, width),
+        _ => ([1.0, 0.0, 0.0, 1.0, x1, y1], width, height),
+    })
+}
+
+fn show_text(text: &str, font_size: f32, color: Color, at: [f32; 6]) -> Vec<Operation> {
+    let [r, g, b] = color.0;
+    vec![
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(FONT_NAME.into()), font_size.into()]),
+        Operation::new("rg", vec![r.into(), g.into(), b.into()]),
+        Operation::new("Tm", at.iter().map(|v| (*v).into()).collect()),
+        Operation::new(
+            "Tj",
+            vec![Object::String(win_ansi_bytes(text), StringFormat::Literal)],
+        ),
+        Operation::new("ET", vec![]),
+    ]
+}
+
+/// Adds the stamp font (and graphics state) to a page's resources. They
+/// are copied onto the page rather than edited in place, since form
+/// XObjects and other pages may share the originals.
+fn add_resources(
+    doc: &Document,
+    page: &mut Dictionary,
+    font_id: ObjectId,
+    gstate_id: Option<ObjectId>,
+) {
+    let resolve = |object: Result<&Object, lopdf::Error>| {
+        object
+            .and_then(|o| doc.dereference(o))
+            .and_then(|(_, o)| o.as_dict())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let mut resources = resolve(page.get(b"Resources"));
+    for (category, name, id) in [
+        ("Font", FONT_NAME, Some(font_id)),
+        ("ExtGState", GSTATE_NAME, gstate_id),
+    ] {
+        let Some(id) = id else {
+            continue;
+        };
+        let mut entries = resolve(resources.get(category.as_bytes()));
+        entries.set(name, id);
+        resources.set(category, entries);
+    }
+    page.set("Resources", resources);
+}
+
+/// Stamps every page of `doc`. `files` holds the source file name of each
+/// page, for `{file}`.
+pub fn stamp_pages(
+    doc: &mut Document,
+    stamp: &Stamp,
+    files: &[String],
+) -> Result<(), Box<dyn Error>> {
+    if !(0.0..=1.0).contains(&stamp.watermark_opacity) {
+        return Err("Watermark opacity must be between 0 and 1".into());
+    }
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let gstate_id = stamp.watermark.as_ref().map(|_| {
+        doc.add_object(dictionary! {
+            "Type" => "ExtGState",
+            "ca" => stamp.watermark_opacity,
+            "CA" => stamp.watermark_opacity,
+        })
+    });
+    // The page's own content runs inside q/Q, so whatever graphics state it
+    // leaves behind does not move or recolour the stamp
+    let save_id = doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let page_count = pages.len();
+    for (index, page_id) in pages.into_iter().enumerate() {
+        let mut page = merge::materialize_inherited(doc, page_id)?;
+        let (to_page, width, height) = display_space(&page)?;
+
+        let mut operations = vec![
+            Operation::new("Q", vec![]),
+            Operation::new("q", vec![]),
+            Operation::new("cm", to_page.iter().map(|v| (*v).into()).collect()),
+        ];
+        if let Some(watermark) = &stamp.watermark {
+            // Across the diagonal, filling most of it
+            let diagonal = width.hypot(height);
+            let font_size = 0.75 * diagonal / text_width(watermark, 1.0).max(1.0);
+            let angle = height.atan2(width);
+            let (sin, cos) = angle.sin_cos();
+            operations.extend([
+                Operation::new("q", vec![]),
+                Operation::new("gs", vec![Object::Name(GSTATE_NAME.into())]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        cos.into(),
+                        sin.into(),
+                        (-sin).into(),
+                        cos.into(),
+                        (width / 2.0).into(),
+                        (height / 2.0).into(),
+                    ],
+                ),
+            ]);
+            let x = -text_width(watermark, font_size) / 2.0;
+            let y = -0.35 * font_size;
+            operations.extend(show_text(
+                watermark,
+                font_size,
+                stamp.color,
+                [1.0, 0.0, 0.0, 1.0, x, y],
+            ));
+            operations.push(Operation::new("Q", vec![]));
+        }
+        let file = files.get(index).map_or("", String::as_str);
+        if let Some(text) = stamp.expand(index, page_count, file) {
+            let text_width = text_width(&text, stamp.font_size);
+            let x = match stamp.position {
+                Position::TopLeft | Position::BottomLeft => stamp.margin,
+                Position::TopCenter | Position::BottomCenter => (width - text_width) / 2.0,
+                Position::TopRight | Position::BottomRight => width - stamp.margin - text_width,
+            };
+            // Baseline placed so the cap height sits against the top margin
+            let y = match stamp.position {
+                Position::TopLeft | Position::TopCenter | Position::TopRight => {
+                    height - stamp.margin - 0.72 * stamp.font_size
+                }
+                _ => stamp.margin,
+            };
+            operations.extend(show_text(
+                &text,
+                stamp.font_size,
+                stamp.color,
+                [1.0, 0.0, 0.0, 1.0, x, y],
+            ));
+        }
+        operations.push(Operation::new("Q", vec![]));
+        // Leading newline: the previous stream may end mid-line
+        let mut content = b"\n".to_vec();
+        content.extend(Content { operations }.encode()?);
+        let stamp_id = doc.add_object(Stream::new(Dictionary::new(), content));
+
+        let mut contents = vec![Object::Reference(save_id)];
+        match page.get(b"Contents").and_then(|c| doc.dereference(c)) {
+            Ok((_, Object::Array(streams))) => contents.extend(streams.iter().cloned()),
+            Ok((Some(id), Object::Stream(_))) => contents.push(Object::Reference(id)),
+            _ => {}
+        }
+        contents.push(Object::Reference(stamp_id));
+        page.set("Contents", contents);
+        add_resources(doc, &mut page, font_id, gstate_id);
+        doc.objects.insert(page_id, Object::Dictionary(page));
+    }
+    doc.compress();
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! Merging without holding every input in memory. Each input is loaded,
+//! its selected pages and whatever they use are written straight to the
+//! output, and the document is dropped before the next one is read. Only the
+//! page ids, bookmarks, named destinations and form fields are kept until
+//! the page tree, catalog and cross-reference table are written at the end.
+
+use crate::acroform::FormMerger;
+use crate::compact;
+use crate::merge::{self, MergeOptions};
+use crate::outline::{self, DestResolver, OutlineNode};
+use crate::selection::{self, InputSpec};
+use crate::serialize::CountingFile;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Reserved for the objects written last, so every page can point at its
+/// parent before the page tree exists.
+const CATALOG_ID: ObjectId = (1, 0);
+const PAGES_ID: ObjectId = (2, 0);
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StreamReport {
+    pub pages: usize,
+    pub bytes: u64,
+}
+
+/// Offsets and generations of the objects written so far, indexed by
+/// object number.
+#[derive(Default)]
+struct Xref {
+    offsets: Vec<(u64, u16)>,
+}
+
+impl Xref {
+    fn record(&mut self, (id, generation): ObjectId, offset: u64) {
+        let index = id as usize;
+        if self.offsets.len() <= index {
+            self.offsets.resize(index + 1, (0, 0));
+        }
+        self.offsets[index] = (offset, generation);
+    }
+
+    /// Writes a classic xref table and trailer. Numbers that were never
+    /// written (objects nothing on the kept pages used) are marked free.
+    fn write(&self, out: &mut CountingFile) -> io::Result<()> {
+        let start = out.offset();
+        let size = self.offsets.len().max(1);
+        let mut table = Vec::with_capacity(size * 20 + 64);
+        let _ = write!(table, "xref\n0 {}\n", size);
+        table.extend_from_slice(b"0000000000 65535 f \n");
+        for &(offset, generation) in self.offsets.iter().skip(1) {
+            if offset == 0 {
+                table.extend_from_slice(b"0000000000 00000 f \n");
+            } else {
+                let _ = writeln!(table, "{:010} {:05} n ", offset, generation);
+            }
+        }
+        let _ = write!(
+            table,
+            "trailer\n<</Size {}/Root {} {} R>>\nstartxref\n{}\n%%EOF\n",
+            size, CATALOG_ID.0, CATALOG_ID.1, start
+        );
+        out.emit(&table)?;
+        Ok(())
+    }
+}
+
+fn push_references(object: &Object, pending: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => pending.push(*id),
+        Object::Array(items) => {
+            for item in items {
+                push_references(item, pending);
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter() {
+                push_references(value, pending);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter() {
+                push_references(value, pending);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Values outline items carry over from their source document, which may
+/// refer to its objects (an action dictionary, a title string...).
+fn collect_outline_objects<'a>(items: &'a [OutlineNode], found: &mut Vec<&'a Object>) {
+    for item in items {
+        found.push(&item.title);
+        found.extend(&item.action);
+        found.extend(item.style.iter().map(|(_, value)| value));
+        collect_outline_objects(&item.children, found);
+    }
+}
+
+/// Objects of `doc` reachable from `roots` without passing through the
+/// document structure: another page (e.g. an annotation's `/P` pointing at
+/// a page that was not selected), the page tree or the catalog. References
+/// to those are left dangling, which readers treat as null.
+fn reachable<'a>(
+    doc: &Document,
+    pages: &BTreeSet<ObjectId>,
+    roots: impl IntoIterator<Item = &'a Object>,
+) -> BTreeSet<ObjectId> {
+    let mut pending = Vec::new();
+    for root in roots {
+        push_references(root, &mut pending);
+    }
+    let mut found = BTreeSet::new();
+    while let Some(id) = pending.pop() {
+        let Some(object) = doc.objects.get(&id) else {
+            continue;
+        };
+        let structural = matches!(
+            object.type_name(),
+            Ok("Page" | "Pages" | "Catalog" | "Outlines")
+        );
+        if (structural && !pages.contains(&id)) || !found.insert(id) {
+            continue;
+        }
+        push_references(object, &mut pending);
+    }
+    found
+}
+
+/// Merges the selected pages of every input into `output`, one input at a
+/// time. Peak memory is roughly that of the largest input rather than of
+/// all of them together.
+///
+/// Identical objects are not collapsed across inputs, since that would
+/// need every earlier object at hand; `recompress` is applied per input.
+/// A partly written `output` is removed if an input fails.
+pub fn merge_to_file(
+    inputs: &[InputSpec],
+    options: &MergeOptions,
+    recompress: bool,
+    output: &Path,
+) -> Result<StreamReport, Box<dyn Error>> {
+    let result = write_merged(inputs, options, recompress, output);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn write_merged(
+    inputs: &[InputSpec],
+    options: &MergeOptions,
+    recompress: bool,
+    output: &Path,
+) -> Result<StreamReport, Box<dyn Error>> {
+    if options.rotation.is_some() {
+        return Err("Rotation is not supported when streaming".into());
+    }
+    let write_error = |e: io::Error| format!("Failed to write '{}': {}", output.display(), e);
+
+    let mut out = CountingFile::create(output).map_err(write_error)?;
+    out.emit(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n")
+        .map_err(write_error)?;
+
+    let mut xref = Xref::default();
+    let mut next_id = PAGES_ID.0 + 1;
+    let mut groups: Vec<Vec<ObjectId>> = Vec::new();
+    let mut outline_items = Vec::new();
+    let mut named_dests: BTreeMap<Vec<u8>, Vec<Object>> = BTreeMap::new();
+    let mut forms = FormMerger::default();
+
+    for input in inputs {
+        let mut doc = merge::load_document(input)?;
+        doc.renumber_objects_with(next_id);
+        next_id = doc.max_id + 1;
+
+        let group = merge::selected_page_ids(&doc, input)?;
+        let resolver = DestResolver::new(&doc, group.iter().copied().collect());
+        let mut pages = BTreeMap::new();
+        for &page_id in &group {
+            if let Entry::Vacant(entry) = pages.entry(page_id) {
+                outline::rewrite_links(&mut doc, page_id, &resolver);
+                let mut page = merge::materialize_inherited(&doc, page_id)?;
+                page.set("Parent", PAGES_ID);
+                entry.insert(page);
+            }
+        }
+        merge::add_named_destinations(&mut named_dests, &resolver, input);
+        merge::add_form_fields(&mut forms, &mut doc, &group, input);
+
+        let items = outline::read_outline(&doc, &resolver);
+        let first_page = group[0];
+
+        // A page selected more than once gets a copy
+        let mut placed = BTreeSet::new();
+        let mut kids = Vec::with_capacity(group.len());
+        for page_id in group {
+            let target_id = if placed.insert(page_id) {
+                page_id
+            } else {
+                next_id += 1;
+                (next_id - 1, 0)
+            };
+            doc.objects
+                .insert(target_id, Object::Dictionary(pages[&page_id].clone()));
+            kids.push(target_id);
+        }
+
+        let kept: BTreeSet<ObjectId> = kids.iter().copied().collect();
+        let roots: Vec<Object> = kids.iter().map(|id| Object::Reference(*id)).collect();
+        let mut outline_objects = Vec::new();
+        collect_outline_objects(&items, &mut outline_objects);
+        let keep = reachable(
+            &doc,
+            &kept,
+            roots.iter().chain(forms.entries()).chain(outline_objects),
+        );
+        doc.objects.retain(|id, _| keep.contains(id));
+        doc.compress();
+        if recompress {
+            compact::recompress(&mut doc);
+        }
+
+        for ((id, generation), object) in &doc.objects {
+            let start = out
+                .emit_object(*id, *generation, object)
+                .map_err(write_error)?;
+            xref.record((*id, *generation), start);
+        }
+        groups.push(kids);
+
+        if options.file_bookmarks {
+            outline_items.push(OutlineNode::for_file(
+                &merge::bookmark_title(input),
+                first_page,
+                items,
+            ));
+        } else {
+            outline_items.extend(items);
+        }
+    }
+
+    let mut order: Vec<ObjectId> = if options.interleave {
+        selection::interleave(groups)
+    } else {
+        groups.into_iter().flatten().collect()
+    };
+    if options.reverse {
+        order.reverse();
+    }
+    let page_count = order.len();
+
+    // The outline is built in a scratch document that only hands out ids
+    let mut skeleton = Document::new();
+    skeleton.max_id = next_id - 1;
+    let outlines_id = outline::write_outline(&mut skeleton, outline_items);
+
+    let mut pages = Dictionary::new();
+    pages.set("Type", "Pages");
+    pages.set("Count", page_count as u32);
+    pages.set(
+        "Kids",
+        order.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+    );
+    skeleton.objects.insert(PAGES_ID, Object::Dictionary(pages));
+
+    let mut catalog = Dictionary::new();
+    catalog.set("Type", "Catalog");
+    catalog.set("Pages", PAGES_ID);
+    if let Some(outlines_id) = outlines_id {
+        catalog.set("Outlines", outlines_id);
+    }
+    if let Some(acroform) = forms.into_acroform() {
+        catalog.set("AcroForm", acroform);
+    }
+    if let Some(dests) = merge::dests_name_tree(named_dests) {
+        let mut names = Dictionary::new();
+        names.set("Dests", dests);
+        catalog.set("Names", names);
+    }
+    skeleton
+        .objects
+        .insert(CATALOG_ID, Object::Dictionary(catalog));
+
+    for ((id, generation), object) in &skeleton.objects {
+        let start = out
+            .emit_object(*id, *generation, object)
+            .map_err(write_error)?;
+        xref.record((*id, *generation), start);
+    }
+    xref.write(&mut out).map_err(write_error)?;
+    let bytes = out.finish().map_err(write_error)?;
+
+    Ok(StreamReport {
+        pages: page_count,
+        bytes,
+    })
+}
@@ -0,0 +1,381 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{Document, Object, ObjectId, Stream, dictionary};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Writes a PDF whose pages each show one label, e.g. `a-1`, `a-2`, ...
+/// The pages share a Helvetica font object with the given generation.
+fn write_pdf(dir: &TempDir, name: &str, pages: u32, generation: u16) -> PathBuf {
+    let stem = name.trim_end_matches(".pdf");
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = (doc.new_object_id().0, generation);
+    doc.objects.insert(
+        font_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        }),
+    );
+
+    let mut kids = Vec::new();
+    for number in 1..=pages {
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                Operation::new("Td", vec![100.into(), 600.into()]),
+                Operation::new(
+                    "Tj",
+                    vec![Object::string_literal(format!("{}-{}", stem, number))],
+                ),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "Contents" => content_id,
+        });
+        kids.push(Object::Reference(page_id));
+    }
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => pages,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    let path = dir.path().join(name);
+    doc.save(&path).unwrap();
+    path
+}
+
+fn merge(dir: &TempDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_merge"))
+        .current_dir(dir.path())
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn merge_ok(dir: &TempDir, args: &[&str]) -> Document {
+    let output = merge(dir, args);
+    assert!(
+        output.status.success(),
+        "merge {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Document::load(dir.path().join("out.pdf")).unwrap()
+}
+
+/// The strings shown by a content stream, in order.
+fn shown_text(doc: &Document, content_id: ObjectId) -> Vec<String> {
+    let stream = doc.get_object(content_id).unwrap().as_stream().unwrap();
+    let data = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Content::decode(&data)
+        .unwrap()
+        .operations
+        .into_iter()
+        .filter(|op| op.operator == "Tj")
+        .map(|op| String::from_utf8(op.operands[0].as_str().unwrap().to_vec()).unwrap())
+        .collect()
+}
+
+/// The label of every page, in page order.
+fn labels(doc: &Document) -> Vec<String> {
+    doc.get_pages()
+        .values()
+        .flat_map(|&page_id| {
+            let page = doc.get_dictionary(page_id).unwrap();
+            shown_text(doc, page.get(b"Contents").unwrap().as_reference().unwrap())
+        })
+        .collect()
+}
+
+/// The XObjects drawn on each imposed sheet side, by name, with the label
+/// each one shows.
+fn sheet_sides(doc: &Document) -> Vec<Vec<(String, String)>> {
+    doc.get_pages()
+        .values()
+        .map(|&page_id| {
+            let page = doc.get_dictionary(page_id).unwrap();
+            let content_id = page.get(b"Contents").unwrap().as_reference().unwrap();
+            let xobjects = page
+                .get(b"Resources")
+                .unwrap()
+                .as_dict()
+                .unwrap()
+                .get(b"XObject")
+                .unwrap()
+                .as_dict()
+                .unwrap();
+            let stream = doc.get_object(content_id).unwrap().as_stream().unwrap();
+            let data = stream
+                .decompressed_content()
+                .unwrap_or_else(|_| stream.content.clone());
+            Content::decode(&data)
+                .unwrap()
+                .operations
+                .into_iter()
+                .filter(|op| op.operator == "Do")
+                .map(|op| {
+                    let name = op.operands[0].as_name().unwrap();
+                    let xobject = xobjects.get(name).unwrap().as_reference().unwrap();
+                    let name = String::from_utf8(name.to_vec()).unwrap();
+                    (name, shown_text(doc, xobject).concat())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn outline_titles(doc: &Document) -> Vec<String> {
+    let outlines = doc
+        .catalog()
+        .unwrap()
+        .get(b"Outlines")
+        .unwrap()
+        .as_reference()
+        .unwrap();
+    let mut next = doc.get_dictionary(outlines).unwrap().get(b"First").ok();
+    let mut titles = Vec::new();
+    while let Some(Object::Reference(id)) = next {
+        let item = doc.get_dictionary(*id).unwrap();
+        let title = item.get(b"Title").unwrap().as_str().unwrap();
+        titles.push(lopdf::decode_text_string(&Object::string_literal(title)).unwrap());
+        next = item.get(b"Next").ok();
+    }
+    titles
+}
+
+fn fonts(doc: &Document) -> usize {
+    doc.objects
+        .values()
+        .filter(|object| object.type_name().is_ok_and(|name| name == "Font"))
+        .count()
+}
+
+#[test]
+fn merges_selected_pages_in_the_order_given() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 3, 0);
+    write_pdf(&dir, "b.pdf", 2, 0);
+
+    let doc = merge_ok(
+        &dir,
+        &["a.pdf:3,1", "b.pdf:last", "-t", "First", "-o", "out.pdf"],
+    );
+    assert_eq!(labels(&doc), ["a-3", "a-1", "b-2"]);
+    assert_eq!(outline_titles(&doc), ["First", "b.pdf"]);
+    // Both inputs' Helvetica collapse into one object
+    assert_eq!(fonts(&doc), 1);
+}
+
+#[test]
+fn interleaves_and_reverses() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "front.pdf", 3, 0);
+    write_pdf(&dir, "back.pdf", 3, 0);
+
+    let doc = merge_ok(
+        &dir,
+        &[
+            "front.pdf",
+            "back.pdf:last-1",
+            "--interleave",
+            "-o",
+            "out.pdf",
+        ],
+    );
+    assert_eq!(
+        labels(&doc),
+        [
+            "front-1", "back-3", "front-2", "back-2", "front-3", "back-1"
+        ]
+    );
+
+    let doc = merge_ok(&dir, &["front.pdf:1-2", "--reverse", "-o", "out.pdf"]);
+    assert_eq!(labels(&doc), ["front-2", "front-1"]);
+}
+
+#[test]
+fn rejects_inputs_without_pages_or_selected_pages() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 2, 0);
+    write_pdf(&dir, "empty.pdf", 0, 0);
+
+    let output = merge(&dir, &["a.pdf", "empty.pdf", "-o", "out.pdf"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'empty.pdf' has no pages"));
+
+    let output = merge(&dir, &["a.pdf:5", "-o", "out.pdf"]);
+    assert!(!output.status.success());
+    assert!(!dir.path().join("out.pdf").exists());
+}
+
+#[test]
+fn streams_inputs_to_the_output() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 2, 0);
+    write_pdf(&dir, "b.pdf", 3, 2);
+
+    let doc = merge_ok(
+        &dir,
+        &[
+            "a.pdf",
+            "b.pdf:even",
+            "a.pdf:1",
+            "--stream",
+            "-o",
+            "out.pdf",
+        ],
+    );
+    assert_eq!(labels(&doc), ["a-1", "a-2", "b-2", "a-1"]);
+    assert_eq!(outline_titles(&doc), ["a.pdf", "b.pdf", "a.pdf"]);
+}
+
+#[test]
+fn streaming_removes_the_output_when_an_input_fails() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 2, 0);
+
+    let output = merge(&dir, &["a.pdf", "missing.pdf", "--stream", "-o", "out.pdf"]);
+    assert!(!output.status.success());
+    assert!(!dir.path().join("out.pdf").exists());
+}
+
+#[test]
+fn writes_object_streams_with_an_xref_stream() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 2, 0);
+    write_pdf(&dir, "b.pdf", 1, 0);
+
+    let doc = merge_ok(
+        &dir,
+        &["a.pdf", "b.pdf", "--object-streams", "-o", "out.pdf"],
+    );
+    assert_eq!(labels(&doc), ["a-1", "a-2", "b-1"]);
+    assert_eq!(fonts(&doc), 1);
+
+    let bytes = std::fs::read(dir.path().join("out.pdf")).unwrap();
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"/ObjStm"));
+    assert!(contains(b"/XRef"));
+    assert!(!contains(b"\ntrailer"));
+    // lopdf drops /W when it reads the xref stream back, so look at the bytes
+    assert!(contains(b"/W [1 4 2]"));
+}
+
+#[test]
+fn imposes_four_up() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 6, 0);
+
+    let doc = merge_ok(&dir, &["a.pdf", "--nup", "4", "-o", "out.pdf"]);
+    let sides = sheet_sides(&doc);
+    let named = |side: &[(String, String)]| -> Vec<String> {
+        side.iter()
+            .map(|(name, label)| format!("{}={}", name, label))
+            .collect()
+    };
+    assert_eq!(sides.len(), 2);
+    assert_eq!(named(&sides[0]), ["P1=a-1", "P2=a-2", "P3=a-3", "P4=a-4"]);
+    assert_eq!(named(&sides[1]), ["P5=a-5", "P6=a-6"]);
+    // Portrait A4 sheets
+    let sheet = doc.get_dictionary(doc.get_pages()[&1]).unwrap();
+    let media_box = sheet.get(b"MediaBox").unwrap().as_array().unwrap();
+    assert!((media_box[2].as_float().unwrap() - 595.28).abs() < 0.01);
+}
+
+#[test]
+fn imposes_a_booklet_in_fold_order() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 5, 0);
+
+    let doc = merge_ok(&dir, &["a.pdf", "--booklet", "-o", "out.pdf"]);
+    let sides: Vec<Vec<String>> = sheet_sides(&doc)
+        .into_iter()
+        .map(|side| side.into_iter().map(|(_, label)| label).collect())
+        .collect();
+    // Eight slots, the last three blank: 8|1, 2|7, 6|3, 4|5
+    assert_eq!(
+        sides,
+        [vec!["a-1"], vec!["a-2"], vec!["a-3"], vec!["a-4", "a-5"]]
+    );
+}
+
+#[test]
+fn rejects_unsupported_nup_counts() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 2, 0);
+
+    let output = merge(&dir, &["a.pdf", "--nup", "3", "-o", "out.pdf"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Can't put 3 pages on a sheet"));
+}
+
+fn split_parts(dir: &TempDir, every: &str) -> Vec<Vec<String>> {
+    let parts = dir.path().join(format!("parts-{}", every));
+    let output = merge(
+        dir,
+        &[
+            "split",
+            "a.pdf",
+            "--every",
+            every,
+            "-o",
+            parts.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&parts)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .map(|path: &PathBuf| labels(&Document::load(Path::new(path)).unwrap()))
+        .collect()
+}
+
+#[test]
+fn splits_every_n_pages() {
+    let dir = TempDir::new().unwrap();
+    write_pdf(&dir, "a.pdf", 5, 0);
+
+    assert_eq!(
+        split_parts(&dir, "2"),
+        [vec!["a-1", "a-2"], vec!["a-3", "a-4"], vec!["a-5"]]
+    );
+    // A part size past the page count doesn't overflow
+    assert_eq!(
+        split_parts(&dir, "4294967295"),
+        [vec!["a-1", "a-2", "a-3", "a-4", "a-5"]]
+    );
+    let names: Vec<String> = std::fs::read_dir(dir.path().join("parts-2"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert!(names.contains(&"a-001.pdf".to_string()));
+}
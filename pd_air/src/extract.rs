@@ -0,0 +1,490 @@
+use crate::font::Font;
+use crate::ExtractionError;
+use log::{info, warn};
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+// Form XObjects nested deeper than this are assumed to be cyclic
+const MAX_FORM_DEPTH: usize = 12;
+
+// A TJ adjustment moving right by more than this share of the font size
+// separates words, so it starts a new run
+const TJ_GAP: f32 = 0.25;
+
+/// Text shown by one string operand, or the part of a TJ array between
+/// word-sized gaps, placed in page space.
+#[derive(Debug, Clone)]
+pub struct TextRun {
+    pub page: u32,
+    /// Start of the baseline.
+    pub x: f32,
+    pub y: f32,
+    /// Distance advanced along the baseline.
+    pub width: f32,
+    pub font_size: f32,
+    pub font: String,
+    pub text: String,
+    /// How sure the reader is of the text, from 0 to 1: always 1 for a
+    /// text layer, and the recognition confidence for OCR.
+    pub confidence: f32,
+}
+
+/// An image XObject drawn on a page. `matrix` maps the unit square the
+/// image fills onto page space.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct ImagePlacement {
+    pub page: u32,
+    pub matrix: Matrix,
+    pub id: ObjectId,
+}
+
+pub type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// The product of applying `a`, then `b`
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+fn translate(x: f32, y: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, x, y]
+}
+
+fn numbers(operands: &[Object]) -> Vec<f32> {
+    operands.iter().filter_map(|o| o.as_float().ok()).collect()
+}
+
+fn matrix(operands: &[Object]) -> Option<Matrix> {
+    let values = numbers(operands);
+    (values.len() == 6).then(|| {
+        [
+            values[0], values[1], values[2], values[3], values[4], values[5],
+        ]
+    })
+}
+
+// Graphics state, including the text state parameters saved by q and Q
+#[derive(Clone)]
+struct State {
+    ctm: Matrix,
+    font: Option<Rc<Font>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            ctm: IDENTITY,
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+struct Extractor<'a> {
+    doc: &'a Document,
+    fonts: HashMap<ObjectId, Rc<Font>>,
+    runs: Vec<TextRun>,
+    images: Vec<ImagePlacement>,
+    page: u32,
+}
+
+impl<'a> Extractor<'a> {
+    fn font(&mut self, resources: &Dictionary, name: &[u8]) -> Option<Rc<Font>> {
+        let fonts = resources
+            .get_deref(b"Font", self.doc)
+            .ok()?
+            .as_dict()
+            .ok()?;
+        let (id, object) = self.doc.dereference(fonts.get(name).ok()?).ok()?;
+        let dict = object.as_dict().ok()?;
+        let Some(id) = id else {
+            return Some(Rc::new(Font::load(self.doc, dict)));
+        };
+        let doc = self.doc;
+        Some(
+            self.fonts
+                .entry(id)
+                .or_insert_with(|| Rc::new(Font::load(doc, dict)))
+                .clone(),
+        )
+    }
+
+    // Shows one string, advancing the text matrix, and records the runs
+    // it produced
+    fn show(
+        &mut self,
+        state: &State,
+        text_matrix: &mut Matrix,
+        bytes: &[u8],
+        run: &mut Option<TextRun>,
+    ) {
+        let Some(font) = state.font.clone() else {
+            return;
+        };
+        for glyph in font.decode(bytes) {
+            let start = self.position(state, text_matrix);
+            let mut advance = glyph.width / 1000.0 * state.font_size + state.char_spacing;
+            if glyph.is_space {
+                advance += state.word_spacing;
+            }
+            *text_matrix = multiply(&translate(advance * state.scale, 0.0), text_matrix);
+            let end = self.position(state, text_matrix);
+
+            let current = run.get_or_insert_with(|| TextRun {
+                page: self.page,
+                x: start.0,
+                y: start.1,
+                width: 0.0,
+                font_size: font_size(state, text_matrix),
+                font: font.name.clone(),
+                text: String::new(),
+                confidence: 1.0,
+            });
+            current.text.push_str(&glyph.text);
+            current.width = (end.0 - current.x).hypot(end.1 - current.y);
+        }
+    }
+
+    fn position(&self, state: &State, text_matrix: &Matrix) -> (f32, f32) {
+        let rendering = multiply(
+            &multiply(&translate(0.0, state.rise), text_matrix),
+            &state.ctm,
+        );
+        (rendering[4], rendering[5])
+    }
+
+    fn finish(&mut self, run: &mut Option<TextRun>) {
+        if let Some(run) = run.take() {
+            if !run.text.is_empty() {
+                self.runs.push(run);
+            }
+        }
+    }
+
+    fn xobject(&mut self, state: &State, resources: &Dictionary, name: &[u8], depth: usize) {
+        if depth >= MAX_FORM_DEPTH {
+            warn!(
+                "Form XObjects nested too deeply on page {}; skipping",
+                self.page
+            );
+            return;
+        }
+        let Some((id, stream)) = resources
+            .get_deref(b"XObject", self.doc)
+            .and_then(Object::as_dict)
+            .and_then(|xobjects| self.doc.dereference(xobjects.get(name)?))
+            .and_then(|(id, object)| Ok((id, object.as_stream()?)))
+            .ok()
+        else {
+            return;
+        };
+        match stream.dict.get(b"Subtype").and_then(Object::as_name).ok() {
+            Some(b"Form") => self.form(state, resources, stream, depth),
+            Some(b"Image") => {
+                if let Some(id) = id {
+                    self.images.push(ImagePlacement {
+                        page: self.page,
+                        matrix: state.ctm,
+                        id,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn form(&mut self, state: &State, resources: &Dictionary, stream: &Stream, depth: usize) {
+        let content = match stream
+            .decompressed_content()
+            .or_else(|_| Ok::<_, lopdf::Error>(stream.content.clone()))
+            .and_then(|data| Content::decode(&data))
+        {
+            Ok(content) => content,
+            Err(e) => {
+                warn!(
+                    "Could not decode a form XObject on page {}: {:?}",
+                    self.page, e
+                );
+                return;
+            }
+        };
+        let mut form_state = state.clone();
+        if let Some(form_matrix) = stream
+            .dict
+            .get(b"Matrix")
+            .ok()
+            .and_then(|m| m.as_array().ok())
+            .and_then(|m| matrix(m))
+        {
+            form_state.ctm = multiply(&form_matrix, &state.ctm);
+        }
+        // Forms without their own resources use those of their parent
+        let form_resources = stream
+            .dict
+            .get_deref(b"Resources", self.doc)
+            .and_then(Object::as_dict)
+            .unwrap_or(resources);
+        self.run(&content.operations, form_state, form_resources, depth + 1);
+    }
+
+    fn run(
+        &mut self,
+        operations: &[Operation],
+        initial: State,
+        resources: &Dictionary,
+        depth: usize,
+    ) {
+        let mut state = initial;
+        let mut saved: Vec<State> = Vec::new();
+        let mut text_matrix = IDENTITY;
+        let mut line_matrix = IDENTITY;
+        let mut run: Option<TextRun> = None;
+
+        for operation in operations {
+            let operands = &operation.operands;
+            let values = numbers(operands);
+            match operation.operator.as_str() {
+                "q" => saved.push(state.clone()),
+                "Q" => {
+                    if let Some(previous) = saved.pop() {
+                        state = previous;
+                    }
+                }
+                "cm" => {
+                    if let Some(m) = matrix(operands) {
+                        state.ctm = multiply(&m, &state.ctm);
+                    }
+                }
+                "BT" => {
+                    text_matrix = IDENTITY;
+                    line_matrix = IDENTITY;
+                }
+                "ET" => self.finish(&mut run),
+                "Tf" => {
+                    self.finish(&mut run);
+                    state.font = operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| self.font(resources, name));
+                    if let Some(size) = operands.get(1).and_then(|s| s.as_float().ok()) {
+                        state.font_size = size;
+                    }
+                }
+                "Tc" => state.char_spacing = values.first().copied().unwrap_or(0.0),
+                "Tw" => state.word_spacing = values.first().copied().unwrap_or(0.0),
+                "Tz" => state.scale = values.first().copied().unwrap_or(100.0) / 100.0,
+                "TL" => state.leading = values.first().copied().unwrap_or(0.0),
+                "Ts" => state.rise = values.first().copied().unwrap_or(0.0),
+                "Td" | "TD" => {
+                    self.finish(&mut run);
+                    if let [x, y] = values[..] {
+                        if operation.operator == "TD" {
+                            state.leading = -y;
+                        }
+                        line_matrix = multiply(&translate(x, y), &line_matrix);
+                        text_matrix = line_matrix;
+                    }
+                }
+                "Tm" => {
+                    self.finish(&mut run);
+                    if let Some(m) = matrix(operands) {
+                        line_matrix = m;
+                        text_matrix = m;
+                    }
+                }
+                "T*" | "'" | "\"" => {
+                    self.finish(&mut run);
+                    if operation.operator == "\"" {
+                        if let [word, char, ..] = values[..] {
+                            state.word_spacing = word;
+                            state.char_spacing = char;
+                        }
+                    }
+                    line_matrix = multiply(&translate(0.0, -state.leading), &line_matrix);
+                    text_matrix = line_matrix;
+                    if let Some(Object::String(bytes, _)) =
+                        operands.last().filter(|_| operation.operator != "T*")
+                    {
+                        self.show(&state, &mut text_matrix, bytes, &mut run);
+                        self.finish(&mut run);
+                    }
+                }
+                "Tj" => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show(&state, &mut text_matrix, bytes, &mut run);
+                    }
+                    self.finish(&mut run);
+                }
+                "TJ" => {
+                    let Some(Object::Array(items)) = operands.first() else {
+                        continue;
+                    };
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => {
+                                self.show(&state, &mut text_matrix, bytes, &mut run)
+                            }
+                            _ => {
+                                let Ok(adjustment) = item.as_float() else {
+                                    continue;
+                                };
+                                if -adjustment / 1000.0 > TJ_GAP {
+                                    self.finish(&mut run);
+                                }
+                                let shift = -adjustment / 1000.0 * state.font_size * state.scale;
+                                text_matrix = multiply(&translate(shift, 0.0), &text_matrix);
+                            }
+                        }
+                    }
+                    self.finish(&mut run);
+                }
+                "Do" => {
+                    self.finish(&mut run);
+                    if let Some(name) = operands.first().and_then(|name| name.as_name().ok()) {
+                        self.xobject(&state, resources, name, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.finish(&mut run);
+    }
+}
+
+// Font size as it appears on the page, after the text and current matrices
+fn font_size(state: &State, text_matrix: &Matrix) -> f32 {
+    let m = multiply(text_matrix, &state.ctm);
+    (state.font_size * m[2].hypot(m[3])).abs()
+}
+
+fn page_resources(doc: &Document, page_id: ObjectId) -> Dictionary {
+    // Resources are inherited from the page tree; the nearest entry wins
+    let (direct, inherited) = doc.get_page_resources(page_id);
+    let mut resources = Dictionary::new();
+    for dict in inherited
+        .iter()
+        .rev()
+        .filter_map(|id| doc.get_dictionary(*id).ok())
+        .chain(direct)
+    {
+        for (key, value) in dict.iter() {
+            resources.set(key.clone(), value.clone());
+        }
+    }
+    resources
+}
+
+// Runs the content streams of every page, collecting text runs and the
+// images drawn
+fn walk(doc: &Document) -> Extractor<'_> {
+    let mut extractor = Extractor {
+        doc,
+        fonts: HashMap::new(),
+        runs: Vec::new(),
+        images: Vec::new(),
+        page: 0,
+    };
+    for (page_number, page_id) in doc.get_pages() {
+        extractor.page = page_number;
+        let content = match doc
+            .get_page_content(page_id)
+            .and_then(|data| Content::decode(&data))
+        {
+            Ok(content) => content,
+            Err(e) => {
+                warn!(
+                    "Could not decode content stream for page {}: {:?}",
+                    page_number, e
+                );
+                continue;
+            }
+        };
+        let resources = page_resources(doc, page_id);
+        extractor.run(&content.operations, State::default(), &resources, 0);
+    }
+    extractor
+}
+
+/// Extracts every text run in the document, page by page and in the order
+/// the content streams show them.
+pub fn extract_runs(doc: &Document) -> Vec<TextRun> {
+    walk(doc).runs
+}
+
+/// Finds every image drawn in the document, with where it was drawn.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+pub fn placed_images(doc: &Document) -> Vec<ImagePlacement> {
+    walk(doc).images
+}
+
+/// A text entry of the document information dictionary, such as
+/// `Producer`.
+pub fn document_info(doc: &Document, key: &str) -> Option<String> {
+    let info = doc
+        .trailer
+        .get_deref(b"Info", doc)
+        .and_then(Object::as_dict)
+        .ok()?;
+    let bytes = info
+        .get_deref(key.as_bytes(), doc)
+        .and_then(Object::as_str)
+        .ok()?;
+    let text = match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        // Close enough to PDFDocEncoding for names of producers
+        _ => bytes.iter().map(|byte| *byte as char).collect(),
+    };
+    Some(text)
+}
+
+/// Loads the PDF at `path` and extracts its text runs.
+pub fn load_and_extract(path: &str) -> Result<(Document, Vec<TextRun>), ExtractionError> {
+    info!("Extracting text runs from PDF: {}", path);
+    if !Path::new(path).exists() {
+        return Err(ExtractionError::FileNotFound(path.to_string()));
+    }
+    let doc = Document::load(path)?;
+    let runs = extract_runs(&doc);
+    if runs.is_empty() && !doc.get_pages().is_empty() {
+        warn!("No text was found in a document with pages.");
+    } else {
+        let mut fonts: Vec<&str> = runs.iter().map(|run| run.font.as_str()).collect();
+        fonts.sort_unstable();
+        fonts.dedup();
+        info!(
+            "Extracted {} text runs in fonts: {}",
+            runs.len(),
+            fonts.join(", ")
+        );
+    }
+    Ok((doc, runs))
+}
@@ -0,0 +1,947 @@
+use log::warn;
+use lopdf::{Dictionary, Document, Object};
+use std::collections::HashMap;
+
+// Codes 0x80-0x9F of WinAnsiEncoding; the rest of its upper half is Latin-1
+const WIN_ANSI_C1: &str = "€\0‚ƒ„…†‡ˆ‰Š‹Œ\0Ž\0\0‘’“”•–—˜™š›œ\0žŸ";
+
+// Codes 0x80-0xFF of MacRomanEncoding
+const MAC_ROMAN_HIGH: &str = "ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø\
+    ¿¡¬√ƒ≈∆«»…\u{a0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄¤‹›ﬁﬂ‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\0ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
+
+// Codes 0xA0-0xFF of StandardEncoding
+const STANDARD_HIGH: &str =
+    "\0¡¢£⁄¥ƒ§¤'“«‹›ﬁﬂ\0–†‡·\0¶•‚„”»…‰\0¿\0`´ˆ˜¯˘˙¨\0˚¸\0˝˛ˇ—\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\
+    \0Æ\0ª\0\0\0\0ŁØŒº\0\0\0\0\0æ\0\0\0ı\0\0łøœß\0\0\0\0";
+
+// Glyph names used in Differences arrays beyond single letters and the
+// uniXXXX/uXXXX forms, from the Adobe Glyph List
+const GLYPH_NAMES: &[(&str, char)] = &[
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("quotesingle", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("hyphen", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("zero", '0'),
+    ("one", '1'),
+    ("two", '2'),
+    ("three", '3'),
+    ("four", '4'),
+    ("five", '5'),
+    ("six", '6'),
+    ("seven", '7'),
+    ("eight", '8'),
+    ("nine", '9'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+    ("nbspace", '\u{a0}'),
+    ("nonbreakingspace", '\u{a0}'),
+    ("exclamdown", '¡'),
+    ("cent", '¢'),
+    ("sterling", '£'),
+    ("currency", '¤'),
+    ("yen", '¥'),
+    ("brokenbar", '¦'),
+    ("section", '§'),
+    ("dieresis", '¨'),
+    ("copyright", '©'),
+    ("ordfeminine", 'ª'),
+    ("guillemotleft", '«'),
+    ("logicalnot", '¬'),
+    ("sfthyphen", '\u{ad}'),
+    ("registered", '®'),
+    ("macron", '¯'),
+    ("degree", '°'),
+    ("plusminus", '±'),
+    ("twosuperior", '²'),
+    ("threesuperior", '³'),
+    ("acute", '´'),
+    ("mu", 'µ'),
+    ("paragraph", '¶'),
+    ("periodcentered", '·'),
+    ("cedilla", '¸'),
+    ("onesuperior", '¹'),
+    ("ordmasculine", 'º'),
+    ("guillemotright", '»'),
+    ("onequarter", '¼'),
+    ("onehalf", '½'),
+    ("threequarters", '¾'),
+    ("questiondown", '¿'),
+    ("Agrave", 'À'),
+    ("Aacute", 'Á'),
+    ("Acircumflex", 'Â'),
+    ("Atilde", 'Ã'),
+    ("Adieresis", 'Ä'),
+    ("Aring", 'Å'),
+    ("AE", 'Æ'),
+    ("Ccedilla", 'Ç'),
+    ("Egrave", 'È'),
+    ("Eacute", 'É'),
+    ("Ecircumflex", 'Ê'),
+    ("Edieresis", 'Ë'),
+    ("Igrave", 'Ì'),
+    ("Iacute", 'Í'),
+    ("Icircumflex", 'Î'),
+    ("Idieresis", 'Ï'),
+    ("Eth", 'Ð'),
+    ("Ntilde", 'Ñ'),
+    ("Ograve", 'Ò'),
+    ("Oacute", 'Ó'),
+    ("Ocircumflex", 'Ô'),
+    ("Otilde", 'Õ'),
+    ("Odieresis", 'Ö'),
+    ("multiply", '×'),
+    ("Oslash", 'Ø'),
+    ("Ugrave", 'Ù'),
+    ("Uacute", 'Ú'),
+    ("Ucircumflex", 'Û'),
+    ("Udieresis", 'Ü'),
+    ("Yacute", 'Ý'),
+    ("Thorn", 'Þ'),
+    ("germandbls", 'ß'),
+    ("agrave", 'à'),
+    ("aacute", 'á'),
+    ("acircumflex", 'â'),
+    ("atilde", 'ã'),
+    ("adieresis", 'ä'),
+    ("aring", 'å'),
+    ("ae", 'æ'),
+    ("ccedilla", 'ç'),
+    ("egrave", 'è'),
+    ("eacute", 'é'),
+    ("ecircumflex", 'ê'),
+    ("edieresis", 'ë'),
+    ("igrave", 'ì'),
+    ("iacute", 'í'),
+    ("icircumflex", 'î'),
+    ("idieresis", 'ï'),
+    ("eth", 'ð'),
+    ("ntilde", 'ñ'),
+    ("ograve", 'ò'),
+    ("oacute", 'ó'),
+    ("ocircumflex", 'ô'),
+    ("otilde", 'õ'),
+    ("odieresis", 'ö'),
+    ("divide", '÷'),
+    ("oslash", 'ø'),
+    ("ugrave", 'ù'),
+    ("uacute", 'ú'),
+    ("ucircumflex", 'û'),
+    ("udieresis", 'ü'),
+    ("yacute", 'ý'),
+    ("thorn", 'þ'),
+    ("ydieresis", 'ÿ'),
+    ("dotlessi", 'ı'),
+    ("Lslash", 'Ł'),
+    ("lslash", 'ł'),
+    ("OE", 'Œ'),
+    ("oe", 'œ'),
+    ("Scaron", 'Š'),
+    ("scaron", 'š'),
+    ("Ydieresis", 'Ÿ'),
+    ("Zcaron", 'Ž'),
+    ("zcaron", 'ž'),
+    ("florin", 'ƒ'),
+    ("circumflex", 'ˆ'),
+    ("caron", 'ˇ'),
+    ("breve", '˘'),
+    ("dotaccent", '˙'),
+    ("ring", '˚'),
+    ("ogonek", '˛'),
+    ("tilde", '˜'),
+    ("hungarumlaut", '˝'),
+    ("endash", '–'),
+    ("emdash", '—'),
+    ("quoteleft", '‘'),
+    ("quoteright", '’'),
+    ("quotesinglbase", '‚'),
+    ("quotedblleft", '“'),
+    ("quotedblright", '”'),
+    ("quotedblbase", '„'),
+    ("dagger", '†'),
+    ("daggerdbl", '‡'),
+    ("bullet", '•'),
+    ("ellipsis", '…'),
+    ("perthousand", '‰'),
+    ("guilsinglleft", '‹'),
+    ("guilsinglright", '›'),
+    ("fraction", '⁄'),
+    ("Euro", '€'),
+    ("trademark", '™'),
+    ("minus", '−'),
+    ("fi", 'ﬁ'),
+    ("fl", 'ﬂ'),
+    ("ff", 'ﬀ'),
+    ("ffi", 'ﬃ'),
+    ("ffl", 'ﬄ'),
+];
+
+// Glyph width used for the standard 14 fonts, which may come without widths
+const DEFAULT_WIDTH: f32 = 500.0;
+const MONOSPACE_WIDTH: f32 = 600.0;
+
+fn char_from_code(code: u32) -> Option<char> {
+    char::from_u32(code).filter(|c| *c != '\0')
+}
+
+fn glyph_name_to_string(name: &str) -> Option<String> {
+    // Suffixes such as ".sc" or ".alt" name variants of the same character
+    let base = name.split('.').next().unwrap_or(name);
+    if base.contains('_') {
+        return base.split('_').map(glyph_name_to_string).collect();
+    }
+    if let Some((_, c)) = GLYPH_NAMES.iter().find(|(glyph, _)| *glyph == base) {
+        return Some(c.to_string());
+    }
+    if base.len() == 1 && base.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Some(base.to_string());
+    }
+    if let Some(hex) = base.strip_prefix("uni") {
+        if hex.is_ascii() && hex.len() % 4 == 0 && !hex.is_empty() {
+            let units: Option<Vec<u16>> = (0..hex.len())
+                .step_by(4)
+                .map(|i| u16::from_str_radix(&hex[i..i + 4], 16).ok())
+                .collect();
+            return units.and_then(|units| String::from_utf16(&units).ok());
+        }
+    }
+    if let Some(hex) = base.strip_prefix('u') {
+        if (4..=6).contains(&hex.len()) {
+            return u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char_from_code)
+                .map(String::from);
+        }
+    }
+    None
+}
+
+fn base_encoding(name: &str) -> Box<[Option<char>; 256]> {
+    let mut table = Box::new([None; 256]);
+    for (entry, code) in table[0x20..0x7F].iter_mut().zip(0x20..) {
+        *entry = char_from_code(code);
+    }
+    match name {
+        "WinAnsiEncoding" => {
+            for (i, c) in WIN_ANSI_C1.chars().enumerate() {
+                table[0x80 + i] = Some(c).filter(|c| *c != '\0');
+            }
+            for (entry, code) in table[0xA0..].iter_mut().zip(0xA0..) {
+                *entry = char_from_code(code);
+            }
+        }
+        "MacRomanEncoding" => {
+            for (i, c) in MAC_ROMAN_HIGH.chars().enumerate() {
+                table[0x80 + i] = Some(c).filter(|c| *c != '\0');
+            }
+        }
+        _ => {
+            table[0x27] = Some('’');
+            table[0x60] = Some('‘');
+            for (i, c) in STANDARD_HIGH.chars().enumerate() {
+                table[0xA0 + i] = Some(c).filter(|c| *c != '\0');
+            }
+        }
+    }
+    table
+}
+
+fn utf16_be(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |code, byte| (code << 8) | *byte as u32)
+}
+
+enum CMapToken {
+    Hex(Vec<u8>),
+    Number(i64),
+    Keyword(String),
+    ArrayStart,
+    ArrayEnd,
+}
+
+fn cmap_tokens(data: &[u8]) -> Vec<CMapToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'%' => {
+                while i < data.len() && data[i] != b'\n' && data[i] != b'\r' {
+                    i += 1;
+                }
+            }
+            b'<' if data.get(i + 1) == Some(&b'<') => i += 2,
+            b'>' if data.get(i + 1) == Some(&b'>') => i += 2,
+            b'<' => {
+                let end = data[i..]
+                    .iter()
+                    .position(|b| *b == b'>')
+                    .map_or(data.len(), |p| i + p);
+                let digits: Vec<u8> = data[i + 1..end]
+                    .iter()
+                    .filter(|b| b.is_ascii_hexdigit())
+                    .copied()
+                    .collect();
+                let bytes = digits
+                    .chunks(2)
+                    .map(|pair| {
+                        let text = std::str::from_utf8(pair).unwrap_or("0");
+                        // An odd final digit is followed by an implied 0
+                        u8::from_str_radix(&format!("{:0<2}", text), 16).unwrap_or(0)
+                    })
+                    .collect();
+                tokens.push(CMapToken::Hex(bytes));
+                i = end + 1;
+            }
+            b'(' => {
+                // Literal strings only appear in the CIDSystemInfo header
+                while i < data.len() && data[i] != b')' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'[' => {
+                tokens.push(CMapToken::ArrayStart);
+                i += 1;
+            }
+            b']' => {
+                tokens.push(CMapToken::ArrayEnd);
+                i += 1;
+            }
+            byte if byte.is_ascii_whitespace() => i += 1,
+            _ => {
+                let start = i;
+                i += 1;
+                while i < data.len()
+                    && !data[i].is_ascii_whitespace()
+                    && !b"[]<>()/%".contains(&data[i])
+                {
+                    i += 1;
+                }
+                let word = String::from_utf8_lossy(&data[start..i]).to_string();
+                match word.parse::<i64>() {
+                    Ok(number) => tokens.push(CMapToken::Number(number)),
+                    Err(_) => tokens.push(CMapToken::Keyword(word)),
+                }
+            }
+        }
+    }
+    tokens
+}
+
+// A CMap's code space and character mappings; used both for ToUnicode maps
+// and for the encoding CMaps of composite fonts
+#[derive(Default)]
+struct CMap {
+    code_space: Vec<(Vec<u8>, Vec<u8>)>,
+    chars: HashMap<u32, String>,
+    ranges: Vec<(u32, u32, RangeTarget)>,
+}
+
+enum RangeTarget {
+    // Destination of the first code, incremented for the codes after it
+    Start(Vec<u8>),
+    Each(Vec<String>),
+    Cid(u32),
+}
+
+impl CMap {
+    fn parse(data: &[u8]) -> CMap {
+        let tokens = cmap_tokens(data);
+        let mut cmap = CMap::default();
+        let mut i = 0;
+        let hex = |token: Option<&CMapToken>| match token {
+            Some(CMapToken::Hex(bytes)) => Some(bytes.clone()),
+            _ => None,
+        };
+        while i < tokens.len() {
+            let CMapToken::Keyword(keyword) = &tokens[i] else {
+                i += 1;
+                continue;
+            };
+            i += 1;
+            match keyword.as_str() {
+                "begincodespacerange" => {
+                    while let (Some(low), Some(high)) = (hex(tokens.get(i)), hex(tokens.get(i + 1)))
+                    {
+                        cmap.code_space.push((low, high));
+                        i += 2;
+                    }
+                }
+                "beginbfchar" => {
+                    while let (Some(code), Some(target)) =
+                        (hex(tokens.get(i)), hex(tokens.get(i + 1)))
+                    {
+                        cmap.chars.insert(code_value(&code), utf16_be(&target));
+                        i += 2;
+                    }
+                }
+                "begincidchar" => {
+                    while let (Some(code), Some(CMapToken::Number(cid))) =
+                        (hex(tokens.get(i)), tokens.get(i + 1))
+                    {
+                        cmap.ranges.push((
+                            code_value(&code),
+                            code_value(&code),
+                            RangeTarget::Cid(*cid as u32),
+                        ));
+                        i += 2;
+                    }
+                }
+                "beginbfrange" | "begincidrange" => {
+                    while let (Some(low), Some(high)) = (hex(tokens.get(i)), hex(tokens.get(i + 1)))
+                    {
+                        let (low, high) = (code_value(&low), code_value(&high));
+                        i += 2;
+                        match tokens.get(i) {
+                            Some(CMapToken::Hex(start)) => {
+                                cmap.ranges
+                                    .push((low, high, RangeTarget::Start(start.clone())));
+                                i += 1;
+                            }
+                            Some(CMapToken::Number(cid)) => {
+                                cmap.ranges.push((low, high, RangeTarget::Cid(*cid as u32)));
+                                i += 1;
+                            }
+                            Some(CMapToken::ArrayStart) => {
+                                i += 1;
+                                let mut targets = Vec::new();
+                                while let Some(target) = hex(tokens.get(i)) {
+                                    targets.push(utf16_be(&target));
+                                    i += 1;
+                                }
+                                if matches!(tokens.get(i), Some(CMapToken::ArrayEnd)) {
+                                    i += 1;
+                                }
+                                cmap.ranges.push((low, high, RangeTarget::Each(targets)));
+                            }
+                            _ => break,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        cmap
+    }
+
+    // Length of the code starting the bytes, from the code space ranges
+    fn code_length(&self, bytes: &[u8]) -> Option<usize> {
+        self.code_space.iter().find_map(|(low, high)| {
+            let length = low.len();
+            let code = bytes.get(..length)?;
+            let inside = code
+                .iter()
+                .zip(low.iter().zip(high))
+                .all(|(byte, (low, high))| low <= byte && byte <= high);
+            inside.then_some(length)
+        })
+    }
+
+    fn unicode(&self, code: u32) -> Option<String> {
+        if let Some(text) = self.chars.get(&code) {
+            return Some(text.clone());
+        }
+        self.ranges.iter().find_map(|(low, high, target)| {
+            if code < *low || code > *high {
+                return None;
+            }
+            let offset = code - low;
+            match target {
+                RangeTarget::Start(start) => {
+                    // Producers increment the whole value, not just the last
+                    // byte as the specification has it, so add to the last
+                    // UTF-16 unit
+                    let mut units: Vec<u16> = start
+                        .chunks(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                        .collect();
+                    let last = units.last_mut()?;
+                    *last = last.wrapping_add(offset as u16);
+                    Some(String::from_utf16_lossy(&units))
+                }
+                RangeTarget::Each(targets) => targets.get(offset as usize).cloned(),
+                RangeTarget::Cid(_) => None,
+            }
+        })
+    }
+
+    fn cid(&self, code: u32) -> Option<u32> {
+        self.ranges
+            .iter()
+            .find_map(|(low, high, target)| match target {
+                RangeTarget::Cid(first) if *low <= code && code <= *high => {
+                    Some(first + code - low)
+                }
+                _ => None,
+            })
+    }
+}
+
+fn stream_data(doc: &Document, object: &Object) -> Option<Vec<u8>> {
+    let (_, object) = doc.dereference(object).ok()?;
+    let stream = object.as_stream().ok()?;
+    stream
+        .decompressed_content()
+        .ok()
+        .or_else(|| Some(stream.content.clone()))
+}
+
+fn get<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.get(key)
+        .ok()
+        .and_then(|object| doc.dereference(object).ok())
+        .map(|(_, object)| object)
+}
+
+fn number(object: &Object) -> Option<f32> {
+    object.as_float().ok()
+}
+
+// How the codes in a composite font's strings become characters when it
+// has no ToUnicode map
+enum CompositeEncoding {
+    // Identity-H/V and embedded CMaps: codes select glyphs, not characters
+    Cids(Option<CMap>),
+    // The Unicode CMaps such as UniGB-UCS2-H: codes are UTF-16
+    Unicode,
+}
+
+enum Kind {
+    Simple {
+        // Text of each code; a Differences entry may name a ligature such
+        // as f_i, which stands for more than one character
+        encoding: Vec<Option<String>>,
+        widths: HashMap<u32, f32>,
+        default_width: f32,
+    },
+    Composite {
+        encoding: CompositeEncoding,
+        widths: HashMap<u32, f32>,
+        default_width: f32,
+    },
+}
+
+/// A decoded glyph: its text and its advance in thousandths of the font
+/// size.
+pub struct Glyph {
+    pub text: String,
+    pub width: f32,
+    /// Word spacing applies to the single-byte code 32 only.
+    pub is_space: bool,
+}
+
+/// A font resource, able to turn the bytes of shown strings into text.
+pub struct Font {
+    pub name: String,
+    to_unicode: Option<CMap>,
+    kind: Kind,
+    warned: std::cell::Cell<bool>,
+}
+
+impl Font {
+    pub fn load(doc: &Document, dict: &Dictionary) -> Font {
+        let base_font = get(doc, dict, b"BaseFont")
+            .and_then(|name| name.as_name_str().ok())
+            .unwrap_or("Unknown");
+        // Subset fonts carry a six-letter tag such as "ABCDEF+"
+        let name = match base_font.split_once('+') {
+            Some((tag, rest)) if tag.len() == 6 => rest.to_string(),
+            _ => base_font.to_string(),
+        };
+        let to_unicode = get(doc, dict, b"ToUnicode")
+            .and_then(|object| stream_data(doc, object))
+            .map(|data| CMap::parse(&data));
+        let subtype = get(doc, dict, b"Subtype").and_then(|name| name.as_name_str().ok());
+
+        let kind = if subtype == Some("Type0") {
+            Font::composite(doc, dict)
+        } else {
+            Font::simple(doc, dict, &name)
+        };
+        Font {
+            name,
+            to_unicode,
+            kind,
+            warned: std::cell::Cell::new(false),
+        }
+    }
+
+    fn simple(doc: &Document, dict: &Dictionary, name: &str) -> Kind {
+        let descriptor = get(doc, dict, b"FontDescriptor").and_then(|d| d.as_dict().ok());
+        let symbolic = descriptor
+            .and_then(|d| get(doc, d, b"Flags"))
+            .and_then(|flags| flags.as_i64().ok())
+            .is_some_and(|flags| flags & 4 != 0 && flags & 32 == 0);
+
+        let mut encoding = base_encoding("StandardEncoding");
+        let mut differences = None;
+        match get(doc, dict, b"Encoding") {
+            Some(Object::Name(base)) => {
+                encoding = base_encoding(&String::from_utf8_lossy(base));
+            }
+            Some(Object::Dictionary(encoding_dict)) => {
+                if let Some(Object::Name(base)) = get(doc, encoding_dict, b"BaseEncoding") {
+                    encoding = base_encoding(&String::from_utf8_lossy(base));
+                }
+                differences =
+                    get(doc, encoding_dict, b"Differences").and_then(|d| d.as_array().ok());
+            }
+            _ if symbolic => {
+                // A symbolic font's built-in encoding is unknown here; Latin-1
+                // is what producers of such fonts nearly always mean
+                for (entry, code) in encoding[0x20..].iter_mut().zip(0x20..) {
+                    *entry = char_from_code(code);
+                }
+            }
+            _ => {}
+        }
+        let mut encoding: Vec<Option<String>> =
+            encoding.iter().map(|c| c.map(String::from)).collect();
+        if let Some(differences) = differences {
+            let mut code = 0usize;
+            for item in differences {
+                match item {
+                    Object::Integer(start) => code = *start as usize,
+                    Object::Name(glyph) => {
+                        if code < 256 {
+                            if let Some(text) =
+                                glyph_name_to_string(&String::from_utf8_lossy(glyph))
+                            {
+                                encoding[code] = Some(text);
+                            }
+                        }
+                        code += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut widths = HashMap::new();
+        let first_char = get(doc, dict, b"FirstChar")
+            .and_then(|c| c.as_i64().ok())
+            .unwrap_or(0);
+        if let Some(array) = get(doc, dict, b"Widths").and_then(|w| w.as_array().ok()) {
+            for (i, width) in array.iter().enumerate() {
+                let width = doc.dereference(width).ok().and_then(|(_, w)| number(w));
+                if let Some(width) = width {
+                    widths.insert(first_char as u32 + i as u32, width);
+                }
+            }
+        }
+        let default_width = descriptor
+            .and_then(|d| get(doc, d, b"MissingWidth"))
+            .and_then(number)
+            .filter(|_| !widths.is_empty())
+            .unwrap_or(if name.starts_with("Courier") {
+                MONOSPACE_WIDTH
+            } else {
+                DEFAULT_WIDTH
+            });
+        Kind::Simple {
+            encoding,
+            widths,
+            default_width,
+        }
+    }
+
+    fn composite(doc: &Document, dict: &Dictionary) -> Kind {
+        let encoding = match get(doc, dict, b"Encoding") {
+            Some(Object::Name(name)) => {
+                let name = String::from_utf8_lossy(name);
+                if name.starts_with("Uni") && (name.contains("UCS2") || name.contains("UTF16")) {
+                    CompositeEncoding::Unicode
+                } else {
+                    CompositeEncoding::Cids(None)
+                }
+            }
+            Some(object @ Object::Stream(_)) => {
+                CompositeEncoding::Cids(stream_data(doc, object).map(|data| CMap::parse(&data)))
+            }
+            _ => CompositeEncoding::Cids(None),
+        };
+
+        let descendant = get(doc, dict, b"DescendantFonts")
+            .and_then(|fonts| fonts.as_array().ok())
+            .and_then(|fonts| fonts.first())
+            .and_then(|font| doc.dereference(font).ok())
+            .and_then(|(_, font)| font.as_dict().ok());
+        let mut widths = HashMap::new();
+        let mut default_width = 1000.0;
+        if let Some(descendant) = descendant {
+            if let Some(dw) = get(doc, descendant, b"DW").and_then(number) {
+                default_width = dw;
+            }
+            // W holds "first [w1 w2 ...]" and "first last w" entries
+            if let Some(array) = get(doc, descendant, b"W").and_then(|w| w.as_array().ok()) {
+                let array: Vec<&Object> = array
+                    .iter()
+                    .filter_map(|item| doc.dereference(item).ok().map(|(_, item)| item))
+                    .collect();
+                let mut i = 0;
+                while i + 1 < array.len() {
+                    let Ok(first) = array[i].as_i64() else {
+                        break;
+                    };
+                    if let Ok(list) = array[i + 1].as_array() {
+                        for (offset, width) in list.iter().enumerate() {
+                            if let Some(width) = number(width) {
+                                widths.insert(first as u32 + offset as u32, width);
+                            }
+                        }
+                        i += 2;
+                    } else if let (Ok(last), Some(width)) = (
+                        array[i + 1].as_i64(),
+                        array.get(i + 2).and_then(|w| number(w)),
+                    ) {
+                        for cid in first..=last.min(first + 0xFFFF) {
+                            widths.insert(cid as u32, width);
+                        }
+                        i += 3;
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+        Kind::Composite {
+            encoding,
+            widths,
+            default_width,
+        }
+    }
+
+    /// Splits a shown string into glyphs. Simple fonts always use one byte
+    /// per code, whatever their Encoding claims; composite fonts take code
+    /// lengths from their encoding CMap, then their ToUnicode map, and
+    /// default to two bytes.
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Glyph> {
+        let mut glyphs = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            let length = match &self.kind {
+                Kind::Simple { .. } => 1,
+                Kind::Composite { encoding, .. } => {
+                    let embedded = match encoding {
+                        CompositeEncoding::Cids(Some(cmap)) => cmap.code_length(rest),
+                        _ => None,
+                    };
+                    embedded
+                        .or_else(|| {
+                            self.to_unicode
+                                .as_ref()
+                                .and_then(|cmap| cmap.code_length(rest))
+                        })
+                        .unwrap_or(2)
+                        .min(rest.len())
+                }
+            };
+            let code = code_value(&rest[..length]);
+            i += length;
+
+            let mapped = self.to_unicode.as_ref().and_then(|cmap| cmap.unicode(code));
+            let glyph = match &self.kind {
+                Kind::Simple {
+                    encoding,
+                    widths,
+                    default_width,
+                } => Glyph {
+                    text: mapped
+                        .or_else(|| encoding[code as usize].clone())
+                        .unwrap_or_default(),
+                    width: widths.get(&code).copied().unwrap_or(*default_width),
+                    is_space: code == 32,
+                },
+                Kind::Composite {
+                    encoding,
+                    widths,
+                    default_width,
+                } => {
+                    let cid = match encoding {
+                        CompositeEncoding::Cids(Some(cmap)) => cmap.cid(code).unwrap_or(code),
+                        _ => code,
+                    };
+                    let text = mapped.or_else(|| match encoding {
+                        CompositeEncoding::Unicode => Some(utf16_be(&rest[..length])),
+                        CompositeEncoding::Cids(_) => None,
+                    });
+                    Glyph {
+                        text: text.unwrap_or_else(|| {
+                            if !self.warned.replace(true) {
+                                warn!(
+                                    "Font '{}' maps glyphs without a ToUnicode map; its text can't be recovered",
+                                    self.name
+                                );
+                            }
+                            '\u{FFFD}'.to_string()
+                        }),
+                        width: widths.get(&cid).copied().unwrap_or(*default_width),
+                        is_space: length == 1 && code == 32,
+                    }
+                }
+            };
+            glyphs.push(glyph);
+        }
+        glyphs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    fn text(font: &Font, bytes: &[u8]) -> Vec<String> {
+        font.decode(bytes)
+            .into_iter()
+            .map(|glyph| glyph.text)
+            .collect()
+    }
+
+    fn to_unicode(doc: &mut Document, cmap: &str) -> Object {
+        Object::Reference(doc.add_object(Stream::new(dictionary! {}, cmap.as_bytes().to_vec())))
+    }
+
+    fn composite(doc: &mut Document, cmap: &str) -> Font {
+        let to_unicode = to_unicode(doc, cmap);
+        let dict = dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "ABCDEF+NotoSans",
+            "Encoding" => "Identity-H",
+            "ToUnicode" => to_unicode,
+        };
+        Font::load(doc, &dict)
+    }
+
+    #[test]
+    fn maps_bfchar_entries() {
+        let mut doc = Document::with_version("1.5");
+        let to_unicode = to_unicode(
+            &mut doc,
+            "1 begincodespacerange <00> <FF> endcodespacerange\n\
+             2 beginbfchar <01> <0041> <02> <00660069> endbfchar",
+        );
+        let dict = dictionary! {
+            "Subtype" => "TrueType",
+            "BaseFont" => "Arial",
+            "ToUnicode" => to_unicode,
+        };
+        let font = Font::load(&doc, &dict);
+        assert_eq!(text(&font, &[1, 2, b'z']), ["A", "fi", "z"]);
+    }
+
+    #[test]
+    fn maps_bfrange_from_a_start_value() {
+        let mut doc = Document::with_version("1.5");
+        let font = composite(
+            &mut doc,
+            "1 begincodespacerange <0000> <FFFF> endcodespacerange\n\
+             1 beginbfrange <0003> <0005> <0061> endbfrange",
+        );
+        assert_eq!(font.name, "NotoSans");
+        assert_eq!(text(&font, &[0, 3, 0, 4, 0, 5]), ["a", "b", "c"]);
+        // Outside every range: no text to recover
+        assert_eq!(text(&font, &[0, 9]), ["\u{FFFD}"]);
+    }
+
+    #[test]
+    fn maps_bfrange_from_an_array() {
+        let mut doc = Document::with_version("1.5");
+        let font = composite(
+            &mut doc,
+            "1 begincodespacerange <0000> <FFFF> endcodespacerange\n\
+             1 beginbfrange <0010> <0011> [<0058> <D835DC00>] endbfrange",
+        );
+        assert_eq!(text(&font, &[0, 0x10, 0, 0x11]), ["X", "𝐀"]);
+    }
+
+    #[test]
+    fn reads_glyph_names_from_differences() {
+        let doc = Document::with_version("1.5");
+        let dict = dictionary! {
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => dictionary! {
+                "BaseEncoding" => "WinAnsiEncoding",
+                "Differences" => vec![
+                    65.into(),
+                    "uni00E9".into(),
+                    "f_i".into(),
+                    "Euro".into(),
+                    "a.sc".into(),
+                    "uniD83DDE00".into(),
+                    "u1F600".into(),
+                    "notaglyph".into(),
+                ],
+            },
+        };
+        let font = Font::load(&doc, &dict);
+        assert_eq!(
+            text(&font, b"ABCDEFGH"),
+            ["é", "fi", "€", "a", "😀", "😀", "G", "H"]
+        );
+    }
+
+    #[test]
+    fn rejects_glyph_names_with_non_ascii_hex() {
+        assert_eq!(glyph_name_to_string("uni000é000"), None);
+        assert_eq!(glyph_name_to_string("uni00E"), None);
+        assert_eq!(glyph_name_to_string("uniZZZZ"), None);
+        assert_eq!(glyph_name_to_string("uniFB01"), Some("ﬁ".to_string()));
+    }
+
+    #[test]
+    fn decodes_win_ansi_high_codes() {
+        let doc = Document::with_version("1.5");
+        let dict = dictionary! {
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        };
+        let font = Font::load(&doc, &dict);
+        assert_eq!(
+            text(&font, &[0x80, 0x93, 0x94, 0x96, 0x81, 0xE9, 0xA0]),
+            ["€", "“", "”", "–", "", "é", "\u{a0}"]
+        );
+        let glyphs = font.decode(b" ");
+        assert!(glyphs[0].is_space);
+        assert_eq!(glyphs[0].width, DEFAULT_WIDTH);
+    }
+}
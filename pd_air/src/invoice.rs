@@ -0,0 +1,59 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::fmt;
+
+/// An invoice as read by an extraction template. Header fields the
+/// template has no rule for, or whose rule found nothing, are `None`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Invoice {
+    /// Name of the template that read the invoice.
+    pub template: String,
+    pub supplier: Option<String>,
+    pub invoice_number: Option<String>,
+    pub client_name: Option<String>,
+    pub invoice_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub currency: Option<String>,
+    pub subtotal: Option<f64>,
+    pub tax: Option<f64>,
+    /// The amount taken off, positive however the invoice prints it.
+    pub discount: Option<f64>,
+    pub total: Option<f64>,
+    pub items: Vec<LineItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineItem {
+    pub no: u32,
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    pub total: f64,
+}
+
+fn or_unknown<T: fmt::Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "?".to_string(), |value| value.to_string())
+}
+
+impl fmt::Display for Invoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invoice {} from {} to {} dated {}, due {}: {} items, subtotal {}, tax {}, discount {}, total {} {} (template '{}')",
+            or_unknown(&self.invoice_number),
+            or_unknown(&self.supplier),
+            or_unknown(&self.client_name),
+            or_unknown(&self.invoice_date),
+            or_unknown(&self.due_date),
+            self.items.len(),
+            or_unknown(&self.subtotal),
+            or_unknown(&self.tax),
+            or_unknown(&self.discount),
+            or_unknown(&self.total),
+            self.currency.as_deref().unwrap_or(""),
+            self.template
+        )
+    }
+}
@@ -0,0 +1,200 @@
+use crate::extract::TextRun;
+
+// Runs whose baselines are closer than this share of the smaller font size
+// sit on one line; table cells are often a fraction of a point apart
+const BASELINE_TOLERANCE: f32 = 0.3;
+
+// Gaps wider than SPACE_GAP font sizes separate words, and gaps wider than
+// SEGMENT_GAP separate segments such as table cells
+const SPACE_GAP: f32 = 0.15;
+const SEGMENT_GAP: f32 = 1.0;
+
+// A gutter between text columns is at least this many font sizes wide
+const COLUMN_GUTTER: f32 = 1.5;
+
+/// How runs are laid out into lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LayoutOptions {
+    /// Read pages with a vertical gutter one column after another instead
+    /// of straight across. Off by default, since table columns look the
+    /// same as text columns.
+    pub detect_columns: bool,
+}
+
+/// Text on a line without a wide gap in it, such as one table cell.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub x: f32,
+    pub width: f32,
+    pub text: String,
+}
+
+impl Segment {
+    pub fn end(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn center(&self) -> f32 {
+        self.x + self.width / 2.0
+    }
+}
+
+/// Runs sharing a baseline, in reading order.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub page: u32,
+    pub y: f32,
+    pub font_size: f32,
+    pub segments: Vec<Segment>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn median_font_size(runs: &[&TextRun]) -> f32 {
+    let mut sizes: Vec<f32> = runs.iter().map(|run| run.font_size).collect();
+    sizes.sort_by(f32::total_cmp);
+    sizes.get(sizes.len() / 2).copied().unwrap_or(12.0).max(1.0)
+}
+
+// Horizontal ranges of the text columns on a page. A gutter is a vertical
+// strip no run crosses, with text beside it over at least half the height
+// of the page's text on both sides.
+fn columns(runs: &[&TextRun]) -> Vec<(f32, f32)> {
+    let mut spans: Vec<(f32, f32)> = runs.iter().map(|run| (run.x, run.x + run.width)).collect();
+    spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let Some(&(first, _)) = spans.first() else {
+        return Vec::new();
+    };
+
+    let min_gutter = median_font_size(runs) * COLUMN_GUTTER;
+    let height = |start: f32, end: f32| {
+        let ys = runs
+            .iter()
+            .filter(|run| run.x >= start && run.x + run.width <= end)
+            .map(|run| run.y);
+        let (low, high) = ys.fold((f32::MAX, f32::MIN), |(low, high), y| {
+            (low.min(y), high.max(y))
+        });
+        (high - low).max(0.0)
+    };
+    let page_height = height(f32::MIN, f32::MAX);
+
+    let mut columns = Vec::new();
+    let (mut start, mut end) = (first, first);
+    for (span_start, span_end) in spans {
+        if span_start - end >= min_gutter {
+            columns.push((start, end));
+            start = span_start;
+        }
+        end = end.max(span_end);
+    }
+    columns.push((start, end));
+
+    let tall = columns
+        .iter()
+        .all(|(start, end)| height(*start, *end) >= page_height / 2.0);
+    if tall {
+        columns
+    } else {
+        vec![(f32::MIN, f32::MAX)]
+    }
+}
+
+fn lines_in(runs: &mut [&TextRun], page: u32, lines: &mut Vec<Line>) {
+    runs.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    let mut groups: Vec<Vec<&TextRun>> = Vec::new();
+    for run in runs.iter() {
+        match groups.last_mut() {
+            Some(group)
+                if (group[0].y - run.y).abs()
+                    <= BASELINE_TOLERANCE * group[0].font_size.min(run.font_size) =>
+            {
+                group.push(run)
+            }
+            _ => groups.push(vec![run]),
+        }
+    }
+
+    for mut group in groups {
+        group.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let font_size = group.iter().map(|run| run.font_size).fold(0.0, f32::max);
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut previous: Option<&TextRun> = None;
+        for run in group.iter() {
+            // Some producers draw text twice, slightly offset, to embolden it
+            if let Some(last) = previous {
+                if last.text == run.text && (last.x - run.x).abs() < last.font_size * SPACE_GAP {
+                    continue;
+                }
+            }
+            let em = run.font_size.max(1.0);
+            match segments.last_mut() {
+                Some(segment) if run.x - segment.end() <= em * SEGMENT_GAP => {
+                    let gap = run.x - segment.end();
+                    if gap > em * SPACE_GAP
+                        && !segment.text.ends_with(' ')
+                        && !run.text.starts_with(' ')
+                    {
+                        segment.text.push(' ');
+                    }
+                    segment.text.push_str(&run.text);
+                    segment.width = segment.width.max(run.x + run.width - segment.x);
+                }
+                _ => segments.push(Segment {
+                    x: run.x,
+                    width: run.width,
+                    text: run.text.clone(),
+                }),
+            }
+            previous = Some(run);
+        }
+        for segment in &mut segments {
+            segment.text = segment.text.trim().to_string();
+        }
+        segments.retain(|segment| !segment.text.is_empty());
+        if !segments.is_empty() {
+            lines.push(Line {
+                page,
+                y: group[0].y,
+                font_size,
+                segments,
+            });
+        }
+    }
+}
+
+/// Groups runs into lines by baseline, top to bottom and left to right on
+/// each page, splitting each line into segments at wide gaps.
+pub fn lines(runs: &[TextRun], options: &LayoutOptions) -> Vec<Line> {
+    let mut pages: Vec<u32> = runs.iter().map(|run| run.page).collect();
+    pages.sort_unstable();
+    pages.dedup();
+
+    let mut lines = Vec::new();
+    for page in pages {
+        let page_runs: Vec<&TextRun> = runs.iter().filter(|run| run.page == page).collect();
+        let columns = if options.detect_columns {
+            columns(&page_runs)
+        } else {
+            vec![(f32::MIN, f32::MAX)]
+        };
+        for (start, end) in columns {
+            let mut column_runs: Vec<&TextRun> = page_runs
+                .iter()
+                .filter(|run| run.x >= start && run.x <= end)
+                .copied()
+                .collect();
+            lines_in(&mut column_runs, page, &mut lines);
+        }
+    }
+    lines
+}
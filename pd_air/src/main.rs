@@ -1,13 +1,24 @@
-use chrono::NaiveDate; // Used for date string validation in Phase 1
+use invoice_export::InvoiceItem;
 use log::{error, info, warn};
-use reqwest::header;
-use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::path::Path;
 use thiserror::Error;
 
-// lopdf related imports
-use lopdf::{Dictionary, Document, Object, Stream as LopdfStream};
+mod extract;
+mod font;
+mod invoice;
+mod layout;
+#[cfg(feature = "ocr")]
+mod ocr;
+mod review;
+mod template;
+mod validate;
+
+use invoice::Invoice;
+use layout::{LayoutOptions, Line};
+use review::ReviewEntry;
+use template::Template;
 
 #[derive(Error, Debug)]
 enum ExtractionError {
@@ -15,438 +26,94 @@ enum ExtractionError {
     LopdfError(#[from] lopdf::Error),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
-    #[error("Environment variable error: {0}")]
-    EnvError(String),
-    #[error("HTTP request error: {0}")]
-    RequestError(String),
     #[error("No invoice items found in PDF")]
     NoItemsFound,
     #[error("PDF file not found: {0}")]
     FileNotFound(String),
+    #[error("Template error: {0}")]
+    TemplateError(String),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Export error: {0}")]
+    SinkError(#[from] invoice_export::Error),
+    #[cfg(feature = "ocr")]
+    #[error("OCR error: {0}")]
+    OcrError(String),
     // #[error("Content decoding error: {0}")]
     // ContentDecodeError(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AirtableRecord {
-    fields: InvoiceItem,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct InvoiceItem {
-    #[serde(rename = "No")]
-    no: u32,
-    #[serde(rename = "Description")]
-    description: String,
-    #[serde(rename = "Quantity")]
-    quantity: u32,
-    #[serde(rename = "Price")]
-    price: f64,
-    #[serde(rename = "Total")]
-    total: f64,
-    #[serde(rename = "Client Name")]
-    client_name: String,
-    #[serde(rename = "Invoice Date")]
-    invoice_date: String,
-}
-
-#[derive(Debug, Serialize)]
-struct AirtablePayload {
-    records: Vec<AirtableRecord>,
-}
-
-async fn upload_to_airtable(items: Vec<InvoiceItem>) -> Result<(), ExtractionError> {
-    let base_id =
-        env::var("AIRTABLE_BASE_ID").map_err(|e| ExtractionError::EnvError(e.to_string()))?;
-    let table_name = env::var("AIRTABLE_TABLE_NAME").unwrap_or_else(|_| "Invoices".to_string());
-    let api_key =
-        env::var("AIRTABLE_API_KEY").map_err(|e| ExtractionError::EnvError(e.to_string()))?;
-
-    let client = reqwest::Client::new();
-    let url = format!("https://api.airtable.com/v0/{}/{}", base_id, table_name);
-
-    for chunk in items.chunks(10) {
-        info!("Preparing to upload {} items to Airtable", chunk.len());
-        let records: Vec<AirtableRecord> = chunk
-            .iter()
-            .map(|item| AirtableRecord {
-                fields: item.clone(),
-            })
-            .collect();
-        let payload = AirtablePayload { records };
-        let response = client
-            .post(&url)
-            .json(&payload)
-            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
-            .send()
-            .await
-            .map_err(|e| ExtractionError::RequestError(e.to_string()))?;
-        let status = response.status();
-        if !status.is_success() {
-            let response_body = response
-                .text()
-                .await
-                .map_err(|e| ExtractionError::RequestError(e.to_string()))?;
-            error!("Airtable API error (status {}): {}", status, response_body);
-            return Err(ExtractionError::RequestError(format!(
-                "API returned status: {}, body: {}",
-                status, response_body
-            )));
-        }
-        info!("Successfully uploaded batch of {} items", chunk.len());
-    }
-    Ok(())
-}
-
-fn clean_and_parse_currency(s: &str, field_name: &str, line_number: usize) -> Result<f64, String> {
-    let cleaned = s.replace('$', "").replace(',', "").trim().to_string();
-    if cleaned.is_empty() {
-        return Err(format!(
-            "Empty string after cleaning for {} on line {}",
-            field_name, line_number
-        ));
-    }
-    cleaned.parse::<f64>().map_err(|e| {
-        format!(
-            "Failed to parse {} '{}' (cleaned: '{}') on line {}: {}",
-            field_name, s, cleaned, line_number, e
-        )
-    })
-}
-
-fn decode_text_bytes(bytes: &[u8], _font_info: Option<&lopdf::Dictionary>) -> String {
-    if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
-        if let Ok(s) = String::from_utf16(
-            &bytes[2..]
-                .chunks_exact(2)
-                .map(|c| u16::from_be_bytes([c[0], c[1]]))
-                .collect::<Vec<u16>>(),
-        ) {
-            return s;
-        }
-    }
-    if let Ok(s) = String::from_utf8(bytes.to_vec()) {
-        return s;
-    }
-    if bytes.len() % 2 == 0 && bytes.len() > 0 {
-        let utf16_words: Vec<u16> = bytes
-            .chunks_exact(2)
-            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
-            .collect();
-        if let Ok(s_utf16) = String::from_utf16(&utf16_words) {
-            if s_utf16.chars().any(|c| !c.is_control() && c != '\u{FFFD}') {
-                return s_utf16;
-            }
-        }
-    }
-    String::from_utf8_lossy(bytes).to_string()
+// Recognizes the text of scanned pages, for documents without a text layer
+#[cfg(feature = "ocr")]
+fn ocr_fallback(doc: &lopdf::Document) -> Result<Vec<extract::TextRun>, ExtractionError> {
+    let language = env::var("OCR_LANGUAGE").unwrap_or_else(|_| "eng".to_string());
+    info!(
+        "No text layer found; running OCR ({}) on the page images",
+        language
+    );
+    ocr::recognize(doc, &language)
 }
 
-fn extract_text_from_pdf_lopdf(path: &str) -> Result<String, ExtractionError> {
-    info!("Extracting text from PDF using lopdf: {}", path);
-    if !Path::new(path).exists() {
-        return Err(ExtractionError::FileNotFound(path.to_string()));
-    }
-
-    let doc = Document::load(path)?;
-    let mut full_text = String::new();
-
-    for page_id_tuple in doc.page_iter() {
-        let mut current_page_text = String::new();
-        match doc.get_page_content(page_id_tuple) {
-            Ok(content_data) => {
-                let stream = LopdfStream::new(Dictionary::new(), content_data);
-                match stream.decode_content() {
-                    Ok(decoded_content) => {
-                        let operations = decoded_content.operations;
-                        for operation in operations {
-                            match operation.operator.as_str() {
-                                "Tj" | "'" => {
-                                    if let Some(Object::String(text_bytes, _)) =
-                                        operation.operands.get(0)
-                                    {
-                                        current_page_text
-                                            .push_str(&decode_text_bytes(text_bytes, None));
-                                    }
-                                    if operation.operator == "'" {
-                                        current_page_text.push('\n');
-                                    }
-                                }
-                                "TJ" => {
-                                    if let Some(Object::Array(arr)) = operation.operands.get(0) {
-                                        for obj in arr {
-                                            if let Object::String(text_bytes, _) = obj {
-                                                current_page_text
-                                                    .push_str(&decode_text_bytes(text_bytes, None));
-                                            }
-                                        }
-                                    }
-                                }
-                                "\"" => {
-                                    if let Some(Object::String(text_bytes, _)) =
-                                        operation.operands.get(2)
-                                    {
-                                        current_page_text
-                                            .push_str(&decode_text_bytes(text_bytes, None));
-                                    }
-                                    current_page_text.push('\n');
-                                }
-                                "Td" | "TD" | "T*" => {
-                                    if !current_page_text.is_empty()
-                                        && !current_page_text.ends_with('\n')
-                                    {
-                                        current_page_text.push('\n');
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!(
-                            "Could not decode content stream for page {:?}: {:?}",
-                            page_id_tuple, e
-                        );
-                    }
-                }
-            }
-            Err(e) => {
-                warn!(
-                    "Could not get content for page {:?}: {:?}",
-                    page_id_tuple, e
-                );
-            }
-        }
-        full_text.push_str(&current_page_text);
-        if !current_page_text.is_empty() {
-            full_text.push_str("\n\n--- Page Break ---\n\n");
-        }
-    }
-
-    if full_text.is_empty() && doc.page_iter().count() > 0 {
-        warn!("lopdf extraction resulted in empty text for a document with pages.");
-    } else {
-        info!(
-            "lopdf extraction: First 500 chars: {}",
-            full_text.chars().take(500).collect::<String>()
-        );
-    }
-    Ok(full_text)
+#[cfg(not(feature = "ocr"))]
+fn ocr_fallback(_doc: &lopdf::Document) -> Result<Vec<extract::TextRun>, ExtractionError> {
+    warn!("No text layer found, and OCR is not available: build with `--features ocr` to read scanned invoices.");
+    Ok(Vec::new())
 }
 
-fn parse_invoice_items(text: &str) -> Result<Vec<InvoiceItem>, ExtractionError> {
-    info!("Starting full invoice parsing including client and date.");
-    let lines: Vec<&str> = text.lines().collect();
-
-    // --- Phase 1: Extract Client Name and Invoice Date ---
-    let mut client_name_str = String::new();
-    let mut invoice_date_str = String::new();
-    let mut looking_for_client_name_after_to = false;
-    let mut found_date_keyword_line = false; // Flag: true if "Date :" line is found
-
-    for (line_idx, line_content) in lines.iter().enumerate() {
-        let trimmed_line = line_content.trim();
-        let lower_line = trimmed_line.to_lowercase();
-
-        info!("[Phase 1 Debug] Line {}: '{}'", line_idx + 1, trimmed_line);
-
-        // Client Name Logic
-        if client_name_str.is_empty() {
-            if looking_for_client_name_after_to && !trimmed_line.is_empty() {
-                client_name_str = trimmed_line.to_string();
-                info!("Client Name extracted: {}", client_name_str);
-                looking_for_client_name_after_to = false;
-            }
-            if lower_line == "to" {
-                looking_for_client_name_after_to = true;
-            }
-        }
-
-        // Invoice Date Logic (for lopdf output: "Date :" then value on a subsequent line)
-        if invoice_date_str.is_empty() {
-            if found_date_keyword_line {
-                if !trimmed_line.is_empty() {
-                    // The line after "Date :" might be Invoice No or the Date itself.
-                    // Check if current line is a parseable date.
-                    if NaiveDate::parse_from_str(trimmed_line, "%d %B %Y").is_ok() {
-                        invoice_date_str = trimmed_line.to_string();
-                        info!("Invoice Date extracted: '{}'", invoice_date_str);
-                        found_date_keyword_line = false; // Date found, stop this specific search path
-                    } else {
-                        info!("Line '{}' after 'Date :' was not the date (e.g. might be Invoice No), still looking for date.", trimmed_line);
-                        // Keep found_date_keyword_line = true to check the *next* non-empty line.
-                        // This handles the case: Date : \n InvoiceNo \n ActualDate
-                    }
-                }
-                // If trimmed_line is empty, flag remains true to process next non-empty line.
-            } else if trimmed_line == "Date :" {
-                // Exact match for "Date :" on its own line
-                info!("Found 'Date :' keyword on line {}.", line_idx + 1);
-                found_date_keyword_line = true;
-            }
-            // Note: The "ValueDate :" pattern from previous PDF is removed as logs show "Date :" is on its own line now.
-        }
-    }
-
-    if client_name_str.is_empty() {
-        warn!("Client Name could not be extracted.");
+// Reads the invoice, along with the mean confidence in the text it was read
+// from
+fn read_invoice(
+    path: &str,
+    options: &LayoutOptions,
+    templates: &[Template],
+) -> Result<(Invoice, f32), ExtractionError> {
+    let (doc, mut runs) = extract::load_and_extract(path)?;
+    if runs.iter().all(|run| run.text.trim().is_empty()) {
+        runs = ocr_fallback(&doc)?;
     }
-    if invoice_date_str.is_empty() {
-        warn!("Invoice Date could not be extracted.");
-    }
-
-    // --- Phase 2: Parse Invoice Items ---
-    info!("Moving to parse invoice line items based on Price/Total on separate lines.");
-    let mut items: Vec<InvoiceItem> = Vec::new();
-
-    let header_pos_items = lines.iter().position(|line_content| {
-        let lower = line_content.to_lowercase().replace(" ", "");
-        lower == "total"
-    });
-    let item_start_index = match header_pos_items {
-        Some(pos) => {
-            info!(
-                "Found end-of-header line ('TOTAL') at position {}: '{}'",
-                pos, lines[pos]
-            );
-            pos + 1
-        }
-        None => {
-            warn!("Could not find 'TOTAL' as end-of-header line. Trying to find first numeric item number.");
-            // Fallback: find first line that is purely numeric and short (likely an item number)
-            lines
-                .iter()
-                .skip_while(|l| {
-                    l.trim().is_empty()
-                        || !l
-                            .trim()
-                            .chars()
-                            .next()
-                            .map_or(false, |c| c.is_numeric() || l.trim().len() > 3)
-                })
-                .position(|l| l.trim().chars().all(char::is_numeric) && l.trim().len() < 3)
-                .unwrap_or(0)
-        }
-    };
+    let lines = layout::lines(&runs, options);
+    let text: Vec<String> = lines.iter().map(Line::text).collect();
     info!(
-        "Item parsing will start from line index: {}",
-        item_start_index
+        "Laid out {} lines. First lines:\n{}",
+        lines.len(),
+        text.iter().take(20).cloned().collect::<Vec<_>>().join("\n")
     );
 
-    let mut current_item_no_str = String::new();
-    let mut current_description_str = String::new();
-    let mut current_quantity_str = String::new();
-    let mut current_price_str = String::new();
-    // current_total_str will be the line after price
-
-    let mut line_idx = item_start_index;
-    while line_idx < lines.len() {
-        let trimmed_line = lines[line_idx].trim();
-        info!("[Item Parse] Line {}: '{}'", line_idx + 1, trimmed_line);
-
-        if trimmed_line.is_empty() {
-            line_idx += 1;
-            continue;
-        }
-        // More robust stop condition based on typical summary section keywords
-        let lower_trimmed_line = trimmed_line.to_lowercase();
-        if lower_trimmed_line.starts_with("payment method")
-            || lower_trimmed_line.starts_with("sub total")
-            || lower_trimmed_line.starts_with("vat")
-            || lower_trimmed_line.starts_with("discount")
-            || lower_trimmed_line.starts_with("grand total")
-            || lower_trimmed_line.starts_with("term and conditions")
-        {
-            info!(
-                "Stopping item parsing at summary section: '{}'",
-                trimmed_line
-            );
-            break;
-        }
-
-        // State machine for item parts
-        if current_item_no_str.is_empty()
-            && trimmed_line.chars().all(char::is_numeric)
-            && trimmed_line.len() < 3
-        {
-            current_item_no_str = trimmed_line.to_string();
-            info!("  -> ItemNo: {}", current_item_no_str);
-        } else if !current_item_no_str.is_empty()
-            && current_description_str.is_empty()
-            && !trimmed_line.contains('$')
-            && !trimmed_line.chars().all(char::is_numeric)
-        {
-            current_description_str = trimmed_line.to_string();
-            info!("  -> Description: {}", current_description_str);
-        } else if !current_description_str.is_empty()
-            && current_quantity_str.is_empty()
-            && trimmed_line.chars().all(char::is_numeric)
-        {
-            current_quantity_str = trimmed_line.to_string();
-            info!("  -> Quantity: {}", current_quantity_str);
-        } else if !current_quantity_str.is_empty()
-            && current_price_str.is_empty()
-            && trimmed_line.contains('$')
-        {
-            current_price_str = trimmed_line.to_string();
-            info!("  -> Price: {}", current_price_str);
-        } else if !current_price_str.is_empty() && trimmed_line.contains('$') {
-            let current_total_str = trimmed_line.to_string();
-            info!("  -> Total: {}", current_total_str);
-
-            let quantity_val: u32 = current_quantity_str.parse().unwrap_or_else(|e| {
-                warn!(
-                    "Failed to parse quantity '{}': {}. Defaulting to 1.",
-                    current_quantity_str, e
-                );
-                1
-            });
-
-            let price_val = clean_and_parse_currency(&current_price_str, "Price", line_idx)
-                .unwrap_or_else(|e| {
-                    warn!("Error parsing price '{}': {}", current_price_str, e);
-                    0.0
-                });
-            let total_val = clean_and_parse_currency(&current_total_str, "Total", line_idx + 1)
-                .unwrap_or_else(|e| {
-                    warn!("Error parsing total '{}': {}", current_total_str, e);
-                    price_val
-                });
+    let producers: Vec<String> = ["Producer", "Creator"]
+        .iter()
+        .filter_map(|key| extract::document_info(&doc, key))
+        .collect();
+    let generic = Template::generic();
+    let template = template::select(templates, &text.join("\n"), &producers).unwrap_or(&generic);
+    info!("Using template '{}'", template.name);
+    let text_confidence = if runs.is_empty() {
+        0.0
+    } else {
+        runs.iter().map(|run| run.confidence).sum::<f32>() / runs.len() as f32
+    };
+    Ok((template.extract(&lines), text_confidence))
+}
 
-            items.push(InvoiceItem {
-                no: current_item_no_str
-                    .parse()
-                    .unwrap_or((items.len() + 1) as u32),
-                description: current_description_str.clone(),
-                quantity: quantity_val,
-                price: price_val,
-                total: total_val,
-                client_name: client_name_str.clone(),
-                invoice_date: invoice_date_str.clone(),
-            });
-            info!("  SUCCESSFULLY PARSED ITEM: {:?}", items.last().unwrap());
-
-            current_item_no_str.clear();
-            current_description_str.clear();
-            current_quantity_str.clear();
-            current_price_str.clear();
-        } else {
-            if !current_item_no_str.is_empty() {
-                warn!("  -> Unexpected line '{}' while parsing item starting with No '{}'. Resetting current item state.", trimmed_line, current_item_no_str);
-                current_item_no_str.clear();
-                current_description_str.clear();
-                current_quantity_str.clear();
-                current_price_str.clear();
-            } else if !trimmed_line.starts_with("--- Page Break ---") {
-                // Don't warn for page breaks
-                info!(
-                    "  -> Skipping line '{}' as it doesn't fit expected item structure start.",
-                    trimmed_line
-                );
-            }
-        }
-        line_idx += 1;
-    }
+fn parse_invoice_items(invoice: &Invoice) -> Result<Vec<InvoiceItem>, ExtractionError> {
+    let client_name = invoice.client_name.clone().unwrap_or_default();
+    let invoice_date = invoice
+        .invoice_date
+        .map(|date| date.format("%-d %B %Y").to_string())
+        .unwrap_or_default();
+    let items: Vec<InvoiceItem> = invoice
+        .items
+        .iter()
+        .map(|item| InvoiceItem {
+            no: item.no,
+            description: item.description.clone(),
+            // Validation sent fractional and negative quantities to review
+            quantity: item.quantity as u32,
+            price: item.unit_price,
+            total: item.total,
+            client_name: client_name.clone(),
+            invoice_date: invoice_date.clone(),
+        })
+        .collect();
 
     if items.is_empty() {
         error!("No invoice items found after parsing all relevant lines.");
@@ -476,15 +143,64 @@ async fn main() -> Result<(), ExtractionError> {
     info!("Starting PDF to Airtable processor");
     info!("Loading PDF file: {}", pdf_path);
 
-    let text = extract_text_from_pdf_lopdf(&pdf_path)?;
-
-    let items = parse_invoice_items(&text)?;
-
-    if !items.is_empty() {
-        upload_to_airtable(items).await?;
-    } else {
-        info!("No items were parsed from the PDF to upload.");
+    // Column detection suits multi-column layouts but splits tables
+    let detect_columns = env::var("DETECT_COLUMNS")
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let template_dir = env::var("TEMPLATE_DIR").unwrap_or_else(|_| "templates".to_string());
+    let templates = template::load_templates(Path::new(&template_dir))?;
+
+    let (invoice, text_confidence) =
+        read_invoice(&pdf_path, &LayoutOptions { detect_columns }, &templates)?;
+    info!("Extracted {}", invoice);
+
+    // Invoices that fail, including ones with no items, are queued for
+    // review rather than rejected
+    let report = validate::validate(&invoice, text_confidence);
+    for check in &report.checks {
+        if check.passed {
+            info!("Check passed: {}", check.name);
+        } else {
+            warn!(
+                "Check failed: {} (expected {:.2}, found {:.2})",
+                check.name, check.expected, check.found
+            );
+        }
     }
+    let threshold = env::var("REVIEW_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(validate::DEFAULT_THRESHOLD);
+    let reasons = report.review_reasons(threshold);
+    if !reasons.is_empty() {
+        let queue = env::var("REVIEW_QUEUE").unwrap_or_else(|_| "review_queue.json".to_string());
+        let entry = ReviewEntry {
+            pdf: &pdf_path,
+            queued_at: chrono::Local::now().to_rfc3339(),
+            reasons,
+            invoice: &invoice,
+            report: &report,
+        };
+        let queued = review::enqueue(Path::new(&queue), &entry)?;
+        warn!(
+            "Invoice needs review and was not uploaded: {}",
+            entry.reasons.join("; ")
+        );
+        info!("Added to review queue {} ({} entries)", queue, queued);
+        return Ok(());
+    }
+
+    let items = parse_invoice_items(&invoice)?;
+    // Sinks are built only now, so invoices sent to review need no
+    // credentials
+    let mut sinks = invoice_export::from_env()?;
+    let sha256 = format!("{:x}", Sha256::digest(std::fs::read(&pdf_path)?));
+    let export = invoice_export::Export {
+        source: &pdf_path,
+        sha256: &sha256,
+        items: &items,
+    };
+    invoice_export::export_all(&mut sinks, &export, &mut Vec::new()).await?;
 
     info!("Processing completed successfully");
     Ok(())
@@ -0,0 +1,569 @@
+use crate::extract::{self, ImagePlacement, Matrix, TextRun};
+use crate::ExtractionError;
+use fax::decoder::{self as fax_decoder, pels};
+use fax::Color;
+use image::imageops::{self, FilterType};
+use image::{GrayImage, ImageFormat, Luma};
+use log::{info, warn};
+use lopdf::{Dictionary, Document, Object, Stream};
+use std::collections::BTreeMap;
+use tesseract::Tesseract;
+
+// Tesseract reads best at around this resolution, so coarser scans are
+// scaled up before recognition, by at most MAX_SCALE
+const TARGET_DPI: f32 = 300.0;
+const MAX_SCALE: f32 = 4.0;
+
+// Skew angles tried when straightening a scan, in degrees either way
+const MAX_SKEW: f32 = 5.0;
+const SKEW_STEP: f32 = 0.1;
+
+// Ink pixels sampled when measuring skew
+const SKEW_SAMPLES: usize = 50_000;
+
+// Images drawn smaller than this, in points, are logos or decorations
+// rather than scanned pages
+const MIN_IMAGE_SIZE: f32 = 72.0;
+
+// Font name given to recognized runs
+const OCR_FONT: &str = "OCR";
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    // One colorant, where a full tint is dark
+    Separation,
+    Indexed {
+        base: Box<ColorSpace>,
+        lookup: Vec<u8>,
+    },
+}
+
+impl ColorSpace {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Separation | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    // Gray level of a color given as components between 0 and 1
+    fn gray(&self, color: &[f32]) -> u8 {
+        let level = match (self, color) {
+            (ColorSpace::Gray, [g, ..]) => *g,
+            (ColorSpace::Separation, [tint, ..]) => 1.0 - tint,
+            (ColorSpace::Rgb, [r, g, b, ..]) => 0.299 * r + 0.587 * g + 0.114 * b,
+            (ColorSpace::Cmyk, [c, m, y, k, ..]) => {
+                0.299 * (1.0 - c) * (1.0 - k)
+                    + 0.587 * (1.0 - m) * (1.0 - k)
+                    + 0.114 * (1.0 - y) * (1.0 - k)
+            }
+            _ => 1.0,
+        };
+        (level.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+fn integer(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<i64> {
+    dict.get_deref(key, doc).and_then(Object::as_i64).ok()
+}
+
+fn color_space(doc: &Document, object: &Object) -> ColorSpace {
+    let object = doc.dereference(object).map_or(object, |(_, o)| o);
+    let (family, rest) = match object {
+        Object::Name(name) => (name.as_slice(), &[][..]),
+        Object::Array(items) => match items.split_first() {
+            Some((Object::Name(name), rest)) => (name.as_slice(), rest),
+            _ => return ColorSpace::Gray,
+        },
+        _ => return ColorSpace::Gray,
+    };
+    match family {
+        b"DeviceRGB" | b"CalRGB" | b"RGB" | b"Lab" => ColorSpace::Rgb,
+        b"DeviceCMYK" | b"CMYK" => ColorSpace::Cmyk,
+        b"Separation" | b"DeviceN" => ColorSpace::Separation,
+        b"ICCBased" => {
+            let components = rest
+                .first()
+                .and_then(|profile| doc.dereference(profile).ok())
+                .and_then(|(_, profile)| profile.as_stream().ok())
+                .and_then(|profile| integer(doc, &profile.dict, b"N"));
+            match components {
+                Some(3) => ColorSpace::Rgb,
+                Some(4) => ColorSpace::Cmyk,
+                _ => ColorSpace::Gray,
+            }
+        }
+        b"Indexed" | b"I" => {
+            let base = rest
+                .first()
+                .map_or(ColorSpace::Rgb, |base| color_space(doc, base));
+            let lookup = match rest
+                .get(2)
+                .map(|l| doc.dereference(l).map_or(l, |(_, o)| o))
+            {
+                Some(Object::String(bytes, _)) => bytes.clone(),
+                Some(Object::Stream(stream)) => stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone()),
+                _ => Vec::new(),
+            };
+            ColorSpace::Indexed {
+                base: Box::new(base),
+                lookup,
+            }
+        }
+        _ => ColorSpace::Gray,
+    }
+}
+
+// Unpacks samples of `bits` bits each into gray levels
+fn raw_image(
+    doc: &Document,
+    dict: &Dictionary,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<GrayImage, String> {
+    let mask = matches!(dict.get(b"ImageMask"), Ok(Object::Boolean(true)));
+    let space = if mask {
+        ColorSpace::Gray
+    } else {
+        dict.get(b"ColorSpace")
+            .map_or(ColorSpace::Gray, |cs| color_space(doc, cs))
+    };
+    let bits = if mask {
+        1
+    } else {
+        integer(doc, dict, b"BitsPerComponent").unwrap_or(8) as usize
+    };
+    if !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+        return Err(format!("{} bits per component", bits));
+    }
+    let components = space.components();
+    let row_len = (width as usize * components * bits).div_ceil(8);
+    if data.len() < row_len * height as usize {
+        return Err(format!(
+            "image data is {} bytes, expected {}",
+            data.len(),
+            row_len * height as usize
+        ));
+    }
+
+    let max = ((1u32 << bits) - 1) as f32;
+    let sample = |row: &[u8], index: usize| -> u32 {
+        let start = index * bits;
+        if bits >= 8 {
+            row[start / 8..(start + bits) / 8]
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as u32)
+        } else {
+            let byte = row[start / 8] as u32;
+            (byte >> (8 - bits - start % 8)) & ((1 << bits) - 1)
+        }
+    };
+    let mut color = [0.0; 4];
+    Ok(GrayImage::from_fn(width, height, |x, y| {
+        let row = &data[y as usize * row_len..][..row_len];
+        let first = x as usize * components;
+        let gray = match &space {
+            ColorSpace::Indexed { base, lookup } => {
+                let n = base.components();
+                let index = sample(row, first) as usize;
+                match lookup.get(index * n..index * n + n) {
+                    Some(entry) => {
+                        for (value, byte) in color.iter_mut().zip(entry) {
+                            *value = *byte as f32 / 255.0;
+                        }
+                        base.gray(&color[..n])
+                    }
+                    None => 255,
+                }
+            }
+            _ => {
+                for (c, value) in color.iter_mut().take(components).enumerate() {
+                    *value = sample(row, first + c) as f32 / max;
+                }
+                space.gray(&color[..components])
+            }
+        };
+        Luma([gray])
+    }))
+}
+
+fn fax_image(doc: &Document, stream: &Stream, height: u32) -> Result<GrayImage, String> {
+    let params = stream
+        .dict
+        .get_deref(b"DecodeParms", doc)
+        .ok()
+        .and_then(|params| match params {
+            Object::Array(items) => items.first().and_then(|p| p.as_dict().ok()),
+            _ => params.as_dict().ok(),
+        });
+    let param = |key: &[u8]| params.and_then(|params| integer(doc, params, key));
+    let k = param(b"K").unwrap_or(0);
+    let columns = param(b"Columns").unwrap_or(1728) as u16;
+    let rows = param(b"Rows").unwrap_or(height as i64) as u16;
+    let black_is_1 = params
+        .and_then(|params| params.get(b"BlackIs1").ok())
+        .is_some_and(|value| matches!(value, Object::Boolean(true)));
+
+    // Samples of 0 are black unless BlackIs1 says otherwise
+    let mut pixels: Vec<u8> = Vec::with_capacity(columns as usize * rows as usize);
+    let line = |transitions: &[u16]| {
+        pixels.extend(pels(transitions, columns).map(|color| {
+            if (color == Color::Black) != black_is_1 {
+                0
+            } else {
+                255
+            }
+        }))
+    };
+    let data = stream.content.iter().copied();
+    let decoded = match k {
+        k if k < 0 => fax_decoder::decode_g4(data, columns, Some(rows), line),
+        0 => fax_decoder::decode_g3(data, line),
+        _ => return Err("mixed one- and two-dimensional fax data".to_string()),
+    };
+    if decoded.is_none() && pixels.is_empty() {
+        return Err("damaged fax data".to_string());
+    }
+    let lines = (pixels.len() / columns as usize) as u32;
+    pixels.truncate(lines as usize * columns as usize);
+    GrayImage::from_raw(columns as u32, lines, pixels).ok_or_else(|| "empty fax image".to_string())
+}
+
+// Decodes an image XObject into gray levels
+fn decode_image(doc: &Document, stream: &Stream) -> Result<GrayImage, String> {
+    let dict = &stream.dict;
+    let width = integer(doc, dict, b"Width").ok_or("image without a width")? as u32;
+    let height = integer(doc, dict, b"Height").ok_or("image without a height")? as u32;
+    let filters = if dict.has(b"Filter") {
+        stream
+            .filters()
+            .map_err(|e| format!("unreadable filter: {:?}", e))?
+    } else {
+        Vec::new()
+    };
+
+    let mut image = match filters.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["DCTDecode"] => image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg)
+            .map_err(|e| e.to_string())?
+            .to_luma8(),
+        ["CCITTFaxDecode"] => fax_image(doc, stream, height)?,
+        [] => raw_image(doc, dict, &stream.content, width, height)?,
+        _ if filters
+            .iter()
+            .all(|filter| filter == "FlateDecode" || filter == "LZWDecode") =>
+        {
+            // lopdf refuses to decompress image streams as such
+            let mut plain = stream.clone();
+            plain.dict.remove(b"Subtype");
+            let data = plain
+                .decompressed_content()
+                .map_err(|e| format!("could not decompress: {:?}", e))?;
+            raw_image(doc, dict, &data, width, height)?
+        }
+        _ => return Err(format!("{} images are not supported", filters.join(", "))),
+    };
+
+    // A Decode array of [1 0] swaps dark and light
+    let inverted = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| Some(decode.first()?.as_float().ok()? > decode.get(1)?.as_float().ok()?))
+        .unwrap_or(false);
+    if inverted {
+        imageops::invert(&mut image);
+    }
+    Ok(image)
+}
+
+// Otsu's threshold: the gray level that best splits the histogram into ink
+// and paper
+fn threshold(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = image.pixels().len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, count)| level as f64 * *count as f64)
+        .sum();
+
+    let (mut best, mut best_variance) = (127, 0.0);
+    let (mut dark, mut dark_sum) = (0.0, 0.0);
+    for (level, count) in histogram.iter().enumerate() {
+        dark += *count as f64;
+        dark_sum += level as f64 * *count as f64;
+        let light = total - dark;
+        if dark == 0.0 || light == 0.0 {
+            continue;
+        }
+        let difference = dark_sum / dark - (sum - dark_sum) / light;
+        let variance = dark * light * difference * difference;
+        if variance > best_variance {
+            best_variance = variance;
+            best = level as u8;
+        }
+    }
+    best
+}
+
+fn binarize(image: &mut GrayImage) {
+    let threshold = threshold(image);
+    for pixel in image.pixels_mut() {
+        pixel.0[0] = if pixel.0[0] > threshold { 255 } else { 0 };
+    }
+}
+
+// The slope of the text on a binarized scan, in degrees, found as the angle
+// whose row profile of ink pixels is most sharply peaked
+fn skew_angle(image: &GrayImage) -> f32 {
+    let ink: Vec<(u32, u32)> = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0[0] == 0)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    if ink.len() < 100 {
+        return 0.0;
+    }
+    let step = ink.len().div_ceil(SKEW_SAMPLES);
+    let samples: Vec<(f32, f32)> = ink
+        .iter()
+        .step_by(step)
+        .map(|(x, y)| (*x as f32, *y as f32))
+        .collect();
+
+    let (width, height) = image.dimensions();
+    let margin = width as f32 * MAX_SKEW.to_radians().sin() + 1.0;
+    let mut rows = vec![0u64; (height as f32 + 2.0 * margin) as usize + 1];
+    let steps = (MAX_SKEW / SKEW_STEP).round() as i32;
+    let (mut best, mut best_score) = (0.0, 0);
+    for i in -steps..=steps {
+        let angle = i as f32 * SKEW_STEP;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        rows.fill(0);
+        for (x, y) in &samples {
+            let row = (y * cos - x * sin + margin).max(0.0) as usize;
+            if let Some(count) = rows.get_mut(row) {
+                *count += 1;
+            }
+        }
+        let score = rows.iter().map(|count| count * count).sum::<u64>();
+        // Prefer the smallest correction among equal scores
+        if score > best_score || (score == best_score && angle.abs() < f32::abs(best)) {
+            best = angle;
+            best_score = score;
+        }
+    }
+    best
+}
+
+// Turns the image so text sloping by `degrees` runs level, filling the
+// corners with paper
+fn rotate(image: &GrayImage, degrees: f32) -> GrayImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = image.dimensions();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    GrayImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let sx = cx + dx * cos - dy * sin;
+        let sy = cy + dx * sin + dy * cos;
+        if sx >= 0.0 && sy >= 0.0 && (sx as u32) < width && (sy as u32) < height {
+            *image.get_pixel(sx as u32, sy as u32)
+        } else {
+            Luma([255])
+        }
+    })
+}
+
+// Scales, binarizes and straightens a scan, returning it with its
+// resolution
+fn prepare(image: GrayImage, placement: &ImagePlacement) -> (GrayImage, f32) {
+    let inches = placement.matrix[0].hypot(placement.matrix[1]) / 72.0;
+    let dpi = image.width() as f32 / inches.max(f32::EPSILON);
+    let scale = (TARGET_DPI / dpi).min(MAX_SCALE);
+    let (mut image, dpi) = if scale > 1.25 {
+        let (width, height) = image.dimensions();
+        let resized = imageops::resize(
+            &image,
+            (width as f32 * scale).round() as u32,
+            (height as f32 * scale).round() as u32,
+            FilterType::Triangle,
+        );
+        (resized, dpi * scale)
+    } else {
+        (image, dpi)
+    };
+
+    binarize(&mut image);
+    let angle = skew_angle(&image);
+    if angle != 0.0 {
+        info!(
+            "Straightening the scan on page {} by {:.1}°",
+            placement.page, angle
+        );
+        image = rotate(&image, angle);
+    }
+    (image, dpi)
+}
+
+fn tsv(image: &GrayImage, dpi: f32, language: &str) -> Result<String, ExtractionError> {
+    let failed = |e: &dyn std::fmt::Display| ExtractionError::OcrError(e.to_string());
+    let (width, height) = image.dimensions();
+    let mut engine = Tesseract::new(None, Some(language))
+        .map_err(|e| {
+            ExtractionError::OcrError(format!(
+                "could not start Tesseract with language '{}': {}",
+                language, e
+            ))
+        })?
+        .set_frame(image.as_raw(), width as i32, height as i32, 1, width as i32)
+        .map_err(|e| failed(&e))?
+        .set_source_resolution(dpi.round() as i32)
+        .recognize()
+        .map_err(|e| failed(&e))?;
+    engine.get_tsv_text(0).map_err(|e| failed(&e))
+}
+
+// Where a pixel of the prepared image lands on the page
+fn to_page(matrix: &Matrix, image: &GrayImage, x: f32, y: f32) -> (f32, f32) {
+    let u = x / image.width() as f32;
+    let v = 1.0 - y / image.height() as f32;
+    (
+        u * matrix[0] + v * matrix[2] + matrix[4],
+        u * matrix[1] + v * matrix[3] + matrix[5],
+    )
+}
+
+struct Word {
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+    confidence: f32,
+    text: String,
+}
+
+// Turns Tesseract's TSV into runs, one per word. Words on one recognized
+// line share its bottom as their baseline, so descenders do not split it.
+fn words_to_runs(tsv: &str, image: &GrayImage, placement: &ImagePlacement) -> Vec<TextRun> {
+    let mut lines: BTreeMap<(u32, u32, u32), Vec<Word>> = BTreeMap::new();
+    for row in tsv.lines() {
+        let fields: Vec<&str> = row.split('\t').collect();
+        let [numbers @ .., text] = &fields[..] else {
+            continue;
+        };
+        let Some(numbers) = numbers
+            .iter()
+            .map(|field| field.trim().parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()
+        else {
+            continue;
+        };
+        // Level 5 rows are words; the others are pages, blocks and lines
+        let [5.0, _, block, paragraph, line, _, left, top, width, height, confidence] = numbers[..]
+        else {
+            continue;
+        };
+        if text.trim().is_empty() || confidence < 0.0 {
+            continue;
+        }
+        lines
+            .entry((block as u32, paragraph as u32, line as u32))
+            .or_default()
+            .push(Word {
+                left,
+                top,
+                width,
+                height,
+                confidence,
+                text: text.trim().to_string(),
+            });
+    }
+
+    let mut runs = Vec::new();
+    let mut confidence = 0.0;
+    for words in lines.values() {
+        let bottom = words.iter().map(|w| w.top + w.height).fold(0.0, f32::max);
+        let top = words.iter().map(|w| w.top).fold(f32::MAX, f32::min);
+        for word in words {
+            let start = to_page(&placement.matrix, image, word.left, bottom);
+            let end = to_page(&placement.matrix, image, word.left + word.width, bottom);
+            let above = to_page(&placement.matrix, image, word.left, top);
+            runs.push(TextRun {
+                page: placement.page,
+                x: start.0,
+                y: start.1,
+                width: (end.0 - start.0).hypot(end.1 - start.1),
+                font_size: (above.0 - start.0).hypot(above.1 - start.1),
+                font: OCR_FONT.to_string(),
+                text: word.text.clone(),
+                confidence: word.confidence / 100.0,
+            });
+            confidence += word.confidence;
+        }
+    }
+    if !runs.is_empty() {
+        info!(
+            "Recognized {} words on page {} with a mean confidence of {:.0}%",
+            runs.len(),
+            placement.page,
+            confidence / runs.len() as f32
+        );
+    }
+    runs
+}
+
+/// Recognizes the text of the scanned images in the document with
+/// Tesseract, returning one run per word placed where the word appears on
+/// the page. `language` takes Tesseract language codes such as `eng` or
+/// `eng+deu`.
+pub fn recognize(doc: &Document, language: &str) -> Result<Vec<TextRun>, ExtractionError> {
+    let placements: Vec<ImagePlacement> = extract::placed_images(doc)
+        .into_iter()
+        .filter(|placement| {
+            let m = &placement.matrix;
+            m[0].hypot(m[1]).min(m[2].hypot(m[3])) >= MIN_IMAGE_SIZE
+        })
+        .collect();
+    if placements.is_empty() {
+        warn!("No page-sized images to run OCR on.");
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    let mut last_error = None;
+    for placement in &placements {
+        let image = match doc
+            .get_object(placement.id)
+            .and_then(Object::as_stream)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|stream| decode_image(doc, stream))
+        {
+            Ok(image) => image,
+            Err(e) => {
+                warn!("Skipping an image on page {}: {}", placement.page, e);
+                continue;
+            }
+        };
+        let (image, dpi) = prepare(image, placement);
+        match tsv(&image, dpi, language) {
+            Ok(tsv) => runs.extend(words_to_runs(&tsv, &image, placement)),
+            Err(e) => {
+                warn!("OCR failed on page {}: {}", placement.page, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if runs.is_empty() => Err(e),
+        _ => Ok(runs),
+    }
+}
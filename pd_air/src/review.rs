@@ -0,0 +1,35 @@
+use crate::invoice::Invoice;
+use crate::validate::Report;
+use crate::ExtractionError;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// An invoice held back from upload for a person to check.
+#[derive(Debug, Serialize)]
+pub struct ReviewEntry<'a> {
+    pub pdf: &'a str,
+    pub queued_at: String,
+    pub reasons: Vec<String>,
+    pub invoice: &'a Invoice,
+    pub report: &'a Report,
+}
+
+/// Appends an entry to the review queue, a JSON array kept in `path`, and
+/// returns how many entries the queue holds.
+pub fn enqueue(path: &Path, entry: &ReviewEntry) -> Result<usize, ExtractionError> {
+    // Entries are kept as plain JSON, so ones queued by older versions
+    // survive changes to the entry's shape
+    let mut queue: Vec<serde_json::Value> = if path.exists() {
+        serde_json::from_str(&fs::read_to_string(path)?)?
+    } else {
+        Vec::new()
+    };
+    queue.push(serde_json::to_value(entry)?);
+
+    // Replace the queue in one step, so a crash never leaves it half written
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, serde_json::to_string_pretty(&queue)?)?;
+    fs::rename(&temporary, path)?;
+    Ok(queue.len())
+}
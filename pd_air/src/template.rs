@@ -0,0 +1,704 @@
+use crate::invoice::{Invoice, LineItem};
+use crate::layout::{Line, Segment};
+use crate::ExtractionError;
+use chrono::NaiveDate;
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::Path;
+
+// Used when no supplier template matches an invoice
+const GENERIC_TEMPLATE: &str = include_str!("../templates/generic.toml");
+
+// A label's value may sit below it, within this many of its font sizes
+const LABEL_REACH: f32 = 3.0;
+
+/// A regular expression compiled when its template is loaded.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Regex::new(&source)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Pattern {
+    // The first capture group of a match, or the whole match
+    fn extract<'a>(&self, text: &'a str) -> Option<&'a str> {
+        let captures = self.0.captures(text)?;
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|m| m.as_str().trim())
+    }
+}
+
+/// How a supplier's invoices are recognised and read.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    pub name: String,
+    /// Supplier name given to every invoice the template reads, unless a
+    /// `supplier` field rule finds one on the page.
+    #[serde(default)]
+    supplier: Option<String>,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    fingerprint: Fingerprint,
+    /// chrono formats tried in order for date fields.
+    #[serde(default = "default_date_formats")]
+    date_formats: Vec<String>,
+    #[serde(default = "default_decimal_separator")]
+    decimal_separator: char,
+    #[serde(default)]
+    fields: Fields,
+    #[serde(default)]
+    table: Option<TableRule>,
+}
+
+fn default_date_formats() -> Vec<String> {
+    vec!["%d %B %Y".to_string()]
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+/// What identifies a supplier's invoices: text that must appear on them,
+/// and a pattern for the PDF's Producer or Creator.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fingerprint {
+    #[serde(default)]
+    markers: Vec<String>,
+    #[serde(default)]
+    producer: Option<Pattern>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fields {
+    supplier: Option<FieldRule>,
+    invoice_number: Option<FieldRule>,
+    client_name: Option<FieldRule>,
+    invoice_date: Option<FieldRule>,
+    due_date: Option<FieldRule>,
+    currency: Option<FieldRule>,
+    subtotal: Option<FieldRule>,
+    tax: Option<FieldRule>,
+    discount: Option<FieldRule>,
+    total: Option<FieldRule>,
+}
+
+/// Where a header field's value is found. With an anchor, the value is
+/// next to the segment the anchor matches: the rest of that segment and
+/// the segments after it on the line (`right`), the segment nearest below
+/// it (`below`), or both in that order (`auto`). A region replaces those
+/// with the text inside a rectangle placed relative to the anchor. Without
+/// an anchor, the pattern is searched for in the whole text.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldRule {
+    anchor: Option<Pattern>,
+    #[serde(default)]
+    position: Position,
+    region: Option<Region>,
+    /// The value must match; its first capture group, if any, is kept.
+    pattern: Option<Pattern>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Position {
+    #[default]
+    Auto,
+    Right,
+    Below,
+}
+
+/// A rectangle in points from the start of the anchor's baseline: `x`
+/// to the right and `y` down to its top edge.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Region {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+/// The line items table. Its header is the first line matching `header`,
+/// or else the first line on which every required column's heading is
+/// found; it ends at the first line matching `end`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableRule {
+    header: Option<Pattern>,
+    end: Option<Pattern>,
+    columns: Vec<ColumnRule>,
+}
+
+/// A table column, located by its heading on the header line or by a
+/// fixed horizontal range in points.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnRule {
+    field: ItemField,
+    header: Option<Pattern>,
+    x: Option<f32>,
+    width: Option<f32>,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ItemField {
+    No,
+    Description,
+    Quantity,
+    UnitPrice,
+    Total,
+}
+
+// A column located on the page, with its horizontal range
+type Column = (ItemField, f32, f32);
+
+fn nearest_segment(line: &Line, x: f32) -> Option<&Segment> {
+    line.segments
+        .iter()
+        .min_by(|a, b| (a.x - x).abs().total_cmp(&(b.x - x).abs()))
+}
+
+// Up to two lines below a label line, on the same page and near enough
+fn lines_below(lines: &[Line], index: usize) -> impl Iterator<Item = &Line> {
+    let line = &lines[index];
+    lines[index + 1..].iter().take(2).filter(move |below| {
+        below.page == line.page && line.y - below.y <= line.font_size * LABEL_REACH
+    })
+}
+
+impl FieldRule {
+    // Texts that may hold the value, most likely first
+    fn candidates(&self, lines: &[Line]) -> Vec<String> {
+        let Some(anchor) = &self.anchor else {
+            let text: Vec<String> = lines.iter().map(Line::text).collect();
+            let text = text.join("\n");
+            return match &self.pattern {
+                Some(pattern) => pattern
+                    .0
+                    .find_iter(&text)
+                    .map(|m| m.as_str().to_string())
+                    .collect(),
+                None => Vec::new(),
+            };
+        };
+
+        let mut candidates = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            for (segment_index, segment) in line.segments.iter().enumerate() {
+                let Some(label) = anchor.0.find(&segment.text) else {
+                    continue;
+                };
+                if let Some(region) = &self.region {
+                    let left = segment.x + region.x;
+                    let top = line.y - region.y;
+                    let inside: Vec<&str> = lines
+                        .iter()
+                        .filter(|other| {
+                            other.page == line.page
+                                && other.y <= top
+                                && other.y >= top - region.height
+                        })
+                        .flat_map(|other| other.segments.iter())
+                        .filter(|other| other.x >= left && other.x <= left + region.width)
+                        .map(|other| other.text.as_str())
+                        .collect();
+                    candidates.push(inside.join(" "));
+                    continue;
+                }
+                if self.position != Position::Below {
+                    let rest = segment.text[label.end()..].trim_start_matches([':', ' ']);
+                    candidates.push(rest.trim().to_string());
+                    candidates.extend(
+                        line.segments[segment_index + 1..]
+                            .iter()
+                            .map(|s| s.text.clone()),
+                    );
+                }
+                if self.position != Position::Right {
+                    for below in lines_below(lines, index) {
+                        candidates
+                            .extend(nearest_segment(below, segment.x).map(|s| s.text.clone()));
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    // The first candidate matching the pattern that `parse` accepts
+    fn find<T>(&self, lines: &[Line], parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+        self.candidates(lines).iter().find_map(|candidate| {
+            let value = match &self.pattern {
+                Some(pattern) => pattern.extract(candidate)?,
+                None => candidate.trim(),
+            };
+            if value.is_empty() {
+                return None;
+            }
+            parse(value)
+        })
+    }
+}
+
+/// Parses an amount such as "$1,234.50" or "1.234,50 €", ignoring
+/// currency symbols and thousands separators. A leading minus sign or
+/// parentheses make it negative.
+pub fn parse_amount(text: &str, decimal_separator: char) -> Option<f64> {
+    let text = text.trim();
+    let cleaned: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == decimal_separator)
+        .map(|c| if c == decimal_separator { '.' } else { c })
+        .collect();
+    if !cleaned.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let value: f64 = cleaned.parse().ok()?;
+    let negative = text.starts_with('-') || (text.starts_with('(') && text.ends_with(')'));
+    Some(if negative { -value } else { value })
+}
+
+fn is_amount(text: &str) -> bool {
+    text.chars().any(|c| c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || "$€£,.-() ".contains(c))
+}
+
+// The horizontal range of a pattern's match in a segment, estimated from
+// the position of the matched characters in its text
+fn match_range(pattern: &Pattern, segment: &Segment) -> Option<(f32, f32)> {
+    let found = pattern.0.find(&segment.text)?;
+    let length = segment.text.chars().count().max(1) as f32;
+    let start = segment.text[..found.start()].chars().count() as f32;
+    let end = segment.text[..found.end()].chars().count() as f32;
+    Some((
+        segment.x + segment.width * start / length,
+        segment.x + segment.width * end / length,
+    ))
+}
+
+// The column a segment belongs to: the one it overlaps most, or else the
+// one whose middle is nearest
+fn column_for(segment: &Segment, columns: &[Column]) -> Option<ItemField> {
+    let overlap = |start: f32, end: f32| segment.end().min(end) - segment.x.max(start);
+    let overlapping = columns
+        .iter()
+        .filter(|(_, start, end)| overlap(*start, *end) > 0.0)
+        .max_by(|a, b| overlap(a.1, a.2).total_cmp(&overlap(b.1, b.2)));
+    let nearest = || {
+        columns.iter().min_by(|a, b| {
+            let distance = |start: f32, end: f32| (segment.center() - (start + end) / 2.0).abs();
+            distance(a.1, a.2).total_cmp(&distance(b.1, b.2))
+        })
+    };
+    overlapping.or_else(nearest).map(|(field, _, _)| *field)
+}
+
+// Without a header, a line ending in two amounts is read from the right as
+// total, unit price and an optional quantity, with a leading number as the
+// item number and the rest as the description
+fn headerless_cells(line: &Line) -> Option<Vec<(ItemField, String)>> {
+    let texts: Vec<&str> = line.segments.iter().map(|s| s.text.as_str()).collect();
+    let [rest @ .., price, total] = texts.as_slice() else {
+        return None;
+    };
+    if !is_amount(price) || !is_amount(total) || rest.is_empty() {
+        return None;
+    }
+    let mut cells = vec![
+        (ItemField::UnitPrice, price.to_string()),
+        (ItemField::Total, total.to_string()),
+    ];
+    let mut rest = rest;
+    if let [before @ .., quantity] = rest {
+        if quantity.chars().all(|c| c.is_ascii_digit()) && !before.is_empty() {
+            cells.push((ItemField::Quantity, quantity.to_string()));
+            rest = before;
+        }
+    }
+    if let [no, after @ ..] = rest {
+        if no.len() < 3 && no.chars().all(|c| c.is_ascii_digit()) && !after.is_empty() {
+            cells.push((ItemField::No, no.to_string()));
+            rest = after;
+        }
+    }
+    cells.push((ItemField::Description, rest.join(" ")));
+    Some(cells)
+}
+
+impl TableRule {
+    // The table's columns on a line, if it is the table's header
+    fn columns_on(&self, line: &Line) -> Option<Vec<Column>> {
+        let explicit_header = self
+            .header
+            .as_ref()
+            .map(|header| header.0.is_match(&line.text()));
+        if explicit_header == Some(false) {
+            return None;
+        }
+        let mut columns = Vec::new();
+        for rule in &self.columns {
+            let range = match (&rule.header, rule.x) {
+                (_, Some(x)) => Some((x, x + rule.width.unwrap_or(0.0))),
+                (Some(header), None) => line
+                    .segments
+                    .iter()
+                    .find_map(|segment| match_range(header, segment)),
+                (None, None) => None,
+            };
+            match range {
+                Some((start, end)) => columns.push((rule.field, start, end)),
+                None if rule.optional => {}
+                None if explicit_header.is_some() => {
+                    warn!("Table column {:?} not found on the header line", rule.field);
+                }
+                None => return None,
+            }
+        }
+        (!columns.is_empty()).then_some(columns)
+    }
+}
+
+impl Template {
+    /// The built-in template for invoices no supplier template matches.
+    pub fn generic() -> Template {
+        toml::from_str(GENERIC_TEMPLATE).expect("built-in generic template is valid")
+    }
+
+    // How well the template's fingerprint fits, if it fits at all: one
+    // point per marker, and one for the producer
+    fn score(&self, text: &str, producers: &[String]) -> Option<usize> {
+        let fingerprint = &self.fingerprint;
+        if fingerprint.markers.is_empty() && fingerprint.producer.is_none() {
+            return None;
+        }
+        let text = text.to_lowercase();
+        let markers = fingerprint
+            .markers
+            .iter()
+            .all(|marker| text.contains(&marker.to_lowercase()));
+        let producer = fingerprint
+            .producer
+            .as_ref()
+            .is_none_or(|pattern| producers.iter().any(|p| pattern.0.is_match(p)));
+        (markers && producer)
+            .then(|| fingerprint.markers.len() + usize::from(fingerprint.producer.is_some()))
+    }
+
+    fn amount(&self, rule: &Option<FieldRule>, lines: &[Line]) -> Option<f64> {
+        rule.as_ref()?
+            .find(lines, |value| parse_amount(value, self.decimal_separator))
+    }
+
+    fn date(&self, rule: &Option<FieldRule>, lines: &[Line]) -> Option<NaiveDate> {
+        rule.as_ref()?.find(lines, |value| {
+            self.date_formats
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        })
+    }
+
+    fn text(rule: &Option<FieldRule>, lines: &[Line]) -> Option<String> {
+        rule.as_ref()?.find(lines, |value| Some(value.to_string()))
+    }
+
+    fn item(&self, cells: &[(ItemField, String)], index: usize) -> Option<LineItem> {
+        let cell = |field| {
+            let texts: Vec<&str> = cells
+                .iter()
+                .filter(|(existing, _)| *existing == field)
+                .map(|(_, text)| text.as_str())
+                .collect();
+            (!texts.is_empty()).then(|| texts.join(" "))
+        };
+        let amount = |field| {
+            let text = cell(field)?;
+            let value = parse_amount(&text, self.decimal_separator);
+            if value.is_none() {
+                warn!("Could not parse {:?} '{}'", field, text);
+            }
+            value
+        };
+        let unit_price = amount(ItemField::UnitPrice);
+        let total = amount(ItemField::Total);
+        if unit_price.is_none() && total.is_none() {
+            return None;
+        }
+        let quantity = match cell(ItemField::Quantity) {
+            Some(text) => parse_amount(&text, self.decimal_separator).unwrap_or_else(|| {
+                warn!("Failed to parse quantity '{}'. Defaulting to 1.", text);
+                1.0
+            }),
+            None => 1.0,
+        };
+        let unit_price =
+            unit_price.unwrap_or_else(|| total.unwrap_or(0.0) / quantity.max(f64::EPSILON));
+        Some(LineItem {
+            no: cell(ItemField::No)
+                .and_then(|text| text.trim_end_matches('.').parse().ok())
+                .unwrap_or(index as u32 + 1),
+            description: cell(ItemField::Description).unwrap_or_default(),
+            quantity,
+            unit_price,
+            total: total.unwrap_or(unit_price * quantity),
+        })
+    }
+
+    fn items(&self, lines: &[Line]) -> Vec<LineItem> {
+        let header = self.table.as_ref().and_then(|table| {
+            lines
+                .iter()
+                .enumerate()
+                .find_map(|(index, line)| table.columns_on(line).map(|columns| (index, columns)))
+        });
+        let (start, columns) = match header {
+            Some((index, columns)) => {
+                info!(
+                    "Found table header at line {}: '{}' with columns {:?}",
+                    index + 1,
+                    lines[index].text(),
+                    columns
+                );
+                (index + 1, Some(columns))
+            }
+            None => {
+                warn!("Could not find a table header. Reading lines that end in two amounts as items.");
+                (0, None)
+            }
+        };
+        let end = self.table.as_ref().and_then(|table| table.end.as_ref());
+
+        let mut items: Vec<LineItem> = Vec::new();
+        for (index, line) in lines.iter().enumerate().skip(start) {
+            let text = line.text();
+            info!("[Item Parse] Line {}: '{}'", index + 1, text);
+            if end.is_some_and(|end| end.0.is_match(&text)) {
+                info!("Stopping item parsing at: '{}'", text);
+                break;
+            }
+            let cells = match (&columns, &self.table) {
+                // Headers repeat on every page of a long table
+                (Some(_), Some(table)) if table.columns_on(line).is_some() => continue,
+                (Some(columns), _) => line
+                    .segments
+                    .iter()
+                    .filter_map(|s| column_for(s, columns).map(|field| (field, s.text.clone())))
+                    .collect(),
+                (None, _) => match headerless_cells(line) {
+                    Some(cells) => cells,
+                    None => continue,
+                },
+            };
+
+            match self.item(&cells, items.len()) {
+                Some(item) => {
+                    info!("  SUCCESSFULLY PARSED ITEM: {:?}", item);
+                    items.push(item);
+                }
+                None => {
+                    // A description wrapped onto the next line
+                    let only_description = cells
+                        .iter()
+                        .all(|(field, _)| *field == ItemField::Description);
+                    match items.last_mut() {
+                        Some(last) if only_description && columns.is_some() => {
+                            for (_, text) in &cells {
+                                last.description.push(' ');
+                                last.description.push_str(text);
+                            }
+                            info!("  -> Description continued: {}", last.description);
+                        }
+                        _ => info!(
+                            "  -> Skipping line '{}' as it doesn't fit expected item structure.",
+                            text
+                        ),
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    /// Reads an invoice from laid out lines.
+    pub fn extract(&self, lines: &[Line]) -> Invoice {
+        let fields = &self.fields;
+        let invoice = Invoice {
+            template: self.name.clone(),
+            supplier: Template::text(&fields.supplier, lines).or_else(|| self.supplier.clone()),
+            invoice_number: Template::text(&fields.invoice_number, lines),
+            client_name: Template::text(&fields.client_name, lines),
+            invoice_date: self.date(&fields.invoice_date, lines),
+            due_date: self.date(&fields.due_date, lines),
+            currency: Template::text(&fields.currency, lines).or_else(|| self.currency.clone()),
+            subtotal: self.amount(&fields.subtotal, lines),
+            tax: self.amount(&fields.tax, lines),
+            // Printed as "-$10" or "(10.00)" as often as not; keep the
+            // amount taken off
+            discount: self.amount(&fields.discount, lines).map(f64::abs),
+            total: self.amount(&fields.total, lines),
+            items: self.items(lines),
+        };
+        match &invoice.client_name {
+            Some(name) => info!("Client Name extracted: {}", name),
+            None => warn!("Client Name could not be extracted."),
+        }
+        match &invoice.invoice_date {
+            Some(date) => info!("Invoice Date extracted: '{}'", date),
+            None => warn!("Invoice Date could not be extracted."),
+        }
+        invoice
+    }
+}
+
+/// Loads every `.toml`, `.yaml` and `.yml` template in a directory. A
+/// missing directory holds no templates.
+pub fn load_templates(dir: &Path) -> Result<Vec<Template>, ExtractionError> {
+    if !dir.is_dir() {
+        warn!(
+            "Template directory '{}' not found; using the generic template only.",
+            dir.display()
+        );
+        return Ok(Vec::new());
+    }
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+
+    let mut templates = Vec::new();
+    for path in paths {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        let raw = match extension.as_deref() {
+            Some("toml" | "yaml" | "yml") => fs::read_to_string(&path)?,
+            _ => continue,
+        };
+        let template: Template = if extension.as_deref() == Some("toml") {
+            toml::from_str(&raw).map_err(|e| e.to_string())
+        } else {
+            serde_yaml::from_str(&raw).map_err(|e| e.to_string())
+        }
+        .map_err(|e| ExtractionError::TemplateError(format!("{}: {}", path.display(), e)))?;
+        info!(
+            "Loaded template '{}' from {}",
+            template.name,
+            path.display()
+        );
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+/// The template whose fingerprint best fits the invoice's text and
+/// producer metadata.
+pub fn select<'a>(
+    templates: &'a [Template],
+    text: &str,
+    producers: &[String],
+) -> Option<&'a Template> {
+    templates
+        .iter()
+        .filter_map(|template| {
+            template
+                .score(text, producers)
+                .map(|score| (score, template))
+        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, template)| template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(source: &str) -> Template {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn parses_amounts() {
+        assert_eq!(parse_amount("1.234,50 €", ','), Some(1234.5));
+        assert_eq!(parse_amount("$1,234.50", '.'), Some(1234.5));
+        assert_eq!(parse_amount("(12.00)", '.'), Some(-12.0));
+        assert_eq!(parse_amount("-$5", '.'), Some(-5.0));
+        assert_eq!(parse_amount(" 7 ", '.'), Some(7.0));
+        assert_eq!(parse_amount("n/a", '.'), None);
+        assert_eq!(parse_amount("1.2.3", '.'), None);
+    }
+
+    #[test]
+    fn selects_the_template_with_the_most_matching_fingerprint() {
+        let templates = [
+            template("name = \"generic\""),
+            template("name = \"acme\"\n[fingerprint]\nmarkers = [\"ACME Ltd\"]"),
+            template(
+                "name = \"acme-eu\"\n[fingerprint]\nmarkers = [\"ACME Ltd\", \"VAT no\"]",
+            ),
+            template("name = \"canva\"\n[fingerprint]\nproducer = \"(?i)canva\""),
+            template(
+                "name = \"canva-acme\"\n[fingerprint]\nmarkers = [\"acme ltd\"]\nproducer = \"(?i)canva\"",
+            ),
+        ];
+        let selected = |text: &str, producers: &[&str]| {
+            let producers: Vec<String> = producers.iter().map(|p| p.to_string()).collect();
+            select(&templates, text, &producers).map(|t| t.name.as_str())
+        };
+
+        // Markers match whatever their case
+        assert_eq!(selected("Invoice from acme ltd", &[]), Some("acme"));
+        assert_eq!(
+            selected("ACME LTD\nVAT No 123", &["Microsoft Word"]),
+            Some("acme-eu")
+        );
+        assert_eq!(selected("Invoice", &["Canva"]), Some("canva"));
+        assert_eq!(
+            selected("ACME Ltd", &["Skia/PDF", "Canva"]),
+            Some("canva-acme")
+        );
+        // A template without a fingerprint is never picked
+        assert_eq!(selected("Invoice", &["Microsoft Word"]), None);
+    }
+
+    #[test]
+    fn loads_the_bundled_templates() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+        let templates = load_templates(&dir).unwrap();
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["generic", "reallygreatsite", "saveefforts"]);
+
+        let canva = &templates[2];
+        assert_eq!(
+            canva.score("SAVEEFFORTS.COM", &["Canva".to_string()]),
+            Some(2)
+        );
+        assert_eq!(canva.score("SAVEEFFORTS.COM", &["Word".to_string()]), None);
+        assert_eq!(Template::generic().score("anything", &[]), None);
+    }
+
+    #[test]
+    fn rejects_unknown_template_keys() {
+        let error = toml::from_str::<Template>("name = \"x\"\nmarker = \"typo\"").unwrap_err();
+        assert!(error.to_string().contains("unknown field"));
+    }
+}
@@ -0,0 +1,383 @@
+use crate::invoice::Invoice;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Amounts are printed rounded to the cent, so each may be off by half a
+// cent, and a product by half a cent for every unit
+const ROUNDING: f64 = 0.005;
+
+// Share of the confidence kept by fields the generic template found, since
+// it guesses at labels rather than knowing the layout
+const GENERIC_CONFIDENCE: f32 = 0.7;
+
+// Confidence a field gains when every check it takes part in passes, and
+// the share it keeps when one fails
+const CORROBORATED: f32 = 0.25;
+const CONTRADICTED: f32 = 0.25;
+
+/// Fields below this confidence send the invoice to review, unless
+/// configured otherwise.
+pub const DEFAULT_THRESHOLD: f32 = 0.6;
+
+// Header fields uploaded with every item, and so needed to upload
+const REQUIRED: [&str; 2] = ["client_name", "invoice_date"];
+
+/// The outcome of one arithmetic check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub expected: f64,
+    pub found: f64,
+    pub passed: bool,
+    #[serde(skip)]
+    fields: Vec<String>,
+}
+
+/// Arithmetic checks of an invoice, and the confidence in each of its
+/// fields from 0 to 1. Items' fields are named like `items[0].total`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    pub confidence: BTreeMap<String, f32>,
+    #[serde(skip)]
+    item_count: usize,
+    // Items whose quantity isn't a whole number of units, by item number;
+    // Airtable's Quantity column holds whole numbers only
+    #[serde(skip)]
+    odd_quantities: Vec<(u32, f64)>,
+}
+
+fn check(name: String, expected: f64, found: f64, tolerance: f64, fields: Vec<String>) -> Check {
+    Check {
+        name,
+        expected,
+        found,
+        passed: (expected - found).abs() <= tolerance + f64::EPSILON * expected.abs(),
+        fields,
+    }
+}
+
+fn checks(invoice: &Invoice) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for (index, item) in invoice.items.iter().enumerate() {
+        let field = |name: &str| format!("items[{}].{}", index, name);
+        checks.push(check(
+            format!("item {}: quantity × unit price = total", item.no),
+            item.quantity * item.unit_price,
+            item.total,
+            ROUNDING * (item.quantity.abs() + 1.0),
+            vec![field("quantity"), field("unit_price"), field("total")],
+        ));
+    }
+
+    let items_total: f64 = invoice.items.iter().map(|item| item.total).sum();
+    let item_totals = (0..invoice.items.len()).map(|index| format!("items[{}].total", index));
+    if let Some(subtotal) = invoice.subtotal {
+        checks.push(check(
+            "line totals = subtotal".to_string(),
+            items_total,
+            subtotal,
+            ROUNDING * (invoice.items.len() + 1) as f64,
+            item_totals
+                .clone()
+                .chain(["subtotal".to_string()])
+                .collect(),
+        ));
+    }
+
+    if let Some(total) = invoice.total {
+        // Without a subtotal, the line totals stand in for it
+        let mut fields = vec!["total".to_string()];
+        let subtotal = match invoice.subtotal {
+            Some(subtotal) => {
+                fields.push("subtotal".to_string());
+                subtotal
+            }
+            None => {
+                fields.extend(item_totals);
+                items_total
+            }
+        };
+        for (name, value) in [("tax", invoice.tax), ("discount", invoice.discount)] {
+            if value.is_some() {
+                fields.push(name.to_string());
+            }
+        }
+        checks.push(check(
+            "subtotal + tax − discount = total".to_string(),
+            subtotal + invoice.tax.unwrap_or(0.0) - invoice.discount.unwrap_or(0.0),
+            total,
+            ROUNDING * 4.0,
+            fields,
+        ));
+    }
+    checks
+}
+
+/// Checks the invoice's arithmetic and scores its fields. A field starts
+/// at `text_confidence`, the confidence in the text it was read from,
+/// less for the generic template, or at 0 when it is missing; the checks
+/// then raise or lower it.
+pub fn validate(invoice: &Invoice, text_confidence: f32) -> Report {
+    let base = if invoice.template == "generic" {
+        text_confidence * GENERIC_CONFIDENCE
+    } else {
+        text_confidence
+    };
+    let score = |present: bool| if present { base } else { 0.0 };
+
+    let mut confidence = BTreeMap::new();
+    let header = [
+        ("supplier", invoice.supplier.is_some()),
+        ("invoice_number", invoice.invoice_number.is_some()),
+        ("client_name", invoice.client_name.is_some()),
+        ("invoice_date", invoice.invoice_date.is_some()),
+        ("due_date", invoice.due_date.is_some()),
+        ("currency", invoice.currency.is_some()),
+        ("subtotal", invoice.subtotal.is_some()),
+        ("tax", invoice.tax.is_some()),
+        ("discount", invoice.discount.is_some()),
+        ("total", invoice.total.is_some()),
+    ];
+    for (name, present) in header {
+        confidence.insert(name.to_string(), score(present));
+    }
+    for (index, item) in invoice.items.iter().enumerate() {
+        let fields = [
+            ("description", !item.description.is_empty()),
+            ("quantity", true),
+            ("unit_price", true),
+            ("total", true),
+        ];
+        for (name, present) in fields {
+            confidence.insert(format!("items[{}].{}", index, name), score(present));
+        }
+    }
+
+    let checks = checks(invoice);
+    for (field, value) in confidence.iter_mut() {
+        let mut involved = checks
+            .iter()
+            .filter(|check| check.fields.contains(field))
+            .peekable();
+        if involved.peek().is_none() {
+            continue;
+        }
+        if involved.all(|check| check.passed) {
+            *value = (*value + CORROBORATED).min(1.0);
+        } else {
+            *value *= CONTRADICTED;
+        }
+    }
+    let odd_quantities = invoice
+        .items
+        .iter()
+        .filter(|item| {
+            item.quantity.fract() != 0.0 || !(0.0..=u32::MAX as f64).contains(&item.quantity)
+        })
+        .map(|item| (item.no, item.quantity))
+        .collect();
+    Report {
+        checks,
+        confidence,
+        item_count: invoice.items.len(),
+        odd_quantities,
+    }
+}
+
+impl Report {
+    /// Why the invoice needs a person to look at it, if it does: no line
+    /// items at all, each quantity that isn't a whole number of units,
+    /// each failed check, and each field uploaded to Airtable whose
+    /// confidence is below `threshold`.
+    pub fn review_reasons(&self, threshold: f32) -> Vec<String> {
+        let missing = (self.item_count == 0).then(|| "no line items were found".to_string());
+        let quantities = self.odd_quantities.iter().map(|(no, quantity)| {
+            format!(
+                "item {}: quantity {} is not a whole number of units",
+                no, quantity
+            )
+        });
+        let failed = self
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| {
+                format!(
+                    "{} failed: expected {:.2}, found {:.2}",
+                    check.name, check.expected, check.found
+                )
+            });
+        let doubtful = self
+            .confidence
+            .iter()
+            .filter(|(field, value)| {
+                (REQUIRED.contains(&field.as_str()) || field.starts_with("items["))
+                    && **value < threshold
+            })
+            .map(|(field, value)| format!("{} has a confidence of {:.2}", field, value));
+        missing
+            .into_iter()
+            .chain(quantities)
+            .chain(failed)
+            .chain(doubtful)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoice::LineItem;
+    use chrono::NaiveDate;
+
+    fn item(no: u32, quantity: f64, unit_price: f64, total: f64) -> LineItem {
+        LineItem {
+            no,
+            description: format!("Item {}", no),
+            quantity,
+            unit_price,
+            total,
+        }
+    }
+
+    // Two items adding up to 70.00, with matching subtotal and total
+    fn invoice() -> Invoice {
+        Invoice {
+            template: "acme".to_string(),
+            client_name: Some("Jane Doe".to_string()),
+            invoice_date: NaiveDate::from_ymd_opt(2025, 3, 3),
+            subtotal: Some(70.0),
+            tax: Some(7.0),
+            discount: Some(2.0),
+            total: Some(75.0),
+            items: vec![item(1, 2.0, 10.0, 20.0), item(2, 1.0, 50.0, 50.0)],
+            ..Invoice::default()
+        }
+    }
+
+    fn failed(report: &Report) -> Vec<&str> {
+        report
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn passes_a_consistent_invoice() {
+        let report = validate(&invoice(), 0.9);
+        assert_eq!(report.checks.len(), 4);
+        assert!(failed(&report).is_empty());
+        // Corroborated fields gain confidence, up to 1
+        assert_eq!(report.confidence["items[0].total"], 1.0);
+        assert_eq!(report.confidence["client_name"], 0.9);
+        assert_eq!(report.confidence["due_date"], 0.0);
+        assert!(report.review_reasons(DEFAULT_THRESHOLD).is_empty());
+    }
+
+    #[test]
+    fn fails_a_line_whose_quantity_and_price_disagree_with_its_total() {
+        let mut invoice = invoice();
+        invoice.items[0].total = 25.0;
+        invoice.subtotal = Some(75.0);
+        invoice.total = Some(80.0);
+        let report = validate(&invoice, 0.9);
+        assert_eq!(failed(&report), ["item 1: quantity × unit price = total"]);
+        assert!((report.confidence["items[0].quantity"] - 0.9 * CONTRADICTED).abs() < 1e-6);
+        let reasons = report.review_reasons(DEFAULT_THRESHOLD);
+        assert_eq!(
+            reasons[0],
+            "item 1: quantity × unit price = total failed: expected 20.00, found 25.00"
+        );
+    }
+
+    #[test]
+    fn checks_subtotal_tax_and_discount_against_the_total() {
+        let mut invoice = invoice();
+        // The discount added instead of taken off
+        invoice.total = Some(79.0);
+        let report = validate(&invoice, 0.9);
+        assert_eq!(failed(&report), ["subtotal + tax − discount = total"]);
+
+        // Without a subtotal the line totals stand in for it
+        let mut invoice = self::invoice();
+        invoice.subtotal = None;
+        let report = validate(&invoice, 0.9);
+        assert_eq!(report.checks.len(), 3);
+        assert!(failed(&report).is_empty());
+    }
+
+    #[test]
+    fn tolerates_rounding_to_the_cent() {
+        // 3 × 3.333 = 9.999; four units' worth of half cents is 0.02
+        let mut invoice = invoice();
+        invoice.items = vec![item(1, 3.0, 3.333, 10.0)];
+        invoice.subtotal = None;
+        invoice.tax = None;
+        invoice.discount = None;
+        invoice.total = Some(10.0);
+        assert!(failed(&validate(&invoice, 0.9)).is_empty());
+
+        invoice.items[0].total = 9.999 + ROUNDING * 4.0;
+        invoice.total = Some(invoice.items[0].total);
+        assert!(failed(&validate(&invoice, 0.9)).is_empty());
+        invoice.items[0].total += 0.001;
+        invoice.total = Some(invoice.items[0].total);
+        assert_eq!(
+            failed(&validate(&invoice, 0.9)),
+            ["item 1: quantity × unit price = total"]
+        );
+    }
+
+    #[test]
+    fn sends_invoices_without_items_to_review() {
+        let mut invoice = invoice();
+        invoice.items.clear();
+        invoice.subtotal = None;
+        invoice.total = None;
+        let reasons = validate(&invoice, 0.9).review_reasons(DEFAULT_THRESHOLD);
+        assert_eq!(reasons, ["no line items were found"]);
+    }
+
+    #[test]
+    fn sends_quantities_that_are_not_whole_units_to_review() {
+        let mut invoice = invoice();
+        invoice.items[0] = item(1, 0.5, 40.0, 20.0);
+        invoice.items.push(item(3, -1.0, 5.0, -5.0));
+        invoice.subtotal = Some(65.0);
+        invoice.total = Some(70.0);
+        let reasons = validate(&invoice, 0.9).review_reasons(DEFAULT_THRESHOLD);
+        assert_eq!(
+            reasons,
+            [
+                "item 1: quantity 0.5 is not a whole number of units",
+                "item 3: quantity -1 is not a whole number of units",
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_required_fields_below_the_threshold() {
+        let mut invoice = invoice();
+        invoice.template = "generic".to_string();
+        // 0.8 × 0.7 = 0.56 for fields no check corroborates
+        let report = validate(&invoice, 0.8);
+        assert_eq!(
+            report.review_reasons(DEFAULT_THRESHOLD),
+            [
+                "client_name has a confidence of 0.56",
+                "invoice_date has a confidence of 0.56",
+                "items[0].description has a confidence of 0.56",
+                "items[1].description has a confidence of 0.56",
+            ]
+        );
+        assert!(report.review_reasons(0.5).is_empty());
+
+        // Optional header fields don't count
+        invoice.client_name = None;
+        let reasons = validate(&invoice, 1.0).review_reasons(DEFAULT_THRESHOLD);
+        assert_eq!(reasons, ["client_name has a confidence of 0.00"]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::model::{self, Block, Document, ImageFormat, Span};
+use crate::output::Writer;
+use anyhow::{Context, Result};
+use docx_rs::*;
+use std::fs::File;
+use std::path::Path;
+
+/// Abstract numbering definitions shared by all lists.
+const BULLET_LIST: usize = 1;
+const ORDERED_LIST: usize = 2;
+
+/// Word sizes are in half points.
+fn half_points(size: f32) -> usize {
+    (size * 2.0).round().max(2.0) as usize
+}
+
+/// Drawing sizes are in English Metric Units.
+fn emu(points: f32) -> u32 {
+    (points * 12700.0).round().max(1.0) as u32
+}
+
+/// Table widths are in twentieths of a point.
+fn twips(points: f32) -> usize {
+    (points * 20.0).round().max(0.0) as usize
+}
+
+fn runs(mut paragraph: Paragraph, spans: &[Span]) -> Paragraph {
+    for span in spans {
+        let mut run = Run::new().add_text(&span.text);
+        if span.bold {
+            run = run.bold();
+        }
+        if span.italic {
+            run = run.italic();
+        }
+        paragraph = paragraph.add_run(run);
+    }
+    paragraph
+}
+
+fn list_levels(mut numbering: AbstractNumbering, ordered: bool) -> AbstractNumbering {
+    for level in 0..3 {
+        let (format, text) = match (ordered, level) {
+            (false, 0) => ("bullet", "•".to_string()),
+            (false, 1) => ("bullet", "◦".to_string()),
+            (false, _) => ("bullet", "▪".to_string()),
+            (true, 0) => ("decimal", "%1.".to_string()),
+            (true, 1) => ("lowerLetter", "%2.".to_string()),
+            (true, _) => ("lowerRoman", "%3.".to_string()),
+        };
+        numbering = numbering.add_level(
+            Level::new(
+                level,
+                Start::new(1),
+                NumberFormat::new(format),
+                LevelText::new(text),
+                LevelJc::new("left"),
+            )
+            .indent(
+                Some(720 * (level as i32 + 1)),
+                Some(SpecialIndentType::Hanging(360)),
+                None,
+                None,
+            ),
+        );
+    }
+    numbering
+}
+
+/// A Word table over the same grid, with spans as `gridSpan` and row
+/// spans as a restarted vertical merge continued in the rows below.
+fn table(grid: &model::Table) -> Table {
+    let columns = grid.widths.len();
+    let rows = (0..grid.rows)
+        .map(|row| {
+            let mut cells = Vec::new();
+            let mut column = 0;
+            while column < columns {
+                let Some(cell) = grid.covering(row, column) else {
+                    cells.push(TableCell::new().add_paragraph(Paragraph::new()));
+                    column += 1;
+                    continue;
+                };
+                let mut word_cell = if cell.row == row {
+                    let mut word_cell = TableCell::new();
+                    for spans in &cell.paragraphs {
+                        word_cell = word_cell.add_paragraph(runs(Paragraph::new(), spans));
+                    }
+                    // Word needs at least one paragraph in every cell
+                    if cell.paragraphs.is_empty() {
+                        word_cell = word_cell.add_paragraph(Paragraph::new());
+                    }
+                    if cell.row_span > 1 {
+                        word_cell.vertical_merge(VMergeType::Restart)
+                    } else {
+                        word_cell
+                    }
+                } else {
+                    TableCell::new()
+                        .add_paragraph(Paragraph::new())
+                        .vertical_merge(VMergeType::Continue)
+                };
+                if cell.col_span > 1 {
+                    word_cell = word_cell.grid_span(cell.col_span);
+                }
+                cells.push(word_cell);
+                column = cell.column + cell.col_span;
+            }
+            TableRow::new(cells)
+        })
+        .collect();
+    Table::new(rows).set_grid(grid.widths.iter().map(|width| twips(*width)).collect())
+}
+
+/// Builds the Word document: heading styles sized after the PDF's own
+/// heading sizes, one bullet numbering, and a fresh decimal numbering for
+/// every ordered list so each restarts at 1.
+fn build(document: &Document) -> Docx {
+    let mut docx = Docx::new()
+        .default_size(half_points(document.body_size))
+        .add_abstract_numbering(list_levels(AbstractNumbering::new(BULLET_LIST), false))
+        .add_abstract_numbering(list_levels(AbstractNumbering::new(ORDERED_LIST), true))
+        .add_numbering(Numbering::new(BULLET_LIST, BULLET_LIST));
+
+    for level in 1..=3u8 {
+        let size = document
+            .heading_sizes
+            .get(level as usize - 1)
+            .copied()
+            .unwrap_or(document.body_size * (1.0 + 0.2 * (4 - level) as f32));
+        docx = docx.add_style(
+            Style::new(format!("Heading{}", level), StyleType::Paragraph)
+                .name(format!("Heading {}", level))
+                .size(half_points(size))
+                .bold(),
+        );
+    }
+
+    let mut next_numbering = ORDERED_LIST + 1;
+    let mut in_ordered_list = false;
+    for block in &document.blocks {
+        docx = match block {
+            Block::Heading { level, spans } => docx.add_paragraph(runs(
+                Paragraph::new().style(&format!("Heading{}", level)),
+                spans,
+            )),
+            Block::Paragraph { spans } => docx.add_paragraph(runs(Paragraph::new(), spans)),
+            Block::ListItem {
+                ordered,
+                level,
+                spans,
+            } => {
+                let id = if *ordered {
+                    if !in_ordered_list {
+                        docx = docx.add_numbering(
+                            Numbering::new(next_numbering, ORDERED_LIST)
+                                .add_override(LevelOverride::new(0).start(1)),
+                        );
+                        next_numbering += 1;
+                    }
+                    next_numbering - 1
+                } else {
+                    BULLET_LIST
+                };
+                docx.add_paragraph(runs(
+                    Paragraph::new()
+                        .numbering(NumberingId::new(id), IndentLevel::new(*level as usize)),
+                    spans,
+                ))
+            }
+            Block::Table(grid) => docx.add_table(table(grid)),
+            Block::Figure(figure) => {
+                // docx-rs panics on data it can't decode, so JPEG data taken
+                // straight from the PDF is checked first
+                let picture = &figure.image;
+                if picture.format == ImageFormat::Png
+                    || image::load_from_memory(&picture.data).is_ok()
+                {
+                    let pic = Pic::new(&picture.data).size(emu(figure.width), emu(figure.height));
+                    docx.add_paragraph(Paragraph::new().add_run(Run::new().add_image(pic)))
+                } else {
+                    eprintln!(
+                        "⚠️ Skipping a {}x{} image that could not be decoded",
+                        picture.pixel_width, picture.pixel_height
+                    );
+                    docx
+                }
+            }
+            Block::PageBreak => {
+                docx.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)))
+            }
+        };
+        // An ordered list carries on through items nested under it
+        in_ordered_list = matches!(block, Block::ListItem { ordered: true, .. })
+            || (in_ordered_list && matches!(block, Block::ListItem { .. }));
+    }
+    docx
+}
+
+pub struct DocxWriter;
+
+impl Writer for DocxWriter {
+    fn write(&self, document: &Document, output: &Path) -> Result<()> {
+        let file = File::create(output)
+            .with_context(|| format!("Failed to create output file: {}", output.display()))?;
+        build(document)
+            .build()
+            .pack(file)
+            .with_context(|| format!("Failed to write Word document: {}", output.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+use lopdf::{Dictionary, Document, Object};
+use std::collections::BTreeMap;
+
+/// Glyph names that show up in `/Differences` arrays and don't spell out
+/// their character. Single letters, digits spelled as words and `uniXXXX`
+/// names are handled in `glyph_name_to_char`.
+const GLYPH_NAMES: [(&str, char); 48] = [
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("quotesingle", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("hyphen", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("underscore", '_'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("bullet", '•'),
+    ("endash", '–'),
+    ("emdash", '—'),
+    ("quoteleft", '‘'),
+    ("quoteright", '’'),
+    ("quotedblleft", '“'),
+    ("quotedblright", '”'),
+    ("ellipsis", '…'),
+    ("copyright", '©'),
+    ("registered", '®'),
+    ("trademark", '™'),
+    ("degree", '°'),
+    ("section", '§'),
+    ("paragraph", '¶'),
+    ("Euro", '€'),
+    ("sterling", '£'),
+    ("fi", 'ﬁ'),
+    ("fl", 'ﬂ'),
+];
+
+const DIGIT_NAMES: [&str; 10] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+fn glyph_name_to_char(name: &str) -> Option<char> {
+    if let Some(&(_, c)) = GLYPH_NAMES.iter().find(|(glyph, _)| *glyph == name) {
+        return Some(c);
+    }
+    if let Some(digit) = DIGIT_NAMES.iter().position(|digit| *digit == name) {
+        return char::from_digit(digit as u32, 10);
+    }
+    if let Some(hex) = name.strip_prefix("uni").filter(|hex| hex.len() == 4) {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// One decoded character code.
+pub struct Glyph {
+    pub text: String,
+    /// Advance width in 1/1000 of the font size.
+    pub width: f32,
+    /// Single-byte code 32, which word spacing (`Tw`) applies to.
+    pub is_space: bool,
+}
+
+/// What the text extractor needs to know about a font: how to turn codes
+/// into text, how wide each glyph is, and whether the face looks bold or
+/// italic.
+pub struct Font {
+    /// `/BaseFont` without the subset tag (`ABCDEF+`).
+    pub name: String,
+    pub bold: bool,
+    pub italic: bool,
+    /// Type0 fonts address glyphs with two-byte codes.
+    two_byte: bool,
+    to_unicode: BTreeMap<u32, String>,
+    encoding: Vec<Option<char>>,
+    widths: BTreeMap<u32, f32>,
+    default_width: f32,
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    doc.dereference(object).ok().map(|(_, object)| object)
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    resolve(doc, object)?.as_dict().ok()
+}
+
+/// Hex strings, arrays and keywords of a CMap, enough to read `bfchar` and
+/// `bfrange` sections.
+enum CMapToken {
+    Hex(Vec<u8>),
+    Open,
+    Close,
+    Word(String),
+}
+
+fn cmap_tokens(data: &[u8]) -> Vec<CMapToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'<' if data.get(i + 1) != Some(&b'<') => {
+                let end = data[i..]
+                    .iter()
+                    .position(|&b| b == b'>')
+                    .map_or(data.len(), |p| i + p);
+                let digits: Vec<u8> = data[i + 1..end]
+                    .iter()
+                    .copied()
+                    .filter(u8::is_ascii_hexdigit)
+                    .collect();
+                let bytes = digits
+                    .chunks(2)
+                    .map(|pair| {
+                        let text = std::str::from_utf8(pair).unwrap_or("0");
+                        let value = u8::from_str_radix(text, 16).unwrap_or(0);
+                        // A lone trailing digit stands for its high nibble
+                        if pair.len() == 1 { value << 4 } else { value }
+                    })
+                    .collect();
+                tokens.push(CMapToken::Hex(bytes));
+                i = end + 1;
+            }
+            b'[' => {
+                tokens.push(CMapToken::Open);
+                i += 1;
+            }
+            b']' => {
+                tokens.push(CMapToken::Close);
+                i += 1;
+            }
+            b if b.is_ascii_alphabetic() => {
+                let end = data[i..]
+                    .iter()
+                    .position(|b| !b.is_ascii_alphanumeric())
+                    .map_or(data.len(), |p| i + p);
+                tokens.push(CMapToken::Word(
+                    String::from_utf8_lossy(&data[i..end]).to_string(),
+                ));
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    tokens
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, &b| (value << 8) | b as u32)
+}
+
+fn utf16_text(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Reads the `bfchar` and `bfrange` mappings of a `/ToUnicode` CMap.
+fn parse_to_unicode(data: &[u8]) -> BTreeMap<u32, String> {
+    let tokens = cmap_tokens(data);
+    let mut map = BTreeMap::new();
+    let mut i = 0;
+    let mut section = "";
+    while i < tokens.len() {
+        match (&tokens[i], section) {
+            (CMapToken::Word(word), _) => {
+                section = match word.as_str() {
+                    "beginbfchar" => "bfchar",
+                    "beginbfrange" => "bfrange",
+                    _ => "",
+                };
+                i += 1;
+            }
+            (CMapToken::Hex(code), "bfchar") => {
+                if let Some(CMapToken::Hex(text)) = tokens.get(i + 1) {
+                    map.insert(code_value(code), utf16_text(text));
+                }
+                i += 2;
+            }
+            (CMapToken::Hex(low), "bfrange") => {
+                let (low, high) = match tokens.get(i + 1) {
+                    Some(CMapToken::Hex(high)) => (code_value(low), code_value(high)),
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                match tokens.get(i + 2) {
+                    // One destination, incremented along the range
+                    Some(CMapToken::Hex(start)) => {
+                        let mut units: Vec<u16> = start
+                            .chunks(2)
+                            .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                            .collect();
+                        for code in low..=high.min(low + 0xffff) {
+                            map.insert(code, String::from_utf16_lossy(&units));
+                            if let Some(last) = units.last_mut() {
+                                *last = last.wrapping_add(1);
+                            }
+                        }
+                        i += 3;
+                    }
+                    // An explicit destination per code
+                    Some(CMapToken::Open) => {
+                        let mut code = low;
+                        i += 3;
+                        while let Some(CMapToken::Hex(text)) = tokens.get(i) {
+                            if code <= high {
+                                map.insert(code, utf16_text(text));
+                            }
+                            code += 1;
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    _ => i += 2,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    map
+}
+
+impl Font {
+    pub fn load(doc: &Document, dict: &Dictionary) -> Font {
+        let base = dict
+            .get(b"BaseFont")
+            .and_then(Object::as_name_str)
+            .unwrap_or("");
+        let name = match base.split_once('+') {
+            Some((tag, rest)) if tag.len() == 6 => rest,
+            _ => base,
+        }
+        .to_string();
+        let two_byte = matches!(dict.get(b"Subtype").and_then(Object::as_name), Ok(b"Type0"));
+
+        // Type0 fonts keep their widths and descriptor on the descendant
+        let descendant = dict
+            .get(b"DescendantFonts")
+            .ok()
+            .and_then(|d| resolve(doc, d))
+            .and_then(|d| d.as_array().ok())
+            .and_then(|fonts| fonts.first())
+            .and_then(|font| resolve_dict(doc, font));
+        let metrics = descendant.unwrap_or(dict);
+        let descriptor = metrics
+            .get(b"FontDescriptor")
+            .ok()
+            .and_then(|d| resolve_dict(doc, d));
+
+        let lower = name.to_ascii_lowercase();
+        let flags = descriptor
+            .and_then(|d| d.get(b"Flags").and_then(Object::as_i64).ok())
+            .unwrap_or(0);
+        let weight = descriptor
+            .and_then(|d| d.get(b"FontWeight").ok())
+            .and_then(|w| w.as_float().ok())
+            .unwrap_or(400.0);
+        let italic_angle = descriptor
+            .and_then(|d| d.get(b"ItalicAngle").ok())
+            .and_then(|a| a.as_float().ok())
+            .unwrap_or(0.0);
+        let bold = ["bold", "black", "heavy", "semibold", "demi"]
+            .iter()
+            .any(|word| lower.contains(word))
+            || weight >= 600.0
+            || flags & (1 << 18) != 0;
+        let italic = lower.contains("italic")
+            || lower.contains("oblique")
+            || italic_angle != 0.0
+            || flags & (1 << 6) != 0;
+
+        let to_unicode = dict
+            .get(b"ToUnicode")
+            .ok()
+            .and_then(|t| resolve(doc, t))
+            .and_then(|t| t.as_stream().ok())
+            .map(|stream| {
+                let data = stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone());
+                parse_to_unicode(&data)
+            })
+            .unwrap_or_default();
+
+        let mut encoding: Vec<Option<char>> = (0..=255u8)
+            .map(|code| {
+                Document::decode_text(Some(dict.get_font_encoding()), &[code])
+                    .chars()
+                    .next()
+            })
+            .collect();
+        if let Some(differences) = dict
+            .get(b"Encoding")
+            .ok()
+            .and_then(|e| resolve_dict(doc, e))
+            .and_then(|e| e.get(b"Differences").ok())
+            .and_then(|d| d.as_array().ok())
+        {
+            let mut code = 0usize;
+            for item in differences {
+                match item {
+                    Object::Integer(start) => code = *start as usize,
+                    Object::Name(glyph) => {
+                        if code < 256 {
+                            encoding[code] = glyph_name_to_char(&String::from_utf8_lossy(glyph));
+                        }
+                        code += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut widths = BTreeMap::new();
+        let default_width;
+        if two_byte {
+            default_width = metrics
+                .get(b"DW")
+                .and_then(Object::as_i64)
+                .map_or(1000.0, |w| w as f32);
+            // /W mixes `c [w1 w2 ...]` and `first last w` entries
+            let entries = metrics
+                .get(b"W")
+                .ok()
+                .and_then(|w| resolve(doc, w))
+                .and_then(|w| w.as_array().ok())
+                .cloned()
+                .unwrap_or_default();
+            let mut i = 0;
+            while i + 1 < entries.len() {
+                let Ok(first) = entries[i].as_i64() else {
+                    break;
+                };
+                match resolve(doc, &entries[i + 1]) {
+                    Some(Object::Array(list)) => {
+                        for (offset, width) in list.iter().enumerate() {
+                            if let Ok(width) = width.as_float() {
+                                widths.insert(first as u32 + offset as u32, width);
+                            }
+                        }
+                        i += 2;
+                    }
+                    Some(last) => {
+                        let last = last.as_i64().unwrap_or(first);
+                        let width = entries
+                            .get(i + 2)
+                            .and_then(|w| w.as_float().ok())
+                            .unwrap_or(default_width);
+                        for code in first..=last.min(first + 0xffff) {
+                            widths.insert(code as u32, width);
+                        }
+                        i += 3;
+                    }
+                    None => break,
+                }
+            }
+        } else {
+            default_width = descriptor
+                .and_then(|d| d.get(b"MissingWidth").ok())
+                .and_then(|w| w.as_float().ok())
+                .filter(|w| *w > 0.0)
+                .unwrap_or(500.0);
+            let first = dict.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0);
+            if let Some(list) = dict
+                .get(b"Widths")
+                .ok()
+                .and_then(|w| resolve(doc, w))
+                .and_then(|w| w.as_array().ok())
+            {
+                for (offset, width) in list.iter().enumerate() {
+                    if let Some(width) = resolve(doc, width).and_then(|w| w.as_float().ok()) {
+                        widths.insert((first + offset as i64) as u32, width);
+                    }
+                }
+            }
+        }
+
+        Font {
+            name,
+            bold,
+            italic,
+            two_byte,
+            to_unicode,
+            encoding,
+            widths,
+            default_width,
+        }
+    }
+
+    /// Splits a shown string into glyphs.
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Glyph> {
+        let codes: Vec<u32> = if self.two_byte {
+            bytes.chunks(2).map(code_value).collect()
+        } else {
+            bytes.iter().map(|&b| b as u32).collect()
+        };
+        codes
+            .into_iter()
+            .map(|code| {
+                let text = match self.to_unicode.get(&code) {
+                    Some(text) => text.clone(),
+                    None if self.two_byte => String::new(),
+                    None => self.encoding[code as usize]
+                        .map(String::from)
+                        .unwrap_or_default(),
+                };
+                Glyph {
+                    text,
+                    width: self
+                        .widths
+                        .get(&code)
+                        .copied()
+                        .unwrap_or(self.default_width),
+                    is_space: !self.two_byte && code == 32,
+                }
+            })
+            .collect()
+    }
+}
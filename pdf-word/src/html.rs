@@ -0,0 +1,262 @@
+use crate::model::{Block, Document, Span, Table};
+use crate::output::{self, Writer};
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// CSS pixels per point.
+const PX_PER_POINT: f32 = 96.0 / 72.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn inline(spans: &[Span]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        let mut text = escape(&span.text);
+        if span.italic {
+            text = format!("<em>{}</em>", text);
+        }
+        if span.bold {
+            text = format!("<strong>{}</strong>", text);
+        }
+        out.push_str(&text);
+    }
+    out
+}
+
+fn table(table: &Table) -> String {
+    let mut html = String::from("<table>\n");
+    for row in 0..table.rows {
+        html.push_str("<tr>");
+        for cell in table.cells.iter().filter(|cell| cell.row == row) {
+            html.push_str("<td");
+            if cell.col_span > 1 {
+                let _ = write!(html, " colspan=\"{}\"", cell.col_span);
+            }
+            if cell.row_span > 1 {
+                let _ = write!(html, " rowspan=\"{}\"", cell.row_span);
+            }
+            html.push('>');
+            match cell.paragraphs.as_slice() {
+                [spans] => html.push_str(&inline(spans)),
+                paragraphs => {
+                    for spans in paragraphs {
+                        let _ = write!(html, "<p>{}</p>", inline(spans));
+                    }
+                }
+            }
+            html.push_str("</td>");
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// The lists open at a point, innermost last, by whether they are ordered.
+/// Each has its latest item still open, since a nested list goes inside
+/// its parent's item.
+#[derive(Default)]
+struct Lists {
+    open: Vec<bool>,
+}
+
+impl Lists {
+    fn item(&mut self, html: &mut String, ordered: bool, level: usize, spans: &[Span]) {
+        // A list can't be more than one level deeper than its parent
+        let depth = (level + 1).min(self.open.len() + 1);
+        while self.open.len() > depth {
+            self.close_one(html);
+        }
+        // Switching between bullets and numbers starts a new list
+        if self.open.len() == depth && self.open.last() != Some(&ordered) {
+            self.close_one(html);
+        }
+        if self.open.len() == depth {
+            html.push_str("</li>\n");
+        }
+        while self.open.len() < depth {
+            html.push_str(if ordered { "<ol>\n" } else { "<ul>\n" });
+            self.open.push(ordered);
+        }
+        let _ = write!(html, "<li>{}", inline(spans));
+    }
+
+    fn close_one(&mut self, html: &mut String) {
+        if let Some(ordered) = self.open.pop() {
+            html.push_str(if ordered {
+                "</li>\n</ol>\n"
+            } else {
+                "</li>\n</ul>\n"
+            });
+        }
+    }
+
+    fn close(&mut self, html: &mut String) {
+        while !self.open.is_empty() {
+            self.close_one(html);
+        }
+    }
+}
+
+pub struct HtmlWriter;
+
+impl Writer for HtmlWriter {
+    fn write(&self, document: &Document, output: &Path) -> Result<()> {
+        let mut images = output::save_images(document, output)?.into_iter();
+        let title = output
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("Document");
+
+        let mut html = String::new();
+        let _ = writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>",
+            escape(title)
+        );
+        let mut lists = Lists::default();
+        for block in &document.blocks {
+            if let Block::ListItem {
+                ordered,
+                level,
+                spans,
+            } = block
+            {
+                lists.item(&mut html, *ordered, *level as usize, spans);
+                continue;
+            }
+            lists.close(&mut html);
+            match block {
+                Block::Heading { level, spans } => {
+                    let _ = writeln!(html, "<h{0}>{1}</h{0}>", level, inline(spans));
+                }
+                Block::Paragraph { spans } => {
+                    let _ = writeln!(html, "<p>{}</p>", inline(spans));
+                }
+                Block::Table(grid) => {
+                    let _ = writeln!(html, "{}", table(grid));
+                }
+                Block::Figure(figure) => {
+                    let _ = writeln!(
+                        html,
+                        "<figure><img src=\"{}\" width=\"{}\" height=\"{}\" alt=\"\"></figure>",
+                        escape(&images.next().unwrap_or_default()),
+                        (figure.width * PX_PER_POINT).round(),
+                        (figure.height * PX_PER_POINT).round()
+                    );
+                }
+                Block::PageBreak => html.push_str("<hr>\n"),
+                Block::ListItem { .. } => {}
+            }
+        }
+        lists.close(&mut html);
+        html.push_str("</body>\n</html>\n");
+
+        fs::write(output, html)
+            .with_context(|| format!("Failed to write HTML: {}", output.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Cell;
+
+    fn span(text: &str, bold: bool, italic: bool) -> Span {
+        Span {
+            text: text.to_string(),
+            bold,
+            italic,
+        }
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape(r#"<b>"Fish" & chips</b>"#),
+            "&lt;b&gt;&quot;Fish&quot; &amp; chips&lt;/b&gt;"
+        );
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn emphasis_nests_inside_strong() {
+        let spans = [
+            span("a < b ", false, false),
+            span("bold", true, false),
+            span("both", true, true),
+        ];
+        assert_eq!(
+            inline(&spans),
+            "a &lt; b <strong>bold</strong><strong><em>both</em></strong>"
+        );
+    }
+
+    #[test]
+    fn spans_become_attributes() {
+        let cell = |row, column, row_span, col_span, paragraphs: &[&str]| Cell {
+            row,
+            column,
+            row_span,
+            col_span,
+            paragraphs: paragraphs
+                .iter()
+                .map(|text| vec![span(text, false, false)])
+                .collect(),
+        };
+        let grid = Table {
+            widths: vec![50.0, 50.0, 50.0],
+            rows: 2,
+            cells: vec![
+                cell(0, 0, 2, 1, &["Tall"]),
+                cell(0, 1, 1, 2, &["Wide", "two lines"]),
+                cell(1, 1, 1, 1, &["x"]),
+                cell(1, 2, 1, 1, &[]),
+            ],
+        };
+        assert_eq!(
+            table(&grid),
+            "<table>\n\
+             <tr><td rowspan=\"2\">Tall</td><td colspan=\"2\"><p>Wide</p><p>two lines</p></td></tr>\n\
+             <tr><td>x</td><td></td></tr>\n\
+             </table>"
+        );
+    }
+
+    #[test]
+    fn writes_nested_lists_and_escaped_title() {
+        let item = |ordered, level, text| Block::ListItem {
+            ordered,
+            level,
+            spans: vec![span(text, false, false)],
+        };
+        let document = Document {
+            blocks: vec![
+                item(false, 0, "one"),
+                item(false, 1, "one & a half"),
+                item(true, 0, "two"),
+                Block::Paragraph {
+                    spans: vec![span("end", false, false)],
+                },
+            ],
+            body_size: 11.0,
+            heading_sizes: Vec::new(),
+        };
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("a&b.html");
+        HtmlWriter.write(&document, &output).unwrap();
+        let html = fs::read_to_string(&output).unwrap();
+        assert!(html.contains("<title>a&amp;b</title>"));
+        assert!(html.contains(
+            "<ul>\n<li>one<ul>\n<li>one &amp; a half</li>\n</ul>\n</li>\n</ul>\n\
+             <ol>\n<li>two</li>\n</ol>\n<p>end</p>\n"
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::model::{Image, ImageFormat};
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, GrayImage, ImageOutputFormat, RgbImage};
+use lopdf::{Dictionary, Document, Object, Stream};
+use std::io::{Cursor, Read};
+
+fn filters(doc: &Document, dict: &Dictionary) -> Vec<Vec<u8>> {
+    match dict.get(b"Filter").map(|f| doc.dereference(f)) {
+        Ok(Ok((_, Object::Name(name)))) => vec![name.clone()],
+        Ok(Ok((_, Object::Array(names)))) => names
+            .iter()
+            .filter_map(|name| name.as_name().ok())
+            .map(<[u8]>::to_vec)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Colour components per pixel, for the colour spaces that map directly
+/// onto PNG: gray and RGB, device or calibrated or ICC based.
+fn components(doc: &Document, color_space: &Object) -> Option<u8> {
+    let (_, color_space) = doc.dereference(color_space).ok()?;
+    match color_space {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" | b"G" => Some(1),
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(3),
+            _ => None,
+        },
+        Object::Array(items) => match items.first()?.as_name().ok()? {
+            b"CalGray" => Some(1),
+            b"CalRGB" => Some(3),
+            b"ICCBased" => {
+                let (_, profile) = doc.dereference(items.get(1)?).ok()?;
+                let profile = profile.as_stream().ok()?;
+                match profile.dict.get(b"N").and_then(Object::as_i64).ok()? {
+                    1 => Some(1),
+                    3 => Some(3),
+                    _ => None,
+                }
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Inflates image samples and undoes PNG predictors. lopdf leaves image
+/// streams alone, so this can't go through `decompressed_content`.
+fn inflate(
+    dict: &Dictionary,
+    content: &[u8],
+    row_bytes: usize,
+    pixel_bytes: usize,
+) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(content).read_to_end(&mut data).ok()?;
+    let predictor = dict
+        .get(b"DecodeParms")
+        .and_then(Object::as_dict)
+        .and_then(|params| params.get(b"Predictor"))
+        .and_then(Object::as_i64)
+        .unwrap_or(1);
+    if predictor < 10 {
+        return (predictor == 1).then_some(data);
+    }
+
+    // Each row is a filter type byte followed by the filtered bytes
+    let mut samples = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_bytes];
+    for row in data.chunks(row_bytes + 1) {
+        let (&filter, row) = row.split_first()?;
+        let mut current = row.to_vec();
+        current.resize(row_bytes, 0);
+        for i in 0..row_bytes {
+            let left = if i >= pixel_bytes {
+                current[i - pixel_bytes]
+            } else {
+                0
+            };
+            let up = previous[i];
+            let up_left = if i >= pixel_bytes {
+                previous[i - pixel_bytes]
+            } else {
+                0
+            };
+            current[i] = current[i].wrapping_add(match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => {
+                    let estimate = left as i16 + up as i16 - up_left as i16;
+                    let (a, b, c) = (
+                        (estimate - left as i16).abs(),
+                        (estimate - up as i16).abs(),
+                        (estimate - up_left as i16).abs(),
+                    );
+                    if a <= b && a <= c {
+                        left
+                    } else if b <= c {
+                        up
+                    } else {
+                        up_left
+                    }
+                }
+                _ => return None,
+            });
+        }
+        samples.extend_from_slice(&current);
+        previous = current;
+    }
+    Some(samples)
+}
+
+/// Reads an image XObject. JPEG data is kept as it is; Flate-compressed
+/// or uncompressed 8-bit gray and RGB samples are encoded as PNG.
+/// Anything else, including masks, is left out.
+pub fn load(doc: &Document, stream: &Stream) -> Option<Image> {
+    let dict = &stream.dict;
+    let dimension = |key: &[u8]| {
+        dict.get(key)
+            .ok()
+            .and_then(|value| value.as_i64().ok())
+            .and_then(|value| u32::try_from(value).ok())
+            .filter(|value| *value > 0)
+    };
+    let (pixel_width, pixel_height) = (dimension(b"Width")?, dimension(b"Height")?);
+    if matches!(dict.get(b"ImageMask"), Ok(Object::Boolean(true))) {
+        return None;
+    }
+
+    let filters = filters(doc, dict);
+    if filters == [b"DCTDecode".to_vec()] {
+        return Some(Image {
+            format: ImageFormat::Jpeg,
+            data: stream.content.clone(),
+            pixel_width,
+            pixel_height,
+        });
+    }
+
+    let bits = dict
+        .get(b"BitsPerComponent")
+        .and_then(Object::as_i64)
+        .ok()?;
+    if bits != 8 {
+        return None;
+    }
+    let components = components(doc, dict.get(b"ColorSpace").ok()?)?;
+    let row_bytes = pixel_width as usize * components as usize;
+    let mut samples = match filters.as_slice() {
+        [] => stream.content.clone(),
+        [filter] if filter == b"FlateDecode" => {
+            inflate(dict, &stream.content, row_bytes, components as usize)?
+        }
+        _ => return None,
+    };
+    samples.truncate(row_bytes * pixel_height as usize);
+    let image = match components {
+        1 => DynamicImage::ImageLuma8(GrayImage::from_raw(pixel_width, pixel_height, samples)?),
+        _ => DynamicImage::ImageRgb8(RgbImage::from_raw(pixel_width, pixel_height, samples)?),
+    };
+    let mut data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+        .ok()?;
+    Some(Image {
+        format: ImageFormat::Png,
+        data,
+        pixel_width,
+        pixel_height,
+    })
+}
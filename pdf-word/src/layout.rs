@@ -0,0 +1,589 @@
+use crate::fonts::Font;
+use crate::images;
+use crate::model::Image;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use std::rc::Rc;
+use unicode_normalization::UnicodeNormalization;
+
+/// Form XObjects can nest; deeper than this is almost certainly a cycle.
+const MAX_FORM_DEPTH: usize = 8;
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `a` applied first, then `b`.
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+/// A stretch of text drawn with one font at one size on one baseline.
+/// Coordinates are in page space, origin bottom left.
+#[derive(Debug, Clone)]
+pub struct TextRun {
+    pub text: String,
+    pub x: f32,
+    pub end_x: f32,
+    /// Baseline.
+    pub y: f32,
+    pub size: f32,
+    pub font: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// A straight horizontal or vertical line drawn on the page, either
+/// stroked or as a thin filled rectangle, in page space.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub horizontal: bool,
+    /// y of a horizontal rule, x of a vertical one.
+    pub position: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Rectangles thinner than this are drawn lines, not areas.
+const RULE_THICKNESS: f32 = 2.0;
+
+impl Rule {
+    fn between(x1: f32, y1: f32, x2: f32, y2: f32) -> Option<Rule> {
+        let (width, height) = ((x2 - x1).abs(), (y2 - y1).abs());
+        if width <= RULE_THICKNESS && height <= RULE_THICKNESS {
+            // A dot, like the corner squares where borders meet
+            None
+        } else if height <= RULE_THICKNESS {
+            Some(Rule {
+                horizontal: true,
+                position: (y1 + y2) / 2.0,
+                start: x1.min(x2),
+                end: x1.max(x2),
+            })
+        } else if width <= RULE_THICKNESS {
+            Some(Rule {
+                horizontal: false,
+                position: (x1 + x2) / 2.0,
+                start: y1.min(y2),
+                end: y1.max(y2),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// An image drawn on the page, sized in points as the CTM scales it.
+#[derive(Debug, Clone)]
+pub struct PlacedImage {
+    pub image: Rc<Image>,
+    /// Page-space y of the top edge.
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Everything read from one page.
+#[derive(Debug, Clone)]
+pub struct PageLayout {
+    pub runs: Vec<TextRun>,
+    pub rules: Vec<Rule>,
+    pub images: Vec<PlacedImage>,
+}
+
+/// Graphics state that `q`/`Q` save and restore, text state included.
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Option<Rc<Font>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl Default for GraphicsState {
+    fn default() -> GraphicsState {
+        GraphicsState {
+            ctm: IDENTITY,
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+        }
+    }
+}
+
+fn transform(matrix: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (
+        x * matrix[0] + y * matrix[2] + matrix[4],
+        x * matrix[1] + y * matrix[3] + matrix[5],
+    )
+}
+
+fn numbers(operands: &[Object]) -> Vec<f32> {
+    operands.iter().filter_map(|o| o.as_float().ok()).collect()
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object).ok()?.1.as_dict().ok()
+}
+
+/// Looks a page attribute up the page tree, for inherited keys.
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        node = node
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|parent| doc.get_dictionary(parent))
+            .ok()?;
+    }
+    None
+}
+
+/// Decoded content of a page, with its streams joined by newlines so that
+/// tokens at their edges don't run together.
+fn page_content(doc: &Document, page_id: ObjectId) -> Vec<u8> {
+    let mut content = Vec::new();
+    for stream_id in doc.get_page_contents(page_id) {
+        if let Ok(stream) = doc.get_object(stream_id).and_then(Object::as_stream) {
+            content.extend(
+                stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone()),
+            );
+            content.push(b'\n');
+        }
+    }
+    content
+}
+
+/// The path under construction, in page space. Only straight segments
+/// and rectangles are kept since only they can be table borders.
+#[derive(Default)]
+struct Path {
+    lines: Vec<[f32; 4]>,
+    rectangles: Vec<[f32; 4]>,
+    current: (f32, f32),
+    start: (f32, f32),
+}
+
+impl Path {
+    fn close(&mut self) {
+        let ((x1, y1), (x2, y2)) = (self.current, self.start);
+        self.lines.push([x1, y1, x2, y2]);
+        self.current = self.start;
+    }
+}
+
+/// Walks content streams, tracking just enough state to know where each
+/// glyph lands and in which font, and where lines are drawn.
+struct PageReader<'a> {
+    doc: &'a Document,
+    fonts: HashMap<ObjectId, Rc<Font>>,
+    /// Decoded images, `None` for those in formats that are skipped.
+    images: HashMap<ObjectId, Option<Rc<Image>>>,
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    path: Path,
+    runs: Vec<TextRun>,
+    rules: Vec<Rule>,
+    placed_images: Vec<PlacedImage>,
+}
+
+impl<'a> PageReader<'a> {
+    fn new(doc: &'a Document) -> PageReader<'a> {
+        PageReader {
+            doc,
+            fonts: HashMap::new(),
+            images: HashMap::new(),
+            state: GraphicsState::default(),
+            stack: Vec::new(),
+            text_matrix: IDENTITY,
+            line_matrix: IDENTITY,
+            path: Path::default(),
+            runs: Vec::new(),
+            rules: Vec::new(),
+            placed_images: Vec::new(),
+        }
+    }
+
+    /// Turns the finished path into rules. Stroked segments and rectangle
+    /// edges are lines; filled rectangles only when they are thin, as wider
+    /// ones are backgrounds and shading.
+    fn paint(&mut self, stroke: bool) {
+        let path = std::mem::take(&mut self.path);
+        for [x1, y1, x2, y2] in path.rectangles {
+            if let Some(rule) = Rule::between(x1, y1, x2, y2) {
+                self.rules.push(rule);
+            } else if stroke {
+                let edges = [
+                    (x1, y1, x2, y1),
+                    (x1, y2, x2, y2),
+                    (x1, y1, x1, y2),
+                    (x2, y1, x2, y2),
+                ];
+                self.rules.extend(
+                    edges
+                        .into_iter()
+                        .filter_map(|(a, b, c, d)| Rule::between(a, b, c, d)),
+                );
+            }
+        }
+        if stroke {
+            self.rules.extend(
+                path.lines
+                    .into_iter()
+                    .filter_map(|[x1, y1, x2, y2]| Rule::between(x1, y1, x2, y2)),
+            );
+        }
+    }
+
+    fn font(&mut self, resources: Option<&Dictionary>, name: &[u8]) -> Option<Rc<Font>> {
+        let fonts = resolve_dict(self.doc, resources?.get(b"Font").ok()?)?;
+        let entry = fonts.get(name).ok()?;
+        match entry.as_reference() {
+            Ok(id) => {
+                if let Some(font) = self.fonts.get(&id) {
+                    return Some(font.clone());
+                }
+                let font = Rc::new(Font::load(self.doc, self.doc.get_dictionary(id).ok()?));
+                self.fonts.insert(id, font.clone());
+                Some(font)
+            }
+            Err(_) => Some(Rc::new(Font::load(self.doc, entry.as_dict().ok()?))),
+        }
+    }
+
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        self.line_matrix = multiply(&[1.0, 0.0, 0.0, 1.0, tx, ty], &self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    /// Appends a glyph to the last run when it continues it, otherwise
+    /// starts a new run. Gaps between runs are turned into spaces later,
+    /// when lines are put together.
+    fn push_glyph(&mut self, font: &Font, text: &str, x: f32, end_x: f32, y: f32, size: f32) {
+        if let Some(last) = self.runs.last_mut()
+            && last.font == font.name
+            && (last.size - size).abs() < 0.1
+            && (last.y - y).abs() < size * 0.1
+            && (x - last.end_x).abs() < size * 0.1
+        {
+            last.text.push_str(text);
+            last.end_x = end_x;
+            return;
+        }
+        if text.trim().is_empty() {
+            return;
+        }
+        self.runs.push(TextRun {
+            text: text.to_string(),
+            x,
+            end_x,
+            y,
+            size,
+            font: font.name.clone(),
+            bold: font.bold,
+            italic: font.italic,
+        });
+    }
+
+    fn show(&mut self, bytes: &[u8]) {
+        let Some(font) = self.state.font.clone() else {
+            return;
+        };
+        let state = &self.state;
+        let (size, scale) = (state.font_size, state.horizontal_scale);
+        let (char_spacing, word_spacing, rise) =
+            (state.char_spacing, state.word_spacing, state.rise);
+        for glyph in font.decode(bytes) {
+            let render = multiply(&self.text_matrix, &self.state.ctm);
+            let origin = multiply(&[size * scale, 0.0, 0.0, size, 0.0, rise], &render);
+            let advance = (glyph.width / 1000.0 * size
+                + char_spacing
+                + if glyph.is_space { word_spacing } else { 0.0 })
+                * scale;
+            self.text_matrix = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &self.text_matrix);
+            let end = multiply(&self.text_matrix, &self.state.ctm);
+
+            let text: String = glyph.text.nfkc().collect();
+            let glyph_size = origin[2].hypot(origin[3]);
+            self.push_glyph(&font, &text, origin[4], end[4], origin[5], glyph_size);
+        }
+    }
+
+    /// `TJ`: strings and position adjustments in thousandths of an em.
+    fn show_adjusted(&mut self, items: &[Object]) {
+        for item in items {
+            match item {
+                Object::String(bytes, _) => self.show(bytes),
+                _ => {
+                    if let Ok(adjustment) = item.as_float() {
+                        let tx = -adjustment / 1000.0
+                            * self.state.font_size
+                            * self.state.horizontal_scale;
+                        self.text_matrix =
+                            multiply(&[1.0, 0.0, 0.0, 1.0, tx, 0.0], &self.text_matrix);
+                    }
+                }
+            }
+        }
+    }
+
+    /// `Do`: runs a form XObject's content or records where an image goes.
+    fn draw_xobject(&mut self, resources: Option<&Dictionary>, name: &[u8], depth: usize) {
+        let Some(xobjects) = resources
+            .and_then(|r| r.get(b"XObject").ok())
+            .and_then(|x| resolve_dict(self.doc, x))
+        else {
+            return;
+        };
+        let Some((id, stream)) = xobjects
+            .get(name)
+            .ok()
+            .and_then(|x| self.doc.dereference(x).ok())
+            .and_then(|(id, x)| Some((id, x.as_stream().ok()?)))
+        else {
+            return;
+        };
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Form") => self.run_form(resources, stream, depth),
+            Ok(b"Image") => self.place_image(id, stream),
+            _ => {}
+        }
+    }
+
+    /// An image fills the unit square of the CTM at the time it is drawn.
+    fn place_image(&mut self, id: Option<ObjectId>, stream: &Stream) {
+        let image = match id {
+            Some(id) => self
+                .images
+                .entry(id)
+                .or_insert_with(|| images::load(self.doc, stream).map(Rc::new))
+                .clone(),
+            None => images::load(self.doc, stream).map(Rc::new),
+        };
+        let Some(image) = image else {
+            return;
+        };
+        let ctm = &self.state.ctm;
+        let top = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| transform(ctm, x, y).1)
+            .fold(f32::MIN, f32::max);
+        self.placed_images.push(PlacedImage {
+            image,
+            top,
+            width: ctm[0].hypot(ctm[1]),
+            height: ctm[2].hypot(ctm[3]),
+        });
+    }
+
+    fn run_form(&mut self, resources: Option<&Dictionary>, stream: &Stream, depth: usize) {
+        if depth >= MAX_FORM_DEPTH {
+            return;
+        }
+
+        let matrix = stream
+            .dict
+            .get(b"Matrix")
+            .and_then(Object::as_array)
+            .map(|m| numbers(m))
+            .ok()
+            .and_then(|m| <Matrix>::try_from(m).ok())
+            .unwrap_or(IDENTITY);
+        let content = stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone());
+        // A form without its own resources uses those of whatever draws it
+        let form_resources = stream
+            .dict
+            .get(b"Resources")
+            .ok()
+            .and_then(|r| resolve_dict(self.doc, r))
+            .or(resources);
+
+        self.stack.push(self.state.clone());
+        self.state.ctm = multiply(&matrix, &self.state.ctm);
+        let saved_text = (self.text_matrix, self.line_matrix);
+        self.run(&content, form_resources, depth + 1);
+        (self.text_matrix, self.line_matrix) = saved_text;
+        if let Some(state) = self.stack.pop() {
+            self.state = state;
+        }
+    }
+
+    fn run(&mut self, content: &[u8], resources: Option<&Dictionary>, depth: usize) {
+        let Ok(content) = Content::decode(content) else {
+            return;
+        };
+        let stack_depth = self.stack.len();
+        for operation in content.operations {
+            let operands = &operation.operands;
+            match operation.operator.as_str() {
+                "q" => self.stack.push(self.state.clone()),
+                "Q" => {
+                    // Never pop what an enclosing form pushed
+                    if self.stack.len() > stack_depth
+                        && let Some(state) = self.stack.pop()
+                    {
+                        self.state = state;
+                    }
+                }
+                "cm" => {
+                    if let Ok(matrix) = <Matrix>::try_from(numbers(operands)) {
+                        self.state.ctm = multiply(&matrix, &self.state.ctm);
+                    }
+                }
+                "m" => {
+                    if let [x, y] = numbers(operands)[..] {
+                        self.path.current = transform(&self.state.ctm, x, y);
+                        self.path.start = self.path.current;
+                    }
+                }
+                "l" => {
+                    if let [x, y] = numbers(operands)[..] {
+                        let (x1, y1) = self.path.current;
+                        let (x2, y2) = transform(&self.state.ctm, x, y);
+                        self.path.lines.push([x1, y1, x2, y2]);
+                        self.path.current = (x2, y2);
+                    }
+                }
+                "c" | "v" | "y" => {
+                    if let [.., x, y] = numbers(operands)[..] {
+                        self.path.current = transform(&self.state.ctm, x, y);
+                    }
+                }
+                "h" => self.path.close(),
+                "re" => {
+                    if let [x, y, width, height] = numbers(operands)[..] {
+                        let (x1, y1) = transform(&self.state.ctm, x, y);
+                        let (x2, y2) = transform(&self.state.ctm, x + width, y + height);
+                        self.path.rectangles.push([x1, y1, x2, y2]);
+                        self.path.current = (x1, y1);
+                        self.path.start = (x1, y1);
+                    }
+                }
+                "S" | "B" | "B*" => self.paint(true),
+                "s" | "b" | "b*" => {
+                    self.path.close();
+                    self.paint(true);
+                }
+                "f" | "F" | "f*" => self.paint(false),
+                "n" => self.path = Path::default(),
+                "BT" => {
+                    self.text_matrix = IDENTITY;
+                    self.line_matrix = IDENTITY;
+                }
+                "Tf" => {
+                    if let (Some(name), Some(size)) = (
+                        operands.first().and_then(|n| n.as_name().ok()),
+                        operands.get(1).and_then(|s| s.as_float().ok()),
+                    ) {
+                        self.state.font = self.font(resources, name);
+                        self.state.font_size = size;
+                    }
+                }
+                "Tc" | "Tw" | "Tz" | "TL" | "Ts" => {
+                    let Some(&value) = numbers(operands).first() else {
+                        continue;
+                    };
+                    match operation.operator.as_str() {
+                        "Tc" => self.state.char_spacing = value,
+                        "Tw" => self.state.word_spacing = value,
+                        "Tz" => self.state.horizontal_scale = value / 100.0,
+                        "TL" => self.state.leading = value,
+                        _ => self.state.rise = value,
+                    }
+                }
+                "Td" | "TD" => {
+                    if let [tx, ty] = numbers(operands)[..] {
+                        if operation.operator == "TD" {
+                            self.state.leading = -ty;
+                        }
+                        self.move_line(tx, ty);
+                    }
+                }
+                "Tm" => {
+                    if let Ok(matrix) = <Matrix>::try_from(numbers(operands)) {
+                        self.line_matrix = matrix;
+                        self.text_matrix = matrix;
+                    }
+                }
+                "T*" => self.move_line(0.0, -self.state.leading),
+                "Tj" => {
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show(bytes);
+                    }
+                }
+                "'" => {
+                    self.move_line(0.0, -self.state.leading);
+                    if let Some(Object::String(bytes, _)) = operands.first() {
+                        self.show(bytes);
+                    }
+                }
+                "\"" => {
+                    if let [word_spacing, char_spacing, ..] = numbers(operands)[..] {
+                        self.state.word_spacing = word_spacing;
+                        self.state.char_spacing = char_spacing;
+                    }
+                    self.move_line(0.0, -self.state.leading);
+                    if let Some(Object::String(bytes, _)) = operands.get(2) {
+                        self.show(bytes);
+                    }
+                }
+                "TJ" => {
+                    if let Some(Object::Array(items)) = operands.first() {
+                        self.show_adjusted(items);
+                    }
+                }
+                "Do" => {
+                    if let Some(Ok(name)) = operands.first().map(Object::as_name) {
+                        self.draw_xobject(resources, name, depth);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.stack.truncate(stack_depth);
+    }
+}
+
+/// Reads the positioned text, ruling lines and images of every page.
+pub fn read_pages(doc: &Document) -> Vec<PageLayout> {
+    doc.get_pages()
+        .into_values()
+        .map(|page_id| {
+            let resources =
+                inherited(doc, page_id, b"Resources").and_then(|r| resolve_dict(doc, r));
+
+            let mut reader = PageReader::new(doc);
+            reader.run(&page_content(doc, page_id), resources, 0);
+            PageLayout {
+                runs: reader.runs,
+                rules: reader.rules,
+                images: reader.placed_images,
+            }
+        })
+        .collect()
+}
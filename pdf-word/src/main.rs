@@ -1,75 +1,124 @@
-use anyhow::{Context, Result};
-use docx_rs::*;
+mod docx;
+mod fonts;
+mod html;
+mod images;
+mod layout;
+mod markdown;
+mod model;
+mod output;
+mod pdf;
+mod structure;
+mod tables;
+mod text;
+mod truetype;
+mod typeset;
+mod wordml;
+
+use anyhow::{Context, Result, bail};
+use output::Format;
 use pdf_extract::extract_text;
-use regex::Regex;
-use std::fs::File;
-use std::path::Path;
-use unicode_normalization::UnicodeNormalization;
-
-fn clean_and_structure_text(text: &str) -> Vec<String> {
-    // Normalize Unicode characters (e.g., convert ﬁ to fi)
-    let normalized = text.nfc().collect::<String>();
-
-    // Remove PDF control characters and artifacts
-    let re_control = Regex::new(r"[\x00-\x1F\x7F]").unwrap();
-    let cleaned = re_control.replace_all(&normalized, " ");
-
-    // Normalize whitespace (convert all whitespace to single spaces)
-    let re_whitespace = Regex::new(r"\s+").unwrap();
-    let cleaned = re_whitespace.replace_all(&cleaned, " ");
-
-    // Split into paragraphs at double line breaks
-    let re_paragraphs = Regex::new(r"(?:\n\s*){2,}").unwrap();
-    re_paragraphs
-        .split(&cleaned)
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect()
-}
-
-fn create_word_document(paragraphs: Vec<String>) -> Docx {
-    let mut docx = Docx::new();
-
-    for (i, para) in paragraphs.iter().enumerate() {
-        // Add page break after every 30 paragraphs (simulate PDF pages)
-        if i > 0 && i % 30 == 0 {
-            docx =
-                docx.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
-        }
-
-        docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_text(para)));
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Reads a PDF into the document model every writer works from.
+fn read_document(input_path: &str) -> Result<model::Document> {
+    let pdf = lopdf::Document::load(input_path)
+        .with_context(|| format!("Failed to load PDF: {}", input_path))?;
+
+    // Positioned text runs carry the fonts and sizes the structure is
+    // inferred from
+    let pages = layout::read_pages(&pdf);
+    if pages.iter().any(|page| !page.runs.is_empty()) {
+        return Ok(structure::structure(&pages));
     }
 
-    docx
-}
-
-fn pdf_to_structured_word(input_path: &str, output_path: &str) -> Result<()> {
-    // Extract text from PDF
+    // Nothing decodable in the content streams; fall back to plain text
     let raw_text = extract_text(input_path)
         .with_context(|| format!("Failed to extract text from PDF: {}", input_path))?;
+    Ok(structure::from_plain_text(&raw_text))
+}
 
-    // Process the text
-    let paragraphs = clean_and_structure_text(&raw_text);
-
-    // Create Word document
-    let docx = create_word_document(paragraphs);
-
-    // Save to file
-    let file = File::create(Path::new(output_path))
-        .with_context(|| format!("Failed to create output file: {}", output_path))?;
-    docx.build()
-        .pack(file)
-        .with_context(|| format!("Failed to write Word document: {}", output_path))?;
-
-    Ok(())
+/// Renders a Word document to PDF with embedded fonts.
+fn docx_to_pdf(input: &Path, output: &Path, font: Option<&Path>) -> Result<()> {
+    let document = wordml::read(input)?;
+    let family = truetype::Family::find(font)?;
+    let pages = typeset::typeset(&document, &family);
+    pdf::write(&pages, &family, output)
 }
 
 fn main() -> Result<()> {
-    let input_pdf = "input.pdf";
-    let output_docx = "output.docx";
+    // pdf-word [--format docx|md|html|txt] [--layout] [input.pdf] [output]
+    // pdf-word [--font regular.ttf] input.docx [output.pdf]
+    let mut format = None;
+    let mut font = None;
+    let mut keep_layout = false;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" | "-f" => {
+                let name = args.next().context("--format needs a value")?;
+                match Format::parse(&name) {
+                    Some(parsed) => format = Some(parsed),
+                    None => bail!(
+                        "Unknown output format: {} (use docx, md, html or txt)",
+                        name
+                    ),
+                }
+            }
+            "--font" => font = Some(args.next().context("--font needs a path")?),
+            // Plain text placed by page position instead of reflowed
+            "--layout" => keep_layout = true,
+            _ => paths.push(arg),
+        }
+    }
 
-    pdf_to_structured_word(input_pdf, output_docx)?;
+    let input_pdf = paths.first().map_or("input.pdf", String::as_str);
+    let is_docx = Path::new(input_pdf)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("docx"));
+    if is_docx {
+        if format.is_some() {
+            bail!("--format applies to PDF input; DOCX input is always rendered to PDF");
+        }
+        let output = paths.get(1).map_or("output.pdf", String::as_str);
+        docx_to_pdf(
+            Path::new(input_pdf),
+            Path::new(output),
+            font.as_deref().map(Path::new),
+        )?;
+        println!("Successfully converted {} to {}", input_pdf, output);
+        return Ok(());
+    }
+
+    let output = match paths.get(1) {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!(
+            "output.{}",
+            format.unwrap_or(Format::Docx).extension()
+        )),
+    };
+    // An explicit format wins over the output's extension
+    let format = format
+        .or_else(|| Format::from_path(&output))
+        .unwrap_or(Format::Docx);
+
+    if keep_layout {
+        if format != Format::Text {
+            bail!("--layout applies to txt output only");
+        }
+        let pdf = lopdf::Document::load(input_pdf)
+            .with_context(|| format!("Failed to load PDF: {}", input_pdf))?;
+        text::write_layout(&layout::read_pages(&pdf), &output)?;
+    } else {
+        let document = read_document(input_pdf)?;
+        format.writer().write(&document, &output)?;
+    }
 
-    println!("Successfully converted {} to {}", input_pdf, output_docx);
+    println!(
+        "Successfully converted {} to {}",
+        input_pdf,
+        output.display()
+    );
     Ok(())
 }
@@ -0,0 +1,203 @@
+use crate::model::{Block, Document, Span, Table};
+use crate::output::{self, Writer};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Escapes characters Markdown would take as formatting.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Spans with emphasis markers, keeping whitespace outside the markers
+/// where Markdown requires it.
+fn inline(spans: &[Span]) -> String {
+    let mut out = String::new();
+    for span in spans {
+        let text = escape(&span.text);
+        let marker = match (span.bold, span.italic) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        };
+        let core = text.trim();
+        if marker.is_empty() || core.is_empty() {
+            out.push_str(&text);
+            continue;
+        }
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        out.push_str(leading);
+        out.push_str(marker);
+        out.push_str(core);
+        out.push_str(marker);
+        out.push_str(trailing);
+    }
+    out
+}
+
+/// A paragraph whose text would otherwise start a heading, quote or list.
+fn paragraph(spans: &[Span]) -> String {
+    let text = inline(spans);
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    let starts_block = text.starts_with(['#', '-', '+', '=']) || {
+        digits > 0 && text[digits..].starts_with(['.', ')'])
+    };
+    if starts_block {
+        format!("\\{}", text)
+    } else {
+        text
+    }
+}
+
+/// A pipe table with the first row as its header. Markdown has no merged
+/// cells, so a spanning cell's text goes in its first position and the
+/// rest are left empty.
+fn table(table: &Table) -> String {
+    let columns = table.widths.len();
+    let mut lines = Vec::new();
+    for row in 0..table.rows {
+        let cells: Vec<String> = (0..columns)
+            .map(|column| match table.covering(row, column) {
+                Some(cell) if cell.row == row && cell.column == column => cell
+                    .paragraphs
+                    .iter()
+                    .map(|spans| inline(spans))
+                    .collect::<Vec<_>>()
+                    .join("<br>"),
+                _ => String::new(),
+            })
+            .collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if row == 0 {
+            lines.push(format!("|{}", " --- |".repeat(columns)));
+        }
+    }
+    lines.join("\n")
+}
+
+pub struct MarkdownWriter;
+
+impl Writer for MarkdownWriter {
+    fn write(&self, document: &Document, output: &Path) -> Result<()> {
+        let mut images = output::save_images(document, output)?.into_iter();
+        let mut markdown = String::new();
+        let mut previous: Option<&Block> = None;
+        for block in &document.blocks {
+            let text = match block {
+                Block::Heading { level, spans } => {
+                    format!("{} {}", "#".repeat(*level as usize), inline(spans))
+                }
+                Block::Paragraph { spans } => paragraph(spans),
+                Block::ListItem {
+                    ordered,
+                    level,
+                    spans,
+                } => {
+                    let marker = if *ordered { "1." } else { "-" };
+                    format!(
+                        "{}{} {}",
+                        "    ".repeat(*level as usize),
+                        marker,
+                        inline(spans)
+                    )
+                }
+                Block::Table(grid) => table(grid),
+                Block::Figure(_) => format!("![image]({})", images.next().unwrap_or_default()),
+                Block::PageBreak => "---".to_string(),
+            };
+            // Items of one list stay together
+            if let Some(previous) = previous {
+                let tight = matches!(previous, Block::ListItem { .. })
+                    && matches!(block, Block::ListItem { .. });
+                markdown.push_str(if tight { "\n" } else { "\n\n" });
+            }
+            markdown.push_str(&text);
+            previous = Some(block);
+        }
+        markdown.push('\n');
+
+        fs::write(output, markdown)
+            .with_context(|| format!("Failed to write Markdown: {}", output.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Cell;
+
+    fn span(text: &str, bold: bool, italic: bool) -> Span {
+        Span {
+            text: text.to_string(),
+            bold,
+            italic,
+        }
+    }
+
+    fn cell(row: usize, column: usize, col_span: usize, text: &str) -> Cell {
+        Cell {
+            row,
+            column,
+            row_span: 1,
+            col_span,
+            paragraphs: vec![vec![span(text, false, false)]],
+        }
+    }
+
+    #[test]
+    fn escapes_formatting_characters() {
+        assert_eq!(
+            escape(r"a*b_c `d` [e](f) <g> |h\"),
+            r"a\*b\_c \`d\` \[e\](f) \<g\> \|h\\"
+        );
+        assert_eq!(escape("plain text, 100% fine"), "plain text, 100% fine");
+    }
+
+    #[test]
+    fn emphasis_keeps_whitespace_outside_markers() {
+        let spans = [
+            span("Total ", false, false),
+            span(" due ", true, false),
+            span("now", true, true),
+            span(" *", false, true),
+        ];
+        assert_eq!(inline(&spans), r"Total  **due** ***now*** *\**");
+    }
+
+    #[test]
+    fn paragraphs_that_look_like_blocks_are_escaped() {
+        let text = |text: &str| paragraph(&[span(text, false, false)]);
+        assert_eq!(text("# not a heading"), r"\# not a heading");
+        assert_eq!(text("- not a list"), r"\- not a list");
+        assert_eq!(text("12. not numbered"), r"\12. not numbered");
+        assert_eq!(text("2024 was a year"), "2024 was a year");
+    }
+
+    #[test]
+    fn spanning_cells_leave_positions_empty() {
+        let grid = Table {
+            widths: vec![50.0, 50.0, 50.0],
+            rows: 2,
+            cells: vec![
+                cell(0, 0, 2, "Name|Type"),
+                cell(0, 2, 1, "Size"),
+                cell(1, 0, 1, "a"),
+                cell(1, 1, 1, "b"),
+                cell(1, 2, 1, "c"),
+            ],
+        };
+        assert_eq!(
+            table(&grid),
+            "| Name\\|Type |  | Size |\n| --- | --- | --- |\n| a | b | c |"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::rc::Rc;
+
+/// Text that shares one style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// A table cell. Cells covered by another cell's span are not listed.
+#[derive(Debug, Clone)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+    pub row_span: usize,
+    pub col_span: usize,
+    pub paragraphs: Vec<Vec<Span>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    /// Column widths in points.
+    pub widths: Vec<f32>,
+    pub rows: usize,
+    /// Row by row, left to right.
+    pub cells: Vec<Cell>,
+}
+
+impl Table {
+    /// The cell that starts at or spans over a grid position.
+    pub fn covering(&self, row: usize, column: usize) -> Option<&Cell> {
+        self.cells.iter().find(|cell| {
+            (cell.row..cell.row + cell.row_span).contains(&row)
+                && (cell.column..cell.column + cell.col_span).contains(&column)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// An image as a file other programs can read.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub format: ImageFormat,
+    pub data: Vec<u8>,
+    pub pixel_width: u32,
+    pub pixel_height: u32,
+}
+
+/// An image shown at a size in points. Figures of an image drawn more than
+/// once, like a logo on every page, share it.
+#[derive(Debug, Clone)]
+pub struct Figure {
+    pub image: Rc<Image>,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Block {
+    Heading {
+        level: u8,
+        spans: Vec<Span>,
+    },
+    Paragraph {
+        spans: Vec<Span>,
+    },
+    ListItem {
+        ordered: bool,
+        level: u8,
+        spans: Vec<Span>,
+    },
+    Table(Table),
+    Figure(Figure),
+    PageBreak,
+}
+
+/// What the PDF reader makes of a document, independent of any output
+/// format: its blocks plus the sizes its styles are derived from.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    /// Most common font size, taken as the body text size.
+    pub body_size: f32,
+    /// Font size of heading levels 1, 2, 3... as found in the PDF.
+    pub heading_sizes: Vec<f32>,
+}
+
+impl Document {
+    /// Every figure, in reading order.
+    pub fn figures(&self) -> impl Iterator<Item = &Figure> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Figure(figure) => Some(figure),
+            _ => None,
+        })
+    }
+}
+
+/// The plain text of some spans.
+pub fn plain_text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
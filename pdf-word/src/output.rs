@@ -0,0 +1,96 @@
+use crate::model::{Document, Image};
+use crate::{docx, html, markdown, text};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Writes the document model in one output format.
+pub trait Writer {
+    fn write(&self, document: &Document, output: &Path) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Docx,
+    Markdown,
+    Html,
+    Text,
+}
+
+impl Format {
+    /// Parses a `--format` value or a file extension.
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "docx" | "word" => Some(Format::Docx),
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" | "htm" => Some(Format::Html),
+            "txt" | "text" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::parse(path.extension()?.to_str()?)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Docx => "docx",
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Text => "txt",
+        }
+    }
+
+    pub fn writer(self) -> Box<dyn Writer> {
+        match self {
+            Format::Docx => Box::new(docx::DocxWriter),
+            Format::Markdown => Box::new(markdown::MarkdownWriter),
+            Format::Html => Box::new(html::HtmlWriter),
+            Format::Text => Box::new(text::TextWriter),
+        }
+    }
+}
+
+/// Saves the document's images next to the output, in `<name>_images/`,
+/// for formats that link to them rather than embed them. Returns the path
+/// of each figure's image relative to the output, in reading order; an
+/// image shown more than once is saved once.
+pub fn save_images(document: &Document, output: &Path) -> Result<Vec<String>> {
+    let stem = output
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("output");
+    let directory_name = format!("{}_images", stem);
+    let directory = output.with_file_name(&directory_name);
+
+    let mut saved: Vec<(Rc<Image>, String)> = Vec::new();
+    let mut paths = Vec::new();
+    for figure in document.figures() {
+        if let Some((_, path)) = saved
+            .iter()
+            .find(|(image, _)| Rc::ptr_eq(image, &figure.image))
+        {
+            paths.push(path.clone());
+            continue;
+        }
+        if saved.is_empty() {
+            fs::create_dir_all(&directory).with_context(|| {
+                format!("Failed to create image directory: {}", directory.display())
+            })?;
+        }
+        let file_name = format!(
+            "image{}.{}",
+            saved.len() + 1,
+            figure.image.format.extension()
+        );
+        let file = directory.join(&file_name);
+        fs::write(&file, &figure.image.data)
+            .with_context(|| format!("Failed to write image: {}", file.display()))?;
+        let path = format!("{}/{}", directory_name, file_name);
+        saved.push((figure.image.clone(), path.clone()));
+        paths.push(path);
+    }
+    Ok(paths)
+}
@@ -0,0 +1,416 @@
+use crate::truetype::{Family, deflate};
+use crate::typeset::{Mark, Page};
+use anyhow::{Context, Result};
+use image::ImageFormat;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Slant of synthesized italics.
+const FAKE_ITALIC_SKEW: f32 = 0.2;
+
+/// Outline width of synthesized bold, in font sizes.
+const FAKE_BOLD_STROKE: f32 = 0.03;
+
+fn rgb(color: [u8; 3]) -> Vec<Object> {
+    color.iter().map(|c| (*c as f32 / 255.0).into()).collect()
+}
+
+/// Size and number of components of a baseline or progressive JPEG.
+fn jpeg_info(data: &[u8]) -> Option<(u32, u32, u8)> {
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            return None;
+        }
+        let marker = data[i + 1];
+        let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        // Start of frame markers, other than DHT, JPG and DAC
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+            return Some((width, height, data[i + 9]));
+        }
+        i += 2 + length;
+    }
+    None
+}
+
+/// An image XObject for picture data Word embedded. JPEGs in gray or RGB
+/// are passed through; anything else is decoded and stored deflated, with
+/// a soft mask for transparency.
+fn image_xobject(doc: &mut Document, data: &[u8]) -> Option<ObjectId> {
+    if image::guess_format(data).ok()? == ImageFormat::Jpeg
+        && let Some((width, height, components @ (1 | 3))) = jpeg_info(data)
+    {
+        let color_space = if components == 1 {
+            "DeviceGray"
+        } else {
+            "DeviceRGB"
+        };
+        return Some(doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width as i64,
+                "Height" => height as i64,
+                "ColorSpace" => color_space,
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            },
+            data.to_vec(),
+        )));
+    }
+
+    let decoded = image::load_from_memory(data).ok()?.to_rgba8();
+    let (width, height) = decoded.dimensions();
+    let mut color = Vec::with_capacity((width * height * 3) as usize);
+    let mut alpha = Vec::with_capacity((width * height) as usize);
+    for pixel in decoded.pixels() {
+        color.extend_from_slice(&pixel.0[..3]);
+        alpha.push(pixel.0[3]);
+    }
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width as i64,
+        "Height" => height as i64,
+        "ColorSpace" => "DeviceRGB",
+        "BitsPerComponent" => 8,
+        "Filter" => "FlateDecode",
+    };
+    if alpha.iter().any(|a| *a != 255) {
+        let mask = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width as i64,
+                "Height" => height as i64,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+                "Filter" => "FlateDecode",
+            },
+            deflate(&alpha).ok()?,
+        ));
+        dict.set("SMask", mask);
+    }
+    Some(doc.add_object(Stream::new(dict, deflate(&color).ok()?)))
+}
+
+/// Builds the PDF's objects while pages are written: glyphs used per font
+/// face and images already embedded.
+struct Writer<'a> {
+    family: &'a Family,
+    doc: Document,
+    used: [BTreeMap<u16, char>; 4],
+    /// Embedded images by picture data, `None` if it couldn't be decoded.
+    images: Vec<(Rc<Vec<u8>>, Option<ObjectId>)>,
+}
+
+impl Writer<'_> {
+    /// The resource name of a picture's image, embedding it the first time.
+    fn image(&mut self, data: &Rc<Vec<u8>>) -> Option<String> {
+        let index = match self
+            .images
+            .iter()
+            .position(|(embedded, _)| Rc::ptr_eq(embedded, data))
+        {
+            Some(index) => index,
+            None => {
+                let id = image_xobject(&mut self.doc, data);
+                if id.is_none() {
+                    eprintln!("⚠️ Skipping a picture that could not be decoded");
+                }
+                self.images.push((data.clone(), id));
+                self.images.len() - 1
+            }
+        };
+        self.images[index].1.map(|_| format!("Im{}", index))
+    }
+
+    fn operations(&mut self, marks: &[Mark]) -> Vec<Operation> {
+        let mut operations = Vec::new();
+        for mark in marks {
+            match mark {
+                Mark::Text { x, y, text, style } => {
+                    let face = self.family.face(style.bold, style.italic);
+                    let color = rgb(style.color);
+                    let skew = if face.fake_italic {
+                        FAKE_ITALIC_SKEW
+                    } else {
+                        0.0
+                    };
+                    operations.push(Operation::new("q", vec![]));
+                    operations.push(Operation::new("rg", color.clone()));
+                    if face.fake_bold {
+                        operations.push(Operation::new("RG", color));
+                        operations.push(Operation::new(
+                            "w",
+                            vec![(style.size * FAKE_BOLD_STROKE).into()],
+                        ));
+                    }
+                    operations.push(Operation::new("BT", vec![]));
+                    operations.push(Operation::new(
+                        "Tf",
+                        vec![
+                            Object::Name(format!("F{}", face.index).into_bytes()),
+                            style.size.into(),
+                        ],
+                    ));
+                    if face.fake_bold {
+                        operations.push(Operation::new("Tr", vec![2.into()]));
+                    }
+                    operations.push(Operation::new(
+                        "Tm",
+                        vec![
+                            1.into(),
+                            0.into(),
+                            skew.into(),
+                            1.into(),
+                            (*x).into(),
+                            (*y).into(),
+                        ],
+                    ));
+                    let encoded = face.font.encode(text, &mut self.used[face.index]);
+                    operations.push(Operation::new("Tj", vec![encoded]));
+                    operations.push(Operation::new("ET", vec![]));
+                    operations.push(Operation::new("Q", vec![]));
+                }
+                Mark::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => {
+                    operations.extend([
+                        Operation::new("q", vec![]),
+                        Operation::new("w", vec![(*width).into()]),
+                        Operation::new("RG", rgb(*color)),
+                        Operation::new("m", vec![from.0.into(), from.1.into()]),
+                        Operation::new("l", vec![to.0.into(), to.1.into()]),
+                        Operation::new("S", vec![]),
+                        Operation::new("Q", vec![]),
+                    ]);
+                }
+                Mark::Fill {
+                    x,
+                    y,
+                    width,
+                    height,
+                    color,
+                } => {
+                    operations.extend([
+                        Operation::new("q", vec![]),
+                        Operation::new("rg", rgb(*color)),
+                        Operation::new(
+                            "re",
+                            vec![(*x).into(), (*y).into(), (*width).into(), (*height).into()],
+                        ),
+                        Operation::new("f", vec![]),
+                        Operation::new("Q", vec![]),
+                    ]);
+                }
+                Mark::Picture { x, y, picture } => {
+                    let Some(name) = self.image(&picture.data) else {
+                        continue;
+                    };
+                    operations.extend([
+                        Operation::new("q", vec![]),
+                        Operation::new(
+                            "cm",
+                            vec![
+                                picture.width.into(),
+                                0.into(),
+                                0.into(),
+                                picture.height.into(),
+                                (*x).into(),
+                                (*y).into(),
+                            ],
+                        ),
+                        Operation::new("Do", vec![Object::Name(name.into_bytes())]),
+                        Operation::new("Q", vec![]),
+                    ]);
+                }
+            }
+        }
+        operations
+    }
+}
+
+/// Writes typeset pages to a PDF. Every page shares one resource
+/// dictionary holding the fonts and images used anywhere.
+pub fn write(pages: &[Page], family: &Family, output: &Path) -> Result<()> {
+    let mut writer = Writer {
+        family,
+        doc: Document::with_version("1.5"),
+        used: Default::default(),
+        images: Vec::new(),
+    };
+    let pages_id = writer.doc.new_object_id();
+    let resources_id = writer.doc.new_object_id();
+
+    let mut kids = Vec::new();
+    for page in pages {
+        let content = Content {
+            operations: writer.operations(&page.marks),
+        };
+        let content = content.encode().context("Failed to encode page content")?;
+        let content_id = writer.doc.add_object(Stream::new(
+            dictionary! { "Filter" => "FlateDecode" },
+            deflate(&content)?,
+        ));
+        let page_id = writer.doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), page.width.into(), page.height.into()],
+            "Contents" => content_id,
+            "Resources" => resources_id,
+        });
+        kids.push(page_id.into());
+    }
+
+    let mut fonts = Dictionary::new();
+    for (index, used) in writer.used.iter().enumerate() {
+        if let Some(font) = family.font(index)
+            && !used.is_empty()
+        {
+            let id = font.embed(&mut writer.doc, used)?;
+            fonts.set(format!("F{}", index), id);
+        }
+    }
+    let mut xobjects = Dictionary::new();
+    for (index, (_, id)) in writer.images.iter().enumerate() {
+        if let Some(id) = id {
+            xobjects.set(format!("Im{}", index), *id);
+        }
+    }
+
+    let mut doc = writer.doc;
+    doc.objects.insert(
+        resources_id,
+        Object::Dictionary(dictionary! {
+            "Font" => fonts,
+            "XObject" => xobjects,
+        }),
+    );
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => pages.len() as i64,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    doc.save(output)
+        .with_context(|| format!("Failed to write PDF: {}", output.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Block, plain_text};
+    use crate::{layout, structure, typeset, wordml};
+    use std::fs::File;
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    const PACKAGE_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+</Relationships>"#;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:body>
+<w:p><w:r><w:rPr><w:b/><w:sz w:val="32"/></w:rPr><w:t>Quarterly figures</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Sales rose by </w:t></w:r><w:r><w:rPr><w:i/></w:rPr><w:t>twelve</w:t></w:r><w:r><w:t xml:space="preserve"> percent.</w:t></w:r></w:p>
+<w:tbl>
+<w:tblPr><w:tblBorders><w:top w:val="single"/><w:bottom w:val="single"/><w:insideV w:val="single"/></w:tblBorders></w:tblPr>
+<w:tblGrid><w:gridCol w:w="2000"/><w:gridCol w:w="2000"/></w:tblGrid>
+<w:tr><w:tc><w:p><w:r><w:t>North</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>120</w:t></w:r></w:p></w:tc></w:tr>
+</w:tbl>
+<w:sectPr><w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1440" w:bottom="1440" w:left="1440" w:right="1440"/></w:sectPr>
+</w:body>
+</w:document>"#;
+
+    /// A TrueType font checked into the repository, so the test doesn't
+    /// depend on the fonts installed.
+    fn family() -> Family {
+        let font = Path::new(env!("CARGO_MANIFEST_DIR")).join("../a/fonts/arial.ttf");
+        Family::find(Some(&font)).unwrap()
+    }
+
+    #[test]
+    fn docx_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let input = directory.path().join("report.docx");
+        let mut zip = ZipWriter::new(File::create(&input).unwrap());
+        for (name, content) in [
+            ("_rels/.rels", PACKAGE_RELS),
+            ("word/document.xml", DOCUMENT),
+        ] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let document = wordml::read(&input).unwrap();
+        let family = family();
+        let pages = typeset::typeset(&document, &family);
+        assert_eq!(pages.len(), 1);
+        let output = directory.path().join("report.pdf");
+        write(&pages, &family, &output).unwrap();
+
+        let pdf = Document::load(&output).unwrap();
+        assert_eq!(pdf.get_pages().len(), 1);
+        let page_id = pdf.page_iter().next().unwrap();
+        let media_box = pdf
+            .get_dictionary(page_id)
+            .and_then(|page| page.get(b"MediaBox"))
+            .and_then(Object::as_array)
+            .unwrap();
+        let size: Vec<f32> = media_box
+            .iter()
+            .map(|value| value.as_float().unwrap())
+            .collect();
+        assert_eq!(size, [0.0, 0.0, 595.3, 841.9]);
+
+        // Reading it back with the embedded fonts' ToUnicode maps gives the
+        // same structure
+        let read = structure::structure(&layout::read_pages(&pdf));
+        let blocks = &read.blocks;
+        assert_eq!(blocks.len(), 3, "{:?}", blocks);
+        assert!(matches!(
+            &blocks[0],
+            Block::Heading { level: 1, spans } if plain_text(spans) == "Quarterly figures"
+        ));
+        let Block::Paragraph { spans } = &blocks[1] else {
+            panic!("expected a paragraph, got {:?}", blocks[1]);
+        };
+        assert_eq!(plain_text(spans), "Sales rose by twelve percent.");
+        let Block::Table(table) = &blocks[2] else {
+            panic!("expected a table, got {:?}", blocks[2]);
+        };
+        let cells: Vec<String> = table
+            .cells
+            .iter()
+            .map(|cell| {
+                cell.paragraphs
+                    .iter()
+                    .map(|spans| plain_text(spans))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(cells, ["North", "120"]);
+    }
+}
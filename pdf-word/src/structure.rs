@@ -0,0 +1,537 @@
+use crate::layout::{PageLayout, TextRun};
+use crate::model::{Block, Document, Figure, Span};
+use crate::tables;
+use regex::Regex;
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters that start a bulleted item when followed by a space. The
+/// private-use ones are the Symbol and Wingdings bullets.
+const BULLETS: &[char] = &[
+    '•', '◦', '▪', '▫', '‣', '⁃', '●', '○', '■', '□', '-', '–', '—', '*', '·', '\u{f0b7}',
+    '\u{f0a7}', '\u{f076}',
+];
+
+/// Sizes are compared in half points.
+fn size_key(size: f32) -> i32 {
+    (size * 2.0).round() as i32
+}
+
+/// One visual line of text.
+struct Line {
+    spans: Vec<Span>,
+    x: f32,
+    y: f32,
+    size: f32,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+}
+
+fn all_bold(spans: &[Span]) -> bool {
+    spans
+        .iter()
+        .all(|span| span.bold || span.text.trim().is_empty())
+}
+
+fn push_span(spans: &mut Vec<Span>, text: &str, bold: bool, italic: bool) {
+    if let Some(last) = spans.last_mut()
+        && last.bold == bold
+        && last.italic == italic
+    {
+        last.text.push_str(text);
+        return;
+    }
+    spans.push(Span {
+        text: text.to_string(),
+        bold,
+        italic,
+    });
+}
+
+/// Groups runs that share a baseline, top to bottom, each group left to
+/// right.
+pub fn baseline_groups<'a>(runs: &[&'a TextRun]) -> Vec<Vec<&'a TextRun>> {
+    let mut sorted = runs.to_vec();
+    sorted.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    let mut groups: Vec<Vec<&TextRun>> = Vec::new();
+    for run in sorted {
+        match groups.last_mut() {
+            Some(group) if (group[0].y - run.y).abs() <= 0.35 * group[0].size.max(run.size) => {
+                group.push(run)
+            }
+            _ => groups.push(vec![run]),
+        }
+    }
+    for group in &mut groups {
+        group.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+    groups
+}
+
+/// Puts runs together into lines, with spaces where runs are visibly
+/// apart.
+fn group_lines(runs: &[&TextRun]) -> Vec<Line> {
+    let groups = baseline_groups(runs);
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut spans = Vec::new();
+            let mut previous: Option<&TextRun> = None;
+            for run in &group {
+                if let Some(previous) = previous {
+                    let gap = run.x - previous.end_x;
+                    if gap > 0.12 * run.size
+                        && !previous.text.ends_with(' ')
+                        && !run.text.starts_with(' ')
+                    {
+                        push_span(&mut spans, " ", previous.bold && run.bold, false);
+                    }
+                }
+                push_span(&mut spans, &run.text, run.bold, run.italic);
+                previous = Some(run);
+            }
+            Line {
+                spans,
+                x: group[0].x,
+                y: group[0].y,
+                size: group.iter().map(|run| run.size).fold(0.0, f32::max),
+            }
+        })
+        .collect()
+}
+
+/// Most common size, weighted by the amount of text set in it.
+fn body_size(pages: &[PageLayout]) -> f32 {
+    let mut histogram: BTreeMap<i32, usize> = BTreeMap::new();
+    for run in pages.iter().flat_map(|page| &page.runs) {
+        *histogram.entry(size_key(run.size)).or_default() += run.text.chars().count();
+    }
+    histogram
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map_or(12.0, |(key, _)| key as f32 / 2.0)
+}
+
+/// A list marker at the start of a line: whether it is numbered, and how
+/// many characters it takes up including the following whitespace.
+fn list_marker(text: &str, numbered: &Regex) -> Option<(bool, usize)> {
+    let trimmed = text.trim_start();
+    let leading = text.chars().count() - trimmed.chars().count();
+    let mut chars = trimmed.chars();
+    if let Some(first) = chars.next()
+        && BULLETS.contains(&first)
+    {
+        let spaces = chars.take_while(|c| c.is_whitespace()).count();
+        // "-5%" or "*note" are not bullets
+        if spaces > 0 {
+            return Some((false, leading + 1 + spaces));
+        }
+    }
+    numbered
+        .find(trimmed)
+        .map(|m| (true, leading + m.as_str().chars().count()))
+}
+
+/// Numbered list markers: "1.", "(2)", "a)", "iv." and the like.
+fn numbered_marker() -> Regex {
+    Regex::new(r"^(\d{1,3}[.)]|\(\d{1,3}\)|[a-z][.)]|\([a-z]\)|[ivx]{1,5}[.)]|\([ivx]{1,5}\))\s+")
+        .unwrap()
+}
+
+/// Drops the first `count` characters of a line's text.
+fn strip_chars(spans: &mut Vec<Span>, mut count: usize) {
+    while count > 0 && !spans.is_empty() {
+        let length = spans[0].text.chars().count();
+        if length <= count {
+            spans.remove(0);
+            count -= length;
+        } else {
+            spans[0].text = spans[0].text.chars().skip(count).collect();
+            count = 0;
+        }
+    }
+}
+
+/// Appends a line to a paragraph, undoing end-of-line hyphenation.
+fn append_line(spans: &mut Vec<Span>, line: Vec<Span>) {
+    let next_lowercase = line
+        .first()
+        .and_then(|span| span.text.trim_start().chars().next())
+        .is_some_and(char::is_lowercase);
+    match spans.last_mut() {
+        Some(last) if last.text.ends_with('-') && next_lowercase => {
+            last.text.pop();
+        }
+        Some(last) if !last.text.ends_with(' ') => last.text.push(' '),
+        _ => {}
+    }
+    for span in line {
+        push_span(spans, &span.text, span.bold, span.italic);
+    }
+}
+
+fn trim_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    if let Some(first) = spans.first_mut() {
+        first.text = first.text.trim_start().to_string();
+    }
+    if let Some(last) = spans.last_mut() {
+        last.text = last.text.trim_end().to_string();
+    }
+    spans.retain(|span| !span.text.is_empty());
+    spans
+}
+
+/// The text of runs as paragraphs, such as the content of a table cell.
+/// Lines run on while they are close together and the same size.
+pub fn paragraphs(runs: &[&TextRun]) -> Vec<Vec<Span>> {
+    let mut paragraphs: Vec<Vec<Span>> = Vec::new();
+    let mut previous: Option<Line> = None;
+    for line in group_lines(runs) {
+        match (previous.as_ref(), paragraphs.last_mut()) {
+            (Some(p), Some(spans))
+                if p.y - line.y <= p.size.max(line.size) * 1.8
+                    && size_key(p.size) == size_key(line.size) =>
+            {
+                append_line(spans, line.spans.clone());
+            }
+            _ => paragraphs.push(line.spans.clone()),
+        }
+        previous = Some(line);
+    }
+    paragraphs
+        .into_iter()
+        .map(trim_spans)
+        .filter(|spans| !spans.is_empty())
+        .collect()
+}
+
+/// Infers headings, list items, tables and paragraphs from positioned
+/// text.
+pub fn structure(pages: &[PageLayout]) -> Document {
+    let numbered = numbered_marker();
+    let body = body_size(pages);
+
+    // Sizes clearly above the body size are headings, largest first
+    let mut heading_keys: Vec<i32> = pages
+        .iter()
+        .flat_map(|page| &page.runs)
+        .filter(|run| run.size >= body * 1.15 && !run.text.trim().is_empty())
+        .map(|run| size_key(run.size))
+        .collect();
+    heading_keys.sort_unstable_by(|a, b| b.cmp(a));
+    heading_keys.dedup();
+    let heading_level = |size: f32| {
+        heading_keys
+            .iter()
+            .position(|key| *key == size_key(size))
+            .map(|index| (index + 1).min(3) as u8)
+    };
+    // Bold lines at body size rank below every larger heading
+    let bold_level = (heading_keys.len() + 1).min(3) as u8;
+
+    let mut blocks = Vec::new();
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            blocks.push(Block::PageBreak);
+        }
+        // Tables and images go in where their top edge is
+        let (tables, runs) = tables::extract(page);
+        let mut placed: Vec<(f32, Block)> = tables
+            .into_iter()
+            .map(|(top, table)| (top, Block::Table(table)))
+            .chain(page.images.iter().map(|placed| {
+                let figure = Figure {
+                    image: placed.image.clone(),
+                    width: placed.width,
+                    height: placed.height,
+                };
+                (placed.top, Block::Figure(figure))
+            }))
+            .collect();
+        placed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut placed = placed.into_iter().peekable();
+        let lines = group_lines(&runs);
+        let margin = lines.iter().map(|line| line.x).fold(f32::MAX, f32::min);
+
+        // The block being built and the line it last took
+        let mut current: Option<Block> = None;
+        let mut previous: Option<&Line> = None;
+        for line in &lines {
+            while let Some((_, block)) = placed.next_if(|(top, _)| *top > line.y) {
+                blocks.extend(current.take());
+                blocks.push(block);
+                previous = None;
+            }
+
+            let text = line.text();
+            let gap = previous.map_or(f32::MAX, |p| p.y - line.y);
+            let close = previous.is_some_and(|p| {
+                gap <= p.size.max(line.size) * 1.8 && size_key(p.size) == size_key(line.size)
+            });
+
+            if let Some(level) = heading_level(line.size) {
+                match &mut current {
+                    // A heading wrapped over two lines stays one heading
+                    Some(Block::Heading { level: l, spans }) if close && *l == level => {
+                        append_line(spans, line.spans.clone());
+                    }
+                    _ => {
+                        blocks.extend(current.take());
+                        current = Some(Block::Heading {
+                            level,
+                            spans: line.spans.clone(),
+                        });
+                    }
+                }
+                previous = Some(line);
+                continue;
+            }
+
+            if let Some((ordered, marker)) = list_marker(&text, &numbered) {
+                blocks.extend(current.take());
+                let mut spans = line.spans.clone();
+                strip_chars(&mut spans, marker);
+                current = Some(Block::ListItem {
+                    ordered,
+                    level: ((line.x - margin) / 24.0).clamp(0.0, 2.0) as u8,
+                    spans,
+                });
+                previous = Some(line);
+                continue;
+            }
+
+            match &mut current {
+                Some(Block::Paragraph { spans }) if close => {
+                    append_line(spans, line.spans.clone());
+                }
+                // Continuation of a list item, indented past its marker
+                Some(Block::ListItem { spans, .. })
+                    if close && previous.is_some_and(|p| line.x > p.x + 2.0) =>
+                {
+                    append_line(spans, line.spans.clone());
+                }
+                _ => {
+                    blocks.extend(current.take());
+                    current = Some(Block::Paragraph {
+                        spans: line.spans.clone(),
+                    });
+                }
+            }
+            previous = Some(line);
+        }
+        blocks.extend(current);
+        blocks.extend(placed.map(|(_, block)| block));
+    }
+
+    // A short, entirely bold paragraph on its own reads as a heading
+    for block in &mut blocks {
+        if let Block::Paragraph { spans } = block {
+            let text: String = spans.iter().map(|span| span.text.as_str()).collect();
+            let length = text.trim().chars().count();
+            if all_bold(spans) && (1..=80).contains(&length) && !text.trim_end().ends_with('.') {
+                *block = Block::Heading {
+                    level: bold_level,
+                    spans: std::mem::take(spans),
+                };
+            }
+        }
+    }
+
+    let blocks = blocks
+        .into_iter()
+        .filter_map(|block| match block {
+            Block::Heading { level, spans } => {
+                let spans = trim_spans(spans);
+                (!spans.is_empty()).then_some(Block::Heading { level, spans })
+            }
+            Block::Paragraph { spans } => {
+                let spans = trim_spans(spans);
+                (!spans.is_empty()).then_some(Block::Paragraph { spans })
+            }
+            Block::ListItem {
+                ordered,
+                level,
+                spans,
+            } => {
+                let spans = trim_spans(spans);
+                (!spans.is_empty()).then_some(Block::ListItem {
+                    ordered,
+                    level,
+                    spans,
+                })
+            }
+            block @ (Block::Table(_) | Block::Figure(_) | Block::PageBreak) => Some(block),
+        })
+        .collect();
+
+    Document {
+        blocks,
+        body_size: body,
+        heading_sizes: heading_keys.iter().map(|key| *key as f32 / 2.0).collect(),
+    }
+}
+
+/// Paragraphs from plain extracted text, for PDFs whose fonts can't be
+/// decoded: blank lines separate paragraphs and form feeds separate pages.
+pub fn from_plain_text(text: &str) -> Document {
+    let normalized = text.nfkc().collect::<String>();
+    let re_control = Regex::new(r"[\x00-\x08\x0B\x0E-\x1F\x7F]").unwrap();
+    let re_paragraphs = Regex::new(r"\n[ \t]*\n").unwrap();
+    let re_whitespace = Regex::new(r"\s+").unwrap();
+
+    let mut blocks = Vec::new();
+    for (index, page) in normalized.split('\x0C').enumerate() {
+        if index > 0 {
+            blocks.push(Block::PageBreak);
+        }
+        let page = re_control.replace_all(page, " ");
+        for paragraph in re_paragraphs.split(&page) {
+            let paragraph = re_whitespace.replace_all(paragraph.trim(), " ");
+            if !paragraph.is_empty() {
+                blocks.push(Block::Paragraph {
+                    spans: vec![Span {
+                        text: paragraph.to_string(),
+                        bold: false,
+                        italic: false,
+                    }],
+                });
+            }
+        }
+    }
+    // A trailing form feed would leave an empty last page
+    while matches!(blocks.last(), Some(Block::PageBreak)) {
+        blocks.pop();
+    }
+
+    Document {
+        blocks,
+        body_size: 11.0,
+        heading_sizes: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, y: f32, size: f32, bold: bool) -> TextRun {
+        TextRun {
+            text: text.to_string(),
+            x: 72.0,
+            end_x: 72.0 + text.chars().count() as f32 * size * 0.5,
+            y,
+            size,
+            font: "Helvetica".to_string(),
+            bold,
+            italic: false,
+        }
+    }
+
+    fn text(spans: &[Span]) -> String {
+        crate::model::plain_text(spans)
+    }
+
+    #[test]
+    fn list_markers() {
+        let numbered = numbered_marker();
+        assert_eq!(list_marker("• Apples", &numbered), Some((false, 2)));
+        assert_eq!(list_marker("  -  Pears", &numbered), Some((false, 5)));
+        assert_eq!(list_marker("\u{f0b7}\tPlums", &numbered), Some((false, 2)));
+        assert_eq!(list_marker("1. First", &numbered), Some((true, 3)));
+        assert_eq!(list_marker("(12) Twelfth", &numbered), Some((true, 5)));
+        assert_eq!(list_marker("b) Second", &numbered), Some((true, 3)));
+        assert_eq!(list_marker("iv. Fourth", &numbered), Some((true, 4)));
+        // Signs and footnote marks without a space are text
+        assert_eq!(list_marker("-5% on last year", &numbered), None);
+        assert_eq!(list_marker("*note", &numbered), None);
+        assert_eq!(list_marker("2024 was a good year", &numbered), None);
+        assert_eq!(list_marker("1.5 litres", &numbered), None);
+    }
+
+    #[test]
+    fn infers_headings_paragraphs_and_lists() {
+        let page = PageLayout {
+            runs: vec![
+                run("Annual report", 720.0, 18.0, false),
+                run("Summary", 690.0, 11.0, true),
+                run(
+                    "Sales grew in every region, with the north-",
+                    666.0,
+                    11.0,
+                    false,
+                ),
+                run("ern offices ahead of the rest.", 652.0, 11.0, false),
+                run("• Revenue up", 628.0, 11.0, false),
+                run("2. Costs down", 614.0, 11.0, false),
+            ],
+            rules: Vec::new(),
+            images: Vec::new(),
+        };
+        let document = structure(&[page]);
+        assert_eq!(document.body_size, 11.0);
+        assert_eq!(document.heading_sizes, vec![18.0]);
+
+        let blocks = &document.blocks;
+        assert_eq!(blocks.len(), 5, "{:?}", blocks);
+        assert!(
+            matches!(&blocks[0], Block::Heading { level: 1, spans } if text(spans) == "Annual report")
+        );
+        // Bold body text on its own ranks below the sized headings
+        assert!(
+            matches!(&blocks[1], Block::Heading { level: 2, spans } if text(spans) == "Summary")
+        );
+        assert!(matches!(
+            &blocks[2],
+            Block::Paragraph { spans }
+                if text(spans) == "Sales grew in every region, with the northern offices ahead of the rest."
+        ));
+        assert!(matches!(
+            &blocks[3],
+            Block::ListItem { ordered: false, level: 0, spans } if text(spans) == "Revenue up"
+        ));
+        assert!(matches!(
+            &blocks[4],
+            Block::ListItem { ordered: true, level: 0, spans } if text(spans) == "Costs down"
+        ));
+    }
+
+    #[test]
+    fn bold_sentences_stay_paragraphs() {
+        let page = PageLayout {
+            runs: vec![
+                run("Body text sets the size of the page.", 700.0, 11.0, false),
+                run("Read this before signing.", 670.0, 11.0, true),
+            ],
+            rules: Vec::new(),
+            images: Vec::new(),
+        };
+        let document = structure(&[page]);
+        assert!(document.heading_sizes.is_empty());
+        assert!(
+            document
+                .blocks
+                .iter()
+                .all(|block| matches!(block, Block::Paragraph { .. }))
+        );
+    }
+
+    #[test]
+    fn plain_text_paragraphs_and_pages() {
+        let document = from_plain_text("One\nline.\n\n  Two \x0cThree\x0c");
+        let texts: Vec<String> = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::Paragraph { spans } => text(spans),
+                Block::PageBreak => "---".to_string(),
+                other => panic!("unexpected block {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, ["One line.", "Two", "---", "Three"]);
+    }
+}
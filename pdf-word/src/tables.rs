@@ -0,0 +1,547 @@
+use crate::layout::{PageLayout, Rule, TextRun};
+use crate::model::{Cell, Table};
+use crate::structure;
+
+/// Edges closer than this are the same edge.
+const SNAP: f32 = 2.0;
+
+/// Without borders, text this many font sizes apart is in separate columns.
+const COLUMN_GAP: f32 = 1.5;
+
+/// Lines of a borderless table are at most this many font sizes apart.
+const ROW_GAP: f32 = 2.5;
+
+/// Where a cell sits in its table's grid.
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    row: usize,
+    column: usize,
+    row_span: usize,
+    col_span: usize,
+}
+
+/// The cells of a table before any text is put in them.
+struct Grid {
+    /// Column edges, left to right.
+    xs: Vec<f32>,
+    /// Row edges, top to bottom.
+    ys: Vec<f32>,
+    areas: Vec<Area>,
+}
+
+impl Grid {
+    fn top(&self) -> f32 {
+        self.ys[0]
+    }
+
+    /// Index of the area a point falls in.
+    fn area_at(&self, x: f32, y: f32) -> Option<usize> {
+        let (left, right) = (self.xs[0], self.xs[self.xs.len() - 1]);
+        let (top, bottom) = (self.ys[0], self.ys[self.ys.len() - 1]);
+        if x < left || x > right || y > top || y < bottom {
+            return None;
+        }
+        let column = self.xs[1..].iter().position(|edge| x <= *edge)?;
+        let row = self.ys[1..].iter().position(|edge| y >= *edge)?;
+        self.areas.iter().position(|area| {
+            (area.row..area.row + area.row_span).contains(&row)
+                && (area.column..area.column + area.col_span).contains(&column)
+        })
+    }
+
+    fn into_table(self, runs: &[&TextRun]) -> Table {
+        let mut contents: Vec<Vec<&TextRun>> = vec![Vec::new(); self.areas.len()];
+        for run in runs {
+            let (x, y) = anchor(run);
+            if let Some(index) = self.area_at(x, y) {
+                contents[index].push(run);
+            }
+        }
+        let mut cells: Vec<Cell> = self
+            .areas
+            .iter()
+            .zip(contents)
+            .map(|(area, runs)| Cell {
+                row: area.row,
+                column: area.column,
+                row_span: area.row_span,
+                col_span: area.col_span,
+                paragraphs: structure::paragraphs(&runs),
+            })
+            .collect();
+        cells.sort_by_key(|cell| (cell.row, cell.column));
+        Table {
+            widths: self.xs.windows(2).map(|pair| pair[1] - pair[0]).collect(),
+            rows: self.ys.len() - 1,
+            cells,
+        }
+    }
+}
+
+/// The point that decides which cell a run belongs to: the middle of its
+/// x-height.
+fn anchor(run: &TextRun) -> (f32, f32) {
+    ((run.x + run.end_x) / 2.0, run.y + run.size * 0.3)
+}
+
+/// Averages positions that are within `SNAP` of each other, ascending.
+fn cluster(mut values: Vec<f32>) -> Vec<f32> {
+    values.sort_by(f32::total_cmp);
+    let mut groups: Vec<Vec<f32>> = Vec::new();
+    for value in values {
+        match groups.last_mut() {
+            Some(group) if value - group[group.len() - 1] <= SNAP => group.push(value),
+            _ => groups.push(vec![value]),
+        }
+    }
+    groups
+        .iter()
+        .map(|group| group.iter().sum::<f32>() / group.len() as f32)
+        .collect()
+}
+
+/// Joins collinear rules that touch or overlap, as borders are often drawn
+/// one cell at a time.
+fn join(mut rules: Vec<Rule>) -> Vec<Rule> {
+    rules.sort_by(|a, b| {
+        a.position
+            .total_cmp(&b.position)
+            .then(a.start.total_cmp(&b.start))
+    });
+    let mut joined: Vec<Rule> = Vec::new();
+    for rule in rules {
+        if let Some(last) = joined
+            .iter_mut()
+            .rev()
+            .take_while(|last| rule.position - last.position <= SNAP)
+            .find(|last| rule.start <= last.end + SNAP)
+        {
+            last.end = last.end.max(rule.end);
+            continue;
+        }
+        joined.push(rule);
+    }
+    joined
+}
+
+fn crosses(horizontal: &Rule, vertical: &Rule) -> bool {
+    vertical.position >= horizontal.start - SNAP
+        && vertical.position <= horizontal.end + SNAP
+        && horizontal.position >= vertical.start - SNAP
+        && horizontal.position <= vertical.end + SNAP
+}
+
+/// Whether a rule is drawn at `position` across the point `at`.
+fn drawn(rules: &[&Rule], position: f32, at: f32) -> bool {
+    rules.iter().any(|rule| {
+        (rule.position - position).abs() <= SNAP && rule.start - SNAP <= at && at <= rule.end + SNAP
+    })
+}
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// Tables drawn with borders: each set of crossing horizontal and vertical
+/// rules is a grid, and a missing border between two grid cells merges
+/// them.
+fn ruled_grids(rules: &[Rule]) -> Vec<Grid> {
+    let (horizontal, vertical): (Vec<Rule>, Vec<Rule>) =
+        rules.iter().partition(|rule| rule.horizontal);
+    let rules: Vec<Rule> = join(horizontal).into_iter().chain(join(vertical)).collect();
+
+    let mut parents: Vec<usize> = (0..rules.len()).collect();
+    for (i, a) in rules.iter().enumerate() {
+        for (j, b) in rules.iter().enumerate() {
+            if a.horizontal && !b.horizontal && crosses(a, b) {
+                let (root_a, root_b) = (find(&mut parents, i), find(&mut parents, j));
+                parents[root_a] = root_b;
+            }
+        }
+    }
+    let mut components: Vec<Vec<&Rule>> = vec![Vec::new(); rules.len()];
+    for (index, rule) in rules.iter().enumerate() {
+        let root = find(&mut parents, index);
+        components[root].push(rule);
+    }
+
+    let mut grids = Vec::new();
+    for component in components {
+        let (horizontal, vertical): (Vec<&Rule>, Vec<&Rule>) =
+            component.into_iter().partition(|rule| rule.horizontal);
+        let xs = cluster(vertical.iter().map(|rule| rule.position).collect());
+        let mut ys = cluster(horizontal.iter().map(|rule| rule.position).collect());
+        ys.reverse();
+        // A single box is a frame, not a table
+        if xs.len() < 3 || ys.len() < 2 {
+            continue;
+        }
+
+        let (rows, columns) = (ys.len() - 1, xs.len() - 1);
+        let border_right = |row: usize, column: usize| {
+            drawn(&vertical, xs[column + 1], (ys[row] + ys[row + 1]) / 2.0)
+        };
+        let border_below = |row: usize, column: usize| {
+            drawn(
+                &horizontal,
+                ys[row + 1],
+                (xs[column] + xs[column + 1]) / 2.0,
+            )
+        };
+        let mut taken = vec![vec![false; columns]; rows];
+        let mut areas = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                if taken[row][column] {
+                    continue;
+                }
+                let mut col_span = 1;
+                while column + col_span < columns
+                    && !taken[row][column + col_span]
+                    && !border_right(row, column + col_span - 1)
+                {
+                    col_span += 1;
+                }
+                let mut row_span = 1;
+                while row + row_span < rows
+                    && (column..column + col_span)
+                        .all(|c| !taken[row + row_span][c] && !border_below(row + row_span - 1, c))
+                {
+                    row_span += 1;
+                }
+                for taken_row in &mut taken[row..row + row_span] {
+                    taken_row[column..column + col_span].fill(true);
+                }
+                areas.push(Area {
+                    row,
+                    column,
+                    row_span,
+                    col_span,
+                });
+            }
+        }
+        grids.push(Grid { xs, ys, areas });
+    }
+    grids
+}
+
+/// Splits a line of runs where they are far enough apart to be in
+/// different columns, as (left, right) extents.
+fn segments(line: &[&TextRun]) -> Vec<(f32, f32)> {
+    let mut segments: Vec<(f32, f32)> = Vec::new();
+    let mut previous: Option<&TextRun> = None;
+    for run in line {
+        match (previous, segments.last_mut()) {
+            (Some(previous), Some(last))
+                if run.x - previous.end_x <= COLUMN_GAP * previous.size.max(run.size) =>
+            {
+                last.1 = last.1.max(run.end_x);
+            }
+            _ => segments.push((run.x, run.end_x)),
+        }
+        previous = Some(run);
+    }
+    segments
+}
+
+/// Tables without borders: consecutive lines whose text falls into the
+/// same columns, separated by wide gaps.
+fn aligned_grids(runs: &[&TextRun]) -> Vec<Grid> {
+    let lines = structure::baseline_groups(runs);
+    let line_segments: Vec<Vec<(f32, f32)>> = lines.iter().map(|line| segments(line)).collect();
+    let size = |line: &[&TextRun]| line.iter().map(|run| run.size).fold(0.0, f32::max);
+
+    // Stretches of consecutive, closely spaced lines with two or more columns
+    let mut stretches: Vec<(usize, usize)> = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if line_segments[index].len() < 2 {
+            continue;
+        }
+        match stretches.last_mut() {
+            Some((_, end))
+                if *end + 1 == index
+                    && lines[*end][0].y - line[0].y <= ROW_GAP * size(&lines[*end]) =>
+            {
+                *end = index
+            }
+            _ => stretches.push((index, index)),
+        }
+    }
+
+    let mut grids = Vec::new();
+    for (first, last) in stretches {
+        if first == last {
+            continue;
+        }
+        let table_lines = &line_segments[first..=last];
+
+        // Columns come from the lines that have the most of them, so text
+        // crossing several columns elsewhere becomes a merged cell
+        let most = table_lines.iter().map(Vec::len).max().unwrap_or(0);
+        let mut extents: Vec<(f32, f32)> = table_lines
+            .iter()
+            .filter(|segments| segments.len() == most)
+            .flatten()
+            .copied()
+            .collect();
+        extents.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut bands: Vec<(f32, f32)> = Vec::new();
+        for (left, right) in extents {
+            match bands.last_mut() {
+                Some(band) if left <= band.1 => band.1 = band.1.max(right),
+                _ => bands.push((left, right)),
+            }
+        }
+        if bands.len() < 2 {
+            continue;
+        }
+
+        let mut areas: Vec<Area> = Vec::new();
+        for (row, segments) in table_lines.iter().enumerate() {
+            let mut row_areas: Vec<Area> = Vec::new();
+            for &(left, right) in segments {
+                let start = bands
+                    .iter()
+                    .position(|band| left <= band.1)
+                    .unwrap_or(bands.len() - 1);
+                let end = bands
+                    .iter()
+                    .rposition(|band| right >= band.0)
+                    .unwrap_or(start)
+                    .max(start);
+                match row_areas.last_mut() {
+                    // Two pieces of text in one column share a cell
+                    Some(area) if start < area.column + area.col_span => {
+                        area.col_span = area.col_span.max(end + 1 - area.column);
+                    }
+                    _ => row_areas.push(Area {
+                        row,
+                        column: start,
+                        row_span: 1,
+                        col_span: end + 1 - start,
+                    }),
+                }
+            }
+            // Columns this line leaves empty still need a cell
+            for column in 0..bands.len() {
+                if !row_areas
+                    .iter()
+                    .any(|area| (area.column..area.column + area.col_span).contains(&column))
+                {
+                    row_areas.push(Area {
+                        row,
+                        column,
+                        row_span: 1,
+                        col_span: 1,
+                    });
+                }
+            }
+            areas.extend(row_areas);
+        }
+
+        let mut xs = vec![bands[0].0];
+        xs.extend(bands.windows(2).map(|pair| (pair[0].1 + pair[1].0) / 2.0));
+        xs.push(bands[bands.len() - 1].1);
+        let table_runs = &lines[first..=last];
+        let mut ys = vec![table_runs[0][0].y + size(&table_runs[0])];
+        ys.extend(
+            table_runs
+                .windows(2)
+                .map(|pair| (pair[0][0].y + pair[1][0].y + size(&pair[1])) / 2.0),
+        );
+        ys.push(
+            table_runs[table_runs.len() - 1][0].y - size(&table_runs[table_runs.len() - 1]) * 0.3,
+        );
+        grids.push(Grid { xs, ys, areas });
+    }
+    grids
+}
+
+/// Finds the tables on a page, each with the y of its top edge, and
+/// returns them along with the runs that are outside every table.
+pub fn extract(page: &PageLayout) -> (Vec<(f32, Table)>, Vec<&TextRun>) {
+    let mut tables = Vec::new();
+    let mut runs: Vec<&TextRun> = page.runs.iter().collect();
+
+    for grid in ruled_grids(&page.rules) {
+        let (inside, outside): (Vec<&TextRun>, Vec<&TextRun>) = runs.iter().partition(|run| {
+            let (x, y) = anchor(run);
+            grid.area_at(x, y).is_some()
+        });
+        let top = grid.top();
+        let table = grid.into_table(&inside);
+        // Boxes around a single piece of text are decoration
+        if table
+            .cells
+            .iter()
+            .filter(|cell| !cell.paragraphs.is_empty())
+            .count()
+            < 2
+        {
+            continue;
+        }
+        tables.push((top, table));
+        runs = outside;
+    }
+
+    for grid in aligned_grids(&runs) {
+        let (inside, outside): (Vec<&TextRun>, Vec<&TextRun>) = runs.iter().partition(|run| {
+            let (x, y) = anchor(run);
+            grid.area_at(x, y).is_some()
+        });
+        let top = grid.top();
+        tables.push((top, grid.into_table(&inside)));
+        runs = outside;
+    }
+
+    tables.sort_by(|a, b| b.0.total_cmp(&a.0));
+    (tables, runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::plain_text;
+
+    fn run(text: &str, x: f32, y: f32) -> TextRun {
+        TextRun {
+            text: text.to_string(),
+            x,
+            end_x: x + text.chars().count() as f32 * 5.0,
+            y,
+            size: 10.0,
+            font: "Helvetica".to_string(),
+            bold: false,
+            italic: false,
+        }
+    }
+
+    fn horizontal(y: f32, start: f32, end: f32) -> Rule {
+        Rule {
+            horizontal: true,
+            position: y,
+            start,
+            end,
+        }
+    }
+
+    fn vertical(x: f32, start: f32, end: f32) -> Rule {
+        Rule {
+            horizontal: false,
+            position: x,
+            start,
+            end,
+        }
+    }
+
+    fn cell_text(table: &Table, row: usize, column: usize) -> String {
+        let cell = table
+            .covering(row, column)
+            .expect("a cell covers every position");
+        cell.paragraphs
+            .iter()
+            .map(|spans| plain_text(spans))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn missing_borders_merge_ruled_cells() {
+        // Three columns and two rows; the first column has no border between
+        // its rows, and the top row none between its last two columns
+        let page = PageLayout {
+            runs: vec![
+                run("Name", 10.0, 285.0),
+                run("Details", 110.0, 285.0),
+                run("Size", 110.0, 265.0),
+                run("Weight", 210.0, 265.0),
+                run("Below the table", 10.0, 200.0),
+            ],
+            rules: vec![
+                horizontal(300.0, 0.0, 300.0),
+                horizontal(280.0, 100.0, 300.0),
+                horizontal(260.0, 0.0, 300.0),
+                vertical(0.0, 260.0, 300.0),
+                vertical(100.0, 260.0, 300.0),
+                vertical(200.0, 260.0, 280.0),
+                vertical(300.0, 260.0, 300.0),
+            ],
+            images: Vec::new(),
+        };
+        let (tables, outside) = extract(&page);
+        assert_eq!(tables.len(), 1);
+        let (top, table) = &tables[0];
+        assert_eq!(*top, 300.0);
+        assert_eq!(table.rows, 2);
+        assert_eq!(table.widths, vec![100.0, 100.0, 100.0]);
+
+        let spans: Vec<_> = table
+            .cells
+            .iter()
+            .map(|cell| (cell.row, cell.column, cell.row_span, cell.col_span))
+            .collect();
+        assert_eq!(
+            spans,
+            [(0, 0, 2, 1), (0, 1, 1, 2), (1, 1, 1, 1), (1, 2, 1, 1)]
+        );
+        assert_eq!(cell_text(table, 1, 0), "Name");
+        assert_eq!(cell_text(table, 0, 2), "Details");
+        assert_eq!(cell_text(table, 1, 1), "Size");
+        assert_eq!(cell_text(table, 1, 2), "Weight");
+
+        assert_eq!(outside.len(), 1);
+        assert_eq!(outside[0].text, "Below the table");
+    }
+
+    #[test]
+    fn a_single_box_is_not_a_table() {
+        let page = PageLayout {
+            runs: vec![run("Boxed note", 10.0, 285.0)],
+            rules: vec![
+                horizontal(300.0, 0.0, 300.0),
+                horizontal(260.0, 0.0, 300.0),
+                vertical(0.0, 260.0, 300.0),
+                vertical(300.0, 260.0, 300.0),
+            ],
+            images: Vec::new(),
+        };
+        let (tables, outside) = extract(&page);
+        assert!(tables.is_empty());
+        assert_eq!(outside.len(), 1);
+    }
+
+    #[test]
+    fn aligned_text_without_borders() {
+        let page = PageLayout {
+            runs: vec![
+                run("Fruit", 50.0, 700.0),
+                run("Price", 200.0, 700.0),
+                run("Stock", 350.0, 700.0),
+                run("Apple", 50.0, 686.0),
+                run("0.40", 200.0, 686.0),
+                run("120", 350.0, 686.0),
+                // Runs into the price column, so it becomes one merged cell
+                run("Apples and pears, mixed in one box", 50.0, 672.0),
+                run("35", 350.0, 672.0),
+            ],
+            rules: Vec::new(),
+            images: Vec::new(),
+        };
+        let (tables, outside) = extract(&page);
+        assert!(outside.is_empty());
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0].1;
+        assert_eq!(table.rows, 3);
+        assert_eq!(table.widths.len(), 3);
+        assert_eq!(cell_text(table, 0, 1), "Price");
+        assert_eq!(cell_text(table, 1, 2), "120");
+        let merged = table.covering(2, 1).unwrap();
+        assert_eq!((merged.column, merged.col_span), (0, 2));
+        assert_eq!(cell_text(table, 2, 0), "Apples and pears, mixed in one box");
+        assert_eq!(cell_text(table, 2, 2), "35");
+    }
+}
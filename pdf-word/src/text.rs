@@ -0,0 +1,195 @@
+use crate::layout::PageLayout;
+use crate::model::{Block, Document, Span, Table, plain_text};
+use crate::output::Writer;
+use crate::structure::baseline_groups;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Space between table columns.
+const COLUMN_SEPARATOR: &str = "  ";
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// A table as aligned columns. A spanning cell takes the width of the
+/// columns it covers, and the rows a cell spans down into stay blank.
+fn table(table: &Table) -> String {
+    let columns = table.widths.len();
+    let text = |paragraphs: &[Vec<Span>]| {
+        paragraphs
+            .iter()
+            .map(|spans| plain_text(spans))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut widths = vec![0; columns];
+    for cell in table.cells.iter().filter(|cell| cell.col_span == 1) {
+        widths[cell.column] = widths[cell.column].max(width(&text(&cell.paragraphs)));
+    }
+    // Widen the last column under a spanning cell that doesn't fit
+    for cell in table.cells.iter().filter(|cell| cell.col_span > 1) {
+        let covered = cell.column..cell.column + cell.col_span;
+        let available = widths[covered.clone()].iter().sum::<usize>()
+            + COLUMN_SEPARATOR.len() * (cell.col_span - 1);
+        let needed = width(&text(&cell.paragraphs));
+        if needed > available {
+            widths[covered.end - 1] += needed - available;
+        }
+    }
+
+    let mut lines = Vec::new();
+    for row in 0..table.rows {
+        let mut line = String::new();
+        let mut column = 0;
+        while column < columns {
+            let (content, span) = match table.covering(row, column) {
+                Some(cell) if cell.row == row => (text(&cell.paragraphs), cell.col_span),
+                Some(cell) => (String::new(), cell.col_span),
+                None => (String::new(), 1),
+            };
+            let cell_width = widths[column..column + span].iter().sum::<usize>()
+                + COLUMN_SEPARATOR.len() * (span - 1);
+            if column > 0 {
+                line.push_str(COLUMN_SEPARATOR);
+            }
+            line.push_str(&content);
+            line.push_str(&" ".repeat(cell_width.saturating_sub(width(&content))));
+            column += span;
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+/// Plain text that keeps the shape of the document: underlined headings,
+/// indented and numbered lists, tables as aligned columns and a form feed
+/// between pages, so that conversions diff cleanly. Paragraphs are reflowed
+/// onto one line each; `write_layout` keeps the page positions instead.
+pub struct TextWriter;
+
+impl Writer for TextWriter {
+    fn write(&self, document: &Document, output: &Path) -> Result<()> {
+        let mut text = String::new();
+        // Item numbers of the ordered lists currently open, by level
+        let mut numbers: Vec<usize> = Vec::new();
+        let mut previous: Option<&Block> = None;
+        for block in &document.blocks {
+            let content = match block {
+                Block::Heading { level, spans } => {
+                    let heading = plain_text(spans);
+                    let underline = if *level == 1 { "=" } else { "-" };
+                    format!("{}\n{}", heading, underline.repeat(width(&heading)))
+                }
+                Block::Paragraph { spans } => plain_text(spans),
+                Block::ListItem {
+                    ordered,
+                    level,
+                    spans,
+                } => {
+                    let level = *level as usize;
+                    numbers.resize(level + 1, 0);
+                    let marker = if *ordered {
+                        numbers[level] += 1;
+                        format!("{}.", numbers[level])
+                    } else {
+                        "*".to_string()
+                    };
+                    format!("{}{} {}", "  ".repeat(level), marker, plain_text(spans))
+                }
+                Block::Table(grid) => table(grid),
+                Block::Figure(figure) => {
+                    format!("[image {:.0} x {:.0} pt]", figure.width, figure.height)
+                }
+                Block::PageBreak => "\x0C".to_string(),
+            };
+            if !matches!(block, Block::ListItem { .. }) {
+                numbers.clear();
+            }
+            if let Some(previous) = previous {
+                let tight = matches!(previous, Block::ListItem { .. })
+                    && matches!(block, Block::ListItem { .. });
+                text.push_str(if tight { "\n" } else { "\n\n" });
+            }
+            text.push_str(&content);
+            previous = Some(block);
+        }
+        text.push('\n');
+
+        fs::write(output, text)
+            .with_context(|| format!("Failed to write text: {}", output.display()))
+    }
+}
+
+/// One page of text placed on a character grid: each baseline becomes a
+/// line, each run starts at the column its x position falls in, and gaps
+/// between baselines become blank lines.
+fn layout_page(page: &PageLayout) -> String {
+    let runs: Vec<_> = page.runs.iter().collect();
+    if runs.is_empty() {
+        return String::new();
+    }
+
+    // The page's average advance per character sets the column width
+    let (advance, characters) = runs.iter().fold((0.0, 0), |(advance, characters), run| {
+        (
+            advance + (run.end_x - run.x).max(0.0),
+            characters + width(&run.text),
+        )
+    });
+    let column_width = if characters > 0 && advance > 0.0 {
+        advance / characters as f32
+    } else {
+        runs[0].size * 0.5
+    };
+    let left = runs.iter().map(|run| run.x).fold(f32::INFINITY, f32::min);
+
+    let mut sizes: Vec<f32> = runs.iter().map(|run| run.size).collect();
+    sizes.sort_by(f32::total_cmp);
+    let line_height = (sizes[sizes.len() / 2] * 1.2).max(1.0);
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut previous_y: Option<f32> = None;
+    for group in baseline_groups(&runs) {
+        let y = group[0].y;
+        if let Some(previous_y) = previous_y {
+            let gap = ((previous_y - y) / line_height).round() as usize;
+            for _ in 1..gap {
+                lines.push(String::new());
+            }
+        }
+        previous_y = Some(y);
+
+        let mut line = String::new();
+        for run in group {
+            let column = ((run.x - left) / column_width).round().max(0.0) as usize;
+            let used = width(&line);
+            if column > used {
+                line.push_str(&" ".repeat(column - used));
+            } else if used > 0 && !line.ends_with(' ') && !run.text.starts_with(' ') {
+                // Overlapping runs still stay apart
+                line.push(' ');
+            }
+            line.push_str(&run.text);
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+/// Plain text with every line placed by its x and y position on the page
+/// rather than reflowed, a form feed between pages. Tables, columns and
+/// indentation come out as they are printed, at the cost of hyphenation and
+/// line breaks following the PDF's.
+pub fn write_layout(pages: &[PageLayout], output: &Path) -> Result<()> {
+    let mut text = pages
+        .iter()
+        .map(layout_page)
+        .collect::<Vec<_>>()
+        .join("\n\x0C\n");
+    text.push('\n');
+
+    fs::write(output, text).with_context(|| format!("Failed to write text: {}", output.display()))
+}
@@ -0,0 +1,543 @@
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use lopdf::{Document, Object, ObjectId, Stream, StringFormat, dictionary};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use ttf_parser::{Face, name_id};
+
+/// Folders searched for a font family, in order: a `fonts/` folder in the
+/// working directory, then the usual system locations.
+const FONT_DIRS: &[&str] = &[
+    "fonts",
+    "/usr/share/fonts/truetype/dejavu",
+    "/usr/share/fonts/dejavu",
+    "/usr/share/fonts/truetype",
+    "/usr/share/fonts/TTF",
+    "/usr/share/fonts/truetype/liberation",
+    "/usr/share/fonts/liberation",
+    "/Library/Fonts",
+    "/System/Library/Fonts/Supplemental",
+    "C:\\Windows\\Fonts",
+];
+
+/// Font families that cover Western text, as their regular, bold, italic
+/// and bold italic files.
+const FAMILIES: &[[&str; 4]] = &[
+    [
+        "DejaVuSans.ttf",
+        "DejaVuSans-Bold.ttf",
+        "DejaVuSans-Oblique.ttf",
+        "DejaVuSans-BoldOblique.ttf",
+    ],
+    [
+        "LiberationSans-Regular.ttf",
+        "LiberationSans-Bold.ttf",
+        "LiberationSans-Italic.ttf",
+        "LiberationSans-BoldItalic.ttf",
+    ],
+    ["arial.ttf", "arialbd.ttf", "ariali.ttf", "arialbi.ttf"],
+    [
+        "Arial.ttf",
+        "Arial Bold.ttf",
+        "Arial Italic.ttf",
+        "Arial Bold Italic.ttf",
+    ],
+];
+
+/// bfchar entries per block; the CMap format allows at most 100.
+const CMAP_BLOCK: usize = 100;
+
+/// Tables kept in an embedded font: those drawing glyphs needs, plus the
+/// small ones some viewers look for. Layout tables and glyph names go.
+const EMBEDDED_TABLES: [&[u8; 4]; 11] = [
+    b"OS/2", b"cmap", b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp",
+    b"prep",
+];
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// The font with the outlines of glyphs not in `used` emptied, and the
+/// tables PDF viewers don't use left out. Glyph ids don't change, so the
+/// text encoded against the whole font still shows.
+fn subset(data: &[u8], used: &BTreeMap<u16, char>) -> Option<Vec<u8>> {
+    let mut tables = BTreeMap::new();
+    for i in 0..be16(data, 4)? as usize {
+        let record = 12 + 16 * i;
+        let tag: [u8; 4] = data.get(record..record + 4)?.try_into().ok()?;
+        let offset = be32(data, record + 8)? as usize;
+        let length = be32(data, record + 12)? as usize;
+        tables.insert(tag, data.get(offset..offset + length)?);
+    }
+    let head = *tables.get(b"head")?;
+    let loca = *tables.get(b"loca")?;
+    let glyf = *tables.get(b"glyf")?;
+    let long_offsets = be16(head, 50)? == 1;
+    let glyph_count = be16(tables.get(b"maxp")?, 4)? as usize;
+    let outline = |glyph: usize| {
+        let (start, end) = if long_offsets {
+            (
+                be32(loca, glyph * 4)? as usize,
+                be32(loca, glyph * 4 + 4)? as usize,
+            )
+        } else {
+            (
+                be16(loca, glyph * 2)? as usize * 2,
+                be16(loca, glyph * 2 + 2)? as usize * 2,
+            )
+        };
+        glyf.get(start..end)
+    };
+
+    // Composite glyphs are made of other glyphs, which are kept too
+    let mut keep = BTreeSet::new();
+    let mut pending: Vec<u16> = used.keys().copied().chain([0]).collect();
+    while let Some(glyph) = pending.pop() {
+        if glyph as usize >= glyph_count || !keep.insert(glyph) {
+            continue;
+        }
+        let outline = outline(glyph as usize)?;
+        if outline.len() < 10 || (be16(outline, 0)? as i16) >= 0 {
+            continue;
+        }
+        let mut at = 10;
+        loop {
+            let flags = be16(outline, at)?;
+            pending.push(be16(outline, at + 2)?);
+            at += if flags & 0x0001 != 0 { 8 } else { 6 };
+            at += match flags {
+                f if f & 0x0008 != 0 => 2,
+                f if f & 0x0040 != 0 => 4,
+                f if f & 0x0080 != 0 => 8,
+                _ => 0,
+            };
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count + 1) * 4);
+    for glyph in 0..glyph_count {
+        new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(glyph as u16)) {
+            new_glyf.extend_from_slice(outline(glyph)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+    let mut new_head = head.to_vec();
+    new_head.get_mut(8..12)?.fill(0);
+    // The new loca table has long offsets
+    new_head.get_mut(50..52)?.copy_from_slice(&[0, 1]);
+
+    let kept: Vec<([u8; 4], Vec<u8>)> = tables
+        .into_iter()
+        .filter(|(tag, _)| EMBEDDED_TABLES.contains(&tag))
+        .map(|(tag, table)| {
+            let table = match &tag {
+                b"glyf" => mem::take(&mut new_glyf),
+                b"loca" => mem::take(&mut new_loca),
+                b"head" => mem::take(&mut new_head),
+                _ => table.to_vec(),
+            };
+            (tag, table)
+        })
+        .collect();
+
+    let count = kept.len() as u16;
+    let entry_selector = (u16::BITS - 1 - count.leading_zeros()) as u16;
+    let search_range = 16u16 << entry_selector;
+    let mut font = vec![0, 1, 0, 0];
+    for value in [
+        count,
+        search_range,
+        entry_selector,
+        count * 16 - search_range,
+    ] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * kept.len();
+    let mut head_offset = 0;
+    for (tag, table) in &kept {
+        if tag == b"head" {
+            head_offset = offset;
+        }
+        font.extend(tag);
+        font.extend(checksum(table).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+    for (_, table) in &kept {
+        font.extend(table);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+    font.get_mut(head_offset + 8..head_offset + 12)?
+        .copy_from_slice(&adjustment.to_be_bytes());
+    Some(font)
+}
+
+/// The six letter tag that marks a subset font's name, different for
+/// different subsets.
+fn subset_tag(used: &BTreeMap<u16, char>) -> String {
+    let mut hasher = DefaultHasher::new();
+    used.keys().collect::<Vec<_>>().hash(&mut hasher);
+    let mut hash = hasher.finish();
+    (0..6)
+        .map(|_| {
+            let letter = (b'A' + (hash % 26) as u8) as char;
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+/// A TrueType font to measure text with and embed whole. Text is shown
+/// with glyph ids as character codes (Identity-H), so any character the
+/// font has can be used.
+pub struct TrueType {
+    data: Vec<u8>,
+    name: String,
+    units_per_em: f32,
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    cap_height: i16,
+    italic_angle: f32,
+    bbox: [i16; 4],
+    underline: (i16, i16),
+    strikeout: (i16, i16),
+    /// Glyph id and advance width of every character the font maps.
+    glyphs: HashMap<char, (u16, u16)>,
+    missing_advance: u16,
+}
+
+impl TrueType {
+    pub fn load(path: &Path) -> Result<TrueType> {
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read font: {}", path.display()))?;
+        let face = Face::parse(&data, 0)
+            .with_context(|| format!("Failed to parse font: {}", path.display()))?;
+        if face.tables().glyf.is_none() {
+            bail!(
+                "{} has PostScript outlines; use a TrueType (.ttf) font",
+                path.display()
+            );
+        }
+
+        let mut glyphs = HashMap::new();
+        if let Some(cmap) = face.tables().cmap {
+            for subtable in cmap.subtables.into_iter().filter(|s| s.is_unicode()) {
+                subtable.codepoints(|code_point| {
+                    if let Some(c) = char::from_u32(code_point)
+                        && let Some(glyph) = subtable.glyph_index(code_point)
+                    {
+                        let advance = face.glyph_hor_advance(glyph).unwrap_or(0);
+                        glyphs.entry(c).or_insert((glyph.0, advance));
+                    }
+                });
+            }
+        }
+
+        let name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .or_else(|| {
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .map(String::from)
+            })
+            .unwrap_or_else(|| "Font".to_string())
+            .chars()
+            .filter(|c| c.is_ascii_graphic() && !"()<>[]{}/%#".contains(*c))
+            .collect();
+        let bbox = face.global_bounding_box();
+        let metrics = |m: Option<ttf_parser::LineMetrics>| m.map(|m| (m.position, m.thickness));
+        let units_per_em = face.units_per_em();
+
+        Ok(TrueType {
+            name,
+            units_per_em: units_per_em as f32,
+            ascender: face.ascender(),
+            descender: face.descender(),
+            line_gap: face.line_gap(),
+            cap_height: face.capital_height().unwrap_or(face.ascender()),
+            italic_angle: face.italic_angle(),
+            bbox: [bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max],
+            underline: metrics(face.underline_metrics())
+                .unwrap_or((-(units_per_em as i16) / 10, units_per_em as i16 / 20)),
+            strikeout: metrics(face.strikeout_metrics())
+                .unwrap_or((units_per_em as i16 * 3 / 10, units_per_em as i16 / 20)),
+            missing_advance: face
+                .glyph_hor_advance(ttf_parser::GlyphId(0))
+                .unwrap_or(units_per_em / 2),
+            glyphs,
+            data,
+        })
+    }
+
+    /// The glyph id and advance of a character, the missing glyph if the
+    /// font doesn't have it.
+    pub fn glyph(&self, c: char) -> (u16, u16) {
+        self.glyphs
+            .get(&c)
+            .copied()
+            .unwrap_or((0, self.missing_advance))
+    }
+
+    fn scale(&self, units: impl Into<f32>, size: f32) -> f32 {
+        units.into() * size / self.units_per_em
+    }
+
+    pub fn width(&self, text: &str, size: f32) -> f32 {
+        let units: u32 = text.chars().map(|c| self.glyph(c).1 as u32).sum();
+        self.scale(units as f32, size)
+    }
+
+    /// How far the font reaches below the baseline, as a positive length.
+    pub fn descent(&self, size: f32) -> f32 {
+        -self.scale(self.descender, size)
+    }
+
+    /// Baseline to baseline distance of single spaced lines.
+    pub fn line_height(&self, size: f32) -> f32 {
+        self.scale(self.ascender - self.descender + self.line_gap, size)
+    }
+
+    /// Offset from the baseline and thickness of an underline.
+    pub fn underline(&self, size: f32) -> (f32, f32) {
+        (
+            self.scale(self.underline.0, size),
+            self.scale(self.underline.1, size),
+        )
+    }
+
+    pub fn strikeout(&self, size: f32) -> (f32, f32) {
+        (
+            self.scale(self.strikeout.0, size),
+            self.scale(self.strikeout.1, size),
+        )
+    }
+
+    /// In the font's units scaled to the 1000 per em of PDF glyph space.
+    fn pdf_units(&self, units: impl Into<f32>) -> Object {
+        ((units.into() * 1000.0 / self.units_per_em).round() as i64).into()
+    }
+
+    fn to_unicode(used: &BTreeMap<u16, char>) -> Vec<u8> {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+        let entries: Vec<_> = used.iter().collect();
+        for block in entries.chunks(CMAP_BLOCK) {
+            let _ = writeln!(cmap, "{} beginbfchar", block.len());
+            for (glyph, c) in block {
+                let mut utf16 = [0u16; 2];
+                let units: String = c
+                    .encode_utf16(&mut utf16)
+                    .iter()
+                    .map(|unit| format!("{:04X}", unit))
+                    .collect();
+                let _ = writeln!(cmap, "<{:04X}> <{}>", glyph, units);
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str(
+            "endcmap\n\
+             CMapName currentdict /CMap defineresource pop\n\
+             end\n\
+             end\n",
+        );
+        cmap.into_bytes()
+    }
+
+    /// Embeds the glyphs in `used` as a Type0 font and returns its id.
+    /// Widths and the ToUnicode map cover those glyphs, keeping the text
+    /// extractable.
+    pub fn embed(&self, doc: &mut Document, used: &BTreeMap<u16, char>) -> Result<ObjectId> {
+        let (data, name) = match subset(&self.data, used) {
+            Some(data) => (data, format!("{}+{}", subset_tag(used), self.name)),
+            None => (self.data.clone(), self.name.clone()),
+        };
+        let font_file = doc.add_object(Stream::new(
+            dictionary! {
+                "Length1" => data.len() as i64,
+                "Filter" => "FlateDecode",
+            },
+            deflate(&data)?,
+        ));
+
+        // Symbolic, since the glyphs are not addressed by a standard
+        // encoding
+        let mut flags = 4;
+        if self.italic_angle != 0.0 {
+            flags |= 64;
+        }
+        let descriptor = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => Object::Name(name.clone().into_bytes()),
+            "Flags" => flags,
+            "FontBBox" => self.bbox.iter().map(|v| self.pdf_units(*v)).collect::<Vec<_>>(),
+            "ItalicAngle" => self.italic_angle,
+            "Ascent" => self.pdf_units(self.ascender),
+            "Descent" => self.pdf_units(self.descender),
+            "CapHeight" => self.pdf_units(self.cap_height),
+            "StemV" => 80,
+            "FontFile2" => font_file,
+        });
+
+        let mut widths = Vec::new();
+        let advances: HashMap<u16, u16> = self.glyphs.values().copied().collect();
+        for glyph in used.keys() {
+            let advance = advances.get(glyph).copied().unwrap_or(self.missing_advance);
+            widths.push((*glyph as i64).into());
+            widths.push(Object::Array(vec![self.pdf_units(advance)]));
+        }
+        let descendant = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => Object::Name(name.clone().into_bytes()),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => descriptor,
+            "DW" => self.pdf_units(self.missing_advance),
+            "W" => widths,
+            "CIDToGIDMap" => "Identity",
+        });
+
+        let to_unicode = doc.add_object(Stream::new(
+            dictionary! { "Filter" => "FlateDecode" },
+            deflate(&TrueType::to_unicode(used))?,
+        ));
+        Ok(doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => Object::Name(name.clone().into_bytes()),
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![descendant.into()],
+            "ToUnicode" => to_unicode,
+        }))
+    }
+
+    /// Glyph ids of some text as a Type0 string, recording the glyphs used.
+    pub fn encode(&self, text: &str, used: &mut BTreeMap<u16, char>) -> Object {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let (glyph, _) = self.glyph(c);
+            used.entry(glyph).or_insert(c);
+            bytes.extend(glyph.to_be_bytes());
+        }
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+pub fn deflate(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// A regular face and whichever of bold, italic and bold italic were
+/// found. Styles without a face of their own are synthesized.
+pub struct Family {
+    /// Indexed by `face_index`: regular, bold, italic, bold italic.
+    faces: [Option<TrueType>; 4],
+}
+
+/// A face for a style, and what has to be faked to get the style from it.
+pub struct Selected<'a> {
+    pub index: usize,
+    pub font: &'a TrueType,
+    pub fake_bold: bool,
+    pub fake_italic: bool,
+}
+
+fn face_index(bold: bool, italic: bool) -> usize {
+    bold as usize | (italic as usize) << 1
+}
+
+impl Family {
+    /// The font given with `--font` for every style, or the first family
+    /// with a regular face in the font folders.
+    pub fn find(font: Option<&Path>) -> Result<Family> {
+        if let Some(path) = font {
+            return Ok(Family {
+                faces: [Some(TrueType::load(path)?), None, None, None],
+            });
+        }
+        for directory in FONT_DIRS {
+            for files in FAMILIES {
+                let paths: Vec<PathBuf> = files
+                    .iter()
+                    .map(|file| Path::new(directory).join(file))
+                    .collect();
+                if !paths[0].is_file() {
+                    continue;
+                }
+                let mut faces = [None, None, None, None];
+                for (face, path) in faces.iter_mut().zip(&paths) {
+                    if path.is_file() {
+                        *face = Some(TrueType::load(path)?);
+                    }
+                }
+                return Ok(Family { faces });
+            }
+        }
+        bail!("No TrueType font found to embed; pass one with --font")
+    }
+
+    pub fn face(&self, bold: bool, italic: bool) -> Selected<'_> {
+        let wanted = face_index(bold, italic);
+        // The closest face with no style the run doesn't ask for
+        let index = [wanted, wanted & 1, wanted & 2, 0]
+            .into_iter()
+            .find(|index| self.faces[*index].is_some())
+            .unwrap_or(0);
+        Selected {
+            index,
+            font: self.faces[index]
+                .as_ref()
+                .expect("a family always has a regular face"),
+            fake_bold: bold && index & 1 == 0,
+            fake_italic: italic && index & 2 == 0,
+        }
+    }
+
+    pub fn font(&self, index: usize) -> Option<&TrueType> {
+        self.faces[index].as_ref()
+    }
+}
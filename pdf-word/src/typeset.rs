@@ -0,0 +1,729 @@
+use crate::truetype::Family;
+use crate::wordml::{
+    Align, Block, Document, Inline, LineSpacing, Merge, PageSetup, Paragraph, Picture, RunStyle,
+    TabAlign, Table,
+};
+use std::collections::VecDeque;
+use std::mem;
+
+/// Word's default tab stops are every half inch.
+const DEFAULT_TAB: f32 = 36.0;
+
+/// Thickness of table borders.
+const BORDER_WIDTH: f32 = 0.5;
+
+/// Something drawn on a page, in PDF coordinates.
+#[derive(Debug, Clone)]
+pub enum Mark {
+    /// Text starting at `x` on the baseline `y`.
+    Text {
+        x: f32,
+        y: f32,
+        text: String,
+        style: RunStyle,
+    },
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+        width: f32,
+        color: [u8; 3],
+    },
+    Fill {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: [u8; 3],
+    },
+    /// A picture with its lower left corner at `x`, `y`.
+    Picture { x: f32, y: f32, picture: Picture },
+}
+
+pub struct Page {
+    pub width: f32,
+    pub height: f32,
+    pub marks: Vec<Mark>,
+}
+
+/// Part of a word: text in one style, or a picture.
+#[derive(Debug, Clone)]
+enum Part {
+    Text {
+        text: String,
+        style: RunStyle,
+        width: f32,
+    },
+    Picture(Picture),
+}
+
+impl Part {
+    fn width(&self) -> f32 {
+        match self {
+            Part::Text { width, .. } => *width,
+            Part::Picture(picture) => picture.width,
+        }
+    }
+}
+
+/// What lies between two places a line may break.
+#[derive(Debug, Clone, Default)]
+struct Word {
+    parts: Vec<Part>,
+    width: f32,
+    /// Width of the spaces before it.
+    gap: f32,
+    /// Tabs before it.
+    tabs: usize,
+}
+
+enum Token {
+    Word(Word),
+    LineBreak,
+    PageBreak,
+}
+
+/// A line of a paragraph. Positions are from the left of the text column
+/// (or cell), before alignment.
+struct Line {
+    words: Vec<(f32, Word)>,
+    /// Right edge the text may reach.
+    end: f32,
+    /// Whether justified text is spread to the edge; not on the last line
+    /// or a line ended by a break.
+    justify: bool,
+    page_break: bool,
+    height: f32,
+    descent: f32,
+}
+
+/// Space between paragraphs: the previous one's space after plus this one's
+/// space before, except between paragraphs of a style that drops it.
+#[derive(Default)]
+struct Spacing {
+    /// The previous paragraph's style, whether it drops spacing, and its
+    /// space after.
+    previous: Option<(String, bool, f32)>,
+}
+
+impl Spacing {
+    fn before(&mut self, paragraph: &Paragraph) -> f32 {
+        let space = match &self.previous {
+            Some((style, contextual, after)) => {
+                let same = *style == paragraph.style;
+                let after = if same && *contextual { 0.0 } else { *after };
+                let before = if same && paragraph.contextual_spacing {
+                    0.0
+                } else {
+                    paragraph.space_before
+                };
+                after + before
+            }
+            None => paragraph.space_before,
+        };
+        self.previous = Some((
+            paragraph.style.clone(),
+            paragraph.contextual_spacing,
+            paragraph.space_after,
+        ));
+        space
+    }
+
+    /// The last paragraph's space after, ending the run of paragraphs.
+    fn after(&mut self) -> f32 {
+        self.previous.take().map_or(0.0, |(_, _, after)| after)
+    }
+}
+
+struct Typesetter<'a> {
+    family: &'a Family,
+    setup: PageSetup,
+    pages: Vec<Page>,
+    marks: Vec<Mark>,
+    /// Top of the next line.
+    y: f32,
+    /// Nothing has been placed on the page yet.
+    at_top: bool,
+    spacing: Spacing,
+}
+
+impl<'a> Typesetter<'a> {
+    fn new(family: &'a Family, setup: PageSetup) -> Self {
+        Typesetter {
+            family,
+            setup,
+            pages: Vec::new(),
+            marks: Vec::new(),
+            y: setup.height - setup.top,
+            at_top: true,
+            spacing: Spacing::default(),
+        }
+    }
+
+    fn text_width(&self, text: &str, style: &RunStyle) -> f32 {
+        self.family
+            .face(style.bold, style.italic)
+            .font
+            .width(text, style.size)
+    }
+
+    fn text_part(&self, text: String, style: &RunStyle) -> Part {
+        Part::Text {
+            width: self.text_width(&text, style),
+            text,
+            style: style.clone(),
+        }
+    }
+
+    /// Splits a paragraph into words and breaks. Spaces separate words;
+    /// text and pictures with no space between them stay together.
+    fn tokens(&self, paragraph: &Paragraph) -> VecDeque<Token> {
+        let mut tokens = VecDeque::new();
+        let mut word = Word::default();
+        let mut gap = 0.0;
+        let mut tabs = 0;
+        let flush = |word: &mut Word, tokens: &mut VecDeque<Token>| {
+            if !word.parts.is_empty() {
+                tokens.push_back(Token::Word(mem::take(word)));
+            }
+        };
+
+        if let Some((label, style)) = &paragraph.label {
+            let part = self.text_part(label.clone(), style);
+            tokens.push_back(Token::Word(Word {
+                width: part.width(),
+                parts: vec![part],
+                ..Word::default()
+            }));
+            // The label is followed by a tab
+            tabs = 1;
+        }
+
+        for inline in &paragraph.inlines {
+            match inline {
+                Inline::Text { text, style } => {
+                    for c in text.chars() {
+                        if c == ' ' {
+                            flush(&mut word, &mut tokens);
+                            gap += self.text_width(" ", style);
+                            continue;
+                        }
+                        if word.parts.is_empty() {
+                            word.gap = mem::take(&mut gap);
+                            word.tabs = mem::take(&mut tabs);
+                        }
+                        match word.parts.last_mut() {
+                            Some(Part::Text {
+                                text, style: last, ..
+                            }) if last == style => text.push(c),
+                            _ => word.parts.push(Part::Text {
+                                text: c.to_string(),
+                                style: style.clone(),
+                                width: 0.0,
+                            }),
+                        }
+                    }
+                }
+                Inline::Tab => {
+                    flush(&mut word, &mut tokens);
+                    tabs += 1;
+                    gap = 0.0;
+                }
+                Inline::LineBreak | Inline::PageBreak => {
+                    flush(&mut word, &mut tokens);
+                    tokens.push_back(if matches!(inline, Inline::PageBreak) {
+                        Token::PageBreak
+                    } else {
+                        Token::LineBreak
+                    });
+                    gap = 0.0;
+                    tabs = 0;
+                }
+                Inline::Picture(picture) => {
+                    if word.parts.is_empty() {
+                        word.gap = mem::take(&mut gap);
+                        word.tabs = mem::take(&mut tabs);
+                    }
+                    word.parts.push(Part::Picture(picture.clone()));
+                }
+            }
+        }
+        flush(&mut word, &mut tokens);
+
+        // Text widths are measured once the text is complete
+        for token in tokens.iter_mut() {
+            if let Token::Word(word) = token {
+                for part in word.parts.iter_mut() {
+                    if let Part::Text { text, style, width } = part {
+                        *width = self.text_width(text, style);
+                    }
+                }
+                word.width = word.parts.iter().map(Part::width).sum();
+            }
+        }
+        tokens
+    }
+
+    /// Where text after a tab at `x` starts. `following` is the width of
+    /// that text up to the next tab or break, which right and centered
+    /// stops align.
+    fn tab_stop(paragraph: &Paragraph, x: f32, following: f32) -> f32 {
+        let explicit = paragraph.tabs.iter().find(|stop| stop.position > x + 0.5);
+        // A hanging indent also acts as a stop
+        let hanging = (paragraph.first_line < 0.0 && paragraph.indent_left > x + 0.5)
+            .then_some(paragraph.indent_left);
+        match (explicit, hanging) {
+            (Some(stop), hanging) if hanging.is_none_or(|h| stop.position < h) => {
+                let start = match stop.align {
+                    TabAlign::Left => stop.position,
+                    TabAlign::Center => stop.position - following / 2.0,
+                    TabAlign::Right => stop.position - following,
+                };
+                start.max(x)
+            }
+            (_, Some(hanging)) => hanging,
+            _ => ((x / DEFAULT_TAB).floor() + 1.0) * DEFAULT_TAB,
+        }
+    }
+
+    /// Where a word starts when the line so far ends at `x`.
+    fn word_start(
+        paragraph: &Paragraph,
+        word: &Word,
+        x: f32,
+        line_empty: bool,
+        rest: &VecDeque<Token>,
+    ) -> f32 {
+        if word.tabs == 0 {
+            return if line_empty { x } else { x + word.gap };
+        }
+        let following = word.gap
+            + word.width
+            + rest
+                .iter()
+                .map_while(|token| match token {
+                    Token::Word(next) if next.tabs == 0 => Some(next.gap + next.width),
+                    _ => None,
+                })
+                .sum::<f32>();
+        let mut x = x;
+        for tab in 1..=word.tabs {
+            let aligned = if tab == word.tabs { following } else { 0.0 };
+            x = Typesetter::tab_stop(paragraph, x, aligned);
+        }
+        x + word.gap
+    }
+
+    /// Cuts a word too long for any line so that its head fits in `room`.
+    fn split(&self, word: Word, room: f32) -> Option<(Word, Word)> {
+        let mut head = Word {
+            gap: word.gap,
+            tabs: word.tabs,
+            ..Word::default()
+        };
+        let mut tail = Word::default();
+        let mut used = 0.0;
+        let mut full = false;
+        for part in word.parts {
+            if full {
+                tail.parts.push(part);
+                continue;
+            }
+            match part {
+                Part::Text { text, style, .. } => {
+                    let mut fits = String::new();
+                    let mut rest = String::new();
+                    for c in text.chars() {
+                        let mut buffer = [0u8; 4];
+                        let width = self.text_width(c.encode_utf8(&mut buffer), &style);
+                        let first = head.parts.is_empty() && fits.is_empty();
+                        if full || (used + width > room && !first) {
+                            full = true;
+                            rest.push(c);
+                        } else {
+                            used += width;
+                            fits.push(c);
+                        }
+                    }
+                    if !fits.is_empty() {
+                        head.parts.push(self.text_part(fits, &style));
+                    }
+                    if !rest.is_empty() {
+                        tail.parts.push(self.text_part(rest, &style));
+                    }
+                }
+                Part::Picture(picture) => {
+                    if used + picture.width > room && !head.parts.is_empty() {
+                        full = true;
+                        tail.parts.push(Part::Picture(picture));
+                    } else {
+                        used += picture.width;
+                        head.parts.push(Part::Picture(picture));
+                    }
+                }
+            }
+        }
+        if tail.parts.is_empty() {
+            return None;
+        }
+        head.width = head.parts.iter().map(Part::width).sum();
+        tail.width = tail.parts.iter().map(Part::width).sum();
+        Some((head, tail))
+    }
+
+    /// Height and descent of a line under the paragraph's line spacing.
+    fn measure(&self, line: &mut Line, paragraph: &Paragraph) {
+        let mut text_height: f32 = 0.0;
+        let mut descent: f32 = 0.0;
+        let mut picture: f32 = 0.0;
+        let mut text_styles = Vec::new();
+        for part in line.words.iter().flat_map(|(_, word)| &word.parts) {
+            match part {
+                Part::Text { style, .. } => text_styles.push(style),
+                Part::Picture(p) => picture = picture.max(p.height),
+            }
+        }
+        if text_styles.is_empty() {
+            text_styles.push(&paragraph.mark);
+        }
+        for style in text_styles {
+            let font = self.family.face(style.bold, style.italic).font;
+            text_height = text_height.max(font.line_height(style.size));
+            descent = descent.max(font.descent(style.size));
+        }
+        let natural = text_height.max(picture + descent);
+        line.height = match paragraph.line_spacing {
+            LineSpacing::Multiple(multiple) => (text_height * multiple).max(picture + descent),
+            LineSpacing::Exactly(height) => height,
+            LineSpacing::AtLeast(height) => natural.max(height),
+        };
+        line.descent = descent;
+    }
+
+    /// Breaks a paragraph into lines for a column `width` wide.
+    fn lines(&self, paragraph: &Paragraph, width: f32) -> Vec<Line> {
+        let end = width - paragraph.indent_right;
+        let new_line = || Line {
+            words: Vec::new(),
+            end,
+            justify: false,
+            page_break: false,
+            height: 0.0,
+            descent: 0.0,
+        };
+
+        let mut tokens = self.tokens(paragraph);
+        let mut lines = Vec::new();
+        let mut line = new_line();
+        let mut x = paragraph.indent_left + paragraph.first_line;
+        while let Some(token) = tokens.pop_front() {
+            let mut word = match token {
+                Token::Word(word) => word,
+                Token::LineBreak | Token::PageBreak => {
+                    line.page_break = matches!(token, Token::PageBreak);
+                    lines.push(mem::replace(&mut line, new_line()));
+                    x = paragraph.indent_left;
+                    continue;
+                }
+            };
+            let mut start =
+                Typesetter::word_start(paragraph, &word, x, line.words.is_empty(), &tokens);
+            if start + word.width > line.end && !line.words.is_empty() {
+                line.justify = true;
+                lines.push(mem::replace(&mut line, new_line()));
+                x = paragraph.indent_left;
+                start = Typesetter::word_start(paragraph, &word, x, true, &tokens);
+            }
+            if start + word.width > line.end
+                && let Some((head, tail)) = self.split(word.clone(), line.end - start)
+            {
+                tokens.push_front(Token::Word(tail));
+                word = head;
+            }
+            x = start + word.width;
+            line.words.push((start, word));
+        }
+        lines.push(line);
+
+        for line in lines.iter_mut() {
+            self.measure(line, paragraph);
+        }
+        lines
+    }
+
+    /// The marks of a line whose top is at `top`, in a column starting at
+    /// `left`.
+    fn draw_line(&self, line: &Line, paragraph: &Paragraph, left: f32, top: f32) -> Vec<Mark> {
+        let baseline = top - line.height + line.descent;
+        let content_end = line.words.last().map_or(0.0, |(x, word)| x + word.width);
+        let free = (line.end - content_end).max(0.0);
+        // Alignment moves only the text after the last tab
+        let tabbed = line.words.iter().rposition(|(_, word)| word.tabs > 0);
+        let first = tabbed.unwrap_or(0);
+        let gaps = line.words.len().saturating_sub(first + 1);
+        let offset = |i: usize| match paragraph.align {
+            Align::Center if tabbed.is_none() => free / 2.0,
+            Align::Right if tabbed.is_none() => free,
+            Align::Justify if line.justify && gaps > 0 && i > first => {
+                free * (i - first) as f32 / gaps as f32
+            }
+            _ => 0.0,
+        };
+
+        let mut marks = Vec::new();
+        // Underlining carries on through the spaces between underlined words
+        let mut underline_end: Option<f32> = None;
+        for (i, (start, word)) in line.words.iter().enumerate() {
+            let mut x = left + start + offset(i);
+            let continues = word.tabs == 0;
+            for part in &word.parts {
+                let width = part.width();
+                match part {
+                    Part::Text { text, style, .. } => {
+                        let font = self.family.face(style.bold, style.italic).font;
+                        marks.push(Mark::Text {
+                            x,
+                            y: baseline,
+                            text: text.clone(),
+                            style: style.clone(),
+                        });
+                        if style.underline {
+                            let (position, thickness) = font.underline(style.size);
+                            let from = underline_end.filter(|_| continues).unwrap_or(x);
+                            marks.push(Mark::Line {
+                                from: (from, baseline + position),
+                                to: (x + width, baseline + position),
+                                width: thickness,
+                                color: style.color,
+                            });
+                        }
+                        if style.strike {
+                            let (position, thickness) = font.strikeout(style.size);
+                            marks.push(Mark::Line {
+                                from: (x, baseline + position),
+                                to: (x + width, baseline + position),
+                                width: thickness,
+                                color: style.color,
+                            });
+                        }
+                        underline_end = style.underline.then_some(x + width);
+                    }
+                    Part::Picture(picture) => {
+                        marks.push(Mark::Picture {
+                            x,
+                            y: baseline,
+                            picture: picture.clone(),
+                        });
+                        underline_end = None;
+                    }
+                }
+                x += width;
+            }
+        }
+        marks
+    }
+
+    fn column_width(&self) -> f32 {
+        self.setup.width - self.setup.left - self.setup.right
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page {
+            width: self.setup.width,
+            height: self.setup.height,
+            marks: mem::take(&mut self.marks),
+        });
+        self.y = self.setup.height - self.setup.top;
+        self.at_top = true;
+    }
+
+    fn paragraph(&mut self, paragraph: &Paragraph) {
+        if paragraph.page_break_before && !self.at_top {
+            self.new_page();
+        }
+        let space = self.spacing.before(paragraph);
+        if !self.at_top {
+            self.y -= space;
+        }
+
+        for line in self.lines(paragraph, self.column_width()) {
+            if self.y - line.height < self.setup.bottom && !self.at_top {
+                self.new_page();
+            }
+            let marks = self.draw_line(&line, paragraph, self.setup.left, self.y);
+            self.marks.extend(marks);
+            self.y -= line.height;
+            self.at_top = false;
+            if line.page_break {
+                self.new_page();
+            }
+        }
+    }
+
+    /// The lines of a cell's paragraphs with their offsets from the cell's
+    /// top, and the cell's height.
+    fn cell<'p>(
+        &self,
+        paragraphs: &'p [Paragraph],
+        width: f32,
+    ) -> (Vec<(f32, &'p Paragraph, Line)>, f32) {
+        let mut spacing = Spacing::default();
+        let mut placed = Vec::new();
+        let mut offset = 0.0;
+        for paragraph in paragraphs {
+            offset += spacing.before(paragraph);
+            for line in self.lines(paragraph, width) {
+                let height = line.height;
+                placed.push((offset, paragraph, line));
+                offset += height;
+            }
+        }
+        (placed, offset + spacing.after())
+    }
+
+    /// Lays out a table row by row. Rows are not split across pages;
+    /// vertically merged cells show their text in their first row.
+    fn table(&mut self, table: &Table) {
+        if table.widths.is_empty() {
+            return;
+        }
+        if !self.at_top {
+            self.y -= self.spacing.after();
+        }
+        self.spacing = Spacing::default();
+
+        let available = self.column_width() - table.indent;
+        let total: f32 = table.widths.iter().sum();
+        let widths: Vec<f32> = if total <= 0.0 {
+            vec![available / table.widths.len() as f32; table.widths.len()]
+        } else if total > available {
+            table
+                .widths
+                .iter()
+                .map(|width| width * available / total)
+                .collect()
+        } else {
+            table.widths.clone()
+        };
+        let mut edges = vec![self.setup.left + table.indent];
+        for width in &widths {
+            edges.push(edges[edges.len() - 1] + width);
+        }
+        let columns = widths.len();
+        let continues = |row: usize, column: usize| {
+            table.rows.get(row + 1).is_some_and(|next| {
+                next.cells
+                    .iter()
+                    .any(|cell| cell.column == column && cell.merge == Merge::Continue)
+            })
+        };
+
+        let mut layouts = Vec::new();
+        let mut heights: Vec<f32> = table.rows.iter().map(|row| row.min_height).collect();
+        for (r, row) in table.rows.iter().enumerate() {
+            let mut cells = Vec::new();
+            for cell in row.cells.iter().filter(|cell| cell.column < columns) {
+                let end = (cell.column + cell.span).min(columns);
+                let width = edges[end] - edges[cell.column] - 2.0 * table.cell_margin;
+                let layout = match cell.merge {
+                    Merge::Continue => None,
+                    _ => Some(self.cell(&cell.paragraphs, width.max(1.0))),
+                };
+                if cell.merge == Merge::None
+                    && let Some((_, height)) = &layout
+                {
+                    heights[r] = heights[r].max(*height);
+                }
+                cells.push((cell, end, layout));
+            }
+            layouts.push(cells);
+        }
+        // A merged cell's text may need its last row made taller
+        for (r, cells) in layouts.iter().enumerate() {
+            for (cell, _, layout) in cells {
+                if let (Merge::Restart, Some((_, height))) = (cell.merge, layout) {
+                    let mut last = r;
+                    while continues(last, cell.column) {
+                        last += 1;
+                    }
+                    let spanned: f32 = heights[r..=last].iter().sum();
+                    if *height > spanned {
+                        heights[last] += height - spanned;
+                    }
+                }
+            }
+        }
+
+        for (r, cells) in layouts.iter().enumerate() {
+            let height = heights[r];
+            if self.y - height < self.setup.bottom && !self.at_top {
+                self.new_page();
+            }
+            let (top, bottom) = (self.y, self.y - height);
+            let mut fills = Vec::new();
+            let mut content = Vec::new();
+            let mut borders = Vec::new();
+            for (cell, end, layout) in cells {
+                let (left, right) = (edges[cell.column], edges[*end]);
+                if let Some(color) = cell.fill {
+                    fills.push(Mark::Fill {
+                        x: left,
+                        y: bottom,
+                        width: right - left,
+                        height,
+                        color,
+                    });
+                }
+                if let Some((lines, _)) = layout {
+                    for (offset, paragraph, line) in lines {
+                        content.extend(self.draw_line(
+                            line,
+                            paragraph,
+                            left + table.cell_margin,
+                            top - offset,
+                        ));
+                    }
+                }
+                if table.borders {
+                    let mut edge = |from: (f32, f32), to: (f32, f32)| {
+                        borders.push(Mark::Line {
+                            from,
+                            to,
+                            width: BORDER_WIDTH,
+                            color: [0, 0, 0],
+                        })
+                    };
+                    if cell.merge != Merge::Continue {
+                        edge((left, top), (right, top));
+                    }
+                    if !continues(r, cell.column) {
+                        edge((left, bottom), (right, bottom));
+                    }
+                    edge((left, top), (left, bottom));
+                    edge((right, top), (right, bottom));
+                }
+            }
+            self.marks.extend(fills);
+            self.marks.extend(content);
+            self.marks.extend(borders);
+            self.y = bottom;
+            self.at_top = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<Page> {
+        self.new_page();
+        self.pages
+    }
+}
+
+/// Lays a Word document out on pages with the family's fonts.
+pub fn typeset(document: &Document, family: &Family) -> Vec<Page> {
+    let mut typesetter = Typesetter::new(family, document.page);
+    for block in &document.blocks {
+        match block {
+            Block::Paragraph(paragraph) => typesetter.paragraph(paragraph),
+            Block::Table(table) => typesetter.table(table),
+        }
+    }
+    typesetter.finish()
+}
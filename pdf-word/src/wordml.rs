@@ -0,0 +1,1086 @@
+use anyhow::{Context, Result};
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+use zip::ZipArchive;
+
+/// EMUs (drawing units) per point.
+const EMU_PER_POINT: f32 = 12700.0;
+
+/// Word's size when neither the document defaults nor a style give one.
+const DEFAULT_SIZE: f32 = 10.0;
+
+/// Cell padding Word uses left and right unless the table says otherwise.
+const CELL_MARGIN: f32 = 5.4;
+
+/// Markers for bullet levels. Bullet definitions usually name a character
+/// in the Symbol or Wingdings font, which the embedded font doesn't have.
+const BULLET_MARKERS: [&str; 3] = ["•", "◦", "▪"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineSpacing {
+    /// A multiple of the font's own line height.
+    Multiple(f32),
+    Exactly(f32),
+    AtLeast(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabAlign {
+    Left,
+    Center,
+    /// Also used for decimal tabs, which line numbers up on their point.
+    Right,
+}
+
+/// A tab stop, measured from the text column's left edge.
+#[derive(Debug, Clone, Copy)]
+pub struct TabStop {
+    pub position: f32,
+    pub align: TabAlign,
+}
+
+/// Character formatting with styles and direct formatting applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    pub size: f32,
+    pub color: [u8; 3],
+}
+
+/// An image shown at a size in points. Pictures of one image part share
+/// its data, so it is embedded once.
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub data: Rc<Vec<u8>>,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Inline {
+    Text { text: String, style: RunStyle },
+    Tab,
+    LineBreak,
+    PageBreak,
+    Picture(Picture),
+}
+
+/// A paragraph with its formatting resolved. Lengths are in points and
+/// indents are measured from the text column's left and right edges.
+#[derive(Debug, Clone)]
+pub struct Paragraph {
+    pub style: String,
+    pub inlines: Vec<Inline>,
+    /// List number or bullet, set in the first line's indent.
+    pub label: Option<(String, RunStyle)>,
+    pub align: Align,
+    pub indent_left: f32,
+    pub indent_right: f32,
+    /// Negative for a hanging indent.
+    pub first_line: f32,
+    pub space_before: f32,
+    pub space_after: f32,
+    /// Drop the spacing between paragraphs of the same style.
+    pub contextual_spacing: bool,
+    pub line_spacing: LineSpacing,
+    pub tabs: Vec<TabStop>,
+    pub page_break_before: bool,
+    /// Formatting of the paragraph mark, which sizes an empty paragraph.
+    pub mark: RunStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Merge {
+    None,
+    Restart,
+    Continue,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableCell {
+    pub column: usize,
+    pub span: usize,
+    pub merge: Merge,
+    pub fill: Option<[u8; 3]>,
+    /// Nested tables are flattened into their paragraphs.
+    pub paragraphs: Vec<Paragraph>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub cells: Vec<TableCell>,
+    pub min_height: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    /// Grid column widths in points.
+    pub widths: Vec<f32>,
+    pub indent: f32,
+    pub cell_margin: f32,
+    pub borders: bool,
+    pub rows: Vec<Row>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Block {
+    Paragraph(Paragraph),
+    Table(Table),
+}
+
+/// Page size and margins in points.
+#[derive(Debug, Clone, Copy)]
+pub struct PageSetup {
+    pub width: f32,
+    pub height: f32,
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+impl Default for PageSetup {
+    /// US Letter with one inch margins, Word's own default.
+    fn default() -> Self {
+        PageSetup {
+            width: 612.0,
+            height: 792.0,
+            top: 72.0,
+            bottom: 72.0,
+            left: 72.0,
+            right: 72.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub page: PageSetup,
+    pub blocks: Vec<Block>,
+}
+
+// Elements and attributes are matched on their local names, so documents
+// written with other namespace prefixes (or Strict OOXML) read the same.
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+fn children<'a, 'i: 'a>(
+    node: Node<'a, 'i>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'i>> + 'a {
+    node.children().filter(move |n| n.tag_name().name() == name)
+}
+
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+fn val<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    attr(node, "val")
+}
+
+/// A length attribute in twentieths of a point, in points.
+fn twips(node: Node, name: &str) -> Option<f32> {
+    attr(node, name)?.parse::<f32>().ok().map(|v| v / 20.0)
+}
+
+/// An on/off property: present means on unless its value says otherwise.
+fn toggle(properties: Node, name: &str) -> Option<bool> {
+    let node = child(properties, name)?;
+    Some(!matches!(val(node), Some("0" | "false" | "off" | "none")))
+}
+
+fn color(hex: &str) -> Option<[u8; 3]> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Run properties as declared; unset ones come from the level below.
+#[derive(Debug, Clone, Default)]
+struct RunProperties {
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    strike: Option<bool>,
+    caps: Option<bool>,
+    hidden: Option<bool>,
+    size: Option<f32>,
+    color: Option<[u8; 3]>,
+}
+
+impl RunProperties {
+    fn read(rpr: Option<Node>) -> Self {
+        let Some(rpr) = rpr else {
+            return RunProperties::default();
+        };
+        RunProperties {
+            bold: toggle(rpr, "b"),
+            italic: toggle(rpr, "i"),
+            underline: toggle(rpr, "u"),
+            strike: toggle(rpr, "strike").or_else(|| toggle(rpr, "dstrike")),
+            caps: toggle(rpr, "caps"),
+            hidden: toggle(rpr, "vanish"),
+            size: child(rpr, "sz")
+                .and_then(val)
+                .and_then(|v| v.parse::<f32>().ok())
+                .map(|half_points| half_points / 2.0),
+            color: child(rpr, "color").and_then(val).and_then(color),
+        }
+    }
+
+    /// These properties laid over `base`.
+    fn over(&self, base: &RunProperties) -> RunProperties {
+        RunProperties {
+            bold: self.bold.or(base.bold),
+            italic: self.italic.or(base.italic),
+            underline: self.underline.or(base.underline),
+            strike: self.strike.or(base.strike),
+            caps: self.caps.or(base.caps),
+            hidden: self.hidden.or(base.hidden),
+            size: self.size.or(base.size),
+            color: self.color.or(base.color),
+        }
+    }
+
+    fn style(&self) -> RunStyle {
+        RunStyle {
+            bold: self.bold.unwrap_or(false),
+            italic: self.italic.unwrap_or(false),
+            underline: self.underline.unwrap_or(false),
+            strike: self.strike.unwrap_or(false),
+            size: self.size.unwrap_or(DEFAULT_SIZE),
+            color: self.color.unwrap_or([0, 0, 0]),
+        }
+    }
+}
+
+/// Paragraph properties as declared; unset ones come from the level below.
+#[derive(Debug, Clone, Default)]
+struct ParagraphProperties {
+    align: Option<Align>,
+    indent_left: Option<f32>,
+    indent_right: Option<f32>,
+    first_line: Option<f32>,
+    space_before: Option<f32>,
+    space_after: Option<f32>,
+    contextual_spacing: Option<bool>,
+    line_spacing: Option<LineSpacing>,
+    tabs: Option<Vec<TabStop>>,
+    page_break_before: Option<bool>,
+    numbering: Option<(String, usize)>,
+}
+
+impl ParagraphProperties {
+    fn read(ppr: Option<Node>) -> Self {
+        let Some(ppr) = ppr else {
+            return ParagraphProperties::default();
+        };
+        let mut properties = ParagraphProperties {
+            align: child(ppr, "jc").and_then(val).map(|jc| match jc {
+                "center" => Align::Center,
+                "right" | "end" => Align::Right,
+                "both" | "distribute" => Align::Justify,
+                _ => Align::Left,
+            }),
+            contextual_spacing: toggle(ppr, "contextualSpacing"),
+            page_break_before: toggle(ppr, "pageBreakBefore"),
+            ..ParagraphProperties::default()
+        };
+        if let Some(ind) = child(ppr, "ind") {
+            properties.indent_left = twips(ind, "left").or_else(|| twips(ind, "start"));
+            properties.indent_right = twips(ind, "right").or_else(|| twips(ind, "end"));
+            properties.first_line = twips(ind, "hanging")
+                .map(|hanging| -hanging)
+                .or_else(|| twips(ind, "firstLine"));
+        }
+        if let Some(spacing) = child(ppr, "spacing") {
+            properties.space_before = twips(spacing, "before");
+            properties.space_after = twips(spacing, "after");
+            properties.line_spacing = attr(spacing, "line")
+                .and_then(|line| line.parse::<f32>().ok())
+                .map(|line| match attr(spacing, "lineRule") {
+                    Some("exact") => LineSpacing::Exactly(line / 20.0),
+                    Some("atLeast") => LineSpacing::AtLeast(line / 20.0),
+                    _ => LineSpacing::Multiple(line / 240.0),
+                });
+        }
+        if let Some(tabs) = child(ppr, "tabs") {
+            let mut stops: Vec<TabStop> = children(tabs, "tab")
+                .filter_map(|tab| {
+                    let align = match val(tab) {
+                        Some("center") => TabAlign::Center,
+                        Some("right" | "end" | "decimal") => TabAlign::Right,
+                        Some("clear" | "bar") => return None,
+                        _ => TabAlign::Left,
+                    };
+                    Some(TabStop {
+                        position: twips(tab, "pos")?,
+                        align,
+                    })
+                })
+                .collect();
+            stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+            properties.tabs = Some(stops);
+        }
+        if let Some(num_pr) = child(ppr, "numPr") {
+            let id = child(num_pr, "numId").and_then(val);
+            let level = child(num_pr, "ilvl")
+                .and_then(val)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            properties.numbering = id.map(|id| (id.to_string(), level));
+        }
+        properties
+    }
+
+    /// These properties laid over `base`.
+    fn over(&self, base: &ParagraphProperties) -> ParagraphProperties {
+        ParagraphProperties {
+            align: self.align.or(base.align),
+            indent_left: self.indent_left.or(base.indent_left),
+            indent_right: self.indent_right.or(base.indent_right),
+            first_line: self.first_line.or(base.first_line),
+            space_before: self.space_before.or(base.space_before),
+            space_after: self.space_after.or(base.space_after),
+            contextual_spacing: self.contextual_spacing.or(base.contextual_spacing),
+            line_spacing: self.line_spacing.or(base.line_spacing),
+            tabs: self.tabs.clone().or_else(|| base.tabs.clone()),
+            page_break_before: self.page_break_before.or(base.page_break_before),
+            numbering: self.numbering.clone().or_else(|| base.numbering.clone()),
+        }
+    }
+}
+
+struct Style {
+    based_on: Option<String>,
+    paragraph: ParagraphProperties,
+    run: RunProperties,
+    /// Whether a table style draws borders, if it says.
+    borders: Option<bool>,
+}
+
+/// The style sheet: document defaults and every style by id.
+#[derive(Default)]
+struct Styles {
+    paragraph: ParagraphProperties,
+    run: RunProperties,
+    styles: HashMap<String, Style>,
+    default_paragraph: Option<String>,
+    default_table: Option<String>,
+}
+
+/// Whether a `tblBorders` element draws anything.
+fn has_borders(tbl_pr: Node) -> Option<bool> {
+    let borders = child(tbl_pr, "tblBorders")?;
+    Some(
+        borders
+            .children()
+            .filter(Node::is_element)
+            .any(|edge| !matches!(val(edge), Some("nil" | "none"))),
+    )
+}
+
+impl Styles {
+    fn read(xml: &str) -> Result<Styles> {
+        let doc = roxmltree::Document::parse(xml).context("Failed to parse styles")?;
+        let root = doc.root_element();
+        let mut styles = Styles::default();
+
+        if let Some(defaults) = child(root, "docDefaults") {
+            styles.run =
+                RunProperties::read(child(defaults, "rPrDefault").and_then(|d| child(d, "rPr")));
+            styles.paragraph = ParagraphProperties::read(
+                child(defaults, "pPrDefault").and_then(|d| child(d, "pPr")),
+            );
+        }
+        for style in children(root, "style") {
+            let Some(id) = attr(style, "styleId") else {
+                continue;
+            };
+            let is_default = matches!(attr(style, "default"), Some("1" | "true"));
+            match attr(style, "type") {
+                Some("paragraph") if is_default => styles.default_paragraph = Some(id.into()),
+                Some("table") if is_default => styles.default_table = Some(id.into()),
+                _ => {}
+            }
+            styles.styles.insert(
+                id.to_string(),
+                Style {
+                    based_on: child(style, "basedOn").and_then(val).map(String::from),
+                    paragraph: ParagraphProperties::read(child(style, "pPr")),
+                    run: RunProperties::read(child(style, "rPr")),
+                    borders: child(style, "tblPr").and_then(has_borders),
+                },
+            );
+        }
+        Ok(styles)
+    }
+
+    /// A style and the styles it is based on, most specific first.
+    fn chain(&self, id: Option<&str>) -> Vec<&Style> {
+        let mut chain = Vec::new();
+        let mut next = id;
+        // basedOn chains are short; a long one is a cycle
+        while let Some(id) = next
+            && chain.len() < 16
+        {
+            let Some(style) = self.styles.get(id) else {
+                break;
+            };
+            chain.push(style);
+            next = style.based_on.as_deref();
+        }
+        chain
+    }
+
+    fn paragraph(&self, id: Option<&str>) -> (ParagraphProperties, RunProperties) {
+        let id = id.or(self.default_paragraph.as_deref());
+        let mut paragraph = self.paragraph.clone();
+        let mut run = self.run.clone();
+        for style in self.chain(id).into_iter().rev() {
+            paragraph = style.paragraph.over(&paragraph);
+            run = style.run.over(&run);
+        }
+        (paragraph, run)
+    }
+
+    fn character(&self, id: Option<&str>, base: &RunProperties) -> RunProperties {
+        let mut run = base.clone();
+        for style in self.chain(id).into_iter().rev() {
+            run = style.run.over(&run);
+        }
+        run
+    }
+
+    fn table_borders(&self, id: Option<&str>) -> Option<bool> {
+        let id = id.or(self.default_table.as_deref());
+        self.chain(id).into_iter().find_map(|style| style.borders)
+    }
+}
+
+struct Level {
+    format: String,
+    text: String,
+    start: u32,
+    paragraph: ParagraphProperties,
+    run: RunProperties,
+}
+
+/// List definitions and the running count of every list.
+#[derive(Default)]
+struct Numbering {
+    /// Abstract definitions by id, as levels.
+    abstracts: HashMap<String, Vec<Level>>,
+    /// Lists by `numId`: their abstract definition and start overrides.
+    lists: HashMap<String, (String, HashMap<usize, u32>)>,
+    /// Current count at every level of each list.
+    counters: HashMap<String, Vec<u32>>,
+}
+
+fn roman(mut n: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+fn letters(n: u32) -> String {
+    // Word repeats the letter past z: y, z, aa, bb...
+    let letter = (b'a' + ((n.max(1) - 1) % 26) as u8) as char;
+    letter
+        .to_string()
+        .repeat(((n.max(1) - 1) / 26 + 1) as usize)
+}
+
+fn format_number(n: u32, format: &str) -> String {
+    match format {
+        "lowerLetter" => letters(n),
+        "upperLetter" => letters(n).to_uppercase(),
+        "lowerRoman" => roman(n),
+        "upperRoman" => roman(n).to_uppercase(),
+        "decimalZero" => format!("{:02}", n),
+        "none" => String::new(),
+        _ => n.to_string(),
+    }
+}
+
+impl Numbering {
+    fn read(xml: &str) -> Result<Numbering> {
+        let doc = roxmltree::Document::parse(xml).context("Failed to parse numbering")?;
+        let root = doc.root_element();
+        let mut numbering = Numbering::default();
+        for abstract_num in children(root, "abstractNum") {
+            let Some(id) = attr(abstract_num, "abstractNumId") else {
+                continue;
+            };
+            let mut levels: Vec<Level> = children(abstract_num, "lvl")
+                .map(|lvl| Level {
+                    format: child(lvl, "numFmt")
+                        .and_then(val)
+                        .unwrap_or("decimal")
+                        .to_string(),
+                    text: child(lvl, "lvlText")
+                        .and_then(val)
+                        .unwrap_or_default()
+                        .to_string(),
+                    start: child(lvl, "start")
+                        .and_then(val)
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(1),
+                    paragraph: ParagraphProperties::read(child(lvl, "pPr")),
+                    run: RunProperties::read(child(lvl, "rPr")),
+                })
+                .collect();
+            levels.truncate(9);
+            numbering.abstracts.insert(id.to_string(), levels);
+        }
+        for num in children(root, "num") {
+            let (Some(id), Some(abstract_id)) = (
+                attr(num, "numId"),
+                child(num, "abstractNumId").and_then(val),
+            ) else {
+                continue;
+            };
+            let starts = children(num, "lvlOverride")
+                .filter_map(|o| {
+                    let level = attr(o, "ilvl")?.parse().ok()?;
+                    let start = child(o, "startOverride").and_then(val)?.parse().ok()?;
+                    Some((level, start))
+                })
+                .collect();
+            numbering
+                .lists
+                .insert(id.to_string(), (abstract_id.to_string(), starts));
+        }
+        Ok(numbering)
+    }
+
+    fn level(&self, id: &str, level: usize) -> Option<&Level> {
+        let (abstract_id, _) = self.lists.get(id)?;
+        self.abstracts.get(abstract_id)?.get(level)
+    }
+
+    /// Counts the next item of a list and returns its label.
+    fn next_label(&mut self, id: &str, level: usize) -> Option<String> {
+        let (abstract_id, starts) = self.lists.get(id)?;
+        let levels = self.abstracts.get(abstract_id)?;
+        let definition = levels.get(level)?;
+        let start = |l: usize| {
+            starts
+                .get(&l)
+                .copied()
+                .or_else(|| levels.get(l).map(|d| d.start))
+                .unwrap_or(1)
+        };
+
+        let counters = self.counters.entry(id.to_string()).or_default();
+        while counters.len() <= level {
+            counters.push(start(counters.len()) - 1);
+        }
+        counters[level] += 1;
+        // Deeper levels restart under a new item
+        counters.truncate(level + 1);
+
+        if definition.format == "bullet" {
+            return Some(BULLET_MARKERS[level % BULLET_MARKERS.len()].to_string());
+        }
+        let mut label = definition.text.clone();
+        for (l, count) in counters.iter().enumerate() {
+            let format = levels.get(l).map_or("decimal", |d| d.format.as_str());
+            label = label.replace(&format!("%{}", l + 1), &format_number(*count, format));
+        }
+        Some(label)
+    }
+}
+
+/// Reads one part of the package, if it's there.
+fn read_part(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open part: {}", name)),
+    };
+    let mut text = String::new();
+    file.read_to_string(&mut text)
+        .with_context(|| format!("Failed to read part: {}", name))?;
+    Ok(Some(text))
+}
+
+fn read_binary_part(archive: &mut ZipArchive<File>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// A part's relationships as (id, type, target) with targets resolved
+/// against the part's folder.
+fn relationships(
+    archive: &mut ZipArchive<File>,
+    part: &str,
+) -> Result<Vec<(String, String, String)>> {
+    let (folder, file_name) = part.rsplit_once('/').unwrap_or(("", part));
+    let rels_name = if folder.is_empty() {
+        format!("_rels/{}.rels", file_name)
+    } else {
+        format!("{}/_rels/{}.rels", folder, file_name)
+    };
+    let Some(xml) = read_part(archive, &rels_name)? else {
+        return Ok(Vec::new());
+    };
+    let doc = roxmltree::Document::parse(&xml)
+        .with_context(|| format!("Failed to parse relationships: {}", rels_name))?;
+
+    let mut relationships = Vec::new();
+    for rel in children(doc.root_element(), "Relationship") {
+        if attr(rel, "TargetMode") == Some("External") {
+            continue;
+        }
+        let (Some(id), Some(kind), Some(target)) =
+            (attr(rel, "Id"), attr(rel, "Type"), attr(rel, "Target"))
+        else {
+            continue;
+        };
+        let mut path: Vec<&str> = match target.strip_prefix('/') {
+            Some(_) => Vec::new(),
+            None => folder.split('/').filter(|s| !s.is_empty()).collect(),
+        };
+        for segment in target.trim_start_matches('/').split('/') {
+            match segment {
+                ".." => {
+                    path.pop();
+                }
+                "." | "" => {}
+                segment => path.push(segment),
+            }
+        }
+        relationships.push((id.to_string(), kind.to_string(), path.join("/")));
+    }
+    Ok(relationships)
+}
+
+fn target_of<'a>(relationships: &'a [(String, String, String)], kind: &str) -> Option<&'a str> {
+    relationships
+        .iter()
+        .find(|(_, k, _)| k.ends_with(kind))
+        .map(|(_, _, target)| target.as_str())
+}
+
+struct Reader {
+    archive: ZipArchive<File>,
+    relationships: Vec<(String, String, String)>,
+    styles: Styles,
+    numbering: Numbering,
+    /// Image parts already read, by relationship id.
+    pictures: HashMap<String, Option<Rc<Vec<u8>>>>,
+}
+
+impl Reader {
+    fn picture_data(&mut self, id: &str) -> Option<Rc<Vec<u8>>> {
+        if let Some(data) = self.pictures.get(id) {
+            return data.clone();
+        }
+        let data = self
+            .relationships
+            .iter()
+            .find(|(rel_id, _, _)| rel_id == id)
+            .map(|(_, _, target)| target.clone())
+            .and_then(|target| read_binary_part(&mut self.archive, &target))
+            .map(Rc::new);
+        self.pictures.insert(id.to_string(), data.clone());
+        data
+    }
+
+    /// The pictures in a `w:drawing`, inline or anchored. Anchored ones are
+    /// placed in the text flow where they are anchored.
+    fn drawing(&mut self, drawing: Node, inlines: &mut Vec<Inline>) {
+        for frame in drawing.children().filter(Node::is_element) {
+            let Some(extent) = child(frame, "extent") else {
+                continue;
+            };
+            let size = |name| {
+                attr(extent, name)
+                    .and_then(|v| v.parse::<f32>().ok())
+                    .map(|emu| emu / EMU_PER_POINT)
+            };
+            let (Some(width), Some(height)) = (size("cx"), size("cy")) else {
+                continue;
+            };
+            let Some(id) = frame
+                .descendants()
+                .find(|n| n.tag_name().name() == "blip")
+                .and_then(|blip| attr(blip, "embed"))
+            else {
+                continue;
+            };
+            match self.picture_data(id) {
+                Some(data) => inlines.push(Inline::Picture(Picture {
+                    data,
+                    width,
+                    height,
+                })),
+                None => eprintln!("⚠️ Skipping a picture whose image part is missing: {}", id),
+            }
+        }
+    }
+
+    fn run(&mut self, run: Node, base: &RunProperties, inlines: &mut Vec<Inline>) {
+        let rpr = child(run, "rPr");
+        let character = self
+            .styles
+            .character(rpr.and_then(|r| child(r, "rStyle")).and_then(val), base);
+        let properties = RunProperties::read(rpr).over(&character);
+        if properties.hidden == Some(true) {
+            return;
+        }
+        let caps = properties.caps == Some(true);
+        let style = properties.style();
+
+        for item in run.children().filter(Node::is_element) {
+            match item.tag_name().name() {
+                "t" => {
+                    let text = item.text().unwrap_or_default();
+                    let text = if caps {
+                        text.to_uppercase()
+                    } else {
+                        text.to_string()
+                    };
+                    // Runs of one style come in pieces; keep them together
+                    match inlines.last_mut() {
+                        Some(Inline::Text {
+                            text: previous,
+                            style: previous_style,
+                        }) if *previous_style == style => previous.push_str(&text),
+                        _ => inlines.push(Inline::Text {
+                            text,
+                            style: style.clone(),
+                        }),
+                    }
+                }
+                "tab" | "ptab" => inlines.push(Inline::Tab),
+                "br" => inlines.push(match attr(item, "type") {
+                    Some("page") => Inline::PageBreak,
+                    _ => Inline::LineBreak,
+                }),
+                "cr" => inlines.push(Inline::LineBreak),
+                "noBreakHyphen" => inlines.push(Inline::Text {
+                    text: "\u{2011}".into(),
+                    style: style.clone(),
+                }),
+                "drawing" => self.drawing(item, inlines),
+                _ => {}
+            }
+        }
+    }
+
+    /// Runs inside a paragraph, including those in hyperlinks, fields,
+    /// content controls and tracked insertions. Deleted text is left out.
+    fn runs(&mut self, node: Node, base: &RunProperties, inlines: &mut Vec<Inline>) {
+        for item in node.children().filter(Node::is_element) {
+            match item.tag_name().name() {
+                "r" => self.run(item, base, inlines),
+                "hyperlink" | "smartTag" | "ins" | "moveTo" | "customXml" | "fldSimple"
+                | "sdtContent" | "dir" | "bdo" => self.runs(item, base, inlines),
+                "sdt" => {
+                    if let Some(content) = child(item, "sdtContent") {
+                        self.runs(content, base, inlines);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn paragraph(&mut self, p: Node) -> Paragraph {
+        let ppr = child(p, "pPr");
+        let style_id = ppr.and_then(|ppr| child(ppr, "pStyle")).and_then(val);
+        let (style_paragraph, style_run) = self.styles.paragraph(style_id);
+        let direct = ParagraphProperties::read(ppr);
+
+        // List indents sit between the style and direct formatting
+        let numbering = direct
+            .numbering
+            .clone()
+            .or(style_paragraph.numbering.clone());
+        let mut properties = style_paragraph.clone();
+        let mut label = None;
+        // numId 0, which has no definition, switches numbering off
+        if let Some((id, level)) = &numbering
+            && let Some(definition) = self.numbering.level(id, *level)
+        {
+            properties = definition.paragraph.over(&properties);
+            let label_run = definition.run.over(&style_run);
+            if let Some(text) = self.numbering.next_label(id, *level)
+                && !text.is_empty()
+            {
+                let mut label_style = label_run.style();
+                label_style.underline = false;
+                label = Some((text, label_style));
+            }
+        }
+        let properties = direct.over(&properties);
+
+        let mark = RunProperties::read(ppr.and_then(|ppr| child(ppr, "rPr"))).over(&style_run);
+        let mut inlines = Vec::new();
+        self.runs(p, &style_run, &mut inlines);
+        // A section break inside a paragraph starts the next section on a
+        // new page unless it is continuous
+        if let Some(sect_pr) = ppr.and_then(|ppr| child(ppr, "sectPr"))
+            && child(sect_pr, "type").and_then(val) != Some("continuous")
+        {
+            inlines.push(Inline::PageBreak);
+        }
+
+        Paragraph {
+            style: style_id
+                .or(self.styles.default_paragraph.as_deref())
+                .unwrap_or_default()
+                .to_string(),
+            inlines,
+            label,
+            align: properties.align.unwrap_or(Align::Left),
+            indent_left: properties.indent_left.unwrap_or(0.0),
+            indent_right: properties.indent_right.unwrap_or(0.0),
+            first_line: properties.first_line.unwrap_or(0.0),
+            space_before: properties.space_before.unwrap_or(0.0),
+            space_after: properties.space_after.unwrap_or(0.0),
+            contextual_spacing: properties.contextual_spacing.unwrap_or(false),
+            line_spacing: properties
+                .line_spacing
+                .unwrap_or(LineSpacing::Multiple(1.0)),
+            tabs: properties.tabs.unwrap_or_default(),
+            page_break_before: properties.page_break_before.unwrap_or(false),
+            mark: mark.style(),
+        }
+    }
+
+    /// Paragraphs of a cell, with those of nested tables flattened in.
+    fn cell_paragraphs(&mut self, tc: Node, paragraphs: &mut Vec<Paragraph>) {
+        for item in tc.children().filter(Node::is_element) {
+            match item.tag_name().name() {
+                "p" => paragraphs.push(self.paragraph(item)),
+                "tbl" => {
+                    for tr in children(item, "tr") {
+                        for cell in children(tr, "tc") {
+                            self.cell_paragraphs(cell, paragraphs);
+                        }
+                    }
+                }
+                "sdt" => {
+                    if let Some(content) = child(item, "sdtContent") {
+                        self.cell_paragraphs(content, paragraphs);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn table(&mut self, tbl: Node) -> Table {
+        let tbl_pr = child(tbl, "tblPr");
+        let style_id = tbl_pr.and_then(|t| child(t, "tblStyle")).and_then(val);
+        let borders = tbl_pr
+            .and_then(has_borders)
+            .or_else(|| self.styles.table_borders(style_id))
+            .unwrap_or(false);
+        let indent = tbl_pr
+            .and_then(|t| child(t, "tblInd"))
+            .and_then(|ind| twips(ind, "w"))
+            .unwrap_or(0.0);
+        let cell_margin = tbl_pr
+            .and_then(|t| child(t, "tblCellMar"))
+            .and_then(|m| child(m, "left").or_else(|| child(m, "start")))
+            .and_then(|left| twips(left, "w"))
+            .unwrap_or(CELL_MARGIN);
+
+        let mut widths: Vec<f32> = child(tbl, "tblGrid")
+            .map(|grid| {
+                children(grid, "gridCol")
+                    .map(|col| twips(col, "w").unwrap_or(0.0))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut rows = Vec::new();
+        for tr in children(tbl, "tr") {
+            let min_height = child(tr, "trPr")
+                .and_then(|t| child(t, "trHeight"))
+                .and_then(|h| twips(h, "val"))
+                .unwrap_or(0.0);
+            let mut column = child(tr, "trPr")
+                .and_then(|t| child(t, "gridBefore"))
+                .and_then(val)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            let mut cells = Vec::new();
+            for tc in children(tr, "tc") {
+                let tc_pr = child(tc, "tcPr");
+                let span = tc_pr
+                    .and_then(|t| child(t, "gridSpan"))
+                    .and_then(val)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(1usize)
+                    .max(1);
+                let merge = match tc_pr.and_then(|t| child(t, "vMerge")) {
+                    Some(v) if val(v) == Some("restart") => Merge::Restart,
+                    Some(_) => Merge::Continue,
+                    None => Merge::None,
+                };
+                let fill = tc_pr
+                    .and_then(|t| child(t, "shd"))
+                    .and_then(|shd| attr(shd, "fill"))
+                    .and_then(color);
+                // Tables without a grid take their widths from the cells
+                while widths.len() < column + span {
+                    let width = tc_pr
+                        .and_then(|t| child(t, "tcW"))
+                        .filter(|w| matches!(attr(*w, "type"), None | Some("dxa")))
+                        .and_then(|w| twips(w, "w"))
+                        .map_or(0.0, |w| w / span as f32);
+                    widths.push(width);
+                }
+                let mut paragraphs = Vec::new();
+                self.cell_paragraphs(tc, &mut paragraphs);
+                cells.push(TableCell {
+                    column,
+                    span,
+                    merge,
+                    fill,
+                    paragraphs,
+                });
+                column += span;
+            }
+            rows.push(Row { cells, min_height });
+        }
+
+        Table {
+            widths,
+            indent,
+            cell_margin,
+            borders,
+            rows,
+        }
+    }
+
+    fn blocks(&mut self, node: Node, blocks: &mut Vec<Block>) {
+        for item in node.children().filter(Node::is_element) {
+            match item.tag_name().name() {
+                "p" => blocks.push(Block::Paragraph(self.paragraph(item))),
+                "tbl" => blocks.push(Block::Table(self.table(item))),
+                "sdt" => {
+                    if let Some(content) = child(item, "sdtContent") {
+                        self.blocks(content, blocks);
+                    }
+                }
+                "customXml" => self.blocks(item, blocks),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn page_setup(sect_pr: Node) -> PageSetup {
+    let mut page = PageSetup::default();
+    if let Some(size) = child(sect_pr, "pgSz") {
+        page.width = twips(size, "w").unwrap_or(page.width);
+        page.height = twips(size, "h").unwrap_or(page.height);
+    }
+    if let Some(margins) = child(sect_pr, "pgMar") {
+        // Negative top and bottom margins mean the same distance, fixed
+        page.top = twips(margins, "top").map_or(page.top, f32::abs);
+        page.bottom = twips(margins, "bottom").map_or(page.bottom, f32::abs);
+        page.left = twips(margins, "left").unwrap_or(page.left);
+        page.right = twips(margins, "right").unwrap_or(page.right);
+    }
+    page
+}
+
+/// Reads a .docx into paragraphs and tables ready to typeset. Headers,
+/// footers, footnotes and floating shapes are not read.
+pub fn read(path: &Path) -> Result<Document> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open DOCX: {}", path.display()))?;
+    let mut archive = ZipArchive::new(file)
+        .with_context(|| format!("Not a DOCX (zip) package: {}", path.display()))?;
+
+    let package = relationships(&mut archive, "")?;
+    let main_part = target_of(&package, "/officeDocument")
+        .unwrap_or("word/document.xml")
+        .to_string();
+    let relationships = relationships(&mut archive, &main_part)?;
+    let xml = read_part(&mut archive, &main_part)?
+        .with_context(|| format!("Missing document part: {}", main_part))?;
+
+    let styles = match target_of(&relationships, "/styles") {
+        Some(part) => match read_part(&mut archive, part)? {
+            Some(xml) => Styles::read(&xml)?,
+            None => Styles::default(),
+        },
+        None => Styles::default(),
+    };
+    let numbering = match target_of(&relationships, "/numbering") {
+        Some(part) => match read_part(&mut archive, part)? {
+            Some(xml) => Numbering::read(&xml)?,
+            None => Numbering::default(),
+        },
+        None => Numbering::default(),
+    };
+
+    let doc = roxmltree::Document::parse(&xml).context("Failed to parse document part")?;
+    let body = child(doc.root_element(), "body").context("Document part has no body")?;
+    let mut reader = Reader {
+        archive,
+        relationships,
+        styles,
+        numbering,
+        pictures: HashMap::new(),
+    };
+    let mut blocks = Vec::new();
+    reader.blocks(body, &mut blocks);
+
+    Ok(Document {
+        page: child(body, "sectPr").map(page_setup).unwrap_or_default(),
+        blocks,
+    })
+}
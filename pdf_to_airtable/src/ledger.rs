@@ -0,0 +1,171 @@
+use crate::ExtractionError;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// One uploaded invoice, as recorded in the ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub sha256: String,
+    pub file: String,
+    pub uploaded_at: String,
+    pub items: usize,
+    /// Names of the sinks the invoice was exported to. Entries written
+    /// before sinks were tracked have none, and count as exported to all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sinks: Option<Vec<String>>,
+}
+
+impl LedgerEntry {
+    /// Whether the invoice was exported to every one of `sinks`.
+    pub fn covers(&self, sinks: &[String]) -> bool {
+        self.sinks
+            .as_ref()
+            .is_none_or(|done| sinks.iter().all(|sink| done.contains(sink)))
+    }
+}
+
+/// Content hashes of every invoice uploaded so far, kept as JSON lines so
+/// each upload is recorded by appending one line.
+pub struct Ledger {
+    file: File,
+    entries: HashMap<String, LedgerEntry>,
+}
+
+/// The SHA-256 of a file's content, in hex.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+impl Ledger {
+    /// Opens the ledger at `path`; a missing file is an empty ledger. The
+    /// file is opened for appending here, so a ledger that cannot be
+    /// written fails before anything is uploaded.
+    ///
+    /// A last line that doesn't parse was cut short by a crash while it
+    /// was written: it is dropped, with a warning, and the upload it
+    /// recorded is done again. Any other unparsable line is an error.
+    pub fn open(path: &Path) -> Result<Ledger, ExtractionError> {
+        let mut entries = HashMap::new();
+        let mut torn_at = None;
+        if path.exists() {
+            let content = fs::read(path)?;
+            let mut end = 0;
+            for line in content.split_inclusive(|byte| *byte == b'\n') {
+                let start = end;
+                end += line.len();
+                if line.trim_ascii().is_empty() {
+                    continue;
+                }
+                match serde_json::from_slice::<LedgerEntry>(line) {
+                    Ok(entry) => {
+                        entries.insert(entry.sha256.clone(), entry);
+                    }
+                    Err(e) if end == content.len() => {
+                        warn!(
+                            "Dropping the unreadable last line of ledger '{}' ({}); it was probably cut short",
+                            path.display(),
+                            e
+                        );
+                        torn_at = Some(start as u64);
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if let Some(length) = torn_at {
+            file.set_len(length)?;
+        }
+        Ok(Ledger { file, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The earlier upload of the content with this hash, if any; it may
+    /// have reached only some sinks. Later entries for a hash replace
+    /// earlier ones.
+    pub fn get(&self, sha256: &str) -> Option<&LedgerEntry> {
+        self.entries.get(sha256)
+    }
+
+    /// Records an upload. The entry is kept in memory even when writing it
+    /// fails, so the same content is not uploaded twice while running.
+    pub fn record(&mut self, entry: LedgerEntry) -> Result<(), ExtractionError> {
+        let line = serde_json::to_string(&entry)? + "\n";
+        self.entries.insert(entry.sha256.clone(), entry);
+        // One write, so a crash can only cut the last line short
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    // A ledger path under the system temp dir, unique to this test
+    fn scratch(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "pdf_to_airtable-{}-{}.jsonl",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(sha256: &str) -> LedgerEntry {
+        LedgerEntry {
+            sha256: sha256.to_string(),
+            file: format!("{}.pdf", sha256),
+            uploaded_at: "2025-03-03T10:00:00+00:00".to_string(),
+            items: 2,
+            sinks: Some(vec!["csv".to_string()]),
+        }
+    }
+
+    #[test]
+    fn drops_a_torn_last_line() {
+        let path = scratch("torn");
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.record(entry("aaa")).unwrap();
+        ledger.record(entry("bbb")).unwrap();
+        drop(ledger);
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let mut ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.len(), 1);
+        assert!(ledger.get("aaa").is_some());
+        assert!(ledger.get("bbb").is_none());
+        ledger.record(entry("bbb")).unwrap();
+        drop(ledger);
+
+        let ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.len(), 2);
+        assert!(ledger.get("bbb").unwrap().covers(&["csv".to_string()]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_an_unreadable_line_before_the_last() {
+        let path = scratch("corrupt");
+        let line = serde_json::to_string(&entry("aaa")).unwrap();
+        fs::write(&path, format!("{{\"sha256\":\n{}\n", line)).unwrap();
+        assert!(matches!(
+            Ledger::open(&path),
+            Err(ExtractionError::JsonError(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
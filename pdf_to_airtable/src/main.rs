@@ -1,54 +1,30 @@
 use chrono::NaiveDate;
+use invoice_export::InvoiceItem;
 use log::{error, info, warn};
 use pdf_extract::extract_text;
-use reqwest::header;
-use serde::{Deserialize, Serialize};
 use std::env;
 // std::num::ParseIntError is implicitly used by `parse::<u32>()`
 // std::num::ParseFloatError is implicitly used by `parse::<f64>()`;
 use std::path::Path;
 use thiserror::Error;
 
+mod ledger;
+mod watch;
+
 #[derive(Error, Debug)]
 enum ExtractionError {
     #[error("PDF extraction error: {0}")]
     PdfError(String),
-    #[error("Environment variable error: {0}")]
-    EnvError(String),
-    #[error("HTTP request error: {0}")]
-    RequestError(String),
     #[error("No invoice items found in PDF")]
     NoItemsFound,
     #[error("PDF file not found: {0}")]
     FileNotFound(String),
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct AirtableRecord {
-    fields: InvoiceItem,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct InvoiceItem {
-    #[serde(rename = "No")]
-    no: u32,
-    #[serde(rename = "Description")]
-    description: String,
-    #[serde(rename = "Quantity")]
-    quantity: u32,
-    #[serde(rename = "Price")]
-    price: f64, // MODIFIED: Changed to f64
-    #[serde(rename = "Total")]
-    total: f64, // MODIFIED: Changed to f64
-    #[serde(rename = "Client Name")] // New Field
-    client_name: String,
-    #[serde(rename = "Invoice Date")] // New Field
-    invoice_date: String,
-}
-
-#[derive(Debug, Serialize)]
-struct AirtablePayload {
-    records: Vec<AirtableRecord>,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Export error: {0}")]
+    SinkError(#[from] invoice_export::Error),
 }
 
 // Helper function to clean and parse currency strings
@@ -71,52 +47,6 @@ fn clean_and_parse_currency(s: &str, field_name: &str, line_number: usize) -> Re
     })
 }
 
-async fn upload_to_airtable(items: Vec<InvoiceItem>) -> Result<(), ExtractionError> {
-    let base_id =
-        env::var("AIRTABLE_BASE_ID").map_err(|e| ExtractionError::EnvError(e.to_string()))?;
-    let table_name = env::var("AIRTABLE_TABLE_NAME").unwrap_or_else(|_| "Invoices".to_string());
-    let api_key =
-        env::var("AIRTABLE_API_KEY").map_err(|e| ExtractionError::EnvError(e.to_string()))?;
-
-    let client = reqwest::Client::new();
-    let url = format!("https://api.airtable.com/v0/{}/{}", base_id, table_name);
-
-    for chunk in items.chunks(10) {
-        info!("Preparing to upload {} items to Airtable", chunk.len());
-        let records: Vec<AirtableRecord> = chunk
-            .iter()
-            .map(|item| AirtableRecord {
-                fields: item.clone(),
-            })
-            .collect();
-
-        let payload = AirtablePayload { records };
-
-        let response = client
-            .post(&url)
-            .json(&payload)
-            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
-            .send()
-            .await
-            .map_err(|e| ExtractionError::RequestError(e.to_string()))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let response_body = response
-                .text()
-                .await
-                .map_err(|e| ExtractionError::RequestError(e.to_string()))?;
-            error!("Airtable API error (status {}): {}", status, response_body);
-            return Err(ExtractionError::RequestError(format!(
-                "API returned status: {}, body: {}",
-                status, response_body
-            )));
-        }
-        info!("Successfully uploaded batch of {} items", chunk.len());
-    }
-    Ok(())
-}
-
 fn extract_text_from_pdf(path: &str) -> Result<String, ExtractionError> {
     if !Path::new(path).exists() {
         return Err(ExtractionError::FileNotFound(path.to_string()));
@@ -533,6 +463,14 @@ async fn main() -> Result<(), ExtractionError> {
         Err(_) => warn!("No .env file found or failed to load. Relying on environment variables."),
     }
 
+    let mut sinks = invoice_export::from_env()?;
+
+    // With an inbox to watch, run until stopped instead of processing one file
+    if let Ok(watch_dir) = env::var("WATCH_DIR") {
+        info!("Starting PDF to Airtable watcher");
+        return watch::run(Path::new(&watch_dir), sinks).await;
+    }
+
     let pdf_path = env::var("PDF_PATH").unwrap_or_else(|_| "Invoice_Template.pdf".to_string());
     info!("Starting PDF to Airtable processor");
     info!("Loading PDF file: {}", pdf_path);
@@ -541,7 +479,13 @@ async fn main() -> Result<(), ExtractionError> {
     let items = parse_invoice_items(&text)?;
 
     if !items.is_empty() {
-        upload_to_airtable(items).await?;
+        let sha256 = ledger::content_hash(&std::fs::read(&pdf_path)?);
+        let export = invoice_export::Export {
+            source: &pdf_path,
+            sha256: &sha256,
+            items: &items,
+        };
+        invoice_export::export_all(&mut sinks, &export, &mut Vec::new()).await?;
     } else {
         info!("No items were parsed from the PDF to upload.");
     }
@@ -0,0 +1,287 @@
+use crate::ledger::{content_hash, Ledger, LedgerEntry};
+use crate::ExtractionError;
+use crate::{extract_text_from_pdf, parse_invoice_items};
+use futures_util::StreamExt;
+use inotify::{EventMask, Inotify, WatchMask};
+use invoice_export::{Export, InvoiceItem, Sink};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Room for a few hundred inotify events between reads
+const EVENT_BUFFER: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Uploaded,
+    Duplicate,
+    Failed,
+}
+
+// Written next to each file moved out of the inbox, as `<file>.json`
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    file: &'a str,
+    sha256: &'a str,
+    status: Status,
+    processed_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<&'a [InvoiceItem]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<&'a LedgerEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+struct Inbox {
+    dir: PathBuf,
+    processed: PathBuf,
+    failed: PathBuf,
+    ledger: Ledger,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+// PDFs in the inbox, in name order
+fn pdfs_in(dir: &Path) -> Result<Vec<PathBuf>, ExtractionError> {
+    let mut pdfs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_pdf(path))
+        .collect();
+    pdfs.sort();
+    Ok(pdfs)
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+impl Inbox {
+    // Moves the file into `dir` with its sidecar, renaming it if a file of
+    // that name was already moved there
+    fn file_away(&self, path: &Path, dir: &Path, sidecar: &Sidecar) -> Result<(), ExtractionError> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut target = dir.join(name.as_ref());
+        if target.exists() {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+            target = dir.join(format!("{}-{}.pdf", stem, stamp));
+        }
+        fs::rename(path, &target)?;
+        let mut sidecar_path = target.clone().into_os_string();
+        sidecar_path.push(".json");
+        fs::write(sidecar_path, serde_json::to_string_pretty(sidecar)?)?;
+        info!("Moved {} to {}", name, target.display());
+        Ok(())
+    }
+
+    // Exports the PDF to the sinks that do not have it yet and records in
+    // the ledger which ones now do, even when some failed, so a retry skips
+    // them. A ledger that cannot be written after every export succeeded is
+    // not a failure, since the invoice was uploaded; its error is returned
+    // alongside the items instead
+    async fn export(
+        &mut self,
+        path: &Path,
+        sha256: &str,
+    ) -> Result<(Vec<InvoiceItem>, Option<String>), ExtractionError> {
+        let text = extract_text_from_pdf(&path.to_string_lossy())?;
+        let items = parse_invoice_items(&text)?;
+        let file = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let export = Export {
+            source: &file,
+            sha256,
+            items: &items,
+        };
+        let mut done = self
+            .ledger
+            .get(sha256)
+            .and_then(|earlier| earlier.sinks.clone())
+            .unwrap_or_default();
+        let before = done.len();
+        let exported = invoice_export::export_all(&mut self.sinks, &export, &mut done).await;
+        let recorded = if done.len() > before {
+            self.ledger.record(LedgerEntry {
+                sha256: sha256.to_string(),
+                file,
+                uploaded_at: now(),
+                items: items.len(),
+                sinks: Some(done),
+            })
+        } else {
+            Ok(())
+        };
+        if let Err(e) = exported {
+            if let Err(ledger) = recorded {
+                error!(
+                    "Could not record the sinks {} reached in the ledger: {}",
+                    path.display(),
+                    ledger
+                );
+            }
+            return Err(e.into());
+        }
+        let ledger_error = recorded.err().map(|e| {
+            error!(
+                "Uploaded {}, but could not record it in the ledger: {}",
+                path.display(),
+                e
+            );
+            format!("Uploaded, but not recorded in the ledger: {}", e)
+        });
+        Ok((items, ledger_error))
+    }
+
+    // Uploads one PDF unless its content was uploaded before, then files
+    // it away with the outcome
+    async fn process(&mut self, path: &Path) -> Result<(), ExtractionError> {
+        let file = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            // Already handled, by an earlier event for the same file
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let sha256 = content_hash(&bytes);
+        info!("Processing {} (sha256 {})", file, sha256);
+
+        let sinks: Vec<String> = self.sinks.iter().map(|sink| sink.name()).collect();
+        let earlier = self.ledger.get(&sha256);
+        if let Some(earlier) = earlier.filter(|earlier| earlier.covers(&sinks)) {
+            warn!(
+                "{} has the same content as {}, uploaded at {}; not uploading it again.",
+                file, earlier.file, earlier.uploaded_at
+            );
+            let sidecar = Sidecar {
+                file: &file,
+                sha256: &sha256,
+                status: Status::Duplicate,
+                processed_at: now(),
+                items: None,
+                duplicate_of: Some(earlier),
+                error: None,
+            };
+            return self.file_away(path, &self.processed, &sidecar);
+        }
+        if let Some(earlier) = earlier {
+            info!(
+                "{} was exported to {} before; retrying the other sinks.",
+                file,
+                earlier.sinks.as_deref().unwrap_or_default().join(", ")
+            );
+        }
+
+        match self.export(path, &sha256).await {
+            Ok((items, ledger_error)) => {
+                let sidecar = Sidecar {
+                    file: &file,
+                    sha256: &sha256,
+                    status: Status::Uploaded,
+                    processed_at: now(),
+                    items: Some(&items),
+                    duplicate_of: None,
+                    error: ledger_error,
+                };
+                self.file_away(path, &self.processed, &sidecar)
+            }
+            Err(e) => {
+                error!("Failed to process {}: {}", file, e);
+                let sidecar = Sidecar {
+                    file: &file,
+                    sha256: &sha256,
+                    status: Status::Failed,
+                    processed_at: now(),
+                    items: None,
+                    duplicate_of: None,
+                    error: Some(e.to_string()),
+                };
+                self.file_away(path, &self.failed, &sidecar)
+            }
+        }
+    }
+
+    async fn process_logged(&mut self, path: &Path) {
+        if let Err(e) = self.process(path).await {
+            error!("Could not file away {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Watches `dir` for new PDFs and exports each one to `sinks`, moving it to
+/// `processed/` or `failed/` with a JSON sidecar of the outcome. PDFs
+/// already in the inbox are processed first. Runs until the watch fails.
+pub async fn run(dir: &Path, sinks: Vec<Box<dyn Sink>>) -> Result<(), ExtractionError> {
+    let processed = dir.join("processed");
+    let failed = dir.join("failed");
+    fs::create_dir_all(&processed)?;
+    fs::create_dir_all(&failed)?;
+    let ledger_path = env::var("LEDGER_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| dir.join("ledger.jsonl"));
+    let ledger = Ledger::open(&ledger_path)?;
+    info!(
+        "Ledger {} holds {} uploaded invoices",
+        ledger_path.display(),
+        ledger.len()
+    );
+    let mut inbox = Inbox {
+        dir: dir.to_path_buf(),
+        processed,
+        failed,
+        ledger,
+        sinks,
+    };
+
+    // Watch before sweeping, so files dropped during the sweep are not
+    // missed; any seen twice are skipped once moved
+    let inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+    let mut events = inotify.into_event_stream(vec![0u8; EVENT_BUFFER])?;
+
+    let waiting = pdfs_in(dir)?;
+    if !waiting.is_empty() {
+        info!("Processing {} PDFs already in the inbox", waiting.len());
+    }
+    for path in waiting {
+        inbox.process_logged(&path).await;
+    }
+
+    info!("Watching {} for new invoices", dir.display());
+    while let Some(event) = events.next().await {
+        let event = event?;
+        if event.mask.contains(EventMask::Q_OVERFLOW) {
+            warn!("Missed some inotify events; rescanning the inbox.");
+            for path in pdfs_in(&inbox.dir)? {
+                inbox.process_logged(&path).await;
+            }
+            continue;
+        }
+        let Some(name) = event.name else {
+            continue;
+        };
+        let path = inbox.dir.join(name);
+        if is_pdf(&path) {
+            inbox.process_logged(&path).await;
+        }
+    }
+    Ok(())
+}
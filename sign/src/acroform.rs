@@ -0,0 +1,972 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Field flag bits (PDF 32000-1, table 226/228)
+const FF_MULTILINE: i64 = 1 << 12;
+const FF_RADIO: i64 = 1 << 15;
+const FF_PUSHBUTTON: i64 = 1 << 16;
+// Annotation flag bit for hidden widgets
+const ANNOT_HIDDEN: i64 = 1 << 1;
+
+const DEFAULT_FONT_NAME: &str = "Helv";
+const DEFAULT_FONT_SIZE: f64 = 10.0;
+const TEXT_PADDING: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Text,
+    Checkbox,
+    Radio,
+    PushButton,
+    Choice,
+    Signature,
+    Unknown,
+}
+
+impl FieldType {
+    fn from_dict(field_type: Option<&[u8]>, flags: i64) -> FieldType {
+        match field_type {
+            Some(b"Tx") => FieldType::Text,
+            Some(b"Btn") if flags & FF_PUSHBUTTON != 0 => FieldType::PushButton,
+            Some(b"Btn") if flags & FF_RADIO != 0 => FieldType::Radio,
+            Some(b"Btn") => FieldType::Checkbox,
+            Some(b"Ch") => FieldType::Choice,
+            Some(b"Sig") => FieldType::Signature,
+            _ => FieldType::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Checkbox => "checkbox",
+            FieldType::Radio => "radio",
+            FieldType::PushButton => "button",
+            FieldType::Choice => "choice",
+            FieldType::Signature => "signature",
+            FieldType::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Widget {
+    pub id: ObjectId,
+    pub page: Option<u32>,
+    pub rect: [f64; 4],
+    /// Export name of the "on" appearance for checkboxes and radio buttons.
+    pub on_state: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FormField {
+    /// Fully qualified name, e.g. `tenant.name`.
+    pub name: String,
+    pub field_type: FieldType,
+    pub id: ObjectId,
+    pub flags: i64,
+    pub value: Option<String>,
+    pub widgets: Vec<Widget>,
+}
+
+/// A value read from the fill data file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Text(String),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn as_text(&self) -> String {
+        match self {
+            FieldValue::Text(text) => text.clone(),
+            FieldValue::Bool(true) => "Yes".to_string(),
+            FieldValue::Bool(false) => "Off".to_string(),
+        }
+    }
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        other => Some(other),
+    }
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    resolve(doc, object).and_then(|obj| obj.as_dict().ok())
+}
+
+pub(crate) fn number(object: &Object) -> Option<f64> {
+    match object {
+        Object::Integer(value) => Some(*value as f64),
+        Object::Real(value) => Some(f64::from(*value)),
+        _ => None,
+    }
+}
+
+fn pdf_string_to_utf8(bytes: &[u8]) -> String {
+    if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
+        let words: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&words);
+    }
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn utf8_to_pdf_string(text: &str) -> Object {
+    if text.chars().all(|c| (c as u32) < 0x80) {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
+fn acroform_dict(doc: &Document) -> Option<&Dictionary> {
+    let catalog = doc.catalog().ok()?;
+    resolve_dict(doc, catalog.get(b"AcroForm").ok()?)
+}
+
+fn widget_pages(doc: &Document) -> HashMap<ObjectId, u32> {
+    let mut pages = HashMap::new();
+    for (page_number, page_id) in doc.get_pages() {
+        let annots = doc
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|page| page.get(b"Annots").ok())
+            .and_then(|annots| resolve(doc, annots))
+            .and_then(|annots| annots.as_array().ok());
+        for annot in annots.into_iter().flatten() {
+            if let Ok(id) = annot.as_reference() {
+                pages.insert(id, page_number);
+            }
+        }
+    }
+    pages
+}
+
+fn on_state(dict: &Dictionary, doc: &Document) -> Option<String> {
+    let normal = dict
+        .get(b"AP")
+        .ok()
+        .and_then(|ap| resolve_dict(doc, ap))
+        .and_then(|ap| ap.get(b"N").ok())
+        .and_then(|n| resolve_dict(doc, n))?;
+    normal
+        .iter()
+        .map(|(key, _)| String::from_utf8_lossy(key).to_string())
+        .find(|key| key != "Off")
+}
+
+fn read_widget(doc: &Document, id: ObjectId, pages: &HashMap<ObjectId, u32>) -> Option<Widget> {
+    let dict = doc.get_dictionary(id).ok()?;
+    let mut rect = [0.0; 4];
+    if let Some(values) = dict
+        .get(b"Rect")
+        .ok()
+        .and_then(|r| resolve(doc, r))
+        .and_then(|r| r.as_array().ok())
+    {
+        for (slot, value) in rect.iter_mut().zip(values.iter()) {
+            *slot = number(value).unwrap_or(0.0);
+        }
+    }
+    // Normalise so that [x0 y0 x1 y1] is lower-left / upper-right.
+    let rect = [
+        rect[0].min(rect[2]),
+        rect[1].min(rect[3]),
+        rect[0].max(rect[2]),
+        rect[1].max(rect[3]),
+    ];
+    Some(Widget {
+        id,
+        page: pages.get(&id).copied(),
+        rect,
+        on_state: on_state(dict, doc),
+    })
+}
+
+#[derive(Clone, Default)]
+struct Inherited {
+    name: String,
+    field_type: Option<Vec<u8>>,
+    flags: i64,
+    value: Option<Object>,
+}
+
+fn collect_field(
+    doc: &Document,
+    id: ObjectId,
+    parent: &Inherited,
+    pages: &HashMap<ObjectId, u32>,
+    fields: &mut Vec<FormField>,
+    depth: usize,
+) {
+    // Guard against reference cycles in malformed files.
+    if depth > 32 {
+        return;
+    }
+    let Ok(dict) = doc.get_dictionary(id) else {
+        return;
+    };
+
+    let mut inherited = parent.clone();
+    if let Ok(Object::String(partial, _)) = dict.get(b"T") {
+        let partial = pdf_string_to_utf8(partial);
+        inherited.name = if parent.name.is_empty() {
+            partial
+        } else {
+            format!("{}.{}", parent.name, partial)
+        };
+    }
+    if let Ok(field_type) = dict.get(b"FT").and_then(Object::as_name) {
+        inherited.field_type = Some(field_type.to_vec());
+    }
+    if let Ok(flags) = dict.get(b"Ff").and_then(Object::as_i64) {
+        inherited.flags = flags;
+    }
+    if let Ok(value) = dict.get(b"V") {
+        inherited.value = Some(value.clone());
+    }
+
+    let kids: Vec<ObjectId> = dict
+        .get(b"Kids")
+        .ok()
+        .and_then(|kids| resolve(doc, kids))
+        .and_then(|kids| kids.as_array().ok())
+        .map(|kids| kids.iter().filter_map(|k| k.as_reference().ok()).collect())
+        .unwrap_or_default();
+
+    // Kids carrying their own /T are child fields; kids without one are widgets.
+    let (child_fields, child_widgets): (Vec<ObjectId>, Vec<ObjectId>) =
+        kids.into_iter().partition(|kid| {
+            doc.get_dictionary(*kid)
+                .map(|kid| kid.has(b"T"))
+                .unwrap_or(false)
+        });
+
+    for child in child_fields {
+        collect_field(doc, child, &inherited, pages, fields, depth + 1);
+    }
+
+    let widget_ids = if child_widgets.is_empty() && !dict.has(b"Kids") {
+        vec![id]
+    } else {
+        child_widgets
+    };
+    if widget_ids.is_empty() {
+        return;
+    }
+
+    let value = inherited.value.as_ref().and_then(|value| match value {
+        Object::String(bytes, _) => Some(pdf_string_to_utf8(bytes)),
+        Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
+        _ => None,
+    });
+    fields.push(FormField {
+        name: inherited.name.clone(),
+        field_type: FieldType::from_dict(inherited.field_type.as_deref(), inherited.flags),
+        id,
+        flags: inherited.flags,
+        value,
+        widgets: widget_ids
+            .into_iter()
+            .filter_map(|widget_id| read_widget(doc, widget_id, pages))
+            .collect(),
+    });
+}
+
+/// Walks the AcroForm field tree and returns every terminal field.
+pub fn list_fields(doc: &Document) -> Result<Vec<FormField>, Box<dyn Error>> {
+    let Some(acroform) = acroform_dict(doc) else {
+        return Ok(Vec::new());
+    };
+    let roots: Vec<ObjectId> = acroform
+        .get(b"Fields")
+        .ok()
+        .and_then(|fields| resolve(doc, fields))
+        .and_then(|fields| fields.as_array().ok())
+        .map(|fields| {
+            fields
+                .iter()
+                .filter_map(|f| f.as_reference().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let pages = widget_pages(doc);
+    let mut fields = Vec::new();
+    for root in roots {
+        collect_field(doc, root, &Inherited::default(), &pages, &mut fields, 0);
+    }
+    Ok(fields)
+}
+
+fn flatten_json(prefix: &str, value: &serde_json::Value, out: &mut BTreeMap<String, FieldValue>) {
+    let key = |name: &str| {
+        if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{prefix}.{name}")
+        }
+    };
+    match value {
+        serde_json::Value::Object(map) => {
+            for (name, child) in map {
+                flatten_json(&key(name), child, out);
+            }
+        }
+        serde_json::Value::Bool(flag) => {
+            out.insert(prefix.to_string(), FieldValue::Bool(*flag));
+        }
+        serde_json::Value::String(text) => {
+            out.insert(prefix.to_string(), FieldValue::Text(text.clone()));
+        }
+        serde_json::Value::Number(n) => {
+            out.insert(prefix.to_string(), FieldValue::Text(n.to_string()));
+        }
+        serde_json::Value::Null | serde_json::Value::Array(_) => {}
+    }
+}
+
+/// Reads field values from a JSON or TOML file (picked by extension).
+/// Nested tables are flattened into dotted, fully qualified field names.
+pub fn load_field_values(path: &str) -> Result<BTreeMap<String, FieldValue>, Box<dyn Error>> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read field data '{}': {}", path, e))?;
+    let is_toml = Path::new(path)
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("toml"))
+        .unwrap_or(false);
+    let value: serde_json::Value = if is_toml {
+        toml::from_str(&raw).map_err(|e| format!("Invalid TOML in '{}': {}", path, e))?
+    } else {
+        serde_json::from_str(&raw).map_err(|e| format!("Invalid JSON in '{}': {}", path, e))?
+    };
+    let mut values = BTreeMap::new();
+    flatten_json("", &value, &mut values);
+    Ok(values)
+}
+
+/// Standard Helvetica advance widths (1/1000 em) for ASCII 32..=126, from
+/// the `WX` values in Adobe's Core 14 `Helvetica.afm`.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+fn text_width(text: &str, font_size: f64) -> f64 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f64 * font_size / 1000.0
+}
+
+/// Latin-1 bytes for a simple-font `Tj` string, `?` for anything outside it.
+fn win_ansi_bytes(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+        .collect()
+}
+
+/// Font name, size and colour operators parsed from a `/DA` string.
+struct DefaultAppearance {
+    font: String,
+    size: f64,
+    color_ops: Vec<Operation>,
+}
+
+fn parse_da(da: Option<&[u8]>) -> DefaultAppearance {
+    let mut appearance = DefaultAppearance {
+        font: DEFAULT_FONT_NAME.to_string(),
+        size: 0.0,
+        color_ops: vec![Operation::new("g", vec![0.into()])],
+    };
+    let Some(da) = da else {
+        return appearance;
+    };
+    let Ok(content) = Content::decode(da) else {
+        return appearance;
+    };
+    for op in content.operations {
+        match op.operator.as_str() {
+            "Tf" => {
+                if let Some(Ok(name)) = op.operands.first().map(Object::as_name_str) {
+                    appearance.font = name.to_string();
+                }
+                if let Some(size) = op.operands.get(1).and_then(number) {
+                    appearance.size = size;
+                }
+            }
+            "g" | "rg" | "k" => appearance.color_ops = vec![op],
+            _ => {}
+        }
+    }
+    appearance
+}
+
+fn inherited_da(doc: &Document, field_id: ObjectId) -> Option<Vec<u8>> {
+    let mut current = Some(field_id);
+    let mut depth = 0;
+    while let Some(id) = current {
+        let dict = doc.get_dictionary(id).ok()?;
+        if let Ok(Object::String(da, _)) = dict.get(b"DA") {
+            return Some(da.clone());
+        }
+        current = dict.get(b"Parent").and_then(Object::as_reference).ok();
+        depth += 1;
+        if depth > 32 {
+            return None;
+        }
+    }
+    match acroform_dict(doc)?.get(b"DA") {
+        Ok(Object::String(da, _)) => Some(da.clone()),
+        _ => None,
+    }
+}
+
+/// Makes sure the AcroForm default resources carry `font_name`, adding
+/// Helvetica under that name if missing, and returns the font reference.
+fn ensure_dr_font(doc: &mut Document, font_name: &str) -> Result<Object, Box<dyn Error>> {
+    let existing = acroform_dict(doc)
+        .and_then(|acroform| acroform.get(b"DR").ok())
+        .and_then(|dr| resolve_dict(doc, dr))
+        .and_then(|dr| dr.get(b"Font").ok())
+        .and_then(|fonts| resolve_dict(doc, fonts))
+        .and_then(|fonts| fonts.get(font_name.as_bytes()).ok())
+        .cloned();
+    if let Some(font) = existing {
+        return Ok(font);
+    }
+
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let acroform_id = acroform_id(doc)?;
+    let acroform = doc.get_object_mut(acroform_id)?.as_dict_mut()?;
+    if acroform.get(b"DR").and_then(Object::as_dict).is_err() {
+        acroform.set("DR", dictionary! {});
+    }
+    let dr = acroform.get_mut(b"DR")?.as_dict_mut()?;
+    if dr.get(b"Font").and_then(Object::as_dict).is_err() {
+        dr.set("Font", dictionary! {});
+    }
+    dr.get_mut(b"Font")?
+        .as_dict_mut()?
+        .set(font_name.as_bytes().to_vec(), Object::Reference(font_id));
+    Ok(Object::Reference(font_id))
+}
+
+/// Returns the AcroForm object id, moving a direct AcroForm dictionary
+/// out of the catalog so it can be edited in place.
+fn acroform_id(doc: &mut Document) -> Result<ObjectId, Box<dyn Error>> {
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let acroform = doc.get_dictionary(catalog_id)?.get(b"AcroForm")?.clone();
+    match acroform {
+        Object::Reference(id) => Ok(id),
+        Object::Dictionary(dict) => {
+            let id = doc.add_object(dict);
+            doc.get_object_mut(catalog_id)?
+                .as_dict_mut()?
+                .set("AcroForm", Object::Reference(id));
+            Ok(id)
+        }
+        _ => Err("Catalog /AcroForm is not a dictionary".into()),
+    }
+}
+
+fn text_appearance(
+    doc: &mut Document,
+    field: &FormField,
+    widget: &Widget,
+    text: &str,
+) -> Result<ObjectId, Box<dyn Error>> {
+    let da = parse_da(inherited_da(doc, field.id).as_deref());
+    let font = ensure_dr_font(doc, &da.font)?;
+    let width = widget.rect[2] - widget.rect[0];
+    let height = widget.rect[3] - widget.rect[1];
+    let multiline = field.flags & FF_MULTILINE != 0;
+    let lines: Vec<&str> = if multiline {
+        text.lines().collect()
+    } else {
+        vec![text]
+    };
+
+    let font_size = if da.size > 0.0 {
+        da.size
+    } else if multiline {
+        DEFAULT_FONT_SIZE
+    } else {
+        // Auto size: fit the height, then shrink until the text fits the width.
+        let mut size = ((height - 2.0 * TEXT_PADDING) * 0.75).clamp(4.0, 12.0);
+        while size > 4.0 && text_width(text, size) > width - 2.0 * TEXT_PADDING {
+            size -= 0.5;
+        }
+        size
+    };
+    let quadding = doc
+        .get_dictionary(field.id)
+        .ok()
+        .and_then(|dict| dict.get(b"Q").ok())
+        .and_then(|q| q.as_i64().ok())
+        .unwrap_or(0);
+    let leading = font_size * 1.15;
+
+    let mut operations = vec![
+        Operation::new("BMC", vec![Object::Name(b"Tx".to_vec())]),
+        Operation::new("q", vec![]),
+        Operation::new(
+            "re",
+            vec![
+                1.into(),
+                1.into(),
+                (width - 2.0).into(),
+                (height - 2.0).into(),
+            ],
+        ),
+        Operation::new("W", vec![]),
+        Operation::new("n", vec![]),
+        Operation::new("BT", vec![]),
+        Operation::new(
+            "Tf",
+            vec![Object::Name(da.font.as_bytes().to_vec()), font_size.into()],
+        ),
+    ];
+    operations.extend(da.color_ops);
+
+    let first_baseline = if multiline {
+        height - TEXT_PADDING - font_size
+    } else {
+        (height - font_size) / 2.0 + font_size * 0.22
+    };
+    for (index, line) in lines.iter().enumerate() {
+        let line_width = text_width(line, font_size);
+        let x = match quadding {
+            1 => (width - line_width) / 2.0,
+            2 => width - TEXT_PADDING - line_width,
+            _ => TEXT_PADDING,
+        };
+        let y = first_baseline - index as f64 * leading;
+        operations.push(Operation::new(
+            "Tm",
+            vec![1.into(), 0.into(), 0.into(), 1.into(), x.into(), y.into()],
+        ));
+        operations.push(Operation::new(
+            "Tj",
+            vec![Object::String(
+                win_ansi_bytes(line),
+                lopdf::StringFormat::Literal,
+            )],
+        ));
+    }
+    operations.extend([
+        Operation::new("ET", vec![]),
+        Operation::new("Q", vec![]),
+        Operation::new("EMC", vec![]),
+    ]);
+
+    let content = Content { operations }.encode()?;
+    let stream = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Resources" => dictionary! {
+                "Font" => dictionary! { da.font.as_bytes().to_vec() => font },
+            },
+        },
+        content,
+    );
+    Ok(doc.add_object(stream))
+}
+
+/// Builds `/On` and `/Off` appearances for a button widget that has none.
+fn checkbox_appearance(
+    doc: &mut Document,
+    widget: &Widget,
+    radio: bool,
+) -> Result<Dictionary, Box<dyn Error>> {
+    let width = widget.rect[2] - widget.rect[0];
+    let height = widget.rect[3] - widget.rect[1];
+    let size = width.min(height) * 0.8;
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "ZapfDingbats",
+    });
+    // ZapfDingbats '4' is a check mark, 'l' a filled circle.
+    let glyph = if radio { "l" } else { "4" };
+    let on_ops = format!(
+        "q BT /ZaDb {:.2} Tf 0 g {:.2} {:.2} Td ({}) Tj ET Q",
+        size,
+        (width - size * 0.8) / 2.0,
+        (height - size * 0.7) / 2.0,
+        glyph
+    );
+    let bbox = vec![0.into(), 0.into(), width.into(), height.into()];
+    let on_id = doc.add_object(Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => bbox.clone(),
+            "Resources" => dictionary! {
+                "Font" => dictionary! { "ZaDb" => Object::Reference(font_id) },
+            },
+        },
+        on_ops.into_bytes(),
+    ));
+    let off_id = doc.add_object(Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => bbox,
+        },
+        Vec::new(),
+    ));
+    Ok(dictionary! {
+        "Yes" => Object::Reference(on_id),
+        "Off" => Object::Reference(off_id),
+    })
+}
+
+fn set_dict_entry(
+    doc: &mut Document,
+    id: ObjectId,
+    key: &str,
+    value: Object,
+) -> Result<(), Box<dyn Error>> {
+    doc.get_object_mut(id)?.as_dict_mut()?.set(key, value);
+    Ok(())
+}
+
+fn fill_text(
+    doc: &mut Document,
+    field: &FormField,
+    value: &FieldValue,
+) -> Result<(), Box<dyn Error>> {
+    let text = value.as_text();
+    set_dict_entry(doc, field.id, "V", utf8_to_pdf_string(&text))?;
+    for widget in &field.widgets {
+        let appearance_id = text_appearance(doc, field, widget, &text)?;
+        set_dict_entry(
+            doc,
+            widget.id,
+            "AP",
+            Object::Dictionary(dictionary! { "N" => Object::Reference(appearance_id) }),
+        )?;
+    }
+    Ok(())
+}
+
+fn fill_button(
+    doc: &mut Document,
+    field: &FormField,
+    value: &FieldValue,
+) -> Result<(), Box<dyn Error>> {
+    let radio = field.field_type == FieldType::Radio;
+    let requested = value.as_text();
+    let turn_on = !matches!(
+        requested.to_ascii_lowercase().as_str(),
+        "off" | "false" | "no" | "0" | ""
+    );
+
+    let mut selected: Option<String> = None;
+    for widget in &field.widgets {
+        let on_state = match &widget.on_state {
+            Some(state) => state.clone(),
+            None => {
+                let appearances = checkbox_appearance(doc, widget, radio)?;
+                set_dict_entry(
+                    doc,
+                    widget.id,
+                    "AP",
+                    Object::Dictionary(dictionary! { "N" => appearances }),
+                )?;
+                "Yes".to_string()
+            }
+        };
+        // Checkboxes switch on for any truthy value; radio kids only when
+        // the value names their export state.
+        let is_on = if radio {
+            on_state == requested
+        } else {
+            turn_on
+        };
+        let state = if is_on {
+            selected = Some(on_state.clone());
+            on_state
+        } else {
+            "Off".to_string()
+        };
+        set_dict_entry(doc, widget.id, "AS", Object::Name(state.into_bytes()))?;
+    }
+
+    if radio && turn_on && selected.is_none() {
+        let options: Vec<String> = field
+            .widgets
+            .iter()
+            .filter_map(|w| w.on_state.clone())
+            .collect();
+        return Err(format!(
+            "Radio field '{}' has no option '{}' (options: {})",
+            field.name,
+            requested,
+            options.join(", ")
+        )
+        .into());
+    }
+    let value = selected.unwrap_or_else(|| "Off".to_string());
+    set_dict_entry(doc, field.id, "V", Object::Name(value.into_bytes()))?;
+    Ok(())
+}
+
+/// Fills fields by fully qualified name and regenerates their appearance
+/// streams. Returns the number of fields written.
+pub fn fill_fields(
+    doc: &mut Document,
+    values: &BTreeMap<String, FieldValue>,
+) -> Result<usize, Box<dyn Error>> {
+    let fields = list_fields(doc)?;
+    let by_name: HashMap<&str, &FormField> = fields.iter().map(|f| (f.name.as_str(), f)).collect();
+
+    let unknown: Vec<&str> = values
+        .keys()
+        .map(String::as_str)
+        .filter(|name| !by_name.contains_key(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("Unknown form field(s): {}", unknown.join(", ")).into());
+    }
+
+    let mut filled = 0;
+    for (name, value) in values {
+        let field = by_name[name.as_str()];
+        match field.field_type {
+            FieldType::Text | FieldType::Choice => fill_text(doc, field, value)?,
+            FieldType::Checkbox | FieldType::Radio => fill_button(doc, field, value)?,
+            other => {
+                eprintln!(
+                    "⚠️ Skipping field '{}' of unsupported type '{}'",
+                    name,
+                    other.label()
+                );
+                continue;
+            }
+        }
+        filled += 1;
+    }
+
+    // Appearances are now present, so viewers must not regenerate them.
+    if filled > 0 {
+        let acroform_id = acroform_id(doc)?;
+        doc.get_object_mut(acroform_id)?
+            .as_dict_mut()?
+            .remove(b"NeedAppearances");
+    }
+    Ok(filled)
+}
+
+/// Resolves the normal appearance stream a widget currently shows.
+fn current_appearance(doc: &Document, widget_id: ObjectId) -> Option<ObjectId> {
+    let widget = doc.get_dictionary(widget_id).ok()?;
+    let normal = widget
+        .get(b"AP")
+        .ok()
+        .and_then(|ap| resolve_dict(doc, ap))?
+        .get(b"N")
+        .ok()?;
+    match normal {
+        Object::Reference(id) => match doc.get_object(*id).ok()? {
+            Object::Stream(_) => Some(*id),
+            Object::Dictionary(states) => {
+                let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+                states.get(state).and_then(Object::as_reference).ok()
+            }
+            _ => None,
+        },
+        Object::Dictionary(states) => {
+            let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+            states.get(state).and_then(Object::as_reference).ok()
+        }
+        _ => None,
+    }
+}
+
+fn transform_point(matrix: &[f64; 6], x: f64, y: f64) -> (f64, f64) {
+    (
+        matrix[0] * x + matrix[2] * y + matrix[4],
+        matrix[1] * x + matrix[3] * y + matrix[5],
+    )
+}
+
+/// `cm` operands mapping an appearance stream's transformed BBox onto `rect`.
+fn placement_matrix(doc: &Document, appearance_id: ObjectId, rect: &[f64; 4]) -> Option<[f64; 6]> {
+    let stream = doc.get_object(appearance_id).ok()?.as_stream().ok()?;
+    let read_numbers = |key: &[u8], defaults: &[f64]| -> Vec<f64> {
+        stream
+            .dict
+            .get(key)
+            .ok()
+            .and_then(|v| v.as_array().ok())
+            .map(|values| values.iter().filter_map(number).collect::<Vec<f64>>())
+            .filter(|values| values.len() == defaults.len())
+            .unwrap_or_else(|| defaults.to_vec())
+    };
+    let bbox = read_numbers(b"BBox", &[0.0, 0.0, rect[2] - rect[0], rect[3] - rect[1]]);
+    let matrix = read_numbers(b"Matrix", &[1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    let matrix: [f64; 6] = matrix.try_into().ok()?;
+
+    let corners = [
+        transform_point(&matrix, bbox[0], bbox[1]),
+        transform_point(&matrix, bbox[2], bbox[1]),
+        transform_point(&matrix, bbox[0], bbox[3]),
+        transform_point(&matrix, bbox[2], bbox[3]),
+    ];
+    let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let max_x = corners
+        .iter()
+        .map(|c| c.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+    let max_y = corners
+        .iter()
+        .map(|c| c.1)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if max_x - min_x <= 0.0 || max_y - min_y <= 0.0 {
+        return None;
+    }
+    let sx = (rect[2] - rect[0]) / (max_x - min_x);
+    let sy = (rect[3] - rect[1]) / (max_y - min_y);
+    Some([sx, 0.0, 0.0, sy, rect[0] - min_x * sx, rect[1] - min_y * sy])
+}
+
+fn is_hidden(doc: &Document, widget_id: ObjectId) -> bool {
+    doc.get_dictionary(widget_id)
+        .ok()
+        .and_then(|w| w.get(b"F").ok())
+        .and_then(|f| f.as_i64().ok())
+        .map(|flags| flags & ANNOT_HIDDEN != 0)
+        .unwrap_or(false)
+}
+
+/// Burns every widget's current appearance into its page content, removes
+/// the widget annotations and drops the AcroForm so the result is static.
+/// Returns the number of widgets flattened.
+pub fn flatten_fields(doc: &mut Document) -> Result<usize, Box<dyn Error>> {
+    let fields = list_fields(doc)?;
+    let pages = doc.get_pages();
+
+    // page number -> (xobject name, appearance id, cm operands)
+    let mut placements: BTreeMap<u32, Vec<(String, ObjectId, [f64; 6])>> = BTreeMap::new();
+    let mut removed_widgets = Vec::new();
+    let mut counter = 0;
+
+    for field in &fields {
+        for widget in &field.widgets {
+            removed_widgets.push(widget.id);
+            let Some(page) = widget.page else {
+                continue;
+            };
+            if is_hidden(doc, widget.id) {
+                continue;
+            }
+            let Some(appearance_id) = current_appearance(doc, widget.id) else {
+                continue;
+            };
+            let Some(matrix) = placement_matrix(doc, appearance_id, &widget.rect) else {
+                continue;
+            };
+            if let Ok(stream) = doc
+                .get_object_mut(appearance_id)
+                .and_then(Object::as_stream_mut)
+            {
+                stream.dict.set("Type", "XObject");
+                stream.dict.set("Subtype", "Form");
+            }
+            counter += 1;
+            placements.entry(page).or_default().push((
+                format!("FlatField{counter}"),
+                appearance_id,
+                matrix,
+            ));
+        }
+    }
+
+    for (page_number, entries) in &placements {
+        let Some(&page_id) = pages.get(page_number) else {
+            continue;
+        };
+        let mut ops = String::new();
+        for (name, appearance_id, m) in entries {
+            crate::page::add_xobject_resource(doc, page_id, name, *appearance_id)?;
+            ops.push_str(&format!(
+                "q\n{} {} {} {} {} {} cm\n/{} Do\nQ\n",
+                m[0], m[1], m[2], m[3], m[4], m[5], name
+            ));
+        }
+        crate::page::append_content(doc, page_id, ops.into_bytes())?;
+    }
+
+    for (_, page_id) in pages {
+        let annots = doc
+            .get_dictionary(page_id)
+            .ok()
+            .and_then(|page| page.get(b"Annots").ok())
+            .cloned();
+        let (annots_id, mut annots) = match annots {
+            Some(Object::Reference(id)) => match doc.get_object(id).and_then(Object::as_array) {
+                Ok(array) => (Some(id), array.clone()),
+                Err(_) => continue,
+            },
+            Some(Object::Array(array)) => (None, array),
+            _ => continue,
+        };
+        let before = annots.len();
+        annots.retain(|annot| {
+            annot
+                .as_reference()
+                .map(|id| !removed_widgets.contains(&id))
+                .unwrap_or(true)
+        });
+        if annots.len() == before {
+            continue;
+        }
+        match annots_id {
+            Some(id) => *doc.get_object_mut(id)? = Object::Array(annots),
+            None => {
+                let page = doc.get_object_mut(page_id)?.as_dict_mut()?;
+                if annots.is_empty() {
+                    page.remove(b"Annots");
+                } else {
+                    page.set("Annots", Object::Array(annots));
+                }
+            }
+        }
+    }
+
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    doc.get_object_mut(catalog_id)?
+        .as_dict_mut()?
+        .remove(b"AcroForm");
+    doc.prune_objects();
+    Ok(counter)
+}
@@ -0,0 +1,376 @@
+use crate::stamp::{self, PreparedImage};
+use lopdf::Document;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Which pages a placement applies to: a page number, a list of numbers,
+/// or a keyword/range string such as `"all"`, `"last"`, `"odd"` or `"1-3,7"`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PageSelection {
+    Number(u32),
+    List(Vec<u32>),
+    Spec(String),
+}
+
+impl Default for PageSelection {
+    fn default() -> Self {
+        PageSelection::Spec("all".to_string())
+    }
+}
+
+impl PageSelection {
+    fn resolve(&self, page_count: u32) -> Result<Vec<u32>, String> {
+        let in_range = |page: u32| {
+            if page >= 1 && page <= page_count {
+                Ok(page)
+            } else {
+                Err(format!(
+                    "Page {} out of range (document has {} pages)",
+                    page, page_count
+                ))
+            }
+        };
+        match self {
+            PageSelection::Number(page) => Ok(vec![in_range(*page)?]),
+            PageSelection::List(pages) => pages.iter().map(|p| in_range(*p)).collect(),
+            PageSelection::Spec(spec) => match spec.trim().to_lowercase().as_str() {
+                "all" => Ok((1..=page_count).collect()),
+                "first" => Ok(vec![in_range(1)?]),
+                "last" => Ok(vec![in_range(page_count)?]),
+                "odd" => Ok((1..=page_count).step_by(2).collect()),
+                "even" => Ok((2..=page_count).step_by(2).collect()),
+                ranges => {
+                    let mut pages = Vec::new();
+                    for part in ranges.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                        let parse = |s: &str| {
+                            s.trim()
+                                .parse::<u32>()
+                                .map_err(|_| format!("Invalid page selection '{}'", spec))
+                        };
+                        match part.split_once('-') {
+                            Some((start, end)) => {
+                                let (start, end) = (parse(start)?, parse(end)?);
+                                for page in start..=end {
+                                    pages.push(in_range(page)?);
+                                }
+                            }
+                            None => pages.push(in_range(parse(part)?)?),
+                        }
+                    }
+                    Ok(pages)
+                }
+            },
+        }
+    }
+}
+
+/// One stamp from the manifest: either an image file or a line of text.
+/// `{date}` in text is replaced with today's date.
+#[derive(Debug, Deserialize)]
+pub struct PlacementSpec {
+    pub image: Option<String>,
+    pub text: Option<String>,
+    pub font: Option<String>,
+    #[serde(default = "default_font_size")]
+    pub font_size: f32,
+    #[serde(default)]
+    pub pages: PageSelection,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_font_size() -> f32 {
+    33.0
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_date_format() -> String {
+    "%d %B %Y".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(rename = "placement", default)]
+    pub placements: Vec<PlacementSpec>,
+}
+
+struct PreparedPlacement {
+    image: PreparedImage,
+    pages: PageSelection,
+    x: f64,
+    y: f64,
+    scale: f64,
+}
+
+pub struct BatchOptions {
+    pub input_dir: PathBuf,
+    pub pattern: String,
+    pub manifest: PathBuf,
+    pub output_dir: PathBuf,
+    pub report: PathBuf,
+    pub jobs: usize,
+}
+
+struct FileResult {
+    input: PathBuf,
+    output: PathBuf,
+    pages_stamped: usize,
+    elapsed: Duration,
+    error: Option<String>,
+}
+
+/// Renders every manifest entry once up front; workers only copy the
+/// compressed image data into each document.
+fn prepare_placements(manifest_path: &Path) -> Result<Vec<PreparedPlacement>, Box<dyn Error>> {
+    let raw = fs::read_to_string(manifest_path).map_err(|e| {
+        format!(
+            "Failed to read manifest '{}': {}",
+            manifest_path.display(),
+            e
+        )
+    })?;
+    let manifest: Manifest = toml::from_str(&raw)
+        .map_err(|e| format!("Invalid manifest '{}': {}", manifest_path.display(), e))?;
+    if manifest.placements.is_empty() {
+        return Err(format!(
+            "Manifest '{}' has no [[placement]] entries",
+            manifest_path.display()
+        )
+        .into());
+    }
+
+    // Relative image and font paths are taken relative to the manifest.
+    let base = manifest_path.parent().unwrap_or(Path::new("."));
+    let resolve = |p: &str| base.join(p).to_string_lossy().to_string();
+    let today = chrono::Local::now()
+        .format(&manifest.date_format)
+        .to_string();
+
+    let mut prepared = Vec::new();
+    for (index, spec) in manifest.placements.into_iter().enumerate() {
+        let image = match (&spec.image, &spec.text) {
+            (Some(image), _) => PreparedImage::open(&resolve(image))?,
+            (None, Some(text)) => {
+                let font = spec
+                    .font
+                    .as_deref()
+                    .ok_or_else(|| format!("Placement {} has text but no font", index + 1))?;
+                let text = text.replace("{date}", &today);
+                PreparedImage::from_rgba(crate::render_text_image(
+                    &text,
+                    &resolve(font),
+                    spec.font_size,
+                )?)?
+            }
+            (None, None) => {
+                return Err(format!("Placement {} needs an image or text", index + 1).into());
+            }
+        };
+        prepared.push(PreparedPlacement {
+            image,
+            pages: spec.pages,
+            x: spec.x,
+            y: spec.y,
+            scale: spec.scale,
+        });
+    }
+    Ok(prepared)
+}
+
+/// Walks `input_dir` for files matching `pattern`, skipping anything in
+/// `exclude` so a rerun never picks up its own outputs or report.
+fn collect_inputs(
+    input_dir: &Path,
+    pattern: &str,
+    exclude: &[&Path],
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let pattern =
+        glob::Pattern::new(pattern).map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?;
+    // Paths that do not exist yet cannot be inside the walk either.
+    let exclude: Vec<PathBuf> = exclude
+        .iter()
+        .filter_map(|path| fs::canonicalize(path).ok())
+        .collect();
+    let mut found = Vec::new();
+    let mut pending = vec![input_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory '{}': {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if fs::canonicalize(&path).is_ok_and(|canonical| exclude.contains(&canonical)) {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(input_dir).unwrap_or(&path);
+            if pattern.matches_path(relative) {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+fn stamp_file(
+    input: &Path,
+    output: &Path,
+    placements: &[PreparedPlacement],
+) -> Result<usize, Box<dyn Error>> {
+    let mut doc = Document::load(input)?;
+    let pages = doc.get_pages();
+    let page_count = pages.len() as u32;
+    if page_count == 0 {
+        return Err("Document has no pages".into());
+    }
+    let mut stamped = BTreeSet::new();
+
+    for (index, placement) in placements.iter().enumerate() {
+        let selected = placement.pages.resolve(page_count)?;
+        if selected.is_empty() {
+            continue;
+        }
+        let name = format!("BatchStamp{}", index + 1);
+        let xobject_id = placement.image.add_to_document(&mut doc);
+        for page_number in selected {
+            let page_id = pages[&page_number];
+            stamp::place_image(
+                &mut doc,
+                page_id,
+                &placement.image,
+                xobject_id,
+                &name,
+                placement.x,
+                placement.y,
+                placement.scale,
+            )?;
+            stamped.insert(page_number);
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    doc.save(output)?;
+    Ok(stamped.len())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_report(path: &Path, results: &[FileResult]) -> Result<(), Box<dyn Error>> {
+    let mut csv = String::from("file,output,pages_stamped,millis,error\n");
+    for result in results {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&result.input.to_string_lossy()),
+            csv_field(&result.output.to_string_lossy()),
+            result.pages_stamped,
+            result.elapsed.as_millis(),
+            csv_field(result.error.as_deref().unwrap_or("")),
+        ));
+    }
+    fs::write(path, csv)
+        .map_err(|e| format!("Failed to write report '{}': {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Stamps every matching PDF under `input_dir` with the manifest's
+/// placements on a pool of worker threads and writes a CSV report.
+/// Returns the number of files that failed.
+pub fn run_batch(options: &BatchOptions) -> Result<usize, Box<dyn Error>> {
+    let placements = prepare_placements(&options.manifest)?;
+    let inputs = collect_inputs(
+        &options.input_dir,
+        &options.pattern,
+        &[&options.output_dir, &options.report],
+    )?;
+    if inputs.is_empty() {
+        eprintln!(
+            "⚠️ No files matching '{}' under {}",
+            options.pattern,
+            options.input_dir.display()
+        );
+    }
+
+    let total = inputs.len();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<FileResult>>> = Mutex::new((0..total).map(|_| None).collect());
+    let workers = options.jobs.max(1).min(total.max(1));
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(input) = inputs.get(index) else {
+                        break;
+                    };
+                    let relative = input.strip_prefix(&options.input_dir).unwrap_or(input);
+                    let output = options.output_dir.join(relative);
+
+                    let started = Instant::now();
+                    let outcome =
+                        stamp_file(input, &output, &placements).map_err(|e| e.to_string());
+                    let elapsed = started.elapsed();
+
+                    let finished = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    match &outcome {
+                        Ok(pages) => eprintln!(
+                            "[{}/{}] ✅ {} ({} page(s), {} ms)",
+                            finished,
+                            total,
+                            relative.display(),
+                            pages,
+                            elapsed.as_millis()
+                        ),
+                        Err(e) => {
+                            eprintln!("[{}/{}] ❌ {}: {}", finished, total, relative.display(), e)
+                        }
+                    }
+
+                    let result = FileResult {
+                        input: input.clone(),
+                        output,
+                        pages_stamped: *outcome.as_ref().unwrap_or(&0),
+                        elapsed,
+                        error: outcome.err(),
+                    };
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    let results: Vec<FileResult> = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+    write_report(&options.report, &results)?;
+    Ok(results.iter().filter(|r| r.error.is_some()).count())
+}
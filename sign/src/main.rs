@@ -1,11 +1,15 @@
-use flate2::Compression;
-use flate2::write::ZlibEncoder;
-use image::{GenericImageView, ImageBuffer, Rgba};
+mod acroform;
+mod batch;
+mod page;
+mod signature;
+mod stamp;
+
+use clap::{Parser, Subcommand};
+use image::{GenericImageView, ImageBuffer, Rgba, RgbaImage};
 use imageproc::drawing::draw_text_mut;
-use lopdf::{Document, Object, Stream, dictionary};
+use lopdf::Document;
 use rusttype::{Font, Scale};
 use std::fs;
-use std::io::Write;
 
 struct ImagePlacement<'a> {
     path: &'a str,
@@ -16,7 +20,242 @@ struct ImagePlacement<'a> {
     scale: f64,
 }
 
+#[derive(Parser, Debug)]
+#[clap(about = "Stamps signatures onto PDFs and fills AcroForm fields.")]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the AcroForm fields of a PDF with their types and rects.
+    Fields { input: String },
+    /// Fill AcroForm fields from a JSON or TOML data file.
+    Fill {
+        input: String,
+        #[clap(short, long, help = "JSON/TOML file mapping field names to values.")]
+        data: String,
+        #[clap(short, long, default_value = "filled_form.pdf")]
+        output: String,
+        #[clap(
+            long,
+            help = "Burn the fields into the page content so they can't be edited."
+        )]
+        flatten: bool,
+    },
+    /// Render a handwriting-style signature PNG, optionally stamping it on a PDF.
+    Signature {
+        #[clap(
+            short,
+            long,
+            help = "Signature text (ignored when --strokes is given)."
+        )]
+        text: Option<String>,
+        #[clap(short, long, default_value = "fonts/Motterdam-K74zp.ttf")]
+        font: String,
+        #[clap(
+            long,
+            help = "JSON list of captured points ({x, y, pressure}) to render instead of text."
+        )]
+        strokes: Option<String>,
+        #[clap(short, long, default_value = "signature.png")]
+        output: String,
+        #[clap(long, default_value_t = 64.0)]
+        size: f32,
+        #[clap(long, default_value_t = 12.0, allow_negative_numbers = true)]
+        slant: f32,
+        #[clap(long, default_value_t = 2.0)]
+        jitter: f32,
+        #[clap(long, default_value = "#141e5a", value_parser = signature::parse_ink)]
+        ink: Rgba<u8>,
+        #[clap(long, default_value_t = 0.8)]
+        thickness: f32,
+        #[clap(long, default_value_t = 4.0)]
+        pen_width: f32,
+        #[clap(long)]
+        no_underline: bool,
+        #[clap(long, default_value_t = 7)]
+        seed: u64,
+        #[clap(long, help = "PDF to stamp the rendered signature onto.")]
+        pdf: Option<String>,
+        #[clap(long, default_value_t = 1)]
+        page: u32,
+        #[clap(long, default_value_t = 100.0)]
+        x: f64,
+        #[clap(long, default_value_t = 100.0)]
+        y: f64,
+        #[clap(long, default_value_t = 0.5)]
+        scale: f64,
+        #[clap(long, default_value = "signed.pdf")]
+        pdf_output: String,
+    },
+    /// Stamp every matching PDF in a directory using a placement manifest.
+    Batch {
+        #[clap(short, long)]
+        input_dir: String,
+        #[clap(short, long, default_value = "*.pdf")]
+        glob: String,
+        #[clap(short, long, help = "TOML file with [[placement]] entries.")]
+        manifest: String,
+        #[clap(short, long, default_value = "stamped")]
+        output_dir: String,
+        #[clap(short, long, default_value = "batch_report.csv")]
+        report: String,
+        #[clap(short, long, help = "Worker threads (defaults to the number of CPUs).")]
+        jobs: Option<usize>,
+    },
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Args::parse().command {
+        Some(Command::Fields { input }) => list_form_fields(&input),
+        Some(Command::Fill {
+            input,
+            data,
+            output,
+            flatten,
+        }) => fill_form(&input, &data, &output, flatten),
+        Some(Command::Signature {
+            text,
+            font,
+            strokes,
+            output,
+            size,
+            slant,
+            jitter,
+            ink,
+            thickness,
+            pen_width,
+            no_underline,
+            seed,
+            pdf,
+            page,
+            x,
+            y,
+            scale,
+            pdf_output,
+        }) => {
+            let style = signature::SignatureStyle {
+                font_size: size,
+                slant_degrees: slant,
+                jitter,
+                ink,
+                thickness,
+                pen_width,
+                underline: !no_underline,
+                seed,
+            };
+            let image = match (&strokes, &text) {
+                (Some(path), _) => {
+                    signature::render_strokes(&signature::load_strokes(path)?, &style)?
+                }
+                (None, Some(text)) => signature::render_text_signature(text, &font, &style)?,
+                (None, None) => return Err("Pass --text or --strokes".into()),
+            };
+            image.save(&output)?;
+            println!("✅ Signature rendered to {}", output);
+
+            if let Some(pdf) = pdf {
+                let mut doc = Document::load(&pdf)?;
+                let page_id = *doc
+                    .get_pages()
+                    .get(&page)
+                    .ok_or_else(|| format!("Page {} not found in PDF!", page))?;
+                add_png_image_to_pdf(&mut doc, page_id, &output, "Signature", x, y, scale)?;
+                doc.save(&pdf_output)?;
+                println!("✅ Signature stamped on page {} of {}", page, pdf_output);
+            }
+            Ok(())
+        }
+        Some(Command::Batch {
+            input_dir,
+            glob,
+            manifest,
+            output_dir,
+            report,
+            jobs,
+        }) => {
+            let options = batch::BatchOptions {
+                input_dir: input_dir.into(),
+                pattern: glob,
+                manifest: manifest.into(),
+                output_dir: output_dir.into(),
+                report: report.clone().into(),
+                jobs: jobs.unwrap_or_else(|| {
+                    std::thread::available_parallelism()
+                        .map(|n| n.get())
+                        .unwrap_or(1)
+                }),
+            };
+            let failed = batch::run_batch(&options)?;
+            println!("✅ Batch report written to {}", report);
+            if failed > 0 {
+                return Err(format!("{} file(s) failed; see {}", failed, report).into());
+            }
+            Ok(())
+        }
+        None => stamp_contract(),
+    }
+}
+
+fn list_form_fields(input: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let doc = Document::load(input)?;
+    let fields = acroform::list_fields(&doc)?;
+    if fields.is_empty() {
+        println!("No AcroForm fields found in {}", input);
+        return Ok(());
+    }
+    for field in &fields {
+        println!(
+            "{} [{}] value={}",
+            field.name,
+            field.field_type.label(),
+            field.value.as_deref().unwrap_or("")
+        );
+        for widget in &field.widgets {
+            let page = widget
+                .page
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "?".to_string());
+            println!(
+                "    page {} rect [{:.1} {:.1} {:.1} {:.1}]{}",
+                page,
+                widget.rect[0],
+                widget.rect[1],
+                widget.rect[2],
+                widget.rect[3],
+                widget
+                    .on_state
+                    .as_ref()
+                    .map(|s| format!(" on-state /{}", s))
+                    .unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn fill_form(
+    input: &str,
+    data: &str,
+    output: &str,
+    flatten: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let values = acroform::load_field_values(data)?;
+    let filled = acroform::fill_fields(&mut doc, &values)?;
+    println!("✅ Filled {} field(s) from {}", filled, data);
+    if flatten {
+        let flattened = acroform::flatten_fields(&mut doc)?;
+        println!("✅ Flattened {} widget(s) into page content", flattened);
+    }
+    doc.save(output)?;
+    println!("✅ PDF saved to {}", output);
+    Ok(())
+}
+
+fn stamp_contract() -> Result<(), Box<dyn std::error::Error>> {
     let mut doc = Document::load("D:\\rust\\sign\\input.pdf")?;
     let pages = doc.get_pages();
 
@@ -150,17 +389,32 @@ fn create_text_image(
     path: &str,
     font_path: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    render_text_image(text, font_path, 33.0)?.save(path)?;
+    Ok(())
+}
+
+fn render_text_image(
+    text: &str,
+    font_path: &str,
+    font_size: f32,
+) -> Result<RgbaImage, Box<dyn std::error::Error>> {
     let font_data = fs::read(font_path)?;
-    let font = Font::try_from_vec(font_data).unwrap();
-    let scale = Scale::uniform(33.0);
-    let width = 300;
-    let height = 50;
+    let font = Font::try_from_vec(font_data)
+        .ok_or_else(|| format!("Failed to load font from data: {}", font_path))?;
+    let scale = Scale::uniform(font_size);
+    // Keep the original 300x50 canvas at 33px, but grow it for longer text.
+    let text_width = font
+        .layout(text, scale, rusttype::point(5.0, 0.0))
+        .filter_map(|g| g.pixel_bounding_box().map(|bb| bb.max.x))
+        .max()
+        .unwrap_or(0) as f32;
+    let width = (300.0 * font_size / 33.0).max(text_width + 10.0).ceil() as u32;
+    let height = (50.0 * font_size / 33.0).ceil() as u32;
 
     let mut image: ImageBuffer<Rgba<u8>, _> =
         ImageBuffer::from_pixel(width, height, Rgba([0, 0, 0, 0]));
     draw_text_mut(&mut image, Rgba([0, 0, 0, 255]), 5, 2, scale, &font, text);
-    image.save(path)?;
-    Ok(())
+    Ok(image)
 }
 
 fn add_text_image_to_pdf(
@@ -186,100 +440,7 @@ fn add_png_image_to_pdf(
     y: f64,
     scale: f64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut img = image::open(image_path)?.to_rgba8();
-    image::imageops::flip_vertical_in_place(&mut img);
-    let (width, height) = img.dimensions();
-    let width_scaled = (width as f64 * scale) as i64;
-    let height_scaled = (height as f64 * scale) as i64;
-
-    let mut alpha_buf = Vec::with_capacity((width * height) as usize);
-    let mut rgb_buf = Vec::with_capacity((width * height * 3) as usize);
-
-    for pixel in img.pixels() {
-        let [r, g, b, a] = pixel.0;
-        rgb_buf.extend_from_slice(&[r, g, b]);
-        alpha_buf.push(a);
-    }
-
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(&rgb_buf)?;
-    let compressed_rgb = encoder.finish()?;
-
-    let mut alpha_encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    alpha_encoder.write_all(&alpha_buf)?;
-    let compressed_alpha = alpha_encoder.finish()?;
-
-    let smask_id = doc.add_object(Stream::new(
-        dictionary! {
-            "Type" => "XObject",
-            "Subtype" => "Image",
-            "Width" => width as i64,
-            "Height" => height as i64,
-            "ColorSpace" => "DeviceGray",
-            "BitsPerComponent" => 8,
-            "Filter" => "FlateDecode",
-        },
-        compressed_alpha,
-    ));
-
-    let xobject_id = doc.add_object(Stream::new(
-        dictionary! {
-            "Type" => "XObject",
-            "Subtype" => "Image",
-            "Width" => width as i64,
-            "Height" => height as i64,
-            "ColorSpace" => "DeviceRGB",
-            "BitsPerComponent" => 8,
-            "Filter" => "FlateDecode",
-            "SMask" => Object::Reference(smask_id),
-        },
-        compressed_rgb,
-    ));
-
-    {
-        let page = doc.get_object_mut(page_id)?;
-        let dict = page.as_dict_mut()?;
-
-        if !dict.has(b"Resources") {
-            dict.set("Resources", Object::Dictionary(dictionary! {}));
-        }
-
-        let resources = dict.get_mut(b"Resources")?.as_dict_mut()?;
-        if !resources.has(b"XObject") {
-            resources.set("XObject", Object::Dictionary(dictionary! {}));
-        }
-
-        let xobjects = resources.get_mut(b"XObject")?.as_dict_mut()?;
-        xobjects.set(
-            image_name.as_bytes().to_vec(),
-            Object::Reference(xobject_id),
-        );
-    }
-
-    let draw_ops = format!(
-        "q\n{} 0 0 {} {} {} cm\n/{} Do\nQ\n",
-        width_scaled, height_scaled, x, y, image_name
-    );
-    let stream = Stream::new(dictionary! {}, draw_ops.as_bytes().to_vec());
-    let stream_id = doc.add_object(stream);
-
-    {
-        let page = doc.get_object_mut(page_id)?;
-        let dict = page.as_dict_mut()?;
-
-        let new_contents = match dict.remove(b"Contents") {
-            Some(Object::Reference(existing)) => Object::Array(vec![
-                Object::Reference(existing),
-                Object::Reference(stream_id),
-            ]),
-            Some(Object::Array(mut array)) => {
-                array.push(Object::Reference(stream_id));
-                Object::Array(array)
-            }
-            _ => Object::Reference(stream_id),
-        };
-        dict.set("Contents", new_contents);
-    }
-
-    Ok(())
+    let image = stamp::PreparedImage::open(image_path)?;
+    let xobject_id = image.add_to_document(doc);
+    stamp::place_image(doc, page_id, &image, xobject_id, image_name, x, y, scale)
 }
@@ -0,0 +1,231 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, dictionary};
+use std::error::Error;
+
+const WRAP_OPEN: &[u8] = b"q\n";
+const WRAP_CLOSE: &[u8] = b"Q\n";
+
+/// Looks up a page attribute, following `/Parent` for the inheritable ones
+/// (`Resources`, `MediaBox`, `CropBox`, `Rotate`).
+fn inherited_attribute(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut current = Some(page_id);
+    let mut depth = 0;
+    while let Some(id) = current {
+        let dict = doc.get_dictionary(id).ok()?;
+        if let Ok(value) = dict.get(key) {
+            return Some(value.clone());
+        }
+        current = dict.get(b"Parent").and_then(Object::as_reference).ok();
+        depth += 1;
+        if depth > 64 {
+            return None;
+        }
+    }
+    None
+}
+
+fn resolve(doc: &Document, object: Object) -> Option<Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(id).ok().cloned(),
+        other => Some(other),
+    }
+}
+
+fn read_box(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<[f64; 4]> {
+    let values = resolve(doc, inherited_attribute(doc, page_id, key)?)?;
+    let values: Vec<f64> = values
+        .as_array()
+        .ok()?
+        .iter()
+        .filter_map(crate::acroform::number)
+        .collect();
+    if values.len() != 4 {
+        return None;
+    }
+    Some([
+        values[0].min(values[2]),
+        values[1].min(values[3]),
+        values[0].max(values[2]),
+        values[1].max(values[3]),
+    ])
+}
+
+/// The visible area of a page: its CropBox (falling back to MediaBox) and
+/// the `/Rotate` a viewer applies when displaying it.
+#[derive(Debug, Clone, Copy)]
+pub struct PageGeometry {
+    pub crop_box: [f64; 4],
+    pub rotate: i64,
+}
+
+impl PageGeometry {
+    pub fn load(doc: &Document, page_id: ObjectId) -> PageGeometry {
+        let media_box = read_box(doc, page_id, b"MediaBox").unwrap_or([0.0, 0.0, 612.0, 792.0]);
+        let crop_box = read_box(doc, page_id, b"CropBox")
+            .map(|crop| {
+                // The CropBox is clipped to the MediaBox.
+                [
+                    crop[0].max(media_box[0]),
+                    crop[1].max(media_box[1]),
+                    crop[2].min(media_box[2]),
+                    crop[3].min(media_box[3]),
+                ]
+            })
+            .filter(|crop| crop[2] > crop[0] && crop[3] > crop[1])
+            .unwrap_or(media_box);
+        let rotate = inherited_attribute(doc, page_id, b"Rotate")
+            .and_then(|r| resolve(doc, r))
+            .and_then(|r| r.as_i64().ok())
+            .unwrap_or(0)
+            .rem_euclid(360);
+        PageGeometry {
+            crop_box,
+            // Only multiples of 90 are valid; snap anything else.
+            rotate: (rotate + 45) / 90 * 90 % 360,
+        }
+    }
+
+    /// Width and height of the page as the viewer shows it.
+    pub fn visible_size(&self) -> (f64, f64) {
+        let width = self.crop_box[2] - self.crop_box[0];
+        let height = self.crop_box[3] - self.crop_box[1];
+        match self.rotate {
+            90 | 270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// `cm` operands mapping visible-page coordinates (origin at the
+    /// bottom-left corner of the displayed page) into default user space.
+    pub fn visible_to_user(&self) -> [f64; 6] {
+        let [x0, y0, x1, y1] = self.crop_box;
+        let width = x1 - x0;
+        let height = y1 - y0;
+        match self.rotate {
+            90 => [0.0, 1.0, -1.0, 0.0, x0 + width, y0],
+            180 => [-1.0, 0.0, 0.0, -1.0, x0 + width, y0 + height],
+            270 => [0.0, -1.0, 1.0, 0.0, x0, y0 + height],
+            _ => [1.0, 0.0, 0.0, 1.0, x0, y0],
+        }
+    }
+}
+
+/// Returns a `cm` operator line for the given matrix.
+pub fn cm(matrix: &[f64; 6]) -> String {
+    format!(
+        "{} {} {} {} {} {} cm\n",
+        matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5]
+    )
+}
+
+/// Gives the page its own direct `/Resources` dictionary, copying an
+/// indirect or inherited one so shared resources are never modified.
+fn own_resources(doc: &mut Document, page_id: ObjectId) -> Result<(), Box<dyn Error>> {
+    let direct = doc
+        .get_dictionary(page_id)?
+        .get(b"Resources")
+        .map(|r| r.as_dict().is_ok())
+        .unwrap_or(false);
+    if direct {
+        return Ok(());
+    }
+    let resources = inherited_attribute(doc, page_id, b"Resources")
+        .and_then(|r| resolve(doc, r))
+        .and_then(|r| r.as_dict().ok().cloned())
+        .unwrap_or_default();
+    doc.get_object_mut(page_id)?
+        .as_dict_mut()?
+        .set("Resources", Object::Dictionary(resources));
+    Ok(())
+}
+
+/// Registers `xobject_id` under `name` in the page's `/Resources /XObject`,
+/// resolving inherited and indirect dictionaries rather than replacing them.
+pub fn add_xobject_resource(
+    doc: &mut Document,
+    page_id: ObjectId,
+    name: &str,
+    xobject_id: ObjectId,
+) -> Result<(), Box<dyn Error>> {
+    own_resources(doc, page_id)?;
+
+    let existing_xobjects = match doc
+        .get_dictionary(page_id)?
+        .get(b"Resources")?
+        .as_dict()?
+        .get(b"XObject")
+    {
+        Ok(Object::Dictionary(_)) => None,
+        Ok(other) => Some(
+            resolve(doc, other.clone())
+                .and_then(|x| x.as_dict().ok().cloned())
+                .unwrap_or_default(),
+        ),
+        Err(_) => Some(Dictionary::new()),
+    };
+
+    let resources = doc
+        .get_object_mut(page_id)?
+        .as_dict_mut()?
+        .get_mut(b"Resources")?
+        .as_dict_mut()?;
+    if let Some(xobjects) = existing_xobjects {
+        resources.set("XObject", Object::Dictionary(xobjects));
+    }
+    resources
+        .get_mut(b"XObject")?
+        .as_dict_mut()?
+        .set(name.as_bytes().to_vec(), Object::Reference(xobject_id));
+    Ok(())
+}
+
+fn content_refs(doc: &Document, contents: Option<Object>) -> Vec<Object> {
+    match contents {
+        Some(Object::Reference(id)) => match doc.get_object(id) {
+            // An indirect array of streams is legal too.
+            Ok(Object::Array(array)) => array.clone(),
+            Ok(_) => vec![Object::Reference(id)],
+            Err(_) => Vec::new(),
+        },
+        Some(Object::Array(array)) => array,
+        _ => Vec::new(),
+    }
+}
+
+fn is_wrap_open(doc: &Document, object: &Object) -> bool {
+    object
+        .as_reference()
+        .and_then(|id| doc.get_object(id))
+        .and_then(Object::as_stream)
+        .map(|stream| stream.content == WRAP_OPEN)
+        .unwrap_or(false)
+}
+
+/// Appends a new content stream after the page's existing `/Contents`.
+///
+/// The existing content is first wrapped in `q`/`Q` so any graphics state it
+/// leaves behind (an unbalanced `cm`, clipping, colour) can't skew what is
+/// drawn afterwards. Pages already wrapped by an earlier call are not
+/// wrapped again.
+pub fn append_content(
+    doc: &mut Document,
+    page_id: ObjectId,
+    ops: Vec<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let contents = doc.get_dictionary(page_id)?.get(b"Contents").ok().cloned();
+    let mut streams = content_refs(doc, contents);
+
+    if !streams.is_empty() && !is_wrap_open(doc, &streams[0]) {
+        let open_id = doc.add_object(Stream::new(dictionary! {}, WRAP_OPEN.to_vec()));
+        let close_id = doc.add_object(Stream::new(dictionary! {}, WRAP_CLOSE.to_vec()));
+        streams.insert(0, Object::Reference(open_id));
+        streams.push(Object::Reference(close_id));
+    }
+
+    let stream_id = doc.add_object(Stream::new(dictionary! {}, ops));
+    streams.push(Object::Reference(stream_id));
+
+    doc.get_object_mut(page_id)?
+        .as_dict_mut()?
+        .set("Contents", Object::Array(streams));
+    Ok(())
+}
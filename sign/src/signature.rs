@@ -0,0 +1,385 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+use rusttype::{Font, Scale, point};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+const PADDING: f32 = 12.0;
+
+/// Knobs for the handwriting-style renderer. The same seed always yields
+/// the same image.
+#[derive(Debug, Clone)]
+pub struct SignatureStyle {
+    pub font_size: f32,
+    /// Forward lean in degrees; negative values lean backwards.
+    pub slant_degrees: f32,
+    /// Maximum vertical wobble of each glyph, in pixels.
+    pub jitter: f32,
+    pub ink: Rgba<u8>,
+    /// Stroke thickening radius in pixels (0 keeps the font's own weight).
+    pub thickness: f32,
+    /// Maximum pen width in pixels for captured stroke paths.
+    pub pen_width: f32,
+    pub underline: bool,
+    pub seed: u64,
+}
+
+impl Default for SignatureStyle {
+    fn default() -> Self {
+        SignatureStyle {
+            font_size: 64.0,
+            slant_degrees: 12.0,
+            jitter: 2.0,
+            ink: Rgba([20, 30, 90, 255]),
+            thickness: 0.8,
+            pen_width: 4.0,
+            underline: true,
+            seed: 7,
+        }
+    }
+}
+
+/// SplitMix64: tiny, seedable and stable across builds, which keeps
+/// signatures reproducible without pinning an RNG crate's algorithm.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `[min, max)`.
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+}
+
+/// Greyscale ink coverage in `[0, 1]`, composited with `max`.
+struct Canvas {
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            coverage: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    fn plot(&mut self, x: i64, y: i64, value: f32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let idx = (y as u32 * self.width + x as u32) as usize;
+        self.coverage[idx] = self.coverage[idx].max(value.clamp(0.0, 1.0));
+    }
+
+    /// Anti-aliased filled disc.
+    fn disc(&mut self, cx: f32, cy: f32, radius: f32, intensity: f32) {
+        let reach = radius.ceil() as i64 + 1;
+        let (px, py) = (cx.round() as i64, cy.round() as i64);
+        for y in py - reach..=py + reach {
+            for x in px - reach..=px + reach {
+                let dist = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt();
+                let edge = (radius + 0.5 - dist).clamp(0.0, 1.0);
+                if edge > 0.0 {
+                    self.plot(x, y, edge * intensity);
+                }
+            }
+        }
+    }
+
+    /// Grey-scale dilation with a disc of `radius` pixels.
+    fn thicken(&mut self, radius: f32) {
+        if radius <= 0.0 {
+            return;
+        }
+        let reach = radius.ceil() as i64;
+        let source = self.coverage.clone();
+        for y in 0..self.height as i64 {
+            for x in 0..self.width as i64 {
+                let mut best = 0.0f32;
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                        let weight = (radius + 0.5 - dist).clamp(0.0, 1.0);
+                        let (sx, sy) = (x + dx, y + dy);
+                        if weight == 0.0
+                            || sx < 0
+                            || sy < 0
+                            || sx >= self.width as i64
+                            || sy >= self.height as i64
+                        {
+                            continue;
+                        }
+                        let value = source[(sy as u32 * self.width + sx as u32) as usize];
+                        best = best.max(value * weight);
+                    }
+                }
+                let idx = (y as u32 * self.width + x as u32) as usize;
+                self.coverage[idx] = self.coverage[idx].max(best);
+            }
+        }
+    }
+
+    fn into_image(self, ink: Rgba<u8>) -> RgbaImage {
+        let [r, g, b, a] = ink.0;
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let cover = self.coverage[(y * self.width + x) as usize];
+            Rgba([r, g, b, (cover * a as f32).round() as u8])
+        })
+    }
+}
+
+fn cubic(p0: (f32, f32), p1: (f32, f32), p2: (f32, f32), p3: (f32, f32), t: f32) -> (f32, f32) {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (
+        a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+        a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+    )
+}
+
+/// Renders `text` as a signature: slanted, with per-glyph baseline jitter
+/// and pen pressure, thickened strokes and an optional trailing underline.
+pub fn render_text_signature(
+    text: &str,
+    font_path: &str,
+    style: &SignatureStyle,
+) -> Result<RgbaImage, Box<dyn Error>> {
+    let font_data = fs::read(font_path)
+        .map_err(|e| format!("Failed to read font file '{}': {}", font_path, e))?;
+    let font = Font::try_from_vec(font_data)
+        .ok_or_else(|| format!("Failed to load font from data: {}", font_path))?;
+
+    let mut rng = Rng(style.seed);
+    let scale = Scale::uniform(style.font_size);
+    let v_metrics = font.v_metrics(scale);
+    let shear = style.slant_degrees.to_radians().tan();
+    let text_height = v_metrics.ascent - v_metrics.descent;
+
+    let glyphs: Vec<_> = font
+        .layout(text, scale, point(0.0, v_metrics.ascent))
+        .collect();
+    let text_width = glyphs
+        .iter()
+        .filter_map(|g| g.pixel_bounding_box().map(|bb| bb.max.x))
+        .max()
+        .unwrap_or(0) as f32;
+    if text_width <= 0.0 {
+        return Err(format!("Text '{}' produced no visible glyphs", text).into());
+    }
+
+    // Room for the lean, the wobble and a flourish that overshoots the text.
+    let lean = shear.abs() * text_height;
+    let underline_room = if style.underline {
+        style.font_size * 0.45
+    } else {
+        0.0
+    };
+    let overshoot = if style.underline {
+        text_width * 0.12
+    } else {
+        0.0
+    };
+    let margin = PADDING + style.jitter + style.thickness;
+    let width = (text_width + lean + overshoot + 2.0 * margin).ceil() as u32;
+    let height = (text_height + underline_room + 2.0 * margin).ceil() as u32;
+    let mut canvas = Canvas::new(width, height);
+
+    // A forward lean pushes descenders left, a backward lean pushes ascenders left.
+    let origin_x = margin
+        + if shear < 0.0 {
+            -v_metrics.ascent * shear
+        } else {
+            -v_metrics.descent * shear
+        };
+    let origin_y = margin;
+    let baseline = origin_y + v_metrics.ascent;
+
+    for glyph in &glyphs {
+        let Some(bb) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        let wobble = rng.range(-style.jitter, style.jitter);
+        let pressure = rng.range(0.8, 1.0);
+        glyph.draw(|gx, gy, value| {
+            let x = origin_x + (bb.min.x + gx as i32) as f32;
+            let y = origin_y + (bb.min.y + gy as i32) as f32 + wobble;
+            // Shear about the baseline so the lean pivots at the writing line.
+            let x = x + (baseline - y) * shear;
+            canvas.plot(x.round() as i64, y.round() as i64, value * pressure);
+        });
+    }
+
+    canvas.thicken(style.thickness);
+
+    if style.underline {
+        let start_x = origin_x + text_width * rng.range(0.0, 0.15);
+        let end_x = origin_x + text_width + overshoot;
+        let y = baseline - v_metrics.descent * 0.35 + style.font_size * 0.12;
+        let lift = style.font_size * 0.18;
+        let p0 = (start_x, y + rng.range(-1.0, 1.0));
+        let p1 = (
+            start_x + (end_x - start_x) * 0.3,
+            y + lift * rng.range(0.4, 0.9),
+        );
+        let p2 = (
+            start_x + (end_x - start_x) * 0.7,
+            y - lift * rng.range(0.2, 0.6),
+        );
+        let p3 = (end_x, y - lift * rng.range(0.6, 1.2));
+        let max_radius = (style.font_size / 28.0).max(0.8) + style.thickness * 0.5;
+        let steps = ((end_x - start_x) * 2.0).max(8.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let (x, y) = cubic(p0, p1, p2, p3, t);
+            // Heavier in the middle, tapering at both ends like a pen stroke.
+            let taper = (std::f32::consts::PI * t).sin().powf(0.6);
+            canvas.disc(x, y, max_radius * (0.25 + 0.75 * taper), 0.95);
+        }
+    }
+
+    Ok(canvas.into_image(style.ink))
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct StrokePoint {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default = "default_pressure")]
+    pub pressure: f32,
+}
+
+fn default_pressure() -> f32 {
+    0.5
+}
+
+/// Either one stroke (a flat list of points) or a list of pen-down strokes.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StrokeFile {
+    Strokes(Vec<Vec<StrokePoint>>),
+    Single(Vec<StrokePoint>),
+    Wrapped { strokes: Vec<Vec<StrokePoint>> },
+}
+
+pub fn load_strokes(path: &str) -> Result<Vec<Vec<StrokePoint>>, Box<dyn Error>> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read stroke file '{}': {}", path, e))?;
+    let parsed: StrokeFile = serde_json::from_str(&raw)
+        .map_err(|e| format!("Invalid stroke JSON in '{}': {}", path, e))?;
+    let strokes = match parsed {
+        StrokeFile::Strokes(strokes) | StrokeFile::Wrapped { strokes } => strokes,
+        StrokeFile::Single(points) => vec![points],
+    };
+    if strokes.iter().all(|s| s.is_empty()) {
+        return Err(format!("Stroke file '{}' contains no points", path).into());
+    }
+    Ok(strokes)
+}
+
+fn catmull_rom(
+    p0: StrokePoint,
+    p1: StrokePoint,
+    p2: StrokePoint,
+    p3: StrokePoint,
+    t: f32,
+) -> (f32, f32) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let axis = |a: f32, b: f32, c: f32, d: f32| {
+        0.5 * (2.0 * b
+            + (c - a) * t
+            + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+            + (3.0 * b - a - 3.0 * c + d) * t3)
+    };
+    (axis(p0.x, p1.x, p2.x, p3.x), axis(p0.y, p1.y, p2.y, p3.y))
+}
+
+/// Renders captured pen strokes as a smooth, variable-width signature.
+/// Coordinates are in capture pixels with y pointing down.
+pub fn render_strokes(
+    strokes: &[Vec<StrokePoint>],
+    style: &SignatureStyle,
+) -> Result<RgbaImage, Box<dyn Error>> {
+    let points = strokes.iter().flatten();
+    let min_x = points.clone().map(|p| p.x).fold(f32::INFINITY, f32::min);
+    let max_x = points
+        .clone()
+        .map(|p| p.x)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let min_y = points.clone().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    let max_y = points.map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+    if !min_x.is_finite() {
+        return Err("No stroke points to render".into());
+    }
+
+    let margin = PADDING + style.pen_width;
+    let width = (max_x - min_x + 2.0 * margin).ceil() as u32;
+    let height = (max_y - min_y + 2.0 * margin).ceil() as u32;
+    let mut canvas = Canvas::new(width.max(1), height.max(1));
+    let radius = |pressure: f32| 0.5 * style.pen_width * (0.3 + 0.7 * pressure.clamp(0.0, 1.0));
+
+    for stroke in strokes {
+        let shifted: Vec<StrokePoint> = stroke
+            .iter()
+            .map(|p| StrokePoint {
+                x: p.x - min_x + margin,
+                y: p.y - min_y + margin,
+                pressure: p.pressure,
+            })
+            .collect();
+        match shifted.len() {
+            0 => continue,
+            1 => {
+                let p = shifted[0];
+                canvas.disc(p.x, p.y, radius(p.pressure), 1.0);
+                continue;
+            }
+            _ => {}
+        }
+        for i in 0..shifted.len() - 1 {
+            let p0 = shifted[i.saturating_sub(1)];
+            let p1 = shifted[i];
+            let p2 = shifted[i + 1];
+            let p3 = shifted[(i + 2).min(shifted.len() - 1)];
+            let length = ((p2.x - p1.x).powi(2) + (p2.y - p1.y).powi(2)).sqrt();
+            let steps = (length * 2.0).ceil().max(1.0) as u32;
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                let (x, y) = catmull_rom(p0, p1, p2, p3, t);
+                let pressure = p1.pressure + (p2.pressure - p1.pressure) * t;
+                canvas.disc(x, y, radius(pressure), 1.0);
+            }
+        }
+    }
+
+    Ok(canvas.into_image(style.ink))
+}
+
+/// Parses `#rrggbb` / `rrggbb` (optionally with a trailing alpha byte).
+pub fn parse_ink(hex: &str) -> Result<Rgba<u8>, String> {
+    let hex = hex.trim_start_matches('#');
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2).unwrap_or(""), 16)
+            .map_err(|_| format!("Invalid ink colour '{}'. Expected e.g. '#1a2a6c'", hex))
+    };
+    match hex.len() {
+        6 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => Err(format!(
+            "Invalid ink colour '{}'. Expected e.g. '#1a2a6c'",
+            hex
+        )),
+    }
+}
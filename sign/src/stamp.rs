@@ -0,0 +1,125 @@
+use crate::page;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::RgbaImage;
+use lopdf::{Document, Object, ObjectId, Stream, dictionary};
+use std::error::Error;
+use std::io::Write;
+
+/// An RGBA image already split into compressed RGB and alpha planes, so the
+/// same stamp can be added to many documents without re-encoding it.
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    pub width: u32,
+    pub height: u32,
+    rgb: Vec<u8>,
+    alpha: Vec<u8>,
+}
+
+impl PreparedImage {
+    pub fn open(image_path: &str) -> Result<PreparedImage, Box<dyn Error>> {
+        let img = image::open(image_path)
+            .map_err(|e| format!("Failed to open image '{}': {}", image_path, e))?
+            .to_rgba8();
+        PreparedImage::from_rgba(img)
+    }
+
+    pub fn from_rgba(mut img: RgbaImage) -> Result<PreparedImage, Box<dyn Error>> {
+        image::imageops::flip_vertical_in_place(&mut img);
+        let (width, height) = img.dimensions();
+
+        let mut alpha_buf = Vec::with_capacity((width * height) as usize);
+        let mut rgb_buf = Vec::with_capacity((width * height * 3) as usize);
+
+        for pixel in img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            rgb_buf.extend_from_slice(&[r, g, b]);
+            alpha_buf.push(a);
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&rgb_buf)?;
+        let rgb = encoder.finish()?;
+
+        let mut alpha_encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        alpha_encoder.write_all(&alpha_buf)?;
+        let alpha = alpha_encoder.finish()?;
+
+        Ok(PreparedImage {
+            width,
+            height,
+            rgb,
+            alpha,
+        })
+    }
+
+    /// Adds the image (with its soft mask) to `doc` and returns the XObject id.
+    pub fn add_to_document(&self, doc: &mut Document) -> ObjectId {
+        let smask_id = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => self.width as i64,
+                "Height" => self.height as i64,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+                "Filter" => "FlateDecode",
+            },
+            self.alpha.clone(),
+        ));
+
+        doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => self.width as i64,
+                "Height" => self.height as i64,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+                "Filter" => "FlateDecode",
+                "SMask" => Object::Reference(smask_id),
+            },
+            self.rgb.clone(),
+        ))
+    }
+}
+
+/// Draws an image XObject on a page at `x`/`y` (visible page space) sized
+/// `scale` PDF units per image pixel.
+#[allow(clippy::too_many_arguments)]
+pub fn place_image(
+    doc: &mut Document,
+    page_id: ObjectId,
+    image: &PreparedImage,
+    xobject_id: ObjectId,
+    image_name: &str,
+    x: f64,
+    y: f64,
+    scale: f64,
+) -> Result<(), Box<dyn Error>> {
+    let width_scaled = (image.width as f64 * scale) as i64;
+    let height_scaled = (image.height as f64 * scale) as i64;
+
+    page::add_xobject_resource(doc, page_id, image_name, xobject_id)?;
+
+    // x/y are in the visible page space (what a viewer shows, after
+    // /Rotate and relative to the CropBox origin); map them to user space.
+    let geometry = page::PageGeometry::load(doc, page_id);
+    let (visible_width, visible_height) = geometry.visible_size();
+    if x < 0.0 || y < 0.0 || x > visible_width || y > visible_height {
+        eprintln!(
+            "⚠️ Image '{}' at ({}, {}) lies outside the visible page ({} x {})",
+            image_name, x, y, visible_width, visible_height
+        );
+    }
+    let draw_ops = format!(
+        "q\n{}{} 0 0 {} {} {} cm\n/{} Do\nQ\n",
+        page::cm(&geometry.visible_to_user()),
+        width_scaled,
+        height_scaled,
+        x,
+        y,
+        image_name
+    );
+    page::append_content(doc, page_id, draw_ops.into_bytes())
+}